@@ -0,0 +1,252 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! A client for sending GraphQL requests to KubOS services which copes with lost packets
+
+use failure;
+use kubos_system::Config as ServiceConfig;
+use query::parse_response;
+use serde::de::DeserializeOwned;
+use serde_json;
+use std::collections::HashMap;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+/// The result type used by `ServiceClient`
+type ClientResult<T> = Result<T, failure::Error>;
+
+// Largest response which can be received. This is the largest possible UDP payload
+const MAX_RESPONSE: usize = 65507;
+
+/// Errors reported by `ServiceClient` which callers may want to handle differently from
+/// errors returned by the service itself
+#[derive(Debug, Fail, PartialEq)]
+pub enum ClientError {
+    /// The service did not respond to any attempt to send the request
+    #[fail(display = "No response from {} after {} attempt/s", service, attempts)]
+    Timeout {
+        /// Name of the service
+        service: String,
+        /// Number of times the request was sent
+        attempts: u32,
+    },
+    /// The service's address in the config file could not be resolved
+    #[fail(display = "Unable to resolve address {} of {}", addr, service)]
+    BadAddress {
+        /// Name of the service
+        service: String,
+        /// The address from the config file
+        addr: String,
+    },
+}
+
+/// Options controlling how `ServiceClient` sends requests
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClientOptions {
+    /// The config file which the addresses of services are read from. The default config file is
+    /// used if this is `None`
+    pub config_path: Option<String>,
+    /// How long to wait for a response to each attempt
+    pub timeout: Duration,
+    /// The number of times a request is sent before giving up
+    pub attempts: u32,
+    /// How long to wait before the first retry. The wait is doubled for each further retry
+    pub backoff: Duration,
+    /// Whether mutations are retried too. Mutations are only sent once by default, since a
+    /// mutation whose response was lost may still have been carried out
+    pub retry_mutations: bool,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        ClientOptions {
+            config_path: None,
+            timeout: Duration::from_secs(1),
+            attempts: 3,
+            backoff: Duration::from_millis(100),
+            retry_mutations: false,
+        }
+    }
+}
+
+/// Sends GraphQL requests to KubOS services, retrying requests which time out.
+///
+/// The address of each service is read from the config file the first time it's needed and then
+/// remembered. Each request is sent from its own socket, so a late response to an earlier request
+/// can never be mistaken for the response to a later one.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate failure;
+/// # extern crate kubos_app;
+/// #[macro_use]
+/// extern crate serde_derive;
+///
+/// use kubos_app::ServiceClient;
+///
+/// #[derive(Deserialize)]
+/// struct Power {
+///     state: String,
+/// }
+///
+/// #[derive(Deserialize)]
+/// struct PowerResponse {
+///     power: Power,
+/// }
+///
+/// # fn func() -> Result<(), failure::Error> {
+/// let client = ServiceClient::default();
+///
+/// let response = client.query_as::<PowerResponse>("radio-service", "{ power { state } }")?;
+///
+/// assert_eq!(response.power.state, "ON");
+/// # Ok(())
+/// # }
+/// # fn main() {}
+/// ```
+#[derive(Debug, Default)]
+pub struct ServiceClient {
+    options: ClientOptions,
+    addresses: Mutex<HashMap<String, SocketAddr>>,
+}
+
+impl ServiceClient {
+    /// Create a client which sends requests as described by `options`
+    pub fn new(options: ClientOptions) -> ServiceClient {
+        ServiceClient {
+            options,
+            addresses: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Send a GraphQL request to a service, returning the `msg` field of its response
+    ///
+    /// # Arguments
+    ///
+    /// * `service` - The name of the service, as it appears in the config file
+    /// * `request` - The raw GraphQL query or mutation
+    pub fn query(&self, service: &str, request: &str) -> ClientResult<serde_json::Value> {
+        let addr = self.address(service)?;
+        let attempts = if self.options.retry_mutations || !is_mutation(request) {
+            self.options.attempts.max(1)
+        } else {
+            1
+        };
+
+        let result = self.send(service, addr, request, attempts);
+        if result.is_err() {
+            // The service may have moved, so look up its address again next time
+            self.addresses.lock().unwrap().remove(service);
+        }
+        result.and_then(|data| parse_response(&data))
+    }
+
+    /// Send a GraphQL request to a service, converting the `msg` field of its response into `T`
+    ///
+    /// # Arguments
+    ///
+    /// * `service` - The name of the service, as it appears in the config file
+    /// * `request` - The raw GraphQL query or mutation
+    pub fn query_as<T: DeserializeOwned>(&self, service: &str, request: &str) -> ClientResult<T> {
+        let result = self.query(service, request)?;
+        Ok(serde_json::from_value(result)?)
+    }
+
+    // Look up the address of a service, reading the config file if it isn't known yet
+    fn address(&self, service: &str) -> ClientResult<SocketAddr> {
+        let mut addresses = self.addresses.lock().unwrap();
+        if let Some(addr) = addresses.get(service) {
+            return Ok(*addr);
+        }
+
+        let config = match self.options.config_path {
+            Some(ref path) => ServiceConfig::new_from_path(service, path.clone()),
+            None => ServiceConfig::new(service),
+        };
+        let hosturl = config.hosturl();
+        let addr = hosturl
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addrs| addrs.next())
+            .ok_or_else(|| ClientError::BadAddress {
+                service: service.to_owned(),
+                addr: hosturl.clone(),
+            })?;
+
+        addresses.insert(service.to_owned(), addr);
+        Ok(addr)
+    }
+
+    // Send the request until a response arrives, returning the raw response
+    fn send(
+        &self,
+        service: &str,
+        addr: SocketAddr,
+        request: &str,
+        attempts: u32,
+    ) -> ClientResult<Vec<u8>> {
+        let bind_addr = if addr.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        let socket = UdpSocket::bind(bind_addr)?;
+        socket.connect(addr)?;
+        socket.set_read_timeout(Some(self.options.timeout))?;
+
+        let mut buf = vec![0; MAX_RESPONSE];
+        let mut backoff = self.options.backoff;
+        for attempt in 1..=attempts {
+            if attempt > 1 {
+                thread::sleep(backoff);
+                backoff *= 2;
+            }
+
+            // A refused request means nothing is listening yet, such as while the service is
+            // starting up, so it's retried like a lost one
+            match socket.send(request.as_bytes()) {
+                Ok(_) => {}
+                Err(ref err) if err.kind() == io::ErrorKind::ConnectionRefused => continue,
+                Err(err) => return Err(err.into()),
+            }
+            match socket.recv(&mut buf) {
+                Ok(len) => {
+                    buf.truncate(len);
+                    return Ok(buf);
+                }
+                Err(ref err)
+                    if err.kind() == io::ErrorKind::WouldBlock
+                        || err.kind() == io::ErrorKind::TimedOut
+                        || err.kind() == io::ErrorKind::ConnectionRefused => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Err(ClientError::Timeout {
+            service: service.to_owned(),
+            attempts,
+        }
+        .into())
+    }
+}
+
+fn is_mutation(request: &str) -> bool {
+    request.trim_start().starts_with("mutation")
+}
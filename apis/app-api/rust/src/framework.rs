@@ -19,9 +19,21 @@
 #![deny(missing_docs)]
 #![deny(warnings)]
 
+use failure;
 use getopts::Options;
+use kubos_logging::{self, Facility};
+use kubos_system::{Config as ServiceConfig, DEFAULT_PATH};
+use libc;
 use std::env;
 use std::fmt;
+use std::fs;
+use std::io;
+use std::mem;
+use std::path::Path;
+use std::process;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicIsize, Ordering};
+use std::thread;
 
 /// The different ways an application can be started
 #[derive(Clone, Debug, PartialEq)]
@@ -30,6 +42,20 @@ pub enum RunLevel {
     OnBoot,
     /// Logic intended to be run if the application is started manually
     OnCommand,
+    /// A run level declared by the application itself through `AppHandler::run_levels`
+    Custom(String),
+}
+
+impl RunLevel {
+    /// Get the run level with the given name. Any name other than `OnBoot` and `OnCommand`
+    /// is treated as a custom run level
+    pub fn from_name(name: &str) -> RunLevel {
+        match name {
+            "OnBoot" => RunLevel::OnBoot,
+            "OnCommand" => RunLevel::OnCommand,
+            other => RunLevel::Custom(other.to_owned()),
+        }
+    }
 }
 
 impl fmt::Display for RunLevel {
@@ -37,24 +63,97 @@ impl fmt::Display for RunLevel {
         match self {
             RunLevel::OnBoot => write!(f, "OnBoot"),
             RunLevel::OnCommand => write!(f, "OnCommand"),
+            RunLevel::Custom(name) => write!(f, "{}", name),
         }
     }
 }
 
+/// Details of how the application was started, passed to each run level handler
+#[derive(Clone, Debug, PartialEq)]
+pub struct AppContext {
+    /// The run level the application was started with
+    pub run_level: RunLevel,
+    /// The UUID of the application, if it was started by the applications service
+    pub uuid: Option<String>,
+    /// Path to the application's configuration file, if one was given with `-c`
+    pub config: Option<String>,
+    /// Any additional arguments the application was started with. These follow the
+    /// framework's own options, separated by `--`
+    pub args: Vec<String>,
+}
+
+impl AppContext {
+    /// Read the contents of the application's configuration file.
+    /// Returns `None` if the application was started without one
+    pub fn read_config(&self) -> Option<io::Result<String>> {
+        self.config.as_ref().map(fs::read_to_string)
+    }
+}
+
+/// The result returned by run level handlers. An error makes the application exit with a
+/// non-zero exit code
+pub type HandlerResult = Result<(), failure::Error>;
+
+/// An error which makes the application exit with a specific exit code, rather than the
+/// default of 1
+///
+/// # Examples
+///
+/// ```
+/// # extern crate kubos_app;
+/// # use kubos_app::*;
+/// fn check_battery(level: u8) -> HandlerResult {
+///     if level < 20 {
+///         // Tell the applications service that the run was skipped
+///         return Err(ExitCode(3).into());
+///     }
+///     Ok(())
+/// }
+/// # fn main() {}
+/// ```
+#[derive(Debug, Fail)]
+#[fail(display = "Exiting with code {}", _0)]
+pub struct ExitCode(pub i32);
+
 /// Common trait which is used to ensure handlers for all required run levels are defined
-pub trait AppHandler {
+///
+/// Handlers must be `Sync`, since `on_shutdown` is called from a separate thread while the
+/// run level handler may still be running
+pub trait AppHandler: Sync {
     /// Called when the application is started at system boot time
-    fn on_boot(&self);
+    fn on_boot(&self, context: &AppContext) -> HandlerResult;
 
     /// Called when the application is started on-demand through the `start_app` GraphQL mutation
-    fn on_command(&self);
+    fn on_command(&self, context: &AppContext) -> HandlerResult;
+
+    /// Names of the custom run levels the application supports, such as `OnPassStart`.
+    /// Starting the application with one of these calls `on_run_level`
+    fn run_levels(&self) -> &[&str] {
+        &[]
+    }
+
+    /// Called when the application is started with one of the custom run levels it declares
+    fn on_run_level(&self, run_level: &str, _context: &AppContext) -> HandlerResult {
+        Err(format_err!("Run level {} is not handled", run_level))
+    }
+
+    /// Called when the application is asked to stop with SIGTERM or SIGINT, such as when it is
+    /// stopped by the applications service. Once it returns, the application is terminated by
+    /// the signal, unless it returns an error, in which case the application exits with the
+    /// error's exit code
+    fn on_shutdown(&self, _context: &AppContext) -> HandlerResult {
+        Ok(())
+    }
 }
 
-/// A helper macro which detects the requested run level and calls the appropriate handler function
+/// A helper macro which detects the requested run level, calls the appropriate handler function,
+/// and exits the application with the handler's exit code
 ///
 /// # Arguments
 ///
-/// * `handler` - A reference to an object which implements the run level handler functions
+/// * `handler` - A reference to an object which implements the run level handler functions.
+///   It must live for the rest of the program, such as a `static` or a leaked `Box`, since
+///   `on_shutdown` may be called on it from a separate thread
 ///
 /// # Examples
 ///
@@ -62,37 +161,65 @@ pub trait AppHandler {
 /// #[macro_use]
 /// extern crate kubos_app;
 ///
-/// use kubos_app::AppHandler;
+/// use kubos_app::{AppContext, AppHandler, HandlerResult};
 ///
 /// struct MyApp;
 ///
 /// impl AppHandler for MyApp {
-///   fn on_boot(&self) {
+///   fn on_boot(&self, _context: &AppContext) -> HandlerResult {
 ///     println!("OnBoot logic");
+///     Ok(())
 ///   }
-///   fn on_command(&self) {
-///     println!("OnCommand logic");
+///   fn on_command(&self, context: &AppContext) -> HandlerResult {
+///     println!("OnCommand logic with args {:?}", context.args);
+///     Ok(())
 ///   }
 /// }
 ///
+/// static APP: MyApp = MyApp;
+///
 /// fn main() {
-///     let app = MyApp { };
-///     app_main!(&app);
+///     app_main!(&APP);
 /// }
 /// ```
 #[macro_export]
 macro_rules! app_main {
     ($handler:expr) => {{
-        kubos_app::app_start(std::process::id(), $handler)
+        std::process::exit(kubos_app::app_start(std::process::id(), $handler))
     }};
 }
 
-/// The entry point for all KubOS applications. The preferred way to use this application
-/// is through the `app_main!` macro
-pub fn app_start(_pid: u32, handler: &AppHandler) {
-    let args: Vec<String> = env::args().collect();
-    let program = args[0].clone();
+/// Exit code used when the application is started with invalid arguments
+pub const USAGE_EXIT_CODE: i32 = 2;
+
+// Set once SIGTERM or SIGINT has been received
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+// Write end of the pipe the signal handler uses to wake the shutdown thread
+static SIGNAL_PIPE: AtomicIsize = AtomicIsize::new(-1);
 
+// Only async-signal-safe calls may be made here, so the signal is handed to the shutdown thread
+extern "C" fn on_signal(signal: libc::c_int) {
+    SHUTDOWN.store(true, Ordering::SeqCst);
+    let fd = SIGNAL_PIPE.load(Ordering::SeqCst);
+    if fd >= 0 {
+        let byte = signal as u8;
+        unsafe {
+            libc::write(
+                fd as libc::c_int,
+                &byte as *const u8 as *const libc::c_void,
+                1,
+            );
+        }
+    }
+}
+
+/// Whether the application has been asked to stop with SIGTERM or SIGINT. Long-running
+/// handlers can check this to finish their work early
+pub fn shutdown_requested() -> bool {
+    SHUTDOWN.load(Ordering::SeqCst)
+}
+
+fn options() -> Options {
     let mut opts = Options::new();
     opts.optflagopt(
         "r",
@@ -100,34 +227,202 @@ pub fn app_start(_pid: u32, handler: &AppHandler) {
         "Run level which should be executed",
         "RUN_LEVEL",
     );
+    opts.optopt(
+        "c",
+        "config",
+        "Configuration file for the application",
+        "CONFIG",
+    );
     opts.optflag("h", "help", "Print this help menu");
+    opts
+}
 
-    let matches = match opts.parse(&args[1..]) {
-        Ok(r) => r,
-        Err(f) => panic!(f.to_string()),
-    };
+// Work out how the application was started from its command line arguments (not including
+// the program name) and the custom run levels it supports. Returns `None` if the help menu
+// was requested
+pub(crate) fn parse_args(
+    args: &[String],
+    run_levels: &[&str],
+) -> Result<Option<AppContext>, String> {
+    let matches = options().parse(args).map_err(|err| err.to_string())?;
 
     if matches.opt_present("h") {
-        let brief = format!("Usage: {} [options]", program);
-        print!("{}", opts.usage(&brief));
-        return;
+        return Ok(None);
     }
 
-    let _uuid = env::var_os("KUBOS_APP_UUID");
-    let run_level = matches.opt_str("r").unwrap_or("OnCommand".to_owned());
+    let run_level = match matches.opt_str("r") {
+        None => RunLevel::OnCommand,
+        Some(ref level) if level == "OnCommand" => RunLevel::OnCommand,
+        Some(ref level) if level == "OnBoot" => RunLevel::OnBoot,
+        Some(ref level) if run_levels.contains(&level.as_str()) => RunLevel::Custom(level.clone()),
+        Some(level) => {
+            let mut available = vec!["OnBoot", "OnCommand"];
+            available.extend_from_slice(run_levels);
+            return Err(format!(
+                "Unknown run level was requested - {}. Available run levels: {}",
+                level,
+                available.join(", ")
+            ));
+        }
+    };
 
-    match run_level.as_ref() {
-        "OnBoot" => {
-            handler.on_boot();
+    Ok(Some(AppContext {
+        run_level,
+        uuid: env::var("KUBOS_APP_UUID").ok(),
+        config: matches.opt_str("c"),
+        args: matches.free,
+    }))
+}
+
+// Call the handler for the context's run level
+pub(crate) fn dispatch(handler: &dyn AppHandler, context: &AppContext) -> HandlerResult {
+    match context.run_level {
+        RunLevel::OnBoot => handler.on_boot(context),
+        RunLevel::OnCommand => handler.on_command(context),
+        RunLevel::Custom(ref name) => handler.on_run_level(name, context),
+    }
+}
+
+// Work out the exit code for a handler's result, reporting any error
+pub(crate) fn exit_code(result: HandlerResult) -> i32 {
+    match result {
+        Ok(()) => 0,
+        Err(err) => {
+            error!("{}", err);
+            err.downcast_ref::<ExitCode>()
+                .map(|code| code.0)
+                .unwrap_or(1)
         }
-        "OnCommand" => {
-            handler.on_command();
+    }
+}
+
+fn set_signal_handlers(handler: libc::sighandler_t) {
+    unsafe {
+        let mut action: libc::sigaction = mem::zeroed();
+        action.sa_sigaction = handler;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(libc::SIGTERM, &action, ptr::null_mut());
+        libc::sigaction(libc::SIGINT, &action, ptr::null_mut());
+    }
+}
+
+// Tells the shutdown thread that the run level handler has finished, and waits for it to exit
+struct ShutdownWaiter {
+    write_fd: libc::c_int,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Drop for ShutdownWaiter {
+    fn drop(&mut self) {
+        let done = 0u8;
+        unsafe {
+            libc::write(self.write_fd, &done as *const u8 as *const libc::c_void, 1);
         }
-        level => {
-            eprintln!(
-                "Error: Unknown run level was requested - {}. Available run levels: OnBoot, OnCommand", level
-            );
-            return;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+// Run the handler for the context's run level, calling `on_shutdown` from a separate thread
+// if a stop signal arrives before it finishes
+fn run(handler: &'static dyn AppHandler, context: &AppContext) -> i32 {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        error!(
+            "Failed to set up shutdown handling: {}",
+            io::Error::last_os_error()
+        );
+        return exit_code(dispatch(handler, context));
+    }
+    let (read_fd, write_fd) = (fds[0], fds[1]);
+    SIGNAL_PIPE.store(write_fd as isize, Ordering::SeqCst);
+    set_signal_handlers(on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t);
+
+    let thread_context = context.clone();
+    let waiter = ShutdownWaiter {
+        write_fd,
+        thread: Some(thread::spawn(move || {
+            // A zero byte means the run level handler finished
+            let mut signal = 0u8;
+            let read = loop {
+                let read =
+                    unsafe { libc::read(read_fd, &mut signal as *mut u8 as *mut libc::c_void, 1) };
+                if read >= 0 || io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+                    break read;
+                }
+            };
+            if read == 1 && signal != 0 {
+                shutdown(handler, &thread_context, libc::c_int::from(signal));
+            }
+        })),
+    };
+
+    let code = exit_code(dispatch(handler, context));
+    drop(waiter);
+
+    set_signal_handlers(libc::SIG_DFL);
+    SIGNAL_PIPE.store(-1, Ordering::SeqCst);
+    unsafe {
+        libc::close(read_fd);
+        libc::close(write_fd);
+    }
+    code
+}
+
+// Give the application a chance to clean up, then let the signal terminate it as it would have
+// without a handler
+fn shutdown(handler: &dyn AppHandler, context: &AppContext, signal: libc::c_int) {
+    if let Err(err) = handler.on_shutdown(context) {
+        process::exit(exit_code(Err(err)));
+    }
+
+    set_signal_handlers(libc::SIG_DFL);
+    unsafe {
+        libc::raise(signal);
+    }
+    process::exit(128 + signal);
+}
+
+// Log under the name of the application's executable, reading the log level from the
+// application's section of the system config file
+fn init_logging(program: &str) {
+    let name = Path::new(program)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| program.to_owned());
+    let config = ServiceConfig::new_from_path(&name, DEFAULT_PATH.to_owned());
+
+    if let Err(err) = kubos_logging::init(Facility::User, &config) {
+        eprintln!("Failed to set up logging: {}", err);
+    }
+}
+
+/// The entry point for all KubOS applications. The preferred way to use this application
+/// is through the `app_main!` macro.
+///
+/// The handler must live for the rest of the program, since `on_shutdown` may be called on it
+/// from a separate thread while the run level handler is still running.
+///
+/// Returns the exit code the application should exit with: 0 if the handler succeeded,
+/// `USAGE_EXIT_CODE` if the arguments were invalid, or the code of the handler's error
+pub fn app_start(_pid: u32, handler: &'static dyn AppHandler) -> i32 {
+    let args: Vec<String> = env::args().collect();
+    let program = args[0].clone();
+
+    init_logging(&program);
+
+    match parse_args(&args[1..], handler.run_levels()) {
+        Ok(Some(context)) => run(handler, &context),
+        Ok(None) => {
+            let brief = format!("Usage: {} [options] [-- ARGS...]", program);
+            print!("{}", options().usage(&brief));
+            0
+        }
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            USAGE_EXIT_CODE
         }
     }
 }
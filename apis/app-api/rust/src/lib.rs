@@ -17,6 +17,19 @@
 //! A simple API to make standalone Rust applications with high-level hooks
 //! for mission life-cycle management
 //!
+//! Starting an application with `app_main!` sets up logging with the `kubos_logging` crate, so
+//! messages logged with the `log` crate's macros are sent to syslog, tagged with the name of the
+//! application's executable. The level of messages which are logged is set with the `log-level`
+//! key in the application's section of the system config file.
+//!
+//! The `testing` feature adds the `testing` module, which helps test applications against mock
+//! services. It's meant to be enabled from an application's `[dev-dependencies]`:
+//!
+//! ```toml,ignore
+//! [dev-dependencies]
+//! kubos-app = { path = "../../apis/app-api/rust", features = ["testing"] }
+//! ```
+//!
 //! # Examples
 //!
 //! ```
@@ -29,7 +42,7 @@
 //! struct MyApp;
 //!
 //! impl AppHandler for MyApp {
-//!   fn on_boot(&self) {
+//!   fn on_boot(&self, _context: &AppContext) -> HandlerResult {
 //!     println!("OnBoot logic");
 //!
 //!     let request = r#"mutation {
@@ -41,7 +54,7 @@
 //!     match query(ServiceConfig::new("radio-service"), request, Some(Duration::from_secs(1))) {
 //!         Err(error) => {
 //!             eprintln!("Failed to communicate with radio service: {}", error);
-//!             return;
+//!             return Err(error);
 //!         }
 //!         Ok(data) => {
 //!             if let Some(success) = data.get("power")
@@ -54,19 +67,33 @@
 //!                 }
 //!             } else {
 //!                 eprintln!("Failed to fetch radio power state");
-//!                 return;
+//!                 return Err(ExitCode(3).into());
 //!             }
 //!         }
 //!     }
+//!     Ok(())
+//!   }
+//!   fn on_command(&self, context: &AppContext) -> HandlerResult {
+//!     println!("OnCommand logic with args {:?}", context.args);
+//!     Ok(())
+//!   }
+//!   fn run_levels(&self) -> &[&str] {
+//!     &["OnPassStart"]
 //!   }
-//!   fn on_command(&self) {
-//!     println!("OnCommand logic");
+//!   fn on_run_level(&self, run_level: &str, _context: &AppContext) -> HandlerResult {
+//!     println!("{} logic", run_level);
+//!     Ok(())
+//!   }
+//!   fn on_shutdown(&self, _context: &AppContext) -> HandlerResult {
+//!     println!("Putting the radio back into a safe state");
+//!     Ok(())
 //!   }
 //! }
 //!
+//! static APP: MyApp = MyApp;
+//!
 //! fn main() {
-//!     let app = MyApp { };
-//!     app_main!(&app);
+//!     app_main!(&APP);
 //! }
 //! ```
 //!
@@ -79,22 +106,32 @@ extern crate getopts;
 #[cfg(test)]
 #[macro_use]
 extern crate juniper;
+extern crate kubos_logging;
 #[cfg(test)]
 extern crate kubos_service;
 extern crate kubos_system;
-#[cfg(not(test))]
-extern crate serde_json;
+extern crate libc;
+#[macro_use]
+extern crate log;
+extern crate serde;
 #[cfg(test)]
 #[macro_use]
+extern crate serde_derive;
+#[macro_use]
 extern crate serde_json;
-#[cfg(test)]
+#[cfg(any(test, feature = "testing"))]
 extern crate tempfile;
 
+mod client;
 mod framework;
 mod query;
+pub mod telemetry;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 #[cfg(test)]
 mod tests;
 
+pub use client::{ClientError, ClientOptions, ServiceClient};
 pub use framework::*;
 pub use query::query;
 pub use kubos_system::Config as ServiceConfig;
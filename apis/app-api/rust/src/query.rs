@@ -96,7 +96,12 @@ pub fn query(
     let mut buf = [0; 4096];
     let (amt, _) = socket.recv_from(&mut buf)?;
 
-    let v: serde_json::Value = serde_json::from_slice(&buf[0..(amt)])?;
+    parse_response(&buf[0..(amt)])
+}
+
+// Parse a service's response, returning its `msg` field, or an error if the service reported one
+pub(crate) fn parse_response(response: &[u8]) -> AppResult<serde_json::Value> {
+    let v: serde_json::Value = serde_json::from_slice(response)?;
 
     if let Some(errs) = v.get("errs") {
         if errs.is_string() {
@@ -0,0 +1,424 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Recording telemetry from mission applications.
+//!
+//! A `TelemetryPublisher` collects `(parameter, value)` samples, tagged with the name of the app
+//! as their subsystem, and sends them in batches to the telemetry service's direct ingestion
+//! socket. The ingestion socket must be enabled in the telemetry service's config:
+//!
+//! ```toml,ignore
+//! [telemetry-service.ingest]
+//! port = 8090
+//! ```
+//!
+//! Nothing is sent back by the ingestion socket, so before each batch is sent the publisher
+//! checks that the telemetry service is responding to queries. If it isn't, the batch is appended
+//! to a buffer file on disk instead, and the buffered samples are sent ahead of the next batch
+//! once the service is back.
+//!
+//! The check only shows that the service is running. Once it passes, the samples are sent as UDP
+//! datagrams without any acknowledgement, so samples which the service drops (ex. because its
+//! ingestion queue is full) or which are lost on the way are still counted as sent. The buffer
+//! file covers the service being down, but doesn't guarantee that every sample is stored.
+//!
+//! Samples are stamped to the second, and the telemetry database keeps one value per parameter
+//! for each timestamp, so a parameter can only be recorded once a second.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # extern crate failure;
+//! # extern crate kubos_app;
+//! use kubos_app::telemetry::{TelemetryOptions, TelemetryPublisher};
+//!
+//! # fn func() -> Result<(), failure::Error> {
+//! let mut telemetry = TelemetryPublisher::new("mission-app", TelemetryOptions::default())?;
+//!
+//! telemetry.record("temperature", 21.5)?;
+//! telemetry.record("mode", "science")?;
+//!
+//! // Samples are sent when a batch fills up or the flush interval passes, and when the
+//! // publisher is dropped. They can also be sent straight away
+//! telemetry.flush()?;
+//! # Ok(())
+//! # }
+//! # fn main() {}
+//! ```
+
+use client::{ClientError, ClientOptions, ServiceClient};
+use failure;
+use kubos_system::Config as ServiceConfig;
+use serde_json::{self, Value};
+use std::cmp;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// The result type used by `TelemetryPublisher`
+type TelemetryResult<T> = Result<T, failure::Error>;
+
+/// Name of the telemetry service in the config file
+pub const TELEMETRY_SERVICE: &str = "telemetry-service";
+/// The directory buffer files are kept in when no buffer path is given
+pub const DEFAULT_BUFFER_DIR: &str = "/home/system/kubos/telemetry";
+
+// Query used to check whether the telemetry service is running
+const HEALTH_QUERY: &str = "{ ingestStats { received } }";
+// Largest datagram sent to the ingestion socket. Batches which don't fit are split up
+const MAX_DATAGRAM: usize = 8192;
+
+/// Errors which can occur while setting up a `TelemetryPublisher`
+#[derive(Debug, Fail, PartialEq)]
+pub enum TelemetryError {
+    /// The telemetry service's config doesn't enable the direct ingestion socket
+    #[fail(display = "The telemetry service's ingestion socket is not configured")]
+    NoIngestSocket,
+    /// The ingestion socket's port is outside 1 to 65535
+    #[fail(display = "Invalid ingestion port {}", _0)]
+    BadPort(i64),
+    /// The ingestion socket's address could not be resolved
+    #[fail(display = "Unable to resolve ingestion address {}", _0)]
+    BadAddress(String),
+    /// The parameter's last sample has the same timestamp, so the database would only keep one
+    /// of them
+    #[fail(
+        display = "Telemetry parameter {} was already recorded at {}",
+        parameter, timestamp
+    )]
+    DuplicateSample {
+        /// The name of the telemetry parameter
+        parameter: String,
+        /// The sample's timestamp
+        timestamp: i32,
+    },
+}
+
+/// Options controlling how `TelemetryPublisher` batches and buffers samples
+#[derive(Clone, Debug, PartialEq)]
+pub struct TelemetryOptions {
+    /// The config file which the telemetry service's address is read from. The default config
+    /// file is used if this is `None`
+    pub config_path: Option<String>,
+    /// The number of samples collected before they're sent
+    pub batch_size: usize,
+    /// The longest a sample is held before it's sent. Samples are only sent when a new sample is
+    /// recorded, so a sample can be held longer if nothing else is recorded
+    pub flush_interval: Duration,
+    /// The file samples are buffered in while the telemetry service is unreachable. Defaults to
+    /// `<name>.json` in `DEFAULT_BUFFER_DIR`
+    pub buffer_path: Option<PathBuf>,
+    /// The most samples kept in the buffer file. The oldest samples are discarded to make room
+    /// for new ones
+    pub max_buffered: usize,
+    /// How long to wait for the telemetry service to respond when checking that it's running
+    pub timeout: Duration,
+}
+
+impl Default for TelemetryOptions {
+    fn default() -> Self {
+        TelemetryOptions {
+            config_path: None,
+            batch_size: 50,
+            flush_interval: Duration::from_secs(10),
+            buffer_path: None,
+            max_buffered: 10_000,
+            timeout: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Batches telemetry samples and sends them to the telemetry service, buffering them on disk
+/// while the service is unreachable
+pub struct TelemetryPublisher {
+    subsystem: String,
+    options: TelemetryOptions,
+    buffer_path: PathBuf,
+    ingest_addr: SocketAddr,
+    client: ServiceClient,
+    pending: Vec<Value>,
+    // The timestamp of each parameter's last sample
+    last_recorded: HashMap<String, i32>,
+    last_flush: Instant,
+}
+
+impl TelemetryPublisher {
+    /// Create a publisher whose samples are tagged with `name`, normally the name of the app.
+    /// The address of the telemetry service and its ingestion socket are read from the config
+    /// file
+    pub fn new(name: &str, options: TelemetryOptions) -> TelemetryResult<TelemetryPublisher> {
+        let config = match options.config_path {
+            Some(ref path) => ServiceConfig::new_from_path(TELEMETRY_SERVICE, path.clone()),
+            None => ServiceConfig::new(TELEMETRY_SERVICE),
+        };
+        let port = config
+            .get("ingest")
+            .and_then(|ingest| ingest.get("port").and_then(|port| port.as_integer()))
+            .ok_or(TelemetryError::NoIngestSocket)?;
+        if port < 1 || port > 65535 {
+            return Err(TelemetryError::BadPort(port).into());
+        }
+        // The ingestion socket uses the service's IP address
+        let hosturl = config.hosturl();
+        let mut ingest_addr = hosturl
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addrs| addrs.next())
+            .ok_or_else(|| TelemetryError::BadAddress(hosturl.clone()))?;
+        ingest_addr.set_port(port as u16);
+
+        let buffer_path = options
+            .buffer_path
+            .clone()
+            .unwrap_or_else(|| Path::new(DEFAULT_BUFFER_DIR).join(format!("{}.json", name)));
+        let client = ServiceClient::new(ClientOptions {
+            config_path: options.config_path.clone(),
+            timeout: options.timeout,
+            attempts: 1,
+            ..Default::default()
+        });
+
+        Ok(TelemetryPublisher {
+            subsystem: name.to_owned(),
+            options,
+            buffer_path,
+            ingest_addr,
+            client,
+            pending: vec![],
+            last_recorded: HashMap::new(),
+            last_flush: Instant::now(),
+        })
+    }
+
+    /// Record a sample, stamped with the current time. The collected samples are sent if the
+    /// batch is full or the flush interval has passed
+    ///
+    /// Timestamps are in whole seconds, so recording the same parameter again within a second
+    /// fails with `TelemetryError::DuplicateSample` and the new sample is discarded
+    ///
+    /// # Arguments
+    ///
+    /// * `parameter` - The name of the telemetry parameter
+    /// * `value` - The sample's value. Numbers, strings and booleans can all be given
+    pub fn record<V: Into<Value>>(&mut self, parameter: &str, value: V) -> TelemetryResult<()> {
+        self.record_at(now(), parameter, value)
+    }
+
+    /// Record a sample taken at a particular time
+    ///
+    /// Fails with `TelemetryError::DuplicateSample`, discarding the new sample, if the
+    /// parameter's last sample has the same timestamp
+    ///
+    /// # Arguments
+    ///
+    /// * `timestamp` - When the sample was taken, in seconds since the epoch
+    /// * `parameter` - The name of the telemetry parameter
+    /// * `value` - The sample's value
+    pub fn record_at<V: Into<Value>>(
+        &mut self,
+        timestamp: i32,
+        parameter: &str,
+        value: V,
+    ) -> TelemetryResult<()> {
+        if self.last_recorded.get(parameter) == Some(&timestamp) {
+            return Err(TelemetryError::DuplicateSample {
+                parameter: parameter.to_owned(),
+                timestamp,
+            }
+            .into());
+        }
+        self.last_recorded.insert(parameter.to_owned(), timestamp);
+
+        self.pending.push(json!({
+            "timestamp": timestamp,
+            "subsystem": self.subsystem,
+            "parameter": parameter,
+            "value": value.into(),
+        }));
+
+        if self.pending.len() >= self.options.batch_size
+            || self.last_flush.elapsed() >= self.options.flush_interval
+        {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Send the collected samples, along with any buffered on disk, to the telemetry service.
+    /// If it isn't reachable, the collected samples are added to the buffer file instead.
+    ///
+    /// Returns the number of samples which were sent
+    pub fn flush(&mut self) -> TelemetryResult<usize> {
+        self.last_flush = Instant::now();
+        let buffered = self.read_buffer()?;
+        if self.pending.is_empty() && buffered.is_empty() {
+            return Ok(0);
+        }
+        // From here on, the collected samples which are neither sent nor buffered are put back,
+        // so that they can be tried again by the next flush
+        let batch: Vec<Value> = self.pending.drain(..).collect();
+
+        if !self.reachable() {
+            warn!(
+                "Telemetry service unreachable. Buffering {} sample/s",
+                batch.len()
+            );
+            let samples = buffered.into_iter().chain(batch.iter().cloned()).collect();
+            if let Err(err) = self.write_buffer(samples) {
+                self.pending = batch;
+                return Err(err);
+            }
+            return Ok(0);
+        }
+
+        let first_new = buffered.len();
+        let samples: Vec<Value> = buffered.into_iter().chain(batch).collect();
+        match self.send(&samples) {
+            Ok(()) => {
+                if self.buffer_path.exists() {
+                    fs::remove_file(&self.buffer_path)?;
+                }
+                Ok(samples.len())
+            }
+            Err((sent, err)) => {
+                warn!("Failed to send telemetry: {}", err);
+                if let Err(err) = self.write_buffer(samples[sent..].to_vec()) {
+                    self.pending = samples[cmp::max(sent, first_new)..].to_vec();
+                    return Err(err);
+                }
+                Ok(sent)
+            }
+        }
+    }
+
+    /// The number of samples waiting in the buffer file
+    pub fn buffered(&self) -> TelemetryResult<usize> {
+        Ok(self.read_buffer()?.len())
+    }
+
+    // The ingestion socket doesn't respond, so the service's GraphQL socket is checked instead.
+    // Any response, even an error, shows the service is running
+    fn reachable(&self) -> bool {
+        match self.client.query(TELEMETRY_SERVICE, HEALTH_QUERY) {
+            Ok(_) => true,
+            Err(err) => {
+                err.downcast_ref::<ClientError>().is_none()
+                    && err.downcast_ref::<io::Error>().is_none()
+            }
+        }
+    }
+
+    // Send samples in as few datagrams as possible. On failure, returns how many samples were
+    // sent before the error
+    fn send(&self, samples: &[Value]) -> Result<(), (usize, failure::Error)> {
+        let bind_addr = if self.ingest_addr.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        let socket = UdpSocket::bind(bind_addr).map_err(|err| (0, err.into()))?;
+
+        let records: Vec<String> = samples.iter().map(|sample| sample.to_string()).collect();
+        let mut sent = 0;
+        while sent < records.len() {
+            // Every datagram holds at least one sample, and as many more as will fit
+            let mut count = 1;
+            let mut len = records[sent].len() + 2;
+            while sent + count < records.len()
+                && len + records[sent + count].len() < MAX_DATAGRAM
+            {
+                len += records[sent + count].len() + 1;
+                count += 1;
+            }
+
+            let datagram = format!("[{}]", records[sent..sent + count].join(","));
+            socket
+                .send_to(datagram.as_bytes(), self.ingest_addr)
+                .map_err(|err| (sent, err.into()))?;
+            sent += count;
+        }
+        Ok(())
+    }
+
+    fn read_buffer(&self) -> TelemetryResult<Vec<Value>> {
+        let file = match fs::File::open(&self.buffer_path) {
+            Ok(file) => file,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut samples = vec![];
+        for line in BufReader::new(file).lines() {
+            // A partially written line is skipped rather than losing the whole buffer
+            if let Ok(sample) = serde_json::from_str(&line?) {
+                samples.push(sample);
+            }
+        }
+        Ok(samples)
+    }
+
+    // Replace the buffer file's contents, keeping only the newest samples if there are too many.
+    // The samples are written to a temporary file which is then moved into place, so that the
+    // buffer file is never left partially written
+    fn write_buffer(&self, mut samples: Vec<Value>) -> TelemetryResult<()> {
+        let excess = samples.len().saturating_sub(self.options.max_buffered);
+        if excess > 0 {
+            warn!("Telemetry buffer full. Discarding {} sample/s", excess);
+            samples.drain(..excess);
+        }
+
+        if let Some(dir) = self.buffer_path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file_name = self
+            .buffer_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let tmp_path = self
+            .buffer_path
+            .with_file_name(format!(".{}.tmp", file_name));
+
+        let mut file = fs::File::create(&tmp_path)?;
+        for sample in samples {
+            writeln!(file, "{}", sample)?;
+        }
+        file.sync_all()?;
+        fs::rename(&tmp_path, &self.buffer_path)?;
+        Ok(())
+    }
+}
+
+impl Drop for TelemetryPublisher {
+    fn drop(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        if let Err(err) = self.flush() {
+            error!("Failed to flush telemetry: {}", err);
+        }
+    }
+}
+
+fn now() -> i32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs() as i32)
+        .unwrap_or(0)
+}
@@ -0,0 +1,269 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Helpers for testing mission applications without the real services.
+//!
+//! A `MockService` stands in for a service on a local UDP port. It answers queries with canned
+//! responses or from a closure, and records every query it receives so tests can check what the
+//! app sent. Each mock writes a temporary config file pointing at itself, so the app under test
+//! finds it with `ServiceConfig::new_from_path`.
+//!
+//! # Examples
+//!
+//! ```
+//! # extern crate kubos_app;
+//! #[macro_use]
+//! extern crate serde_json;
+//!
+//! use kubos_app::testing::MockService;
+//! use kubos_app::{query, ServiceConfig};
+//!
+//! # fn main() {
+//! let service = MockService::new("radio-service").unwrap();
+//! service.respond("{ power { state } }", json!({ "power": { "state": "ON" } }));
+//!
+//! let config = ServiceConfig::new_from_path("radio-service", service.config_path());
+//! let response = query(config, "{ power { state } }", None).unwrap();
+//!
+//! assert_eq!(response["power"]["state"], "ON");
+//! assert_eq!(service.queries(), vec!["{ power { state } }"]);
+//! # }
+//! ```
+
+use serde_json::Value;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::net::{SocketAddr, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tempfile::TempDir;
+
+// Largest query which can be received. This is the largest possible UDP payload
+const MAX_QUERY: usize = 65507;
+// How often the service thread checks whether it should stop
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A closure which answers queries. It returns the `msg` field of the response, or the error
+/// reported in its `errs` field
+pub type Handler = Box<dyn Fn(&str) -> Result<Value, String> + Send>;
+
+#[derive(Default)]
+struct Responses {
+    // Canned responses, by query with its whitespace normalized
+    canned: Vec<(String, Result<Value, String>)>,
+    handler: Option<Handler>,
+    queries: Vec<String>,
+}
+
+impl Responses {
+    fn answer(&mut self, query: &str) -> String {
+        self.queries.push(query.to_owned());
+
+        let key = normalize(query);
+        let result = match self.canned.iter().find(|(canned, _)| *canned == key) {
+            Some((_, result)) => result.clone(),
+            None => match self.handler {
+                Some(ref handler) => handler(query),
+                None => Err(format!("No response set for query: {}", query)),
+            },
+        };
+
+        match result {
+            Ok(msg) => json!({ "errs": "", "msg": msg }),
+            Err(errs) => json!({ "errs": errs, "msg": null }),
+        }
+        .to_string()
+    }
+}
+
+/// A stand-in for a service, answering queries on a local UDP port. The service stops when the
+/// `MockService` is dropped
+pub struct MockService {
+    name: String,
+    addr: SocketAddr,
+    config_dir: TempDir,
+    responses: Arc<Mutex<Responses>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MockService {
+    /// Start a mock of the named service on an unused port, and write a config file which points
+    /// to it
+    pub fn new(name: &str) -> io::Result<MockService> {
+        MockService::bind(name, SocketAddr::from(([127, 0, 0, 1], 0)))
+    }
+
+    /// Start a mock of the named service on a particular address, such as the address of a mock
+    /// which was stopped, and write a config file which points to it
+    pub fn bind(name: &str, addr: SocketAddr) -> io::Result<MockService> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_read_timeout(Some(POLL_INTERVAL))?;
+        let addr = socket.local_addr()?;
+
+        let config_dir = TempDir::new()?;
+        write_addr(&config_dir.path().join("config.toml"), name, addr)?;
+
+        let responses = Arc::new(Mutex::new(Responses::default()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_responses = responses.clone();
+        let thread_stop = stop.clone();
+        let thread = thread::spawn(move || {
+            let mut buf = vec![0; MAX_QUERY];
+            while !thread_stop.load(Ordering::SeqCst) {
+                let (len, source) = match socket.recv_from(&mut buf) {
+                    Ok(received) => received,
+                    Err(ref err)
+                        if err.kind() == io::ErrorKind::WouldBlock
+                            || err.kind() == io::ErrorKind::TimedOut =>
+                    {
+                        continue
+                    }
+                    Err(_) => break,
+                };
+
+                let query = String::from_utf8_lossy(&buf[..len]).to_string();
+                let response = thread_responses.lock().unwrap().answer(&query);
+                let _ = socket.send_to(response.as_bytes(), source);
+            }
+        });
+
+        Ok(MockService {
+            name: name.to_owned(),
+            addr,
+            config_dir,
+            responses,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// Answer a query with a response whose `msg` field is `msg`. Queries are matched ignoring
+    /// differences in whitespace
+    pub fn respond(&self, query: &str, msg: Value) {
+        self.set(query, Ok(msg));
+    }
+
+    /// Answer a query with an error
+    pub fn respond_error(&self, query: &str, errs: &str) {
+        self.set(query, Err(errs.to_owned()));
+    }
+
+    /// Answer every query without a canned response by calling `handler`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate kubos_app;
+    /// # #[macro_use]
+    /// # extern crate serde_json;
+    /// # use kubos_app::testing::MockService;
+    /// # fn main() {
+    /// let service = MockService::new("radio-service").unwrap();
+    /// service.respond_with(|query| {
+    ///     if query.contains("mutation") {
+    ///         Ok(json!({ "power": { "success": true } }))
+    ///     } else {
+    ///         Err("Unexpected query".to_owned())
+    ///     }
+    /// });
+    /// # }
+    /// ```
+    pub fn respond_with<F>(&self, handler: F)
+    where
+        F: Fn(&str) -> Result<Value, String> + Send + 'static,
+    {
+        self.responses.lock().unwrap().handler = Some(Box::new(handler));
+    }
+
+    /// The queries received so far, oldest first
+    pub fn queries(&self) -> Vec<String> {
+        self.responses.lock().unwrap().queries.clone()
+    }
+
+    /// Forget the queries received so far
+    pub fn clear_queries(&self) {
+        self.responses.lock().unwrap().queries.clear();
+    }
+
+    /// The name of the service being mocked
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The address the service is listening on
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The path of the temporary config file which points to the service
+    pub fn config_path(&self) -> String {
+        self.config_dir
+            .path()
+            .join("config.toml")
+            .to_string_lossy()
+            .to_string()
+    }
+
+    /// Add the service's address to a config file, so that a single config file can point to
+    /// several mock services
+    pub fn add_to_config(&self, path: &Path) -> io::Result<()> {
+        write_addr(path, &self.name, self.addr)
+    }
+
+    /// The directory the temporary config file is in. It's removed when the service is dropped
+    pub fn config_dir(&self) -> PathBuf {
+        self.config_dir.path().to_owned()
+    }
+
+    fn set(&self, query: &str, result: Result<Value, String>) {
+        let key = normalize(query);
+        let mut responses = self.responses.lock().unwrap();
+        responses.canned.retain(|(canned, _)| *canned != key);
+        responses.canned.push((key, result));
+    }
+}
+
+impl Drop for MockService {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+// Append a service's address to a config file
+fn write_addr(path: &Path, name: &str, addr: SocketAddr) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(
+        file,
+        "\n[{}.addr]\nip = \"{}\"\nport = {}",
+        name,
+        addr.ip(),
+        addr.port()
+    )
+}
+
+fn normalize(query: &str) -> String {
+    query.split_whitespace().collect::<Vec<_>>().join(" ")
+}
@@ -0,0 +1,203 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::mock_service::*;
+use client::*;
+use kubos_service::Service;
+use kubos_system::Config as ServiceConfig;
+use testing::MockService;
+
+use std::net::UdpSocket;
+use std::path::Path;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::Duration;
+use tempfile::TempDir;
+
+fn options(config_file: &Path) -> ClientOptions {
+    ClientOptions {
+        config_path: Some(config_file.to_string_lossy().to_string()),
+        timeout: Duration::from_millis(200),
+        backoff: Duration::from_millis(10),
+        ..Default::default()
+    }
+}
+
+// A service which ignores the first `dropped` requests it receives and answers the rest with
+// `response`, reporting each request it receives
+fn lossy_service(config_file: &Path, dropped: usize, response: &'static str) -> Receiver<String> {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    ::std::fs::write(
+        config_file,
+        format!(
+            "[lossy-service.addr]\nip = \"127.0.0.1\"\nport = {}\n",
+            socket.local_addr().unwrap().port()
+        ),
+    )
+    .unwrap();
+
+    let (sender, receiver) = channel();
+    thread::spawn(move || {
+        let mut buf = [0; 4096];
+        let mut count = 0;
+        while let Ok((len, source)) = socket.recv_from(&mut buf) {
+            let _ = sender.send(String::from_utf8_lossy(&buf[..len]).to_string());
+            count += 1;
+            if count > dropped {
+                let _ = socket.send_to(response.as_bytes(), source);
+            }
+        }
+    });
+
+    receiver
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct Ping {
+    ping: String,
+}
+
+#[test]
+fn query_as_typed() {
+    let config_dir = TempDir::new().unwrap();
+    let config_file = config_dir.path().join("config.toml");
+    mock_service!(config_file, "127.0.0.1", 8761);
+
+    let client = ServiceClient::new(options(&config_file));
+
+    assert_eq!(
+        client.query_as::<Ping>("mock-service", "{ ping }").unwrap(),
+        Ping {
+            ping: "query".to_owned()
+        }
+    );
+    assert_eq!(
+        client.query("mock-service", "mutation { ping }").unwrap(),
+        json!({ "ping": "mutation" })
+    );
+
+    let err = client
+        .query_as::<Ping>("mock-service", "{ ping(fail: true) }")
+        .unwrap_err();
+    assert!(err.to_string().contains("Query failed"));
+}
+
+#[test]
+fn query_retried() {
+    let config_dir = TempDir::new().unwrap();
+    let config_file = config_dir.path().join("config.toml");
+    let requests = lossy_service(&config_file, 2, r#"{"errs":"","msg":{"ping":"pong"}}"#);
+
+    let client = ServiceClient::new(options(&config_file));
+
+    assert_eq!(
+        client
+            .query_as::<Ping>("lossy-service", "{ ping }")
+            .unwrap(),
+        Ping {
+            ping: "pong".to_owned()
+        }
+    );
+    assert_eq!(requests.try_iter().count(), 3);
+}
+
+#[test]
+fn query_timeout() {
+    let config_dir = TempDir::new().unwrap();
+    let config_file = config_dir.path().join("config.toml");
+    let requests = lossy_service(&config_file, 10, "");
+
+    let client = ServiceClient::new(ClientOptions {
+        attempts: 2,
+        ..options(&config_file)
+    });
+
+    let err = client.query("lossy-service", "{ ping }").unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<ClientError>(),
+        Some(&ClientError::Timeout {
+            service: "lossy-service".to_owned(),
+            attempts: 2,
+        })
+    );
+    assert_eq!(requests.try_iter().count(), 2);
+
+    // Mutations are only sent once unless retrying them is allowed
+    client
+        .query("lossy-service", "mutation { reset }")
+        .unwrap_err();
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(requests.try_iter().count(), 1);
+}
+
+#[test]
+fn address_cached() {
+    let config_dir = TempDir::new().unwrap();
+    let config_file = config_dir.path().join("config.toml");
+    let requests = lossy_service(&config_file, 0, r#"{"errs":"","msg":{"ping":"pong"}}"#);
+
+    let client = ServiceClient::new(options(&config_file));
+    client.query("lossy-service", "{ ping }").unwrap();
+
+    // Changes to the config file aren't noticed while the service is responding
+    ::std::fs::write(&config_file, "[lossy-service.addr]\nip = \"bad address\"\n").unwrap();
+    client.query("lossy-service", "{ ping }").unwrap();
+    assert_eq!(requests.try_iter().count(), 2);
+}
+
+#[test]
+fn bad_address() {
+    let config_dir = TempDir::new().unwrap();
+    let config_file = config_dir.path().join("config.toml");
+    ::std::fs::write(&config_file, "[bad-service.addr]\nip = \"bad address\"\n").unwrap();
+
+    let client = ServiceClient::new(options(&config_file));
+    let err = client.query("bad-service", "{ ping }").unwrap_err();
+
+    assert_eq!(
+        err.downcast_ref::<ClientError>(),
+        Some(&ClientError::BadAddress {
+            service: "bad-service".to_owned(),
+            addr: "bad address:8080".to_owned(),
+        })
+    );
+}
+
+#[test]
+fn query_retried_until_service_starts() {
+    let config_dir = TempDir::new().unwrap();
+    let config_file = config_dir.path().join("config.toml");
+
+    // Find a free port, then leave nothing listening on it so the first attempt is refused
+    let stopped = MockService::new("radio-service").unwrap();
+    let addr = stopped.addr();
+    stopped.add_to_config(&config_file).unwrap();
+    drop(stopped);
+
+    let client = ServiceClient::new(ClientOptions {
+        attempts: 5,
+        backoff: Duration::from_millis(100),
+        ..options(&config_file)
+    });
+    let query = thread::spawn(move || client.query("radio-service", "{ ping }"));
+
+    thread::sleep(Duration::from_millis(150));
+    let service = MockService::bind("radio-service", addr).unwrap();
+    service.respond("{ ping }", json!("pong"));
+
+    assert_eq!(query.join().unwrap().unwrap(), json!("pong"));
+    assert_eq!(service.queries().len(), 1);
+}
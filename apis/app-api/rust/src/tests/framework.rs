@@ -0,0 +1,166 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use framework::*;
+use std::sync::Mutex;
+
+fn parse(args: &[&str]) -> Result<Option<AppContext>, String> {
+    let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    parse_args(&args, &["OnPassStart"])
+}
+
+// Records which handlers were called
+#[derive(Default)]
+struct TestApp {
+    calls: Mutex<Vec<String>>,
+}
+
+impl AppHandler for TestApp {
+    fn on_boot(&self, _context: &AppContext) -> HandlerResult {
+        self.calls.lock().unwrap().push("OnBoot".to_owned());
+        Err(ExitCode(3).into())
+    }
+
+    fn on_command(&self, context: &AppContext) -> HandlerResult {
+        self.calls.lock().unwrap().push("OnCommand".to_owned());
+        match context.args.first() {
+            Some(arg) => Err(format_err!("Unexpected argument {}", arg)),
+            None => Ok(()),
+        }
+    }
+
+    fn run_levels(&self) -> &[&str] {
+        &["OnPassStart"]
+    }
+
+    fn on_run_level(&self, run_level: &str, _context: &AppContext) -> HandlerResult {
+        self.calls.lock().unwrap().push(run_level.to_owned());
+        Ok(())
+    }
+}
+
+fn context(run_level: RunLevel, args: &[&str]) -> AppContext {
+    AppContext {
+        run_level,
+        uuid: None,
+        config: None,
+        args: args.iter().map(|arg| arg.to_string()).collect(),
+    }
+}
+
+#[test]
+fn parse_defaults() {
+    let context = parse(&[]).unwrap().unwrap();
+
+    assert_eq!(context.run_level, RunLevel::OnCommand);
+    assert_eq!(context.config, None);
+    assert!(context.args.is_empty());
+}
+
+#[test]
+fn parse_config_and_args() {
+    let context = parse(&[
+        "-r",
+        "OnBoot",
+        "-c",
+        "/home/kubos/mission.toml",
+        "--",
+        "--mode",
+        "safe",
+    ])
+    .unwrap()
+    .unwrap();
+
+    assert_eq!(context.run_level, RunLevel::OnBoot);
+    assert_eq!(context.config, Some("/home/kubos/mission.toml".to_owned()));
+    assert_eq!(context.args, vec!["--mode", "safe"]);
+}
+
+#[test]
+fn parse_help() {
+    assert_eq!(parse(&["-h"]), Ok(None));
+}
+
+#[test]
+fn parse_bad_run_level() {
+    assert_eq!(
+        parse(&["-r", "OnFire"]),
+        Err(
+            "Unknown run level was requested - OnFire. Available run levels: OnBoot, OnCommand, OnPassStart"
+                .to_owned()
+        )
+    );
+}
+
+#[test]
+fn parse_custom_run_level() {
+    let context = parse(&["-r", "OnPassStart"]).unwrap().unwrap();
+
+    assert_eq!(context.run_level, RunLevel::Custom("OnPassStart".to_owned()));
+    assert_eq!(context.run_level.to_string(), "OnPassStart");
+    assert_eq!(RunLevel::from_name("OnPassStart"), context.run_level);
+    assert_eq!(RunLevel::from_name("OnBoot"), RunLevel::OnBoot);
+}
+
+#[test]
+fn handlers_dispatched() {
+    let app = TestApp::default();
+
+    dispatch(&app, &context(RunLevel::OnBoot, &[])).unwrap_err();
+    dispatch(&app, &context(RunLevel::OnCommand, &[])).unwrap();
+    dispatch(&app, &context(RunLevel::Custom("OnPassStart".to_owned()), &[])).unwrap();
+
+    assert_eq!(
+        *app.calls.lock().unwrap(),
+        vec!["OnBoot", "OnCommand", "OnPassStart"]
+    );
+}
+
+#[test]
+fn unhandled_run_level() {
+    struct MinimalApp;
+
+    impl AppHandler for MinimalApp {
+        fn on_boot(&self, _context: &AppContext) -> HandlerResult {
+            Ok(())
+        }
+
+        fn on_command(&self, _context: &AppContext) -> HandlerResult {
+            Ok(())
+        }
+    }
+
+    let err = dispatch(
+        &MinimalApp,
+        &context(RunLevel::Custom("OnPassStart".to_owned()), &[]),
+    )
+    .unwrap_err();
+
+    assert_eq!(err.to_string(), "Run level OnPassStart is not handled");
+    assert!(MinimalApp.on_shutdown(&context(RunLevel::OnBoot, &[])).is_ok());
+}
+
+#[test]
+fn exit_codes() {
+    let app = TestApp::default();
+
+    assert_eq!(exit_code(dispatch(&app, &context(RunLevel::OnCommand, &[]))), 0);
+    assert_eq!(
+        exit_code(dispatch(&app, &context(RunLevel::OnCommand, &["--bad"]))),
+        1
+    );
+    assert_eq!(exit_code(dispatch(&app, &context(RunLevel::OnBoot, &[]))), 3);
+}
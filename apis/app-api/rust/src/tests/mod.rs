@@ -20,11 +20,16 @@ macro_rules! mock_service {
     ($config:ident, $addr:expr, $port:expr) => {{
         let config = format!(
             r#"
+            [mock-service]
+            log-file = "{}"
+
             [mock-service.addr]
             ip = "{}"
             port = {}
             "#,
-            $addr, $port
+            $config.with_file_name("mock-service.log").display(),
+            $addr,
+            $port
         );
 
         ::std::fs::write($config.clone(), config).unwrap();
@@ -44,4 +49,8 @@ macro_rules! mock_service {
     }};
 }
 
+mod client;
+mod framework;
 mod query;
+mod telemetry;
+mod testing;
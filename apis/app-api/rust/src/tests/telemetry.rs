@@ -0,0 +1,340 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use serde_json::Value;
+use telemetry::*;
+
+use std::fs;
+use std::net::UdpSocket;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tempfile::TempDir;
+
+// A stand-in for the telemetry service. Its GraphQL socket only answers while `up` is set, and
+// the batches sent to its ingestion socket are reported
+struct TelemetryService {
+    up: Arc<AtomicBool>,
+    batches: Receiver<Vec<Value>>,
+}
+
+impl TelemetryService {
+    fn new(config_file: &Path) -> TelemetryService {
+        let graphql = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let ingest = UdpSocket::bind("127.0.0.1:0").unwrap();
+        ::std::fs::write(
+            config_file,
+            format!(
+                "[telemetry-service.addr]\nip = \"127.0.0.1\"\nport = {}\n\n\
+                 [telemetry-service.ingest]\nport = {}\n",
+                graphql.local_addr().unwrap().port(),
+                ingest.local_addr().unwrap().port()
+            ),
+        )
+        .unwrap();
+
+        let up = Arc::new(AtomicBool::new(true));
+        let up_thread = up.clone();
+        thread::spawn(move || {
+            let mut buf = [0; 4096];
+            while let Ok((_, source)) = graphql.recv_from(&mut buf) {
+                if up_thread.load(Ordering::SeqCst) {
+                    let response = r#"{"errs":"","msg":{"ingestStats":{"received":0}}}"#;
+                    let _ = graphql.send_to(response.as_bytes(), source);
+                }
+            }
+        });
+
+        let (sender, batches) = channel();
+        thread::spawn(move || {
+            let mut buf = [0; 65535];
+            while let Ok(len) = ingest.recv(&mut buf) {
+                let _ = sender.send(::serde_json::from_slice(&buf[..len]).unwrap());
+            }
+        });
+
+        TelemetryService { up, batches }
+    }
+
+    fn set_up(&self, up: bool) {
+        self.up.store(up, Ordering::SeqCst);
+    }
+
+    fn next_batch(&self) -> Option<Vec<Value>> {
+        self.batches.recv_timeout(Duration::from_millis(200)).ok()
+    }
+}
+
+fn options(config_dir: &TempDir) -> TelemetryOptions {
+    TelemetryOptions {
+        config_path: Some(
+            config_dir
+                .path()
+                .join("config.toml")
+                .to_string_lossy()
+                .to_string(),
+        ),
+        buffer_path: Some(config_dir.path().join("buffer").join("test-app.json")),
+        timeout: Duration::from_millis(100),
+        ..Default::default()
+    }
+}
+
+fn parameters(batch: &[Value]) -> Vec<&str> {
+    batch
+        .iter()
+        .map(|sample| sample["parameter"].as_str().unwrap())
+        .collect()
+}
+
+#[test]
+fn batch_sent_when_full() {
+    let config_dir = TempDir::new().unwrap();
+    let service = TelemetryService::new(&config_dir.path().join("config.toml"));
+    let mut telemetry = TelemetryPublisher::new(
+        "test-app",
+        TelemetryOptions {
+            batch_size: 3,
+            ..options(&config_dir)
+        },
+    )
+    .unwrap();
+
+    telemetry.record_at(1000, "voltage", 3.3).unwrap();
+    telemetry.record("mode", "science").unwrap();
+    assert_eq!(service.next_batch(), None);
+
+    telemetry.record("enabled", true).unwrap();
+    let batch = service.next_batch().unwrap();
+    assert_eq!(
+        batch[0],
+        json!({
+            "timestamp": 1000,
+            "subsystem": "test-app",
+            "parameter": "voltage",
+            "value": 3.3,
+        })
+    );
+    assert_eq!(batch[1]["value"], json!("science"));
+    assert!(batch[1]["timestamp"].as_i64().unwrap() > 1000);
+    assert_eq!(batch[2]["value"], json!(true));
+    assert_eq!(batch.len(), 3);
+}
+
+#[test]
+fn batch_sent_after_interval() {
+    let config_dir = TempDir::new().unwrap();
+    let service = TelemetryService::new(&config_dir.path().join("config.toml"));
+    let mut telemetry = TelemetryPublisher::new(
+        "test-app",
+        TelemetryOptions {
+            flush_interval: Duration::from_millis(100),
+            ..options(&config_dir)
+        },
+    )
+    .unwrap();
+
+    telemetry.record("first", 1).unwrap();
+    thread::sleep(Duration::from_millis(150));
+    telemetry.record("second", 2).unwrap();
+    assert_eq!(
+        parameters(&service.next_batch().unwrap()),
+        vec!["first", "second"]
+    );
+
+    // Anything left over is sent when the publisher is dropped
+    telemetry.record("third", 3).unwrap();
+    drop(telemetry);
+    assert_eq!(parameters(&service.next_batch().unwrap()), vec!["third"]);
+}
+
+#[test]
+fn buffered_while_unreachable() {
+    let config_dir = TempDir::new().unwrap();
+    let service = TelemetryService::new(&config_dir.path().join("config.toml"));
+    let buffer_file = config_dir.path().join("buffer").join("test-app.json");
+    let mut telemetry = TelemetryPublisher::new("test-app", options(&config_dir)).unwrap();
+
+    service.set_up(false);
+    telemetry.record("first", 1).unwrap();
+    assert_eq!(telemetry.flush().unwrap(), 0);
+    telemetry.record("second", 2).unwrap();
+    assert_eq!(telemetry.flush().unwrap(), 0);
+
+    assert_eq!(service.next_batch(), None);
+    assert_eq!(telemetry.buffered().unwrap(), 2);
+    assert!(buffer_file.exists());
+
+    // The buffered samples are sent, oldest first, once the service is back
+    service.set_up(true);
+    telemetry.record("third", 3).unwrap();
+    assert_eq!(telemetry.flush().unwrap(), 3);
+    assert_eq!(
+        parameters(&service.next_batch().unwrap()),
+        vec!["first", "second", "third"]
+    );
+    assert_eq!(telemetry.buffered().unwrap(), 0);
+    assert!(!buffer_file.exists());
+}
+
+#[test]
+fn samples_kept_when_buffer_unreadable() {
+    let config_dir = TempDir::new().unwrap();
+    let service = TelemetryService::new(&config_dir.path().join("config.toml"));
+    let buffer_file = config_dir.path().join("buffer").join("test-app.json");
+    let mut telemetry = TelemetryPublisher::new("test-app", options(&config_dir)).unwrap();
+
+    // A directory in the buffer file's place can't be read
+    fs::create_dir_all(&buffer_file).unwrap();
+    telemetry.record("first", 1).unwrap();
+    assert!(telemetry.flush().is_err());
+    assert_eq!(service.next_batch(), None);
+
+    fs::remove_dir(&buffer_file).unwrap();
+    assert_eq!(telemetry.flush().unwrap(), 1);
+    assert_eq!(parameters(&service.next_batch().unwrap()), vec!["first"]);
+}
+
+#[test]
+fn buffer_keeps_newest() {
+    let config_dir = TempDir::new().unwrap();
+    let service = TelemetryService::new(&config_dir.path().join("config.toml"));
+    let mut telemetry = TelemetryPublisher::new(
+        "test-app",
+        TelemetryOptions {
+            max_buffered: 2,
+            ..options(&config_dir)
+        },
+    )
+    .unwrap();
+
+    service.set_up(false);
+    for parameter in &["first", "second", "third"] {
+        telemetry.record(parameter, 0).unwrap();
+    }
+    telemetry.flush().unwrap();
+    assert_eq!(telemetry.buffered().unwrap(), 2);
+
+    // The buffer is kept on disk, so a new publisher picks it up
+    drop(telemetry);
+    service.set_up(true);
+    let mut telemetry = TelemetryPublisher::new("test-app", options(&config_dir)).unwrap();
+    assert_eq!(telemetry.flush().unwrap(), 2);
+    assert_eq!(
+        parameters(&service.next_batch().unwrap()),
+        vec!["second", "third"]
+    );
+}
+
+#[test]
+fn large_batch_split() {
+    let config_dir = TempDir::new().unwrap();
+    let service = TelemetryService::new(&config_dir.path().join("config.toml"));
+    let mut telemetry = TelemetryPublisher::new(
+        "test-app",
+        TelemetryOptions {
+            batch_size: 500,
+            ..options(&config_dir)
+        },
+    )
+    .unwrap();
+
+    for index in 0..500 {
+        telemetry
+            .record(&format!("a_fairly_long_parameter_name_{}", index), index)
+            .unwrap();
+    }
+
+    let mut received = 0;
+    let mut batches = 0;
+    while let Some(batch) = service.next_batch() {
+        assert_eq!(
+            batch[0]["value"].as_i64(),
+            Some(received),
+            "Batches out of order"
+        );
+        received += batch.len() as i64;
+        batches += 1;
+    }
+    assert_eq!(received, 500);
+    assert!(batches > 1);
+}
+
+#[test]
+fn no_ingest_socket() {
+    let config_dir = TempDir::new().unwrap();
+    ::std::fs::write(
+        config_dir.path().join("config.toml"),
+        "[telemetry-service.addr]\nip = \"127.0.0.1\"\nport = 8006\n",
+    )
+    .unwrap();
+
+    let err = TelemetryPublisher::new("test-app", options(&config_dir))
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.downcast_ref::<TelemetryError>(),
+        Some(&TelemetryError::NoIngestSocket)
+    );
+}
+
+#[test]
+fn duplicate_sample_rejected() {
+    let config_dir = TempDir::new().unwrap();
+    let service = TelemetryService::new(&config_dir.path().join("config.toml"));
+    let mut telemetry = TelemetryPublisher::new("test-app", options(&config_dir)).unwrap();
+
+    telemetry.record_at(1000, "voltage", 3.3).unwrap();
+    let err = telemetry.record_at(1000, "voltage", 3.4).err().unwrap();
+    assert_eq!(
+        err.downcast_ref::<TelemetryError>(),
+        Some(&TelemetryError::DuplicateSample {
+            parameter: "voltage".to_owned(),
+            timestamp: 1000,
+        })
+    );
+
+    // Other parameters and later timestamps are still recorded
+    telemetry.record_at(1000, "current", 0.5).unwrap();
+    telemetry.record_at(1001, "voltage", 3.4).unwrap();
+    telemetry.flush().unwrap();
+    assert_eq!(
+        parameters(&service.next_batch().unwrap()),
+        vec!["voltage", "current", "voltage"]
+    );
+}
+
+#[test]
+fn bad_ingest_port() {
+    let config_dir = TempDir::new().unwrap();
+    ::std::fs::write(
+        config_dir.path().join("config.toml"),
+        "[telemetry-service.addr]\nip = \"127.0.0.1\"\nport = 8006\n\n\
+         [telemetry-service.ingest]\nport = 70000\n",
+    )
+    .unwrap();
+
+    let err = TelemetryPublisher::new("test-app", options(&config_dir))
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.downcast_ref::<TelemetryError>(),
+        Some(&TelemetryError::BadPort(70000))
+    );
+}
@@ -0,0 +1,140 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use client::*;
+use kubos_system::Config as ServiceConfig;
+use query::query;
+use testing::*;
+
+use std::net::UdpSocket;
+use std::time::Duration;
+use tempfile::TempDir;
+
+fn config(service: &MockService) -> ServiceConfig {
+    ServiceConfig::new_from_path(service.name(), service.config_path())
+}
+
+#[test]
+fn canned_responses() {
+    let service = MockService::new("radio-service").unwrap();
+    service.respond("{ power { state } }", json!({ "power": { "state": "ON" } }));
+    service.respond_error("mutation { reset }", "Reset failed");
+
+    assert_eq!(config(&service).hosturl(), service.addr().to_string());
+
+    // Whitespace doesn't need to match
+    assert_eq!(
+        query(config(&service), "{\n  power {\n    state\n  }\n}", None).unwrap(),
+        json!({ "power": { "state": "ON" } })
+    );
+
+    let err = query(config(&service), "mutation { reset }", None).unwrap_err();
+    assert_eq!(err.to_string(), "Reset failed");
+
+    let err = query(config(&service), "{ ping }", None).unwrap_err();
+    assert_eq!(err.to_string(), "No response set for query: { ping }");
+
+    // A later canned response replaces an earlier one
+    service.respond(
+        "{ power { state } }",
+        json!({ "power": { "state": "OFF" } }),
+    );
+    assert_eq!(
+        query(config(&service), "{ power { state } }", None).unwrap(),
+        json!({ "power": { "state": "OFF" } })
+    );
+}
+
+#[test]
+fn closure_responses() {
+    let service = MockService::new("radio-service").unwrap();
+    service.respond("{ ping }", json!({ "ping": "canned" }));
+    service.respond_with(|query| {
+        if query.starts_with("mutation") {
+            Ok(json!({ "query": query }))
+        } else {
+            Err("Unexpected query".to_owned())
+        }
+    });
+
+    // Canned responses come first
+    assert_eq!(
+        query(config(&service), "{ ping }", None).unwrap(),
+        json!({ "ping": "canned" })
+    );
+    assert_eq!(
+        query(config(&service), "mutation { noop }", None).unwrap(),
+        json!({ "query": "mutation { noop }" })
+    );
+    assert_eq!(
+        query(config(&service), "{ power }", None)
+            .unwrap_err()
+            .to_string(),
+        "Unexpected query"
+    );
+}
+
+#[test]
+fn queries_recorded() {
+    let service = MockService::new("radio-service").unwrap();
+    service.respond_with(|_| Ok(json!({})));
+
+    query(config(&service), "{ first }", None).unwrap();
+    query(config(&service), "mutation { second }", None).unwrap();
+    assert_eq!(service.queries(), vec!["{ first }", "mutation { second }"]);
+
+    service.clear_queries();
+    assert!(service.queries().is_empty());
+}
+
+#[test]
+fn shared_config() {
+    let config_dir = TempDir::new().unwrap();
+    let config_file = config_dir.path().join("config.toml");
+    let radio = MockService::new("radio-service").unwrap();
+    let power = MockService::new("power-service").unwrap();
+    radio.add_to_config(&config_file).unwrap();
+    power.add_to_config(&config_file).unwrap();
+    radio.respond("{ ping }", json!({ "ping": "radio" }));
+    power.respond("{ ping }", json!({ "ping": "power" }));
+
+    let client = ServiceClient::new(ClientOptions {
+        config_path: Some(config_file.to_string_lossy().to_string()),
+        ..Default::default()
+    });
+    assert_eq!(
+        client.query("radio-service", "{ ping }").unwrap(),
+        json!({ "ping": "radio" })
+    );
+    assert_eq!(
+        client.query("power-service", "{ ping }").unwrap(),
+        json!({ "ping": "power" })
+    );
+}
+
+#[test]
+fn stops_when_dropped() {
+    let service = MockService::new("radio-service").unwrap();
+    let addr = service.addr();
+    drop(service);
+
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket
+        .set_read_timeout(Some(Duration::from_millis(100)))
+        .unwrap();
+    socket.send_to(b"{ ping }", addr).unwrap();
+    assert!(socket.recv(&mut [0; 64]).is_err());
+}
@@ -0,0 +1,183 @@
+/*
+* Copyright (C) 2018 Kubos Corporation
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+*     http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*/
+
+//! Packets and transfer frames from the CCSDS space data link standards, so the ground segment
+//! and the satellite can talk using standard protocols.
+//!
+//! - `SpacePacket` encodes and decodes packets of the Space Packet Protocol (CCSDS 133.0-B),
+//!   including the packet's APID and sequence count, and a secondary header holding the time the
+//!   packet was created as a `CucTime`
+//! - `TmFrame` and `TcFrame` encode and decode TM (CCSDS 132.0-B) and TC (CCSDS 232.0-B) transfer
+//!   frames, with an optional frame error control field
+//! - `TmMultiplexer` packs space packets into fixed length TM frames on separate virtual channels,
+//!   and `TmDemultiplexer` extracts the packets from the frames again
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate ccsds_api;
+//!
+//! use ccsds_api::*;
+//!
+//! # fn main() {
+//! let mut packet = SpacePacket::new(PacketType::Telemetry, 100, b"payload");
+//! packet.sequence_count = 42;
+//!
+//! // Send the packet on virtual channel 1 in 256 byte frames
+//! let mut mux = TmMultiplexer::new(0x1AB, 256, true).unwrap();
+//! mux.push(1, &packet).unwrap();
+//! let frame = mux.next_frame(1).unwrap().unwrap();
+//! assert_eq!(frame.len(), 256);
+//!
+//! let mut demux = TmDemultiplexer::new(true);
+//! assert_eq!(demux.receive(&frame).unwrap(), vec![packet]);
+//! # }
+//! ```
+
+#![deny(missing_docs)]
+
+extern crate crc16;
+#[macro_use]
+extern crate failure;
+
+pub mod packet;
+pub mod tc;
+pub mod time;
+pub mod tm;
+
+pub use packet::{PacketType, SequenceCounter, SequenceFlags, SpacePacket};
+pub use tc::{TcChannel, TcFrame};
+pub use time::CucTime;
+pub use tm::{TmDemultiplexer, TmFrame, TmMultiplexer};
+
+/// Errors which can occur while encoding or decoding packets and frames
+#[derive(Debug, Fail, PartialEq)]
+pub enum CcsdsError {
+    #[fail(
+        display = "Incomplete data: needed {} bytes but only {} were given",
+        needed, available
+    )]
+    /// The data ends before the packet or frame does
+    Incomplete {
+        /// The number of bytes needed
+        needed: usize,
+        /// The number of bytes given
+        available: usize,
+    },
+    #[fail(display = "Unsupported version number {}", _0)]
+    /// The packet or frame uses a version of the protocol which isn't supported
+    BadVersion(u8),
+    #[fail(
+        display = "Frame error control mismatch: received {:#06x}, calculated {:#06x}",
+        received, calculated
+    )]
+    /// The frame's error control field doesn't match its contents
+    BadChecksum {
+        /// The error control field which was received
+        received: u16,
+        /// The error control field calculated from the frame's contents
+        calculated: u16,
+    },
+    #[fail(display = "{} {} is too large. The maximum is {}", field, value, max)]
+    /// A field's value doesn't fit in the bits it's given
+    OutOfRange {
+        /// The name of the field
+        field: &'static str,
+        /// The value which was given
+        value: usize,
+        /// The largest value allowed
+        max: usize,
+    },
+    #[fail(display = "A packet's data field must hold at least one byte")]
+    /// The packet has neither a secondary header nor any data
+    EmptyData,
+    #[fail(display = "Unsupported: {}", _0)]
+    /// The packet or frame uses an optional feature which isn't supported
+    Unsupported(&'static str),
+}
+
+/// Result type used by this crate
+pub type CcsdsResult<T> = Result<T, CcsdsError>;
+
+/// Calculate the frame error control field of a frame, using CRC-16/CCITT-FALSE as both the TM
+/// and TC standards require
+pub fn fecf(frame: &[u8]) -> u16 {
+    crc16::State::<crc16::CCITT_FALSE>::calculate(frame)
+}
+
+// Check that a value fits in a field
+fn check_range(field: &'static str, value: usize, max: usize) -> CcsdsResult<()> {
+    if value > max {
+        return Err(CcsdsError::OutOfRange { field, value, max });
+    }
+    Ok(())
+}
+
+// Check that enough data was given
+fn check_len(input: &[u8], needed: usize) -> CcsdsResult<()> {
+    if input.len() < needed {
+        return Err(CcsdsError::Incomplete {
+            needed,
+            available: input.len(),
+        });
+    }
+    Ok(())
+}
+
+// Append a frame's error control field, calculated over everything before it
+fn append_fecf(frame: &mut Vec<u8>) {
+    let fecf = fecf(frame);
+    frame.push((fecf >> 8) as u8);
+    frame.push(fecf as u8);
+}
+
+// Check and remove the error control field from the end of a frame
+fn strip_fecf(frame: &[u8]) -> CcsdsResult<&[u8]> {
+    check_len(frame, 2)?;
+    let (contents, field) = frame.split_at(frame.len() - 2);
+    let received = (u16::from(field[0]) << 8) | u16::from(field[1]);
+    let calculated = fecf(contents);
+    if received != calculated {
+        return Err(CcsdsError::BadChecksum {
+            received,
+            calculated,
+        });
+    }
+    Ok(contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fecf_check_value() {
+        assert_eq!(fecf(b"123456789"), 0x29B1);
+    }
+
+    #[test]
+    fn fecf_round_trip() {
+        let mut frame = b"frame".to_vec();
+        append_fecf(&mut frame);
+        assert_eq!(strip_fecf(&frame), Ok(&b"frame"[..]));
+
+        frame[0] ^= 0x80;
+        match strip_fecf(&frame) {
+            Err(CcsdsError::BadChecksum { .. }) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,342 @@
+/*
+* Copyright (C) 2018 Kubos Corporation
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+*     http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*/
+
+//! Space Packet Protocol packets (CCSDS 133.0-B)
+
+use std::collections::HashMap;
+use time::{CucTime, CUC_LEN};
+use {check_len, check_range, CcsdsError, CcsdsResult};
+
+/// Length of a packet's primary header
+pub const PRIMARY_HEADER_LEN: usize = 6;
+/// Largest application process identifier
+pub const MAX_APID: u16 = 0x7FF;
+/// APID of idle packets, which carry no data and are used as padding
+pub const IDLE_APID: u16 = 0x7FF;
+/// Largest packet sequence count. Counts wrap around to zero after it
+pub const MAX_SEQUENCE_COUNT: u16 = 0x3FFF;
+/// Largest packet data field, including the secondary header
+pub const MAX_DATA_LEN: usize = 65536;
+
+/// Whether a packet carries telemetry or a telecommand
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PacketType {
+    /// Telemetry, or reporting, packets
+    Telemetry,
+    /// Telecommand, or requesting, packets
+    Telecommand,
+}
+
+/// Where a packet's user data falls within a larger piece of data which was split across
+/// several packets
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SequenceFlags {
+    /// A segment from the middle of the data
+    Continuation,
+    /// The first segment of the data
+    First,
+    /// The last segment of the data
+    Last,
+    /// The data wasn't split
+    Unsegmented,
+}
+
+impl SequenceFlags {
+    fn bits(self) -> u8 {
+        match self {
+            SequenceFlags::Continuation => 0b00,
+            SequenceFlags::First => 0b01,
+            SequenceFlags::Last => 0b10,
+            SequenceFlags::Unsegmented => 0b11,
+        }
+    }
+
+    fn from_bits(bits: u8) -> SequenceFlags {
+        match bits & 0b11 {
+            0b00 => SequenceFlags::Continuation,
+            0b01 => SequenceFlags::First,
+            0b10 => SequenceFlags::Last,
+            _ => SequenceFlags::Unsegmented,
+        }
+    }
+}
+
+/// A space packet.
+///
+/// When a packet has a secondary header, it holds the time the packet was created as a
+/// `CucTime`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpacePacket {
+    /// Whether the packet is telemetry or a telecommand
+    pub packet_type: PacketType,
+    /// Application process identifier, from 0 to `MAX_APID`
+    pub apid: u16,
+    /// Where the packet's data falls within a larger piece of data
+    pub sequence_flags: SequenceFlags,
+    /// The count of packets sent with the same APID, from 0 to `MAX_SEQUENCE_COUNT`
+    pub sequence_count: u16,
+    /// The time held in the packet's secondary header, if it has one
+    pub time: Option<CucTime>,
+    /// The packet's user data
+    pub data: Vec<u8>,
+}
+
+impl SpacePacket {
+    /// Create an unsegmented packet without a secondary header
+    pub fn new(packet_type: PacketType, apid: u16, data: &[u8]) -> SpacePacket {
+        SpacePacket {
+            packet_type,
+            apid,
+            sequence_flags: SequenceFlags::Unsegmented,
+            sequence_count: 0,
+            time: None,
+            data: data.to_vec(),
+        }
+    }
+
+    /// Create an idle packet which is `len` bytes long once encoded. Idle packets are at least
+    /// `PRIMARY_HEADER_LEN + 1` bytes long
+    pub fn idle(len: usize) -> SpacePacket {
+        let data_len = len.saturating_sub(PRIMARY_HEADER_LEN).max(1);
+        SpacePacket {
+            packet_type: PacketType::Telemetry,
+            apid: IDLE_APID,
+            sequence_flags: SequenceFlags::Unsegmented,
+            sequence_count: 0,
+            time: None,
+            data: vec![0; data_len],
+        }
+    }
+
+    /// Whether the packet is an idle packet
+    pub fn is_idle(&self) -> bool {
+        self.apid == IDLE_APID
+    }
+
+    /// The length of the encoded packet
+    pub fn encoded_len(&self) -> usize {
+        PRIMARY_HEADER_LEN + self.data_field_len()
+    }
+
+    fn data_field_len(&self) -> usize {
+        self.time.map_or(0, |_| CUC_LEN) + self.data.len()
+    }
+
+    /// Encode the packet
+    pub fn encode(&self) -> CcsdsResult<Vec<u8>> {
+        check_range("APID", self.apid as usize, MAX_APID as usize)?;
+        check_range(
+            "Sequence count",
+            self.sequence_count as usize,
+            MAX_SEQUENCE_COUNT as usize,
+        )?;
+        let data_len = self.data_field_len();
+        check_range("Data field length", data_len, MAX_DATA_LEN)?;
+        if data_len == 0 {
+            return Err(CcsdsError::EmptyData);
+        }
+
+        let packet_type = match self.packet_type {
+            PacketType::Telemetry => 0,
+            PacketType::Telecommand => 1,
+        };
+        let secondary_header = if self.time.is_some() { 1 } else { 0 };
+        let length = (data_len - 1) as u16;
+
+        let mut output = Vec::with_capacity(self.encoded_len());
+        output.push((packet_type << 4) | (secondary_header << 3) | (self.apid >> 8) as u8);
+        output.push(self.apid as u8);
+        output.push((self.sequence_flags.bits() << 6) | (self.sequence_count >> 8) as u8);
+        output.push(self.sequence_count as u8);
+        output.push((length >> 8) as u8);
+        output.push(length as u8);
+        if let Some(time) = self.time {
+            output.extend_from_slice(&time.encode());
+        }
+        output.extend_from_slice(&self.data);
+        Ok(output)
+    }
+
+    /// Decode the packet at the start of `input`, returning the input which follows it.
+    ///
+    /// Returns an `Incomplete` error if `input` ends before the packet does
+    pub fn decode(input: &[u8]) -> CcsdsResult<(SpacePacket, &[u8])> {
+        check_len(input, PRIMARY_HEADER_LEN)?;
+        let version = input[0] >> 5;
+        if version != 0 {
+            return Err(CcsdsError::BadVersion(version));
+        }
+
+        let data_len = ((usize::from(input[4]) << 8) | usize::from(input[5])) + 1;
+        check_len(input, PRIMARY_HEADER_LEN + data_len)?;
+        let (packet, rest) = input.split_at(PRIMARY_HEADER_LEN + data_len);
+
+        let (time, data) = if packet[0] & 0x08 != 0 {
+            let data = &packet[PRIMARY_HEADER_LEN..];
+            check_len(data, CUC_LEN)?;
+            (Some(CucTime::decode(data)?), &data[CUC_LEN..])
+        } else {
+            (None, &packet[PRIMARY_HEADER_LEN..])
+        };
+
+        let packet = SpacePacket {
+            packet_type: if packet[0] & 0x10 != 0 {
+                PacketType::Telecommand
+            } else {
+                PacketType::Telemetry
+            },
+            apid: (u16::from(packet[0] & 0x07) << 8) | u16::from(packet[1]),
+            sequence_flags: SequenceFlags::from_bits(packet[2] >> 6),
+            sequence_count: (u16::from(packet[2] & 0x3F) << 8) | u16::from(packet[3]),
+            time,
+            data: data.to_vec(),
+        };
+        Ok((packet, rest))
+    }
+}
+
+/// Keeps the sequence count of each APID
+#[derive(Debug, Default)]
+pub struct SequenceCounter {
+    counts: HashMap<u16, u16>,
+}
+
+impl SequenceCounter {
+    /// Create a counter with every APID's count starting from zero
+    pub fn new() -> SequenceCounter {
+        SequenceCounter::default()
+    }
+
+    /// Take the next sequence count for an APID
+    pub fn next(&mut self, apid: u16) -> u16 {
+        let count = self.counts.entry(apid).or_insert(0);
+        let next = *count;
+        *count = (next + 1) & MAX_SEQUENCE_COUNT;
+        next
+    }
+
+    /// Set a packet's sequence count to the next count for its APID
+    pub fn stamp(&mut self, packet: &mut SpacePacket) {
+        packet.sequence_count = self.next(packet.apid);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_primary_header() {
+        let packet = SpacePacket {
+            packet_type: PacketType::Telecommand,
+            apid: 0x123,
+            sequence_flags: SequenceFlags::First,
+            sequence_count: 0x2345,
+            time: None,
+            data: vec![0xAA, 0xBB],
+        };
+        assert_eq!(
+            packet.encode().unwrap(),
+            vec![0x11, 0x23, 0x63, 0x45, 0x00, 0x01, 0xAA, 0xBB]
+        );
+        assert_eq!(packet.encoded_len(), 8);
+    }
+
+    #[test]
+    fn round_trip() {
+        let packet = SpacePacket {
+            packet_type: PacketType::Telemetry,
+            apid: 42,
+            sequence_flags: SequenceFlags::Last,
+            sequence_count: MAX_SEQUENCE_COUNT,
+            time: Some(CucTime {
+                seconds: 1_000_000,
+                subseconds: 0x4000,
+            }),
+            data: b"payload".to_vec(),
+        };
+        let mut encoded = packet.encode().unwrap();
+        assert_eq!(encoded[0] & 0x08, 0x08);
+        assert_eq!(encoded.len(), PRIMARY_HEADER_LEN + CUC_LEN + 7);
+
+        encoded.extend_from_slice(b"next");
+        assert_eq!(SpacePacket::decode(&encoded), Ok((packet, &b"next"[..])));
+    }
+
+    #[test]
+    fn decode_errors() {
+        let encoded = SpacePacket::new(PacketType::Telemetry, 1, b"data")
+            .encode()
+            .unwrap();
+        assert_eq!(
+            SpacePacket::decode(&encoded[..8]),
+            Err(CcsdsError::Incomplete {
+                needed: 10,
+                available: 8
+            })
+        );
+
+        let mut bad_version = encoded.clone();
+        bad_version[0] |= 0x20;
+        assert_eq!(
+            SpacePacket::decode(&bad_version),
+            Err(CcsdsError::BadVersion(1))
+        );
+    }
+
+    #[test]
+    fn encode_errors() {
+        let mut packet = SpacePacket::new(PacketType::Telemetry, 0x800, b"data");
+        assert_eq!(
+            packet.encode(),
+            Err(CcsdsError::OutOfRange {
+                field: "APID",
+                value: 0x800,
+                max: 0x7FF
+            })
+        );
+
+        packet.apid = 1;
+        packet.data.clear();
+        assert_eq!(packet.encode(), Err(CcsdsError::EmptyData));
+    }
+
+    #[test]
+    fn idle_packets() {
+        let idle = SpacePacket::idle(20);
+        assert!(idle.is_idle());
+        assert_eq!(idle.encode().unwrap().len(), 20);
+        assert_eq!(SpacePacket::idle(3).encoded_len(), PRIMARY_HEADER_LEN + 1);
+    }
+
+    #[test]
+    fn sequence_counts() {
+        let mut counter = SequenceCounter::new();
+        assert_eq!(counter.next(1), 0);
+        assert_eq!(counter.next(1), 1);
+        assert_eq!(counter.next(2), 0);
+
+        let mut packet = SpacePacket::new(PacketType::Telemetry, 1, b"data");
+        counter.stamp(&mut packet);
+        assert_eq!(packet.sequence_count, 2);
+
+        // Counts wrap around
+        for _ in 3..=MAX_SEQUENCE_COUNT {
+            counter.next(1);
+        }
+        assert_eq!(counter.next(1), 0);
+    }
+}
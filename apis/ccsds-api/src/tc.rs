@@ -0,0 +1,272 @@
+/*
+* Copyright (C) 2018 Kubos Corporation
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+*     http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*/
+
+//! TC Space Data Link Protocol transfer frames (CCSDS 232.0-B)
+
+use packet::SpacePacket;
+use {append_fecf, check_len, check_range, strip_fecf, CcsdsError, CcsdsResult};
+
+/// Length of a frame's primary header
+pub const PRIMARY_HEADER_LEN: usize = 5;
+/// Length of the frame error control field
+pub const FECF_LEN: usize = 2;
+/// Longest frame allowed by the standard
+pub const MAX_FRAME_LEN: usize = 1024;
+/// Largest spacecraft ID
+pub const MAX_SPACECRAFT_ID: u16 = 0x3FF;
+/// Largest virtual channel ID
+pub const MAX_VIRTUAL_CHANNEL: u8 = 63;
+
+/// A TC transfer frame. The frame's data field holds one or more whole space packets
+#[derive(Clone, Debug, PartialEq)]
+pub struct TcFrame {
+    /// Whether the frame bypasses the receiving end's frame acceptance checks (a type-BD frame)
+    pub bypass: bool,
+    /// Whether the frame holds control commands for the receiving end rather than data
+    pub control_command: bool,
+    /// The spacecraft's ID, from 0 to `MAX_SPACECRAFT_ID`
+    pub spacecraft_id: u16,
+    /// The frame's virtual channel, from 0 to `MAX_VIRTUAL_CHANNEL`
+    pub virtual_channel: u8,
+    /// The frame's sequence number on its virtual channel
+    pub sequence: u8,
+    /// The frame's data field
+    pub data: Vec<u8>,
+}
+
+impl TcFrame {
+    /// Encode the frame, followed by a frame error control field if `fecf` is set
+    pub fn encode(&self, fecf: bool) -> CcsdsResult<Vec<u8>> {
+        check_range(
+            "Spacecraft ID",
+            self.spacecraft_id as usize,
+            MAX_SPACECRAFT_ID as usize,
+        )?;
+        check_range(
+            "Virtual channel",
+            self.virtual_channel as usize,
+            MAX_VIRTUAL_CHANNEL as usize,
+        )?;
+        let len = PRIMARY_HEADER_LEN + self.data.len() + if fecf { FECF_LEN } else { 0 };
+        check_range("Frame length", len, MAX_FRAME_LEN)?;
+
+        let bypass = if self.bypass { 0x20 } else { 0 };
+        let control_command = if self.control_command { 0x10 } else { 0 };
+        let length = (len - 1) as u16;
+
+        let mut output = Vec::with_capacity(len);
+        output.push(bypass | control_command | (self.spacecraft_id >> 8) as u8);
+        output.push(self.spacecraft_id as u8);
+        output.push((self.virtual_channel << 2) | (length >> 8) as u8);
+        output.push(length as u8);
+        output.push(self.sequence);
+        output.extend_from_slice(&self.data);
+        if fecf {
+            append_fecf(&mut output);
+        }
+        Ok(output)
+    }
+
+    /// Decode the frame at the start of `input`, returning the input which follows it. The frame
+    /// ends with a frame error control field if `fecf` is set.
+    ///
+    /// Returns an `Incomplete` error if `input` ends before the frame does
+    pub fn decode(input: &[u8], fecf: bool) -> CcsdsResult<(TcFrame, &[u8])> {
+        check_len(input, PRIMARY_HEADER_LEN)?;
+        let version = input[0] >> 6;
+        if version != 0 {
+            return Err(CcsdsError::BadVersion(version));
+        }
+
+        let len = ((usize::from(input[2] & 0x03) << 8) | usize::from(input[3])) + 1;
+        check_len(input, len)?;
+        let (frame, rest) = input.split_at(len);
+        let frame = if fecf { strip_fecf(frame)? } else { frame };
+        check_len(frame, PRIMARY_HEADER_LEN)?;
+
+        let frame = TcFrame {
+            bypass: frame[0] & 0x20 != 0,
+            control_command: frame[0] & 0x10 != 0,
+            spacecraft_id: (u16::from(frame[0] & 0x03) << 8) | u16::from(frame[1]),
+            virtual_channel: frame[2] >> 2,
+            sequence: frame[4],
+            data: frame[PRIMARY_HEADER_LEN..].to_vec(),
+        };
+        Ok((frame, rest))
+    }
+
+    /// Decode the space packets in the frame's data field
+    pub fn packets(&self) -> CcsdsResult<Vec<SpacePacket>> {
+        let mut packets = vec![];
+        let mut data = &self.data[..];
+        while !data.is_empty() {
+            let (packet, rest) = SpacePacket::decode(data)?;
+            packets.push(packet);
+            data = rest;
+        }
+        Ok(packets)
+    }
+}
+
+/// Builds TC frames for one virtual channel, numbering them in sequence
+pub struct TcChannel {
+    spacecraft_id: u16,
+    virtual_channel: u8,
+    fecf: bool,
+    sequence: u8,
+}
+
+impl TcChannel {
+    /// Create a channel for a spacecraft's virtual channel, whose frames end with a frame error
+    /// control field if `fecf` is set
+    pub fn new(spacecraft_id: u16, virtual_channel: u8, fecf: bool) -> CcsdsResult<TcChannel> {
+        check_range(
+            "Spacecraft ID",
+            spacecraft_id as usize,
+            MAX_SPACECRAFT_ID as usize,
+        )?;
+        check_range(
+            "Virtual channel",
+            virtual_channel as usize,
+            MAX_VIRTUAL_CHANNEL as usize,
+        )?;
+        Ok(TcChannel {
+            spacecraft_id,
+            virtual_channel,
+            fecf,
+            sequence: 0,
+        })
+    }
+
+    /// Build a frame holding `packets`, which is checked by the receiving end's frame acceptance
+    /// checks (a type-AD frame) and takes the channel's next sequence number
+    pub fn frame(&mut self, packets: &[SpacePacket]) -> CcsdsResult<Vec<u8>> {
+        let encoded = self.build(false, self.sequence, packets)?;
+        self.sequence = self.sequence.wrapping_add(1);
+        Ok(encoded)
+    }
+
+    /// Build a frame holding `packets`, which bypasses the receiving end's frame acceptance
+    /// checks (a type-BD frame). Bypass frames don't use the sequence number
+    pub fn bypass_frame(&self, packets: &[SpacePacket]) -> CcsdsResult<Vec<u8>> {
+        self.build(true, 0, packets)
+    }
+
+    fn build(&self, bypass: bool, sequence: u8, packets: &[SpacePacket]) -> CcsdsResult<Vec<u8>> {
+        let mut data = vec![];
+        for packet in packets {
+            data.extend(packet.encode()?);
+        }
+        TcFrame {
+            bypass,
+            control_command: false,
+            spacecraft_id: self.spacecraft_id,
+            virtual_channel: self.virtual_channel,
+            sequence,
+            data,
+        }
+        .encode(self.fecf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use packet::PacketType;
+
+    #[test]
+    fn frame_round_trip() {
+        let frame = TcFrame {
+            bypass: true,
+            control_command: false,
+            spacecraft_id: 0x2AB,
+            virtual_channel: 42,
+            sequence: 7,
+            data: b"command".to_vec(),
+        };
+        let mut encoded = frame.encode(true).unwrap();
+        assert_eq!(&encoded[..5], &[0x22, 0xAB, 0xA8, 13, 7]);
+        assert_eq!(encoded.len(), 14);
+
+        encoded.extend_from_slice(b"next");
+        assert_eq!(
+            TcFrame::decode(&encoded, true),
+            Ok((frame.clone(), &b"next"[..]))
+        );
+
+        let encoded = frame.encode(false).unwrap();
+        assert_eq!(TcFrame::decode(&encoded, false), Ok((frame, &[][..])));
+    }
+
+    #[test]
+    fn frame_errors() {
+        let frame = TcFrame {
+            bypass: false,
+            control_command: false,
+            spacecraft_id: 1,
+            virtual_channel: 1,
+            sequence: 0,
+            data: vec![0; MAX_FRAME_LEN],
+        };
+        assert_eq!(
+            frame.encode(false),
+            Err(CcsdsError::OutOfRange {
+                field: "Frame length",
+                value: MAX_FRAME_LEN + PRIMARY_HEADER_LEN,
+                max: MAX_FRAME_LEN
+            })
+        );
+
+        let mut encoded = TcFrame {
+            data: vec![1, 2, 3],
+            ..frame
+        }
+        .encode(true)
+        .unwrap();
+        assert_eq!(
+            TcFrame::decode(&encoded[..6], true),
+            Err(CcsdsError::Incomplete {
+                needed: 10,
+                available: 6
+            })
+        );
+        encoded[6] ^= 0x01;
+        match TcFrame::decode(&encoded, true) {
+            Err(CcsdsError::BadChecksum { .. }) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn channel_frames() {
+        let mut channel = TcChannel::new(0x10, 3, true).unwrap();
+        let packets = vec![
+            SpacePacket::new(PacketType::Telecommand, 5, b"first"),
+            SpacePacket::new(PacketType::Telecommand, 6, b"second"),
+        ];
+
+        let (first, _) = TcFrame::decode(&channel.frame(&packets).unwrap(), true).unwrap();
+        let (second, _) = TcFrame::decode(&channel.frame(&packets[..1]).unwrap(), true).unwrap();
+        let (bypass, _) = TcFrame::decode(&channel.bypass_frame(&packets).unwrap(), true).unwrap();
+
+        assert_eq!((first.sequence, second.sequence), (0, 1));
+        assert!(!first.bypass && bypass.bypass);
+        assert_eq!(first.virtual_channel, 3);
+        assert_eq!(first.spacecraft_id, 0x10);
+        assert_eq!(first.packets().unwrap(), packets);
+        assert_eq!(second.packets().unwrap(), &packets[..1]);
+    }
+}
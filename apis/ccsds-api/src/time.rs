@@ -0,0 +1,116 @@
+/*
+* Copyright (C) 2018 Kubos Corporation
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+*     http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*/
+
+//! CCSDS Unsegmented time Code (CCSDS 301.0-B)
+
+use std::time::Duration;
+use {check_len, CcsdsResult};
+
+/// Length of an encoded time code
+pub const CUC_LEN: usize = 6;
+
+/// A time in CCSDS Unsegmented time Code format, with four octets of whole seconds and two
+/// octets of fractions of a second. The time code's preamble field is implicit, so it isn't
+/// encoded, and the epoch the time counts from is defined by the mission
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CucTime {
+    /// Whole seconds since the epoch
+    pub seconds: u32,
+    /// Fractions of a second, in units of 2^-16 seconds
+    pub subseconds: u16,
+}
+
+impl CucTime {
+    /// Create a time code from the time since the epoch. Times which don't fit wrap around, and
+    /// fractions of a second are rounded down to the nearest 2^-16 seconds
+    pub fn from_duration(since_epoch: Duration) -> CucTime {
+        CucTime {
+            seconds: since_epoch.as_secs() as u32,
+            subseconds: ((u64::from(since_epoch.subsec_nanos()) << 16) / 1_000_000_000) as u16,
+        }
+    }
+
+    /// The time since the epoch
+    pub fn to_duration(&self) -> Duration {
+        let nanos = (u64::from(self.subseconds) * 1_000_000_000) >> 16;
+        Duration::new(u64::from(self.seconds), nanos as u32)
+    }
+
+    /// Encode the time code
+    pub fn encode(&self) -> [u8; CUC_LEN] {
+        let s = self.seconds;
+        [
+            (s >> 24) as u8,
+            (s >> 16) as u8,
+            (s >> 8) as u8,
+            s as u8,
+            (self.subseconds >> 8) as u8,
+            self.subseconds as u8,
+        ]
+    }
+
+    /// Decode a time code from the start of `input`
+    pub fn decode(input: &[u8]) -> CcsdsResult<CucTime> {
+        check_len(input, CUC_LEN)?;
+        Ok(CucTime {
+            seconds: input[..4]
+                .iter()
+                .fold(0, |seconds, byte| (seconds << 8) | u32::from(*byte)),
+            subseconds: (u16::from(input[4]) << 8) | u16::from(input[5]),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use CcsdsError;
+
+    #[test]
+    fn round_trip() {
+        let time = CucTime {
+            seconds: 0x1234_5678,
+            subseconds: 0x8000,
+        };
+        assert_eq!(time.encode(), [0x12, 0x34, 0x56, 0x78, 0x80, 0x00]);
+        assert_eq!(CucTime::decode(&time.encode()), Ok(time));
+        assert_eq!(
+            CucTime::decode(&[0; 5]),
+            Err(CcsdsError::Incomplete {
+                needed: 6,
+                available: 5
+            })
+        );
+    }
+
+    #[test]
+    fn durations() {
+        let time = CucTime::from_duration(Duration::from_millis(1_500));
+        assert_eq!(
+            time,
+            CucTime {
+                seconds: 1,
+                subseconds: 0x8000
+            }
+        );
+        assert_eq!(time.to_duration(), Duration::from_millis(1_500));
+
+        // The fraction is rounded down to the nearest 2^-16 seconds
+        let duration = Duration::new(10, 123_456_789);
+        let error = duration - CucTime::from_duration(duration).to_duration();
+        assert!(error < Duration::new(0, 1_000_000_000 >> 16));
+    }
+}
@@ -0,0 +1,540 @@
+/*
+* Copyright (C) 2018 Kubos Corporation
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+*     http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*/
+
+//! TM Space Data Link Protocol transfer frames (CCSDS 132.0-B)
+
+use packet::{SpacePacket, PRIMARY_HEADER_LEN as PACKET_HEADER_LEN};
+use std::collections::HashMap;
+use {append_fecf, check_len, check_range, strip_fecf, CcsdsError, CcsdsResult};
+
+/// Length of a frame's primary header
+pub const PRIMARY_HEADER_LEN: usize = 6;
+/// Length of the operational control field
+pub const OCF_LEN: usize = 4;
+/// Length of the frame error control field
+pub const FECF_LEN: usize = 2;
+/// Longest frame allowed by the standard
+pub const MAX_FRAME_LEN: usize = 2048;
+/// Largest spacecraft ID
+pub const MAX_SPACECRAFT_ID: u16 = 0x3FF;
+/// Largest virtual channel ID
+pub const MAX_VIRTUAL_CHANNEL: u8 = 7;
+/// First header pointer of a frame in which no packet starts
+pub const NO_PACKET_START: u16 = 0x7FF;
+/// First header pointer of a frame which holds only idle data
+pub const ONLY_IDLE_DATA: u16 = 0x7FE;
+
+/// A TM transfer frame. Frames with a secondary header aren't supported
+#[derive(Clone, Debug, PartialEq)]
+pub struct TmFrame {
+    /// The spacecraft's ID, from 0 to `MAX_SPACECRAFT_ID`
+    pub spacecraft_id: u16,
+    /// The frame's virtual channel, from 0 to `MAX_VIRTUAL_CHANNEL`
+    pub virtual_channel: u8,
+    /// The count of frames sent on all virtual channels
+    pub master_channel_count: u8,
+    /// The count of frames sent on this frame's virtual channel
+    pub virtual_channel_count: u8,
+    /// The offset in `data` of the first packet which starts in this frame, or
+    /// `NO_PACKET_START` or `ONLY_IDLE_DATA`
+    pub first_header_pointer: u16,
+    /// The frame's data field
+    pub data: Vec<u8>,
+    /// The operational control field, if the frame has one
+    pub ocf: Option<u32>,
+}
+
+impl TmFrame {
+    /// Encode the frame, followed by a frame error control field if `fecf` is set
+    pub fn encode(&self, fecf: bool) -> CcsdsResult<Vec<u8>> {
+        check_range(
+            "Spacecraft ID",
+            self.spacecraft_id as usize,
+            MAX_SPACECRAFT_ID as usize,
+        )?;
+        check_range(
+            "Virtual channel",
+            self.virtual_channel as usize,
+            MAX_VIRTUAL_CHANNEL as usize,
+        )?;
+        check_range(
+            "First header pointer",
+            self.first_header_pointer as usize,
+            NO_PACKET_START as usize,
+        )?;
+        let len = PRIMARY_HEADER_LEN
+            + self.data.len()
+            + self.ocf.map_or(0, |_| OCF_LEN)
+            + if fecf { FECF_LEN } else { 0 };
+        check_range("Frame length", len, MAX_FRAME_LEN)?;
+
+        let ocf_flag = if self.ocf.is_some() { 1 } else { 0 };
+        let mut output = Vec::with_capacity(len);
+        output.push((self.spacecraft_id >> 4) as u8);
+        output.push(((self.spacecraft_id as u8) << 4) | (self.virtual_channel << 1) | ocf_flag);
+        output.push(self.master_channel_count);
+        output.push(self.virtual_channel_count);
+        // No secondary header, packets rather than synchronous data, and the segment length ID
+        // which the standard requires for packets
+        output.push(0b0001_1000 | (self.first_header_pointer >> 8) as u8);
+        output.push(self.first_header_pointer as u8);
+        output.extend_from_slice(&self.data);
+        if let Some(ocf) = self.ocf {
+            output.extend_from_slice(&[
+                (ocf >> 24) as u8,
+                (ocf >> 16) as u8,
+                (ocf >> 8) as u8,
+                ocf as u8,
+            ]);
+        }
+        if fecf {
+            append_fecf(&mut output);
+        }
+        Ok(output)
+    }
+
+    /// Decode a whole frame, which ends with a frame error control field if `fecf` is set
+    pub fn decode(frame: &[u8], fecf: bool) -> CcsdsResult<TmFrame> {
+        let frame = if fecf { strip_fecf(frame)? } else { frame };
+        check_len(frame, PRIMARY_HEADER_LEN)?;
+        let version = frame[0] >> 6;
+        if version != 0 {
+            return Err(CcsdsError::BadVersion(version));
+        }
+        if frame[4] & 0x80 != 0 {
+            return Err(CcsdsError::Unsupported("TM frame secondary header"));
+        }
+
+        let (data, ocf) = if frame[1] & 0x01 != 0 {
+            check_len(frame, PRIMARY_HEADER_LEN + OCF_LEN)?;
+            let (data, ocf) = frame.split_at(frame.len() - OCF_LEN);
+            let ocf = ocf
+                .iter()
+                .fold(0, |ocf, byte| (ocf << 8) | u32::from(*byte));
+            (&data[PRIMARY_HEADER_LEN..], Some(ocf))
+        } else {
+            (&frame[PRIMARY_HEADER_LEN..], None)
+        };
+
+        Ok(TmFrame {
+            spacecraft_id: (u16::from(frame[0] & 0x3F) << 4) | u16::from(frame[1] >> 4),
+            virtual_channel: (frame[1] >> 1) & 0x07,
+            master_channel_count: frame[2],
+            virtual_channel_count: frame[3],
+            first_header_pointer: (u16::from(frame[4] & 0x07) << 8) | u16::from(frame[5]),
+            data: data.to_vec(),
+            ocf,
+        })
+    }
+}
+
+// Encoded packets waiting to be sent on a virtual channel
+#[derive(Default)]
+struct SendQueue {
+    data: Vec<u8>,
+    // Offsets in `data` at which packets start
+    starts: Vec<usize>,
+    // Length of the end of an idle packet at the start of `data`, left over from the last frame
+    idle: usize,
+    count: u8,
+}
+
+/// Packs space packets into fixed length TM frames, keeping a separate queue of packets and
+/// frame count for each virtual channel.
+///
+/// Packets may span frames. When there aren't enough packets waiting to fill a frame, the rest
+/// of the frame is filled with an idle packet. If there's less room than the shortest idle
+/// packet, the idle packet runs over into the start of the next frame, which is only sent once
+/// more packets are queued.
+pub struct TmMultiplexer {
+    spacecraft_id: u16,
+    frame_len: usize,
+    fecf: bool,
+    master_channel_count: u8,
+    channels: HashMap<u8, SendQueue>,
+}
+
+impl TmMultiplexer {
+    /// Create a multiplexer for a spacecraft's frames
+    ///
+    /// # Arguments
+    ///
+    /// * `spacecraft_id` - The spacecraft's ID, from 0 to `MAX_SPACECRAFT_ID`
+    /// * `frame_len` - The length of every frame, including the frame error control field
+    /// * `fecf` - Whether frames end with a frame error control field
+    pub fn new(spacecraft_id: u16, frame_len: usize, fecf: bool) -> CcsdsResult<TmMultiplexer> {
+        check_range(
+            "Spacecraft ID",
+            spacecraft_id as usize,
+            MAX_SPACECRAFT_ID as usize,
+        )?;
+        check_range("Frame length", frame_len, MAX_FRAME_LEN)?;
+
+        let mux = TmMultiplexer {
+            spacecraft_id,
+            frame_len,
+            fecf,
+            master_channel_count: 0,
+            channels: HashMap::new(),
+        };
+        // Every frame must be able to hold an idle packet
+        if frame_len < mux.overhead() + PACKET_HEADER_LEN + 1 {
+            return Err(CcsdsError::Unsupported(
+                "TM frames too short to hold a packet",
+            ));
+        }
+        Ok(mux)
+    }
+
+    fn overhead(&self) -> usize {
+        PRIMARY_HEADER_LEN + if self.fecf { FECF_LEN } else { 0 }
+    }
+
+    /// Queue a packet to be sent on a virtual channel
+    pub fn push(&mut self, virtual_channel: u8, packet: &SpacePacket) -> CcsdsResult<()> {
+        check_range(
+            "Virtual channel",
+            virtual_channel as usize,
+            MAX_VIRTUAL_CHANNEL as usize,
+        )?;
+        let encoded = packet.encode()?;
+        let queue = self.channels.entry(virtual_channel).or_default();
+        queue.starts.push(queue.data.len());
+        queue.data.extend_from_slice(&encoded);
+        Ok(())
+    }
+
+    /// The number of packet bytes waiting to be sent on a virtual channel
+    pub fn pending(&self, virtual_channel: u8) -> usize {
+        self.channels
+            .get(&virtual_channel)
+            .map_or(0, |queue| queue.data.len() - queue.idle)
+    }
+
+    /// Build the next frame for a virtual channel, or return `None` if nothing is waiting to be
+    /// sent on it
+    pub fn next_frame(&mut self, virtual_channel: u8) -> CcsdsResult<Option<Vec<u8>>> {
+        check_range(
+            "Virtual channel",
+            virtual_channel as usize,
+            MAX_VIRTUAL_CHANNEL as usize,
+        )?;
+        let data_len = self.frame_len - self.overhead();
+        let queue = match self.channels.get_mut(&virtual_channel) {
+            Some(ref queue) if queue.data.len() == queue.idle => return Ok(None),
+            Some(queue) => queue,
+            None => return Ok(None),
+        };
+
+        let padded = queue.data.len() < data_len;
+        if padded {
+            // An idle packet can't be shorter than its header plus one byte, so when there's
+            // less room than that, the idle packet runs over the end of the frame
+            let idle = SpacePacket::idle(data_len - queue.data.len()).encode()?;
+            queue.starts.push(queue.data.len());
+            queue.data.extend_from_slice(&idle);
+        }
+
+        let first_header_pointer = queue
+            .starts
+            .first()
+            .filter(|start| **start < data_len)
+            .map_or(NO_PACKET_START, |start| *start as u16);
+        let data: Vec<u8> = queue.data.drain(..data_len).collect();
+        queue.starts.retain(|start| *start >= data_len);
+        for start in &mut queue.starts {
+            *start -= data_len;
+        }
+        // Whatever is left of the idle packet isn't worth a frame of its own, so it waits to
+        // be sent ahead of the next packet
+        queue.idle = if padded { queue.data.len() } else { 0 };
+
+        let frame = TmFrame {
+            spacecraft_id: self.spacecraft_id,
+            virtual_channel,
+            master_channel_count: self.master_channel_count,
+            virtual_channel_count: queue.count,
+            first_header_pointer,
+            data,
+            ocf: None,
+        };
+        queue.count = queue.count.wrapping_add(1);
+        self.master_channel_count = self.master_channel_count.wrapping_add(1);
+        frame.encode(self.fecf).map(Some)
+    }
+}
+
+// Packet data received on a virtual channel
+#[derive(Default)]
+struct ReceiveQueue {
+    data: Vec<u8>,
+    // Whether `data` starts at the start of a packet
+    synced: bool,
+    next_count: Option<u8>,
+}
+
+impl ReceiveQueue {
+    // Move the complete packets at the start of the data to `packets`, discarding idle packets
+    fn extract(&mut self, packets: &mut Vec<SpacePacket>) {
+        loop {
+            match SpacePacket::decode(&self.data) {
+                Ok((packet, rest)) => {
+                    let consumed = self.data.len() - rest.len();
+                    if !packet.is_idle() {
+                        packets.push(packet);
+                    }
+                    self.data.drain(..consumed);
+                }
+                Err(CcsdsError::Incomplete { .. }) => break,
+                Err(_) => {
+                    // The data isn't a packet, so wait for the next frame's first header pointer
+                    self.data.clear();
+                    self.synced = false;
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Extracts space packets from TM frames, reassembling packets which span frames on each virtual
+/// channel. Idle packets are discarded.
+///
+/// When a frame is lost, the partly received packet is dropped, and packets are extracted again
+/// from the first packet which starts in a later frame. A partly received packet is also dropped
+/// if it hasn't ended by the point the next frame's first header pointer gives for the start of
+/// the next packet.
+pub struct TmDemultiplexer {
+    fecf: bool,
+    channels: HashMap<u8, ReceiveQueue>,
+}
+
+impl TmDemultiplexer {
+    /// Create a demultiplexer for frames which end with a frame error control field if `fecf` is
+    /// set
+    pub fn new(fecf: bool) -> TmDemultiplexer {
+        TmDemultiplexer {
+            fecf,
+            channels: HashMap::new(),
+        }
+    }
+
+    /// Receive a frame, returning the packets it completes
+    pub fn receive(&mut self, frame: &[u8]) -> CcsdsResult<Vec<SpacePacket>> {
+        let frame = TmFrame::decode(frame, self.fecf)?;
+        let queue = self.channels.entry(frame.virtual_channel).or_default();
+
+        let lost = match queue.next_count {
+            Some(count) => count != frame.virtual_channel_count,
+            None => false,
+        };
+        if lost {
+            queue.data.clear();
+            queue.synced = false;
+        }
+        queue.next_count = Some(frame.virtual_channel_count.wrapping_add(1));
+
+        let start = frame.first_header_pointer as usize;
+        let has_start = frame.first_header_pointer < ONLY_IDLE_DATA && start < frame.data.len();
+        let mut packets = vec![];
+        if queue.synced && has_start {
+            // The packet carried over from earlier frames must end where the first new one
+            // starts. If it doesn't, the frames disagree about where it ends, so it's dropped
+            queue.data.extend_from_slice(&frame.data[..start]);
+            queue.extract(&mut packets);
+            queue.data = frame.data[start..].to_vec();
+            queue.synced = true;
+        } else if queue.synced {
+            queue.data.extend_from_slice(&frame.data);
+        } else if has_start {
+            queue.data = frame.data[start..].to_vec();
+            queue.synced = true;
+        } else {
+            return Ok(vec![]);
+        }
+
+        queue.extract(&mut packets);
+        Ok(packets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use packet::PacketType;
+    use time::CucTime;
+
+    fn packet(apid: u16, len: usize) -> SpacePacket {
+        let data: Vec<u8> = (0..len).map(|byte| byte as u8).collect();
+        SpacePacket::new(PacketType::Telemetry, apid, &data)
+    }
+
+    #[test]
+    fn frame_round_trip() {
+        let frame = TmFrame {
+            spacecraft_id: 0x2AB,
+            virtual_channel: 5,
+            master_channel_count: 10,
+            virtual_channel_count: 3,
+            first_header_pointer: 0x123,
+            data: vec![0x55; 16],
+            ocf: Some(0x0102_0304),
+        };
+        let encoded = frame.encode(true).unwrap();
+        assert_eq!(&encoded[..6], &[0x2A, 0xBB, 10, 3, 0x19, 0x23]);
+        assert_eq!(encoded.len(), 6 + 16 + 4 + 2);
+        assert_eq!(TmFrame::decode(&encoded, true), Ok(frame.clone()));
+
+        let encoded = frame.encode(false).unwrap();
+        assert_eq!(TmFrame::decode(&encoded, false), Ok(frame));
+    }
+
+    #[test]
+    fn frame_errors() {
+        let frame = TmFrame {
+            spacecraft_id: 1,
+            virtual_channel: 8,
+            master_channel_count: 0,
+            virtual_channel_count: 0,
+            first_header_pointer: 0,
+            data: vec![0; 8],
+            ocf: None,
+        };
+        assert_eq!(
+            frame.encode(true),
+            Err(CcsdsError::OutOfRange {
+                field: "Virtual channel",
+                value: 8,
+                max: 7
+            })
+        );
+
+        let mut encoded = TmFrame {
+            virtual_channel: 1,
+            ..frame
+        }
+        .encode(true)
+        .unwrap();
+        encoded[8] ^= 0x01;
+        match TmFrame::decode(&encoded, true) {
+            Err(CcsdsError::BadChecksum { .. }) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn packets_span_frames() {
+        let mut mux = TmMultiplexer::new(1, 64, true).unwrap();
+        let mut demux = TmDemultiplexer::new(true);
+        let mut timed = packet(2, 20);
+        timed.time = Some(CucTime {
+            seconds: 100,
+            subseconds: 0,
+        });
+        let packets = vec![packet(1, 30), packet(1, 100), timed];
+        for packet in &packets {
+            mux.push(0, packet).unwrap();
+        }
+
+        let mut received = vec![];
+        let mut frames = 0;
+        while let Some(frame) = mux.next_frame(0).unwrap() {
+            assert_eq!(frame.len(), 64);
+            received.extend(demux.receive(&frame).unwrap());
+            frames += 1;
+        }
+        assert_eq!(received, packets);
+        assert_eq!(mux.pending(0), 0);
+        assert!(frames > 3);
+    }
+
+    #[test]
+    fn virtual_channels() {
+        let mut mux = TmMultiplexer::new(1, 64, false).unwrap();
+        let mut demux = TmDemultiplexer::new(false);
+        mux.push(1, &packet(10, 80)).unwrap();
+        mux.push(2, &packet(20, 10)).unwrap();
+
+        // Frames from different channels can be interleaved
+        let first = mux.next_frame(1).unwrap().unwrap();
+        let other = mux.next_frame(2).unwrap().unwrap();
+        let second = mux.next_frame(1).unwrap().unwrap();
+        assert_eq!(mux.next_frame(3).unwrap(), None);
+
+        assert_eq!(demux.receive(&first).unwrap(), vec![]);
+        assert_eq!(demux.receive(&other).unwrap(), vec![packet(20, 10)]);
+        assert_eq!(demux.receive(&second).unwrap(), vec![packet(10, 80)]);
+
+        let first = TmFrame::decode(&first, false).unwrap();
+        let other = TmFrame::decode(&other, false).unwrap();
+        let second = TmFrame::decode(&second, false).unwrap();
+        assert_eq!(
+            (first.master_channel_count, other.master_channel_count),
+            (0, 1)
+        );
+        assert_eq!(second.master_channel_count, 2);
+        assert_eq!(
+            (first.virtual_channel_count, second.virtual_channel_count),
+            (0, 1)
+        );
+        assert_eq!(second.first_header_pointer, 80 + 6 - 58);
+    }
+
+    #[test]
+    fn resync_after_lost_frame() {
+        let mut mux = TmMultiplexer::new(1, 32, false).unwrap();
+        let mut demux = TmDemultiplexer::new(false);
+        mux.push(0, &packet(1, 60)).unwrap();
+        mux.push(0, &packet(2, 10)).unwrap();
+
+        let mut frames = vec![];
+        while let Some(frame) = mux.next_frame(0).unwrap() {
+            frames.push(frame);
+        }
+
+        // The second frame, in which no packet starts, is lost. The first packet is dropped,
+        // but the second is found using the first header pointer of the third frame
+        assert_eq!(demux.receive(&frames[0]).unwrap(), vec![]);
+        let mut received = vec![];
+        for frame in &frames[2..] {
+            received.extend(demux.receive(frame).unwrap());
+        }
+        assert_eq!(received, vec![packet(2, 10)]);
+    }
+
+    #[test]
+    fn idle_packet_continues() {
+        // Leave less room in the frame than the smallest idle packet
+        let mut mux = TmMultiplexer::new(1, 32, false).unwrap();
+        let mut demux = TmDemultiplexer::new(false);
+        mux.push(0, &packet(1, 16)).unwrap();
+
+        let first = mux.next_frame(0).unwrap().unwrap();
+        assert_eq!(demux.receive(&first).unwrap(), vec![packet(1, 16)]);
+
+        // The rest of the idle packet isn't sent on its own
+        assert_eq!(mux.pending(0), 0);
+        assert_eq!(mux.next_frame(0).unwrap(), None);
+
+        // It starts the next frame, ahead of the next packet
+        mux.push(0, &packet(2, 4)).unwrap();
+        let second = mux.next_frame(0).unwrap().unwrap();
+        let decoded = TmFrame::decode(&second, false).unwrap();
+        assert_eq!(decoded.first_header_pointer, 3);
+        assert_eq!(demux.receive(&second).unwrap(), vec![packet(2, 4)]);
+        assert_eq!(mux.next_frame(0).unwrap(), None);
+    }
+}
@@ -0,0 +1,311 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+#![deny(missing_docs)]
+#![deny(warnings)]
+
+//! Logging for KubOS apps and services.
+//!
+//! Messages logged with the [`log`](https://docs.rs/log) crate's macros are sent to the system
+//! logger (syslog), tagged with the name of the app or service which logged them, so that the
+//! logs of all flight software end up in one place. If syslog isn't available, messages are
+//! appended to a log file instead, or written to stderr if the log file's directory doesn't
+//! exist.
+//!
+//! The level of messages which are logged can be set for each app or service in its section of
+//! the system config file:
+//!
+//! ```toml,ignore
+//! [example-service]
+//! # One of "off", "error", "warn", "info", "debug" or "trace". Defaults to "info"
+//! log-level = "debug"
+//! # The file messages are written to when syslog isn't available.
+//! # Defaults to /home/system/log/<name>.log
+//! log-file = "/home/system/log/example-service.log"
+//! ```
+//!
+//! # Examples
+//!
+//! ```no_run
+//! extern crate kubos_logging;
+//! extern crate kubos_system;
+//! #[macro_use]
+//! extern crate log;
+//!
+//! use kubos_logging::Facility;
+//! use kubos_system::Config;
+//!
+//! fn main() {
+//!     kubos_logging::init(Facility::Daemon, &Config::new("example-service")).unwrap();
+//!
+//!     info!("Service started");
+//! }
+//! ```
+
+extern crate chrono;
+#[macro_use]
+extern crate failure;
+extern crate kubos_system;
+extern crate libc;
+extern crate log;
+
+use chrono::Local;
+use kubos_system::Config;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+/// The socket syslog listens on
+pub const SYSLOG_PATH: &str = "/dev/log";
+/// The directory log files are written to when syslog isn't available
+pub const DEFAULT_LOG_DIR: &str = "/home/system/log";
+/// The level of messages which are logged when the config file doesn't give one
+pub const DEFAULT_LEVEL: LevelFilter = LevelFilter::Info;
+
+/// The syslog facility messages are logged under, which tells syslog what kind of program
+/// they came from
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Facility {
+    /// Mission applications
+    User,
+    /// Services
+    Daemon,
+}
+
+impl Facility {
+    fn code(self) -> libc::c_int {
+        match self {
+            Facility::User => libc::LOG_USER,
+            Facility::Daemon => libc::LOG_DAEMON,
+        }
+    }
+}
+
+/// Errors which can occur while setting up logging
+#[derive(Debug, Fail, PartialEq)]
+pub enum LogError {
+    /// The config file's `log-level` is not a valid level
+    #[fail(display = "Invalid log level: {}", _0)]
+    BadLevel(String),
+    /// A logger has already been set up for this process
+    #[fail(display = "Logging has already been set up")]
+    AlreadyInitialized,
+}
+
+/// Where log messages are written
+enum Sink {
+    Syslog(UnixDatagram),
+    File(File),
+    Stderr,
+}
+
+/// A logger which writes to syslog, or to a log file when syslog isn't available
+pub struct Logger {
+    tag: String,
+    facility: Facility,
+    level: LevelFilter,
+    syslog_path: PathBuf,
+    file_path: PathBuf,
+    sink: Mutex<Sink>,
+}
+
+impl Logger {
+    /// Create a logger which sends messages to the syslog socket at `syslog_path`. If it can't be
+    /// connected to, messages are appended to the file at `file_path` instead, and if that can't
+    /// be opened either, they're written to stderr. The file's directory isn't created, so
+    /// logging doesn't leave directories behind on systems which don't use them
+    ///
+    /// # Arguments
+    ///
+    /// * `tag` - The name messages are tagged with, normally the name of the app or service
+    /// * `facility` - The syslog facility messages are logged under
+    /// * `level` - The most detailed level of messages which should be logged
+    /// * `syslog_path` - The socket syslog listens on
+    /// * `file_path` - The file used when syslog isn't available
+    pub fn new(
+        tag: &str,
+        facility: Facility,
+        level: LevelFilter,
+        syslog_path: &Path,
+        file_path: &Path,
+    ) -> Logger {
+        let logger = Logger {
+            tag: tag.to_owned(),
+            facility,
+            level,
+            syslog_path: syslog_path.to_owned(),
+            file_path: file_path.to_owned(),
+            sink: Mutex::new(Sink::Stderr),
+        };
+        *logger.sink.lock().unwrap() = logger.open();
+        logger
+    }
+
+    /// Whether messages are being sent to syslog
+    pub fn is_syslog(&self) -> bool {
+        match *self.sink.lock().unwrap() {
+            Sink::Syslog(_) => true,
+            _ => false,
+        }
+    }
+
+    fn open(&self) -> Sink {
+        if let Ok(socket) = connect_syslog(&self.syslog_path) {
+            return Sink::Syslog(socket);
+        }
+        match OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.file_path)
+        {
+            Ok(file) => Sink::File(file),
+            Err(_) => Sink::Stderr,
+        }
+    }
+
+    fn write(&self, sink: &mut Sink, record: &Record) -> io::Result<()> {
+        match sink {
+            Sink::Syslog(socket) => {
+                let message = format!(
+                    "<{}>{}[{}]: {}",
+                    self.facility.code() | severity(record.level()),
+                    self.tag,
+                    process::id(),
+                    record.args()
+                );
+                socket.send(message.as_bytes()).map(|_| ())
+            }
+            Sink::File(file) => writeln!(
+                file,
+                "{} {}[{}] {}: {}",
+                Local::now().format("%Y-%m-%d %H:%M:%S"),
+                self.tag,
+                process::id(),
+                record.level(),
+                record.args()
+            ),
+            Sink::Stderr => writeln!(
+                io::stderr(),
+                "{}[{}] {}: {}",
+                self.tag,
+                process::id(),
+                record.level(),
+                record.args()
+            ),
+        }
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let mut sink = self.sink.lock().unwrap();
+        if self.write(&mut sink, record).is_ok() {
+            return;
+        }
+
+        // Syslog may have been restarted, so try connecting again before giving up on it
+        *sink = self.open();
+        let _ = self.write(&mut sink, record);
+    }
+
+    fn flush(&self) {
+        if let Sink::File(ref mut file) = *self.sink.lock().unwrap() {
+            let _ = file.flush();
+        }
+    }
+}
+
+fn connect_syslog(path: &Path) -> io::Result<UnixDatagram> {
+    let socket = UnixDatagram::unbound()?;
+    socket.connect(path)?;
+    Ok(socket)
+}
+
+// The syslog severity of a log level
+fn severity(level: Level) -> libc::c_int {
+    match level {
+        Level::Error => libc::LOG_ERR,
+        Level::Warn => libc::LOG_WARNING,
+        Level::Info => libc::LOG_INFO,
+        Level::Debug | Level::Trace => libc::LOG_DEBUG,
+    }
+}
+
+/// Read the level of messages to log from the `log-level` key of a config
+pub fn config_level(config: &Config) -> Result<LevelFilter, LogError> {
+    match config.get("log-level") {
+        None => Ok(DEFAULT_LEVEL),
+        Some(value) => {
+            let level = value
+                .as_str()
+                .map(|level| level.to_owned())
+                .unwrap_or_else(|| value.to_string());
+            LevelFilter::from_str(&level).map_err(|_| LogError::BadLevel(level))
+        }
+    }
+}
+
+/// Read the file to log to when syslog isn't available from the `log-file` key of a config
+pub fn config_file(config: &Config) -> PathBuf {
+    match config
+        .get("log-file")
+        .and_then(|file| file.as_str().map(PathBuf::from))
+    {
+        Some(file) => file,
+        None => Path::new(DEFAULT_LOG_DIR).join(format!("{}.log", config.name())),
+    }
+}
+
+/// Set up logging for this process. Messages are tagged with the config's name, and the level
+/// and fallback file are read from the config.
+///
+/// If the config's `log-level` isn't valid, the default level is used and an error is returned.
+///
+/// # Arguments
+///
+/// * `facility` - The syslog facility messages are logged under
+/// * `config` - The config of the app or service
+pub fn init(facility: Facility, config: &Config) -> Result<(), LogError> {
+    let (level, result) = match config_level(config) {
+        Ok(level) => (level, Ok(())),
+        Err(err) => (DEFAULT_LEVEL, Err(err)),
+    };
+
+    let logger = Logger::new(
+        config.name(),
+        facility,
+        level,
+        Path::new(SYSLOG_PATH),
+        &config_file(config),
+    );
+    log::set_boxed_logger(Box::new(logger)).map_err(|_| LogError::AlreadyInitialized)?;
+    log::set_max_level(level);
+
+    result
+}
@@ -0,0 +1,183 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+#![deny(warnings)]
+extern crate kubos_logging;
+extern crate kubos_system;
+extern crate log;
+extern crate tempfile;
+
+use kubos_logging::*;
+use kubos_system::Config;
+use log::{Level, LevelFilter, Log, Record};
+use std::fs;
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+use std::process;
+use tempfile::TempDir;
+
+fn log(logger: &Logger, level: Level, message: &str) {
+    logger.log(
+        &Record::builder()
+            .level(level)
+            .args(format_args!("{}", message))
+            .build(),
+    );
+}
+
+#[test]
+fn logs_to_syslog() {
+    let dir = TempDir::new().unwrap();
+    let syslog_path = dir.path().join("log");
+    let syslog = UnixDatagram::bind(&syslog_path).unwrap();
+
+    let logger = Logger::new(
+        "mission-app",
+        Facility::User,
+        LevelFilter::Info,
+        &syslog_path,
+        &dir.path().join("mission-app.log"),
+    );
+    assert!(logger.is_syslog());
+
+    log(&logger, Level::Error, "Radio failed to respond");
+    log(&logger, Level::Info, "Pass started");
+    // Below the logger's level
+    log(&logger, Level::Debug, "Polling radio");
+
+    let mut buf = [0; 1024];
+    let len = syslog.recv(&mut buf).unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&buf[..len]),
+        format!(
+            "<11>mission-app[{}]: Radio failed to respond",
+            process::id()
+        )
+    );
+    let len = syslog.recv(&mut buf).unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&buf[..len]),
+        format!("<14>mission-app[{}]: Pass started", process::id())
+    );
+
+    syslog.set_nonblocking(true).unwrap();
+    assert!(syslog.recv(&mut buf).is_err());
+    assert!(!dir.path().join("mission-app.log").exists());
+}
+
+#[test]
+fn falls_back_to_file() {
+    let dir = TempDir::new().unwrap();
+    let file_path = dir.path().join("gps-service.log");
+
+    let logger = Logger::new(
+        "gps-service",
+        Facility::Daemon,
+        LevelFilter::Debug,
+        &dir.path().join("missing-socket"),
+        &file_path,
+    );
+    assert!(!logger.is_syslog());
+
+    log(&logger, Level::Warn, "No fix");
+    log(&logger, Level::Debug, "Polling receiver");
+    logger.flush();
+
+    let contents = fs::read_to_string(&file_path).unwrap();
+    let lines: Vec<&str> = contents.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].ends_with(&format!("gps-service[{}] WARN: No fix", process::id())));
+    assert!(lines[1].ends_with(&format!(
+        "gps-service[{}] DEBUG: Polling receiver",
+        process::id()
+    )));
+}
+
+#[test]
+fn falls_back_to_stderr() {
+    let dir = TempDir::new().unwrap();
+    let log_dir = dir.path().join("logs");
+
+    let logger = Logger::new(
+        "gps-service",
+        Facility::Daemon,
+        LevelFilter::Debug,
+        &dir.path().join("missing-socket"),
+        &log_dir.join("gps-service.log"),
+    );
+    assert!(!logger.is_syslog());
+
+    log(&logger, Level::Warn, "No fix");
+    logger.flush();
+
+    // The log file's directory is left alone when it doesn't exist
+    assert!(!log_dir.exists());
+}
+
+#[test]
+fn reconnects_to_syslog() {
+    let dir = TempDir::new().unwrap();
+    let syslog_path = dir.path().join("log");
+    let syslog = UnixDatagram::bind(&syslog_path).unwrap();
+
+    let logger = Logger::new(
+        "gps-service",
+        Facility::Daemon,
+        LevelFilter::Info,
+        &syslog_path,
+        &dir.path().join("gps-service.log"),
+    );
+
+    // Simulate syslog being restarted
+    drop(syslog);
+    fs::remove_file(&syslog_path).unwrap();
+    let syslog = UnixDatagram::bind(&syslog_path).unwrap();
+
+    log(&logger, Level::Info, "Fix acquired");
+
+    let mut buf = [0; 1024];
+    let len = syslog.recv(&mut buf).unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&buf[..len]),
+        format!("<30>gps-service[{}]: Fix acquired", process::id())
+    );
+}
+
+#[test]
+fn levels_from_config() {
+    let config = Config::new_from_str(
+        "gps-service",
+        r#"
+        [gps-service]
+        log-level = "debug"
+        log-file = "/tmp/gps.log"
+        "#,
+    );
+    assert_eq!(config_level(&config), Ok(LevelFilter::Debug));
+    assert_eq!(config_file(&config), PathBuf::from("/tmp/gps.log"));
+
+    let config = Config::new_from_str("mission-app", "[mission-app]\n");
+    assert_eq!(config_level(&config), Ok(DEFAULT_LEVEL));
+    assert_eq!(
+        config_file(&config),
+        Path::new(DEFAULT_LOG_DIR).join("mission-app.log")
+    );
+
+    let config = Config::new_from_str("mission-app", "[mission-app]\nlog-level = \"loud\"\n");
+    assert_eq!(
+        config_level(&config),
+        Err(LogError::BadLevel("loud".to_owned()))
+    );
+}
@@ -22,7 +22,9 @@ use std::time::Duration;
 /// Connection for communicating with actual
 /// Duplex-D2 hardware
 pub fn serial_connection() -> Connection {
-    Connection::new(Box::new(SerialStream {}))
+    // The largest file the radio can hold takes around four and a half minutes to arrive at
+    // 38400 baud, so reads need to be allowed that long
+    Connection::new(Box::new(SerialStream {})).with_timeout(Duration::from_secs(300))
 }
 
 struct SerialStream {}
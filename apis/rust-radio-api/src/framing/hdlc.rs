@@ -0,0 +1,147 @@
+/*
+* Copyright (C) 2018 Kubos Corporation
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+*     http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*/
+
+//! HDLC-like framing, as used by PPP (RFC 1662)
+
+use super::{
+    frame_error, next_frame, Codec, ERROR_BAD_CHECKSUM, ERROR_BAD_ESCAPE, ERROR_TOO_SHORT,
+};
+use crc16;
+use nom::IResult;
+
+/// Marks the start and end of a frame
+pub const FLAG: u8 = 0x7E;
+/// Starts an escape sequence. The byte which follows is XORed with `ESCAPE_XOR`
+pub const ESCAPE: u8 = 0x7D;
+/// Value escaped bytes are XORed with
+pub const ESCAPE_XOR: u8 = 0x20;
+
+/// HDLC framing. Each frame is followed by a 16-bit frame check sequence (CRC-16/X.25, sent least
+/// significant byte first) and sent between `FLAG` bytes, with any `FLAG` or `ESCAPE` bytes
+/// escaped
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Hdlc;
+
+/// Calculate the frame check sequence of a frame
+pub fn fcs(frame: &[u8]) -> u16 {
+    crc16::State::<crc16::X_25>::calculate(frame)
+}
+
+impl Codec for Hdlc {
+    fn encode(&self, frame: &[u8]) -> Vec<u8> {
+        let fcs = fcs(frame);
+        let mut output = Vec::with_capacity(frame.len() + 4);
+        output.push(FLAG);
+        for byte in frame.iter().chain(&[fcs as u8, (fcs >> 8) as u8]) {
+            match *byte {
+                FLAG | ESCAPE => output.extend_from_slice(&[ESCAPE, byte ^ ESCAPE_XOR]),
+                byte => output.push(byte),
+            }
+        }
+        output.push(FLAG);
+        output
+    }
+
+    fn decode<'a>(&self, input: &'a [u8]) -> IResult<&'a [u8], Vec<u8>> {
+        let (rest, raw) = next_frame(input, FLAG)?;
+
+        let mut frame = Vec::with_capacity(raw.len());
+        let mut bytes = raw.iter();
+        while let Some(byte) = bytes.next() {
+            match *byte {
+                ESCAPE => match bytes.next() {
+                    Some(byte) => frame.push(byte ^ ESCAPE_XOR),
+                    None => return frame_error(rest, ERROR_BAD_ESCAPE),
+                },
+                byte => frame.push(byte),
+            }
+        }
+
+        if frame.len() < 2 {
+            return frame_error(rest, ERROR_TOO_SHORT);
+        }
+        let len = frame.len() - 2;
+        let received = u16::from(frame[len]) | (u16::from(frame[len + 1]) << 8);
+        frame.truncate(len);
+        if received != fcs(&frame) {
+            return frame_error(rest, ERROR_BAD_CHECKSUM);
+        }
+
+        Ok((rest, frame))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nom::{self, Context, ErrorKind, Needed};
+
+    #[test]
+    fn check_value() {
+        assert_eq!(fcs(b"123456789"), 0x906E);
+    }
+
+    #[test]
+    fn round_trip() {
+        let frame = [0x01, FLAG, 0x02, ESCAPE, 0x03];
+        let encoded = Hdlc.encode(&frame);
+        let fcs = fcs(&frame);
+        assert_eq!(
+            &encoded[..8],
+            &[FLAG, 0x01, ESCAPE, 0x5E, 0x02, ESCAPE, 0x5D, 0x03]
+        );
+        assert_eq!(&encoded[8..], &[fcs as u8, (fcs >> 8) as u8, FLAG]);
+        assert_eq!(Hdlc.decode(&encoded), Ok((&[][..], frame.to_vec())));
+
+        // Every byte value survives
+        let frame: Vec<u8> = (0..=255).collect();
+        assert_eq!(Hdlc.decode(&Hdlc.encode(&frame)), Ok((&[][..], frame)));
+    }
+
+    #[test]
+    fn bad_checksum() {
+        let mut encoded = Hdlc.encode(b"frame");
+        encoded[2] ^= 0x01;
+        encoded.extend(Hdlc.encode(b"next"));
+
+        let next = Hdlc.encode(b"next");
+        assert_eq!(
+            Hdlc.decode(&encoded),
+            Err(nom::Err::Error(Context::Code(
+                &next[..],
+                ErrorKind::Custom(ERROR_BAD_CHECKSUM)
+            )))
+        );
+        assert_eq!(Hdlc.decode(&next), Ok((&[][..], b"next".to_vec())));
+    }
+
+    #[test]
+    fn short_and_partial_frames() {
+        assert_eq!(
+            Hdlc.decode(&[FLAG, 0x01, FLAG]),
+            Err(nom::Err::Error(Context::Code(
+                &[][..],
+                ErrorKind::Custom(ERROR_TOO_SHORT)
+            )))
+        );
+
+        let encoded = Hdlc.encode(b"frame");
+        assert_eq!(
+            Hdlc.decode(&encoded[..encoded.len() - 1]),
+            Err(nom::Err::Incomplete(Needed::Unknown))
+        );
+    }
+}
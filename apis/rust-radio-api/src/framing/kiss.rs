@@ -0,0 +1,138 @@
+/*
+* Copyright (C) 2018 Kubos Corporation
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+*     http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*/
+
+//! KISS framing, used to talk to packet radio TNCs
+
+use super::slip::{END, ESC, ESC_END, ESC_ESC};
+use super::{escape_slip, frame_error, next_frame, unescape_slip, Codec};
+use super::{ERROR_BAD_ESCAPE, ERROR_TOO_SHORT};
+use nom::IResult;
+
+/// Marks the start and end of a frame
+pub const FEND: u8 = END;
+/// Starts an escape sequence
+pub const FESC: u8 = ESC;
+/// Follows `FESC` in place of an `FEND` byte within a frame
+pub const TFEND: u8 = ESC_END;
+/// Follows `FESC` in place of an `FESC` byte within a frame
+pub const TFESC: u8 = ESC_ESC;
+
+/// Command of frames which carry data
+pub const DATA_FRAME: u8 = 0x00;
+/// Command which sets the transmitter keyup delay
+pub const TX_DELAY: u8 = 0x01;
+/// Command which sets the persistence parameter
+pub const PERSISTENCE: u8 = 0x02;
+/// Command which sets the slot interval
+pub const SLOT_TIME: u8 = 0x03;
+/// Command which sets how long the transmitter is kept on after a frame
+pub const TX_TAIL: u8 = 0x04;
+/// Command which turns full duplex on or off
+pub const FULL_DUPLEX: u8 = 0x05;
+
+/// KISS framing. Frames are escaped the same way as SLIP, and start with a type byte holding
+/// the TNC port in its high nibble and the command in its low nibble.
+///
+/// Only data frames for the codec's port are decoded. Other frames are skipped
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Kiss {
+    /// The TNC port frames are sent to and received from, from 0 to 15
+    pub port: u8,
+}
+
+impl Kiss {
+    /// Create a codec for one of the TNC's ports
+    pub fn new(port: u8) -> Kiss {
+        Kiss { port: port & 0x0F }
+    }
+
+    /// Encode a command frame, such as `TX_DELAY`, for the codec's port
+    pub fn encode_command(&self, command: u8, data: &[u8]) -> Vec<u8> {
+        let mut output = Vec::with_capacity(data.len() + 3);
+        output.push(FEND);
+        escape_slip(&[(self.port << 4) | (command & 0x0F)], &mut output);
+        escape_slip(data, &mut output);
+        output.push(FEND);
+        output
+    }
+}
+
+impl Codec for Kiss {
+    fn encode(&self, frame: &[u8]) -> Vec<u8> {
+        self.encode_command(DATA_FRAME, frame)
+    }
+
+    fn decode<'a>(&self, input: &'a [u8]) -> IResult<&'a [u8], Vec<u8>> {
+        let mut input = input;
+        loop {
+            let (rest, raw) = next_frame(input, FEND)?;
+            let mut frame = match unescape_slip(raw) {
+                Some(frame) => frame,
+                None => return frame_error(rest, ERROR_BAD_ESCAPE),
+            };
+            if frame.is_empty() {
+                return frame_error(rest, ERROR_TOO_SHORT);
+            }
+
+            if frame[0] == (self.port << 4) | DATA_FRAME {
+                frame.remove(0);
+                return Ok((rest, frame));
+            }
+            input = rest;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nom::{self, Needed};
+
+    #[test]
+    fn round_trip() {
+        let kiss = Kiss::new(2);
+        let frame = [FEND, 0x01, FESC, 0x02];
+        let encoded = kiss.encode(&frame);
+        assert_eq!(
+            encoded,
+            vec![FEND, 0x20, FESC, TFEND, 0x01, FESC, TFESC, 0x02, FEND]
+        );
+        assert_eq!(kiss.decode(&encoded), Ok((&[][..], frame.to_vec())));
+    }
+
+    #[test]
+    fn skips_other_frames() {
+        let kiss = Kiss::new(1);
+        let mut data = kiss.encode_command(TX_DELAY, &[50]);
+        data.extend(Kiss::new(0).encode(b"other port"));
+        data.extend(kiss.encode(b"data"));
+
+        assert_eq!(kiss.decode(&data), Ok((&[][..], b"data".to_vec())));
+        assert_eq!(
+            kiss.decode(&kiss.encode_command(TX_DELAY, &[50])),
+            Err(nom::Err::Incomplete(Needed::Unknown))
+        );
+    }
+
+    #[test]
+    fn escaped_type_byte() {
+        // Port 12 with command 0 gives a type byte of FEND, which must be escaped
+        let kiss = Kiss::new(12);
+        let encoded = kiss.encode(b"data");
+        assert_eq!(&encoded[..3], &[FEND, FESC, TFEND]);
+        assert_eq!(kiss.decode(&encoded), Ok((&[][..], b"data".to_vec())));
+    }
+}
@@ -0,0 +1,109 @@
+/*
+* Copyright (C) 2018 Kubos Corporation
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+*     http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*/
+
+//! Framing codecs which mark where each frame starts and ends in a stream of bytes.
+//!
+//! Each codec's `decode` is a nom parser, so it can be passed to `Connection::read` like any
+//! other parser, and a bad frame is reported with one of the error codes below.
+
+use nom::{self, Context, ErrorKind, IResult, Needed};
+
+pub mod hdlc;
+pub mod kiss;
+pub mod slip;
+
+pub use self::hdlc::Hdlc;
+pub use self::kiss::Kiss;
+pub use self::slip::Slip;
+
+/// Error code reported when an escape byte is followed by a byte which can't be escaped
+pub const ERROR_BAD_ESCAPE: u32 = 1;
+/// Error code reported when a frame's checksum doesn't match its contents
+pub const ERROR_BAD_CHECKSUM: u32 = 2;
+/// Error code reported when a frame is too short to hold its header or checksum
+pub const ERROR_TOO_SHORT: u32 = 3;
+
+/// A way of framing data sent over a byte stream
+pub trait Codec {
+    /// Wrap a frame so it can be written to a stream
+    fn encode(&self, frame: &[u8]) -> Vec<u8>;
+
+    /// Parse the next frame from the start of `input`, returning the input which follows it.
+    /// Returns `Incomplete` until the whole frame has been received
+    fn decode<'a>(&self, input: &'a [u8]) -> IResult<&'a [u8], Vec<u8>>;
+}
+
+/// A description of a framing error code
+pub fn error_description(code: u32) -> &'static str {
+    match code {
+        ERROR_BAD_ESCAPE => "Invalid escape sequence",
+        ERROR_BAD_CHECKSUM => "Checksum mismatch",
+        ERROR_TOO_SHORT => "Frame too short",
+        _ => "Unknown framing error",
+    }
+}
+
+// Find the next non-empty frame between delimiters, returning its raw (still escaped) contents
+// along with the input which follows its closing delimiter. Bytes before the first delimiter
+// are treated as a frame, since the sender may not send an opening delimiter
+fn next_frame(input: &[u8], delimiter: u8) -> IResult<&[u8], &[u8]> {
+    let start = input
+        .iter()
+        .position(|byte| *byte != delimiter)
+        .ok_or(nom::Err::Incomplete(Needed::Unknown))?;
+    let len = input[start..]
+        .iter()
+        .position(|byte| *byte == delimiter)
+        .ok_or(nom::Err::Incomplete(Needed::Unknown))?;
+
+    Ok((&input[start + len + 1..], &input[start..start + len]))
+}
+
+// A parse error which points past the bad frame, so that it can be skipped
+fn frame_error<T>(rest: &[u8], code: u32) -> IResult<&[u8], T> {
+    Err(nom::Err::Error(Context::Code(
+        rest,
+        ErrorKind::Custom(code),
+    )))
+}
+
+// Escape a frame's delimiter and escape bytes, SLIP style. This is shared by SLIP and KISS
+fn escape_slip(frame: &[u8], output: &mut Vec<u8>) {
+    for byte in frame {
+        match *byte {
+            slip::END => output.extend_from_slice(&[slip::ESC, slip::ESC_END]),
+            slip::ESC => output.extend_from_slice(&[slip::ESC, slip::ESC_ESC]),
+            byte => output.push(byte),
+        }
+    }
+}
+
+// Undo SLIP style escaping, returning `None` if an escape sequence is invalid
+fn unescape_slip(raw: &[u8]) -> Option<Vec<u8>> {
+    let mut frame = Vec::with_capacity(raw.len());
+    let mut bytes = raw.iter();
+    while let Some(byte) = bytes.next() {
+        match *byte {
+            slip::ESC => match bytes.next() {
+                Some(&slip::ESC_END) => frame.push(slip::END),
+                Some(&slip::ESC_ESC) => frame.push(slip::ESC),
+                _ => return None,
+            },
+            byte => frame.push(byte),
+        }
+    }
+    Some(frame)
+}
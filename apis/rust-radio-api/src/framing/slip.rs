@@ -0,0 +1,99 @@
+/*
+* Copyright (C) 2018 Kubos Corporation
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+*     http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*/
+
+//! Serial Line Internet Protocol framing (RFC 1055)
+
+use super::{escape_slip, frame_error, next_frame, unescape_slip, Codec, ERROR_BAD_ESCAPE};
+use nom::IResult;
+
+/// Marks the end of a frame
+pub const END: u8 = 0xC0;
+/// Starts an escape sequence
+pub const ESC: u8 = 0xDB;
+/// Follows `ESC` in place of an `END` byte within a frame
+pub const ESC_END: u8 = 0xDC;
+/// Follows `ESC` in place of an `ESC` byte within a frame
+pub const ESC_ESC: u8 = 0xDD;
+
+/// SLIP framing. Each frame is sent between `END` bytes, with any `END` or `ESC` bytes within
+/// the frame escaped
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Slip;
+
+impl Codec for Slip {
+    fn encode(&self, frame: &[u8]) -> Vec<u8> {
+        let mut output = Vec::with_capacity(frame.len() + 2);
+        output.push(END);
+        escape_slip(frame, &mut output);
+        output.push(END);
+        output
+    }
+
+    fn decode<'a>(&self, input: &'a [u8]) -> IResult<&'a [u8], Vec<u8>> {
+        let (rest, raw) = next_frame(input, END)?;
+        match unescape_slip(raw) {
+            Some(frame) => Ok((rest, frame)),
+            None => frame_error(rest, ERROR_BAD_ESCAPE),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use framing::ERROR_BAD_ESCAPE;
+    use nom::{self, Context, ErrorKind, Needed};
+
+    #[test]
+    fn round_trip() {
+        let frame = [0x01, END, 0x02, ESC, 0x03, ESC_END, ESC_ESC];
+        let encoded = Slip.encode(&frame);
+        assert_eq!(
+            encoded,
+            vec![END, 0x01, ESC, ESC_END, 0x02, ESC, ESC_ESC, 0x03, ESC_END, ESC_ESC, END]
+        );
+        assert_eq!(Slip.decode(&encoded), Ok((&[][..], frame.to_vec())));
+    }
+
+    #[test]
+    fn decodes_back_to_back_frames() {
+        let mut data = Slip.encode(b"first");
+        data.extend(Slip.encode(b"second"));
+        data.extend_from_slice(&[END, 0x01]);
+
+        let (rest, first) = Slip.decode(&data).unwrap();
+        assert_eq!(first, b"first");
+        let (rest, second) = Slip.decode(rest).unwrap();
+        assert_eq!(second, b"second");
+        assert_eq!(rest, &[END, 0x01]);
+        assert_eq!(
+            Slip.decode(rest),
+            Err(nom::Err::Incomplete(Needed::Unknown))
+        );
+    }
+
+    #[test]
+    fn bad_escape() {
+        let data = [END, 0x01, ESC, 0x02, END, 0x03];
+        assert_eq!(
+            Slip.decode(&data),
+            Err(nom::Err::Error(Context::Code(
+                &[0x03][..],
+                ErrorKind::Custom(ERROR_BAD_ESCAPE)
+            )))
+        );
+    }
+}
@@ -15,20 +15,33 @@
 */
 
 //! A high level interface for interacting with radios
+//!
+//! Radio drivers implement the `Radio` trait, so the communication path can send and receive
+//! frames without knowing which radio it's using. Drivers which talk to their radio over a byte
+//! stream can use `Connection` to parse its responses, and the codecs in `framing` to mark where
+//! each frame starts and ends.
 
 #![deny(missing_docs)]
 
+extern crate crc16;
 #[macro_use]
 extern crate failure;
 extern crate nom;
 
+pub mod framing;
+mod radio;
+
+pub use radio::{FrameCounts, FramedRadio, LinkStatus, Radio};
+
 use nom::IResult;
 use std::cell::RefCell;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use failure::Error;
 
 /// Common Error for Radio Actions
-#[derive(Debug, Fail)]
+#[derive(Debug, Fail, PartialEq)]
 pub enum RadioError {
     #[fail(display = "Parse error: {}", message)]
     /// There was a problem parsing the result data
@@ -36,20 +49,25 @@ pub enum RadioError {
         /// The message from original error
         message: String,
     },
+    #[fail(display = "Timed out waiting for data from the radio")]
+    /// The data being read didn't arrive before the deadline
+    Timeout,
+    #[fail(display = "Receive buffer overflowed with {} bytes", size)]
+    /// More data was received than fits in the receive buffer without it being parsed
+    BufferOverflow {
+        /// The size of the buffer when it overflowed
+        size: usize,
+    },
 }
 
-fn nom_to_radio_error<T>(err: nom::Err<&[u8]>) -> Result<(&[u8], T), RadioError> {
-    Err(match err {
-        nom::Err::Error(nom::simple_errors::Context::Code(_, e)) => RadioError::ParseError {
-            message: e.description().to_string(),
-        },
-        nom::Err::Failure(nom::simple_errors::Context::Code(_, e)) => RadioError::ParseError {
-            message: e.description().to_string(),
+// Describe why parsing failed
+fn parse_error(kind: &nom::ErrorKind) -> RadioError {
+    RadioError::ParseError {
+        message: match *kind {
+            nom::ErrorKind::Custom(code) => framing::error_description(code).to_string(),
+            ref kind => kind.description().to_string(),
         },
-        nom::Err::Incomplete(_) => RadioError::ParseError {
-            message: "Incomplete data".to_string(),
-        },
-    })
+    }
 }
 
 /// Custom error type for radio operations.
@@ -58,11 +76,20 @@ pub type RadioResult<T> = Result<T, Error>;
 /// The signature of parse functions used in Connection read calls.
 pub type ParseFn<T> = fn(input: &[u8]) -> IResult<&[u8], T>;
 
+/// How long `Connection::read` waits for data by default
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+/// The most data `Connection` buffers while waiting for it to be parsed by default
+pub const DEFAULT_MAX_BUFFER: usize = 1 << 20;
+
+// How long to wait before reading again when the stream has no data
+const IDLE_WAIT: Duration = Duration::from_millis(10);
+
 /// Connections expect a struct instance with this trait to represent streams.
 pub trait Stream {
     /// Write raw bytes to the stream.
     fn write(&self, data: &[u8]) -> RadioResult<()>;
-    /// Read raw bytes from the stream.
+    /// Read raw bytes from the stream. Reads should return, possibly with no data, after a
+    /// short time so that `Connection::read` can stop waiting once its deadline has passed.
     fn read(&self) -> RadioResult<Vec<u8>>;
 }
 
@@ -71,6 +98,8 @@ pub trait Stream {
 pub struct Connection {
     stream: Box<Stream>,
     buffer: RefCell<Vec<u8>>,
+    timeout: Duration,
+    max_buffer: usize,
 }
 
 impl Connection {
@@ -79,31 +108,208 @@ impl Connection {
         Connection {
             stream,
             buffer: RefCell::new(Vec::new()),
+            timeout: DEFAULT_TIMEOUT,
+            max_buffer: DEFAULT_MAX_BUFFER,
         }
     }
 
+    /// Set how long `read` waits for a complete object to arrive.
+    pub fn with_timeout(self, timeout: Duration) -> Connection {
+        Connection { timeout, ..self }
+    }
+
+    /// Set the most data which may be buffered while waiting for a complete object. This must be
+    /// at least as large as the largest object which will be read.
+    pub fn with_max_buffer(self, max_buffer: usize) -> Connection {
+        Connection { max_buffer, ..self }
+    }
+
     /// Write out raw bytes to the underlying stream.
     pub fn write(&self, data: &[u8]) -> RadioResult<()> {
         self.stream.write(data)
     }
 
-    /// Read the next object using provided parser.
-    pub fn read<T>(&self, parse: ParseFn<T>) -> RadioResult<T> {
+    /// Read the next object using provided parser, waiting no longer than the connection's
+    /// timeout for it to arrive.
+    pub fn read<T, F>(&self, parse: F) -> RadioResult<T>
+    where
+        F: Fn(&[u8]) -> IResult<&[u8], T>,
+    {
+        self.read_until(parse, Instant::now() + self.timeout)
+    }
+
+    /// Read the next object using provided parser, giving up once `deadline` has passed.
+    ///
+    /// When the buffered data can't be parsed, the data the parser rejected (or at least one
+    /// byte of it) is discarded so that the next read starts afresh, and a `ParseError` is
+    /// returned. If the buffer fills up without an object being parsed, it's emptied and a
+    /// `BufferOverflow` error is returned. If the deadline passes, a `Timeout` error is returned
+    /// and the data received so far is kept for the next read.
+    pub fn read_until<T, F>(&self, parse: F, deadline: Instant) -> RadioResult<T>
+    where
+        F: Fn(&[u8]) -> IResult<&[u8], T>,
+    {
         let mut buffer = self.buffer.borrow_mut();
         loop {
-            let copy = buffer.clone();
-            let res = parse(&copy);
+            let parsed = match parse(&buffer) {
+                Ok((rest, value)) => Some((buffer.len() - rest.len(), Ok(value))),
+                Err(nom::Err::Error(nom::Context::Code(rest, kind)))
+                | Err(nom::Err::Failure(nom::Context::Code(rest, kind))) => Some((
+                    buffer.len().saturating_sub(rest.len()).max(1),
+                    Err(parse_error(&kind)),
+                )),
+                Err(nom::Err::Incomplete(_)) => None,
+            };
+            if let Some((consumed, result)) = parsed {
+                let consumed = consumed.min(buffer.len());
+                buffer.drain(..consumed);
+                return result.map_err(|err| err.into());
+            }
 
-            if let Err(nom::Err::Incomplete(_)) = res {
-                let more = self.stream.read()?;
-                buffer.extend_from_slice(&more);
-                continue;
+            if buffer.len() >= self.max_buffer {
+                let size = buffer.len();
+                buffer.clear();
+                return Err(RadioError::BufferOverflow { size }.into());
             }
+            if Instant::now() >= deadline {
+                return Err(RadioError::Timeout.into());
+            }
+
+            let more = self.stream.read()?;
+            if more.is_empty() {
+                thread::sleep(IDLE_WAIT);
+            }
+            buffer.extend_from_slice(&more);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use framing::{Codec, Hdlc};
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+
+    // A stream which returns queued chunks of data, and nothing once they run out
+    #[derive(Clone, Default)]
+    struct ChunkStream {
+        chunks: Rc<RefCell<VecDeque<Vec<u8>>>>,
+    }
+
+    impl ChunkStream {
+        fn push(&self, chunk: &[u8]) {
+            self.chunks.borrow_mut().push_back(chunk.to_vec());
+        }
+    }
+
+    impl Stream for ChunkStream {
+        fn write(&self, _: &[u8]) -> RadioResult<()> {
+            Ok(())
+        }
 
-            let (extra, value) = res.or_else(nom_to_radio_error)?;
-            buffer.clear();
-            buffer.extend_from_slice(extra);
-            return Ok(value);
+        fn read(&self) -> RadioResult<Vec<u8>> {
+            Ok(self.chunks.borrow_mut().pop_front().unwrap_or_default())
         }
     }
+
+    fn connection(stream: &ChunkStream) -> Connection {
+        Connection::new(Box::new(stream.clone())).with_timeout(Duration::from_millis(50))
+    }
+
+    fn radio_error<T: ::std::fmt::Debug>(result: RadioResult<T>) -> RadioError {
+        result.unwrap_err().downcast::<RadioError>().unwrap()
+    }
+
+    fn decode(input: &[u8]) -> IResult<&[u8], Vec<u8>> {
+        Hdlc.decode(input)
+    }
+
+    #[test]
+    fn timeout_keeps_partial_data() {
+        let stream = ChunkStream::default();
+        let conn = connection(&stream);
+        let encoded = Hdlc.encode(b"frame");
+        stream.push(&encoded[..4]);
+
+        let start = Instant::now();
+        assert_eq!(radio_error(conn.read(decode)), RadioError::Timeout);
+        assert!(start.elapsed() >= Duration::from_millis(50));
+
+        stream.push(&encoded[4..]);
+        assert_eq!(conn.read(decode).unwrap(), b"frame");
+    }
+
+    #[test]
+    fn read_until_deadline() {
+        let stream = ChunkStream::default();
+        let conn = Connection::new(Box::new(stream.clone()));
+
+        let start = Instant::now();
+        let result = conn.read_until(decode, Instant::now() + Duration::from_millis(20));
+        assert_eq!(radio_error(result), RadioError::Timeout);
+        assert!(start.elapsed() < DEFAULT_TIMEOUT);
+    }
+
+    #[test]
+    fn buffer_overflow() {
+        let stream = ChunkStream::default();
+        let conn = connection(&stream).with_max_buffer(16);
+        for _ in 0..2 {
+            stream.push(&[0x01; 8]);
+        }
+
+        assert_eq!(
+            radio_error(conn.read(decode)),
+            RadioError::BufferOverflow { size: 16 }
+        );
+
+        // The buffer was emptied, so a frame which fits can still be read
+        stream.push(&Hdlc.encode(b"frame"));
+        assert_eq!(conn.read(decode).unwrap(), b"frame");
+    }
+
+    #[test]
+    fn resync_after_parse_error() {
+        let stream = ChunkStream::default();
+        let conn = connection(&stream);
+        let mut corrupt = Hdlc.encode(b"corrupt");
+        corrupt[3] ^= 0x01;
+        stream.push(&corrupt);
+        stream.push(&Hdlc.encode(b"frame"));
+
+        assert_eq!(
+            radio_error(conn.read(decode)),
+            RadioError::ParseError {
+                message: "Checksum mismatch".to_owned(),
+            }
+        );
+        assert_eq!(conn.read(decode).unwrap(), b"frame");
+    }
+
+    #[test]
+    fn resync_discards_rejected_bytes() {
+        fn parse_ok(input: &[u8]) -> IResult<&[u8], u8> {
+            match input.first() {
+                None => Err(nom::Err::Incomplete(nom::Needed::Size(1))),
+                Some(b'+') => Ok((&input[1..], b'+')),
+                Some(_) => Err(nom::Err::Error(nom::Context::Code(
+                    input,
+                    nom::ErrorKind::Char,
+                ))),
+            }
+        }
+
+        let stream = ChunkStream::default();
+        let conn = connection(&stream);
+        stream.push(b"xy+");
+
+        // Parsers which don't say how much to skip have one byte discarded at a time
+        let error = RadioError::ParseError {
+            message: "Char".to_owned(),
+        };
+        assert_eq!(radio_error(conn.read(parse_ok)), error);
+        assert_eq!(radio_error(conn.read(parse_ok)), error);
+        assert_eq!(conn.read(parse_ok).unwrap(), b'+');
+    }
 }
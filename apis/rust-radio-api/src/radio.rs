@@ -0,0 +1,172 @@
+/*
+* Copyright (C) 2018 Kubos Corporation
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+*     http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*/
+
+//! A common interface for radio drivers
+
+use framing::Codec;
+use std::cell::Cell;
+use {Connection, RadioResult};
+
+/// The state of a radio's link
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LinkStatus {
+    /// The radio can reach the other end of the link
+    Up,
+    /// The radio can't reach the other end of the link
+    Down,
+    /// The radio can't tell whether the link is up
+    Unknown,
+}
+
+/// Operations common to all radios, so the communication path doesn't need to know which radio
+/// it's using
+pub trait Radio {
+    /// The telemetry reported by the radio
+    type Telemetry;
+
+    /// Send a frame over the link
+    fn send(&self, frame: &[u8]) -> RadioResult<()>;
+    /// Receive the next frame from the link
+    fn receive(&self) -> RadioResult<Vec<u8>>;
+    /// Check the state of the link
+    fn link_status(&self) -> RadioResult<LinkStatus>;
+    /// Fetch the radio's telemetry
+    fn telemetry(&self) -> RadioResult<Self::Telemetry>;
+}
+
+/// Counts of the frames passed through a `FramedRadio`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FrameCounts {
+    /// Frames sent
+    pub sent: u64,
+    /// Frames received
+    pub received: u64,
+}
+
+/// A radio which transparently passes bytes between its serial interface and the link, such as
+/// a KISS TNC. Frames are wrapped with a framing codec
+pub struct FramedRadio<C: Codec> {
+    conn: Connection,
+    codec: C,
+    counts: Cell<FrameCounts>,
+}
+
+impl<C: Codec> FramedRadio<C> {
+    /// Create a radio which sends and receives frames over a connection using `codec`
+    pub fn new(conn: Connection, codec: C) -> FramedRadio<C> {
+        FramedRadio {
+            conn,
+            codec,
+            counts: Cell::new(FrameCounts::default()),
+        }
+    }
+}
+
+impl<C: Codec> Radio for FramedRadio<C> {
+    type Telemetry = FrameCounts;
+
+    fn send(&self, frame: &[u8]) -> RadioResult<()> {
+        self.conn.write(&self.codec.encode(frame))?;
+        let mut counts = self.counts.get();
+        counts.sent += 1;
+        self.counts.set(counts);
+        Ok(())
+    }
+
+    fn receive(&self) -> RadioResult<Vec<u8>> {
+        let frame = self.conn.read(|input| self.codec.decode(input))?;
+        let mut counts = self.counts.get();
+        counts.received += 1;
+        self.counts.set(counts);
+        Ok(frame)
+    }
+
+    fn link_status(&self) -> RadioResult<LinkStatus> {
+        Ok(LinkStatus::Unknown)
+    }
+
+    fn telemetry(&self) -> RadioResult<FrameCounts> {
+        Ok(self.counts.get())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use framing::{Hdlc, Kiss, Slip};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use Stream;
+
+    // A stream which reads back whatever was written to it, a few bytes at a time
+    struct LoopbackStream {
+        data: Rc<RefCell<Vec<u8>>>,
+    }
+
+    impl Stream for LoopbackStream {
+        fn write(&self, data: &[u8]) -> RadioResult<()> {
+            self.data.borrow_mut().extend_from_slice(data);
+            Ok(())
+        }
+
+        fn read(&self) -> RadioResult<Vec<u8>> {
+            let mut data = self.data.borrow_mut();
+            let len = data.len().min(3);
+            Ok(data.drain(..len).collect())
+        }
+    }
+
+    fn loopback<C: Codec>(codec: C) -> FramedRadio<C> {
+        let stream = LoopbackStream {
+            data: Rc::new(RefCell::new(vec![])),
+        };
+        FramedRadio::new(Connection::new(Box::new(stream)), codec)
+    }
+
+    fn round_trip<C: Codec>(radio: &FramedRadio<C>) {
+        let frames: Vec<Vec<u8>> = vec![b"first".to_vec(), (0..=255).collect(), vec![0xC0; 4]];
+        for frame in &frames {
+            radio.send(frame).unwrap();
+        }
+        for frame in &frames {
+            assert_eq!(&radio.receive().unwrap(), frame);
+        }
+        assert_eq!(
+            radio.telemetry().unwrap(),
+            FrameCounts {
+                sent: 3,
+                received: 3
+            }
+        );
+    }
+
+    #[test]
+    fn slip_round_trip() {
+        round_trip(&loopback(Slip));
+    }
+
+    #[test]
+    fn kiss_round_trip() {
+        round_trip(&loopback(Kiss::new(0)));
+    }
+
+    #[test]
+    fn hdlc_round_trip() {
+        let radio = loopback(Hdlc);
+        round_trip(&radio);
+        assert_eq!(radio.link_status().unwrap(), LinkStatus::Unknown);
+    }
+}
@@ -78,6 +78,7 @@ impl Address {
 /// `"127.0.0.1"` and default port `8080` are used instead.
 #[derive(Debug)]
 pub struct Config {
+    name: String,
     addr: Address,
     raw: Value,
 }
@@ -85,6 +86,7 @@ pub struct Config {
 impl Default for Config {
     fn default() -> Self {
         Config {
+            name: "".to_string(),
             addr: Address::default(),
             raw: Value::String("".to_string()),
         }
@@ -108,7 +110,7 @@ impl Config {
     /// `name` - Category name used as a key in the config file
     /// `path` - Path to configuration file
     pub fn new_from_path(name: &str, path: String) -> Self {
-        parse_config_file(name, path).unwrap_or(Config::default())
+        parse_config_file(name, path).unwrap_or(Config::named(name))
     }
 
     /// Creates and parses configuration data from the passed in configuration
@@ -117,7 +119,19 @@ impl Config {
     /// `name` - Category name used as a key in the config
     /// `config` - Config data as a string
     pub fn new_from_str(name: &str, config: &str) -> Self {
-        parse_config_str(name, config).unwrap_or(Config::default())
+        parse_config_str(name, config).unwrap_or(Config::named(name))
+    }
+
+    fn named(name: &str) -> Self {
+        Config {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Returns the category name the configuration was read from
+    pub fn name(&self) -> &str {
+        &self.name
     }
 
     /// Returns the configured hosturl string in the following
@@ -188,7 +202,7 @@ fn parse_config_file(name: &str, path: String) -> Result<Config, toml::de::Error
 
 fn parse_config_str(name: &str, contents: &str) -> Result<Config, toml::de::Error> {
     let data: Value = toml::from_str(&contents)?;
-    let mut config = Config::default();
+    let mut config = Config::named(name);
 
     if let Some(data) = data.get(name) {
         if let Some(address) = data.get("addr") {
@@ -54,6 +54,7 @@ fn new_from_str() {
     "#,
     );
 
+    assert_eq!(config.name(), "category-1");
     assert_eq!(config.get("a"), Some(Value::Integer(1)));
     assert_eq!(config.get("b"), Some(Value::Integer(2)));
     assert_eq!(
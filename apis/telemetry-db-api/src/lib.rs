@@ -15,21 +15,212 @@
 //
 #[macro_use]
 extern crate diesel;
+#[macro_use]
+extern crate failure;
+extern crate libc;
+extern crate libsqlite3_sys;
+#[macro_use]
+extern crate log;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
 extern crate time;
 
+pub mod limits;
+pub mod migrations;
 pub mod models;
+pub use limits::{AlarmLevel, Limit, LimitChecker};
+pub use migrations::{Migration, MIGRATIONS};
 pub use models::*;
 
-use diesel::dsl::sql;
+use diesel::connection::SimpleConnection;
 use diesel::insert_into;
 use diesel::prelude::*;
 use diesel::sql_query;
-use diesel::sql_types::Bool;
+use diesel::sql_types::Text;
 use diesel::sqlite::SqliteConnection;
 use diesel::*;
+use libsqlite3_sys as ffi;
+use std::ffi::CString;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::ptr;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How long `Database::open` waits for other connections to the same file to release their
+/// locks, in milliseconds
+pub const OPEN_BUSY_TIMEOUT: u64 = 5000;
+
+/// SQLite journal modes which may be selected with `Database::set_journal_mode`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum JournalMode {
+    /// Rollback journal, deleted at the end of each transaction (SQLite's default)
+    Delete,
+    /// Rollback journal, truncated at the end of each transaction
+    Truncate,
+    /// Rollback journal, header zeroed at the end of each transaction
+    Persist,
+    /// Rollback journal kept in memory
+    Memory,
+    /// Write-ahead log
+    Wal,
+    /// No journal. Transactions can't be rolled back
+    Off,
+}
+
+impl JournalMode {
+    fn as_str(&self) -> &'static str {
+        match *self {
+            JournalMode::Delete => "DELETE",
+            JournalMode::Truncate => "TRUNCATE",
+            JournalMode::Persist => "PERSIST",
+            JournalMode::Memory => "MEMORY",
+            JournalMode::Wal => "WAL",
+            JournalMode::Off => "OFF",
+        }
+    }
+}
+
+impl FromStr for JournalMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_uppercase().as_str() {
+            "DELETE" => Ok(JournalMode::Delete),
+            "TRUNCATE" => Ok(JournalMode::Truncate),
+            "PERSIST" => Ok(JournalMode::Persist),
+            "MEMORY" => Ok(JournalMode::Memory),
+            "WAL" => Ok(JournalMode::Wal),
+            "OFF" => Ok(JournalMode::Off),
+            _ => Err(format!("Unknown journal mode: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for JournalMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// SQLite synchronous levels which may be selected with `Database::set_synchronous`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Synchronous {
+    /// Hand data to the OS and continue without syncing
+    Off,
+    /// Sync at the most critical moments. Safe in WAL mode, fewer flash writes than `Full`
+    Normal,
+    /// Sync after every transaction (SQLite's default)
+    Full,
+    /// Like `Full`, but also syncs the directory after deleting a rollback journal
+    Extra,
+}
+
+impl Synchronous {
+    fn as_str(&self) -> &'static str {
+        match *self {
+            Synchronous::Off => "OFF",
+            Synchronous::Normal => "NORMAL",
+            Synchronous::Full => "FULL",
+            Synchronous::Extra => "EXTRA",
+        }
+    }
+}
+
+impl FromStr for Synchronous {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_uppercase().as_str() {
+            "OFF" | "0" => Ok(Synchronous::Off),
+            "NORMAL" | "1" => Ok(Synchronous::Normal),
+            "FULL" | "2" => Ok(Synchronous::Full),
+            "EXTRA" | "3" => Ok(Synchronous::Extra),
+            _ => Err(format!("Unknown synchronous level: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Synchronous {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Errors which can occur while opening or preparing the database
+#[derive(Debug, Fail)]
+pub enum DatabaseError {
+    /// The database file could not be opened
+    #[fail(display = "Failed to connect to database {}: {}", path, cause)]
+    ConnectionError {
+        /// Path to the database file
+        path: String,
+        /// Underlying error
+        cause: String,
+    },
+    /// A query against the database failed
+    #[fail(display = "Database query failed: {}", _0)]
+    QueryError(#[cause] diesel::result::Error),
+    /// A schema upgrade step failed
+    #[fail(display = "Failed to migrate database to schema version {}: {}", version, cause)]
+    MigrationError {
+        /// Version the failed step would have upgraded the database to
+        version: i32,
+        /// Underlying error
+        #[cause]
+        cause: diesel::result::Error,
+    },
+    /// The database was created by newer software than this
+    #[fail(
+        display = "Database schema version {} is newer than the latest supported version {}",
+        found,
+        supported
+    )]
+    UnsupportedVersion {
+        /// Version found in the database
+        found: i32,
+        /// Latest version known to this library
+        supported: i32,
+    },
+    /// A corrupted database file could not be moved out of the way
+    #[fail(display = "Failed to quarantine corrupted database {}: {}", path, cause)]
+    QuarantineError {
+        /// Path to the database file
+        path: String,
+        /// Underlying error
+        cause: String,
+    },
+}
+
+impl From<diesel::result::Error> for DatabaseError {
+    fn from(error: diesel::result::Error) -> Self {
+        DatabaseError::QueryError(error)
+    }
+}
+
+/// Result type used by the database setup functions
+pub type DatabaseResult<T> = Result<T, DatabaseError>;
+
+/// Summary of the checks and repairs performed by `Database::open`
+#[derive(Clone, Debug, Default)]
+pub struct OpenReport {
+    /// Problems reported by the integrity check. Empty if the database was healthy
+    pub problems: Vec<String>,
+    /// Location the corrupted database file was moved to, if it had to be recreated
+    pub quarantined: Option<String>,
+    /// Schema version of the database before it was upgraded
+    pub previous_version: i32,
+    /// Schema version of the database now
+    pub version: i32,
+}
 
 pub struct Database {
     pub connection: SqliteConnection,
+    path: String,
 }
 
 impl Database {
@@ -37,54 +228,154 @@ impl Database {
     ///
     /// # Arguments
     /// `path` - Path to database file
+    pub fn new(path: &str) -> DatabaseResult<Self> {
+        if !Path::new(path).exists() {
+            info!("Creating database {}", path);
+        }
+
+        let connection =
+            SqliteConnection::establish(path).map_err(|err| DatabaseError::ConnectionError {
+                path: path.to_owned(),
+                cause: err.to_string(),
+            })?;
+
+        Ok(Database {
+            connection,
+            path: path.to_owned(),
+        })
+    }
+
+    /// Open a database, making sure it is usable.
     ///
-    /// # Panics
+    /// The database's integrity is checked first. If it is corrupted, the file is renamed to
+    /// `<path>.corrupt.<timestamp>` (so it can still be downlinked for analysis) and a new,
+    /// empty database is created in its place. The schema is then brought up to date.
+    /// `<path>.lock` is locked while the database is being opened, so other processes opening
+    /// the same database wait until it's ready. Processes which already have the database open
+    /// may still be writing to it, so the checks wait up to `OPEN_BUSY_TIMEOUT` for their locks.
+    /// The returned connection keeps that busy timeout until `set_busy_timeout` is called.
     ///
-    /// Attempts to connect to sqlite database and will `panic!` if connection fails.
-    pub fn new(path: &str) -> Self {
-        if !::std::path::Path::new(path).exists() {
-            println!("Creating database {}", path);
+    /// # Arguments
+    /// `path` - Path to database file
+    pub fn open(path: &str) -> DatabaseResult<(Self, OpenReport)> {
+        // Several processes may open the same database at once. Only one of them may check,
+        // repair and upgrade it at a time, or they could each move it aside or apply the same
+        // migration
+        let _lock = lock(path)?;
+        let mut report = OpenReport::default();
+
+        let mut database = Database::new(path)?;
+        database.set_busy_timeout(Duration::from_millis(OPEN_BUSY_TIMEOUT))?;
+        report.problems = database.check_integrity()?;
+
+        if !report.problems.is_empty() {
+            warn!(
+                "Database {} is corrupted: {}",
+                path,
+                report.problems.join(", ")
+            );
+
+            // Close the connection before moving the file out from under it
+            drop(database);
+            report.quarantined = Some(quarantine(path)?);
+            database = Database::new(path)?;
+            database.set_busy_timeout(Duration::from_millis(OPEN_BUSY_TIMEOUT))?;
         }
-        Database {
-            connection: SqliteConnection::establish(&String::from(path)).expect(&format!(
-                "Could not create SQLite database connection to: {}",
-                path
-            )),
+
+        report.previous_version = database.schema_version()?;
+        database.setup()?;
+        report.version = database.schema_version()?;
+
+        Ok((database, report))
+    }
+
+    /// Path to the database file
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Run SQLite's integrity check against the database.
+    ///
+    /// Returns the problems found, or an empty list if the database is healthy. A file which
+    /// isn't a SQLite database at all is reported as a problem rather than an error.
+    pub fn check_integrity(&self) -> DatabaseResult<Vec<String>> {
+        match sql_query("PRAGMA integrity_check").load::<IntegrityCheck>(&self.connection) {
+            Ok(rows) => Ok(rows
+                .into_iter()
+                .map(|row| row.integrity_check)
+                .filter(|message| message != "ok")
+                .collect()),
+            Err(err) => match result_code(&self.path, "PRAGMA integrity_check") {
+                ffi::SQLITE_CORRUPT | ffi::SQLITE_NOTADB => Ok(vec![err.to_string()]),
+                _ => Err(err.into()),
+            },
         }
     }
 
-    /// Check if database has correct table and creates table if needed
-    ///
-    /// # Panics
-    ///
-    /// Will `panic!` if fails to locate and/or create telemetry table
-    pub fn setup(&self) {
-        match select(sql::<Bool>(
-            "EXISTS \
-             (SELECT 1 \
-             FROM sqlite_master \
-             WHERE type = 'table' \
-             AND name = 'telemetry')",
-        )).get_result::<bool>(&self.connection)
-        {
-            Err(err) => panic!("Error querying table: {:?}", err),
-            Ok(true) => println!("Table exists"),
-            Ok(false) => {
-                println!("Telemetry table not found. Creating table.");
-                match sql_query(
-                    "CREATE TABLE telemetry (
-                    timestamp INTEGER NOT NULL,
-                    subsystem VARCHAR(255) NOT NULL,
-                    parameter VARCHAR(255) NOT NULL,
-                    value VARCHAR(255) NOT NULL,
-                    PRIMARY KEY (timestamp, subsystem, parameter))",
-                ).execute(&self.connection)
-                {
-                    Ok(_) => println!("Telemetry table created"),
-                    Err(err) => panic!("Error creating table: {:?}", err),
+    /// Current schema version of the database. Databases which predate schema
+    /// versioning report version 0
+    pub fn schema_version(&self) -> DatabaseResult<i32> {
+        migrations::create_version_table(&self.connection)?;
+        Ok(migrations::current_version(&self.connection)?)
+    }
+
+    /// Bring the database schema up to date by applying any migrations which
+    /// haven't been applied yet
+    pub fn setup(&self) -> DatabaseResult<()> {
+        let current = self.schema_version()?;
+        let latest = migrations::latest_version();
+
+        if current > latest {
+            return Err(DatabaseError::UnsupportedVersion {
+                found: current,
+                supported: latest,
+            });
+        }
+
+        for step in MIGRATIONS.iter().filter(|step| step.version > current) {
+            info!(
+                "Migrating database to version {}: {}",
+                step.version, step.description
+            );
+            migrations::apply(&self.connection, step).map_err(|cause| {
+                DatabaseError::MigrationError {
+                    version: step.version,
+                    cause,
                 }
-            }
-        };
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// Set the journal mode used by the database file
+    ///
+    /// # Arguments
+    /// `mode` - Journal mode to use. `JournalMode::Wal` allows readers and a writer to
+    /// work at the same time and reduces the number of writes per transaction
+    pub fn set_journal_mode(&self, mode: JournalMode) -> QueryResult<()> {
+        self.connection
+            .batch_execute(&format!("PRAGMA journal_mode = {}", mode))
+    }
+
+    /// Set how aggressively SQLite syncs data to storage
+    ///
+    /// # Arguments
+    /// `level` - Synchronous level to use
+    pub fn set_synchronous(&self, level: Synchronous) -> QueryResult<()> {
+        self.connection
+            .batch_execute(&format!("PRAGMA synchronous = {}", level))
+    }
+
+    /// Set how long a statement will wait for another connection to release its lock
+    /// before failing. Needed when other processes write to the same database file
+    ///
+    /// # Arguments
+    /// `timeout` - Maximum time to wait for a lock
+    pub fn set_busy_timeout(&self, timeout: Duration) -> QueryResult<()> {
+        let millis = timeout.as_secs() * 1000 + u64::from(timeout.subsec_millis());
+        self.connection
+            .batch_execute(&format!("PRAGMA busy_timeout = {}", millis))
     }
 
     pub fn insert<'a>(
@@ -108,6 +399,102 @@ impl Database {
             .execute(&self.connection)
     }
 
+    /// Insert multiple entries within a single transaction
+    ///
+    /// Either all of the entries are inserted or, if any insert fails, none of them are.
+    ///
+    /// # Arguments
+    /// `entries` - Entries to insert
+    pub fn insert_batch(&self, entries: &[NewEntry]) -> QueryResult<usize> {
+        use self::telemetry;
+
+        self.connection.transaction(|| {
+            let mut count = 0;
+            for entry in entries {
+                count += insert_into(telemetry::table)
+                    .values(entry)
+                    .execute(&self.connection)?;
+            }
+            Ok(count)
+        })
+    }
+
+    /// Insert multiple entries, skipping any whose key is already in the database
+    ///
+    /// The entries are first inserted as a single batch. If an entry in the batch has a
+    /// duplicate key, which rolls back the whole batch, the entries are retried individually.
+    /// A rejected insert only rolls back its own statement, so the rest are still committed
+    /// together.
+    ///
+    /// Any other error (ex. the database being locked or full) is returned, and none of the
+    /// entries are stored, so that the caller can retry them or count them as lost.
+    ///
+    /// Returns the entries which were stored
+    ///
+    /// # Arguments
+    /// `entries` - Entries to insert
+    pub fn insert_batch_partial<'a>(
+        &self,
+        entries: Vec<NewEntry<'a>>,
+    ) -> QueryResult<Vec<NewEntry<'a>>> {
+        match self.insert_batch(&entries) {
+            Ok(_) => return Ok(entries),
+            Err(ref err) if is_duplicate(err) => (),
+            Err(err) => return Err(err),
+        }
+
+        self.connection.transaction(|| {
+            let mut stored = Vec::with_capacity(entries.len());
+            for entry in entries {
+                match self.insert(
+                    entry.timestamp,
+                    entry.subsystem,
+                    entry.parameter,
+                    entry.value,
+                ) {
+                    Ok(_) => stored.push(entry),
+                    Err(ref err) if is_duplicate(err) => (),
+                    Err(err) => return Err(err),
+                }
+            }
+            Ok(stored)
+        })
+    }
+
+    /// Record an alarm transition
+    ///
+    /// # Arguments
+    /// `alarm` - Alarm to insert
+    pub fn insert_alarm(&self, alarm: &NewAlarm) -> QueryResult<usize> {
+        use self::alarms;
+
+        insert_into(alarms::table)
+            .values(alarm)
+            .execute(&self.connection)
+    }
+
+    /// Mark alarms as acknowledged
+    ///
+    /// Returns the number of alarms which were newly acknowledged
+    ///
+    /// # Arguments
+    /// `ids` - IDs of the alarms to acknowledge. If `None`, all unacknowledged alarms are
+    /// acknowledged
+    pub fn acknowledge_alarms(&self, ids: Option<&[i32]>) -> QueryResult<usize> {
+        use self::alarms::dsl;
+
+        let pending = dsl::alarms.filter(dsl::acknowledged.eq(false));
+
+        match ids {
+            Some(ids) => update(pending.filter(dsl::id.eq_any(ids)))
+                .set(dsl::acknowledged.eq(true))
+                .execute(&self.connection),
+            None => update(pending)
+                .set(dsl::acknowledged.eq(true))
+                .execute(&self.connection),
+        }
+    }
+
     pub fn insert_systime<'a>(
         &self,
         subsystem: &'a str,
@@ -127,3 +514,109 @@ table! {
         value -> Text,
     }
 }
+
+table! {
+    alarms (id) {
+        id -> Integer,
+        timestamp -> Integer,
+        subsystem -> Text,
+        parameter -> Text,
+        value -> Text,
+        level -> Text,
+        previous_level -> Text,
+        acknowledged -> Bool,
+    }
+}
+
+table! {
+    schema_version (version) {
+        version -> Integer,
+        description -> Text,
+        applied -> Integer,
+    }
+}
+
+#[derive(QueryableByName)]
+struct IntegrityCheck {
+    #[sql_type = "Text"]
+    integrity_check: String,
+}
+
+// Run a statement on a separate connection to the database, returning SQLite's primary result
+// code. Diesel only reports the message of a failed statement, which isn't a reliable way to
+// tell whether the database is corrupted
+// Whether an insert was rejected because the entry's key is already in the table
+fn is_duplicate(err: &result::Error) -> bool {
+    match *err {
+        result::Error::DatabaseError(result::DatabaseErrorKind::UniqueViolation, _) => true,
+        _ => false,
+    }
+}
+
+fn result_code(path: &str, sql: &str) -> libc::c_int {
+    let (path, sql) = match (CString::new(path), CString::new(sql)) {
+        (Ok(path), Ok(sql)) => (path, sql),
+        _ => return ffi::SQLITE_MISUSE,
+    };
+
+    unsafe {
+        let mut db = ptr::null_mut();
+        let mut code = ffi::sqlite3_open_v2(
+            path.as_ptr(),
+            &mut db,
+            ffi::SQLITE_OPEN_READONLY,
+            ptr::null(),
+        );
+        if code == ffi::SQLITE_OK {
+            code = ffi::sqlite3_exec(db, sql.as_ptr(), None, ptr::null_mut(), ptr::null_mut());
+        }
+        ffi::sqlite3_close(db);
+        code & 0xff
+    }
+}
+
+// Take an exclusive lock on `<path>.lock`, held until the returned file is closed
+fn lock(path: &str) -> DatabaseResult<File> {
+    let error = |cause: io::Error| DatabaseError::ConnectionError {
+        path: path.to_owned(),
+        cause: cause.to_string(),
+    };
+
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .open(format!("{}.lock", path))
+        .map_err(error)?;
+
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+        return Err(error(io::Error::last_os_error()));
+    }
+
+    Ok(file)
+}
+
+// Move a corrupted database (and any journal files belonging to it) out of the way,
+// returning the database's new location
+fn quarantine(path: &str) -> DatabaseResult<String> {
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|dur| dur.as_secs())
+        .unwrap_or(0);
+    let destination = format!("{}.corrupt.{}", path, stamp);
+
+    let error = |cause: io::Error| DatabaseError::QuarantineError {
+        path: path.to_owned(),
+        cause: cause.to_string(),
+    };
+
+    fs::rename(path, &destination).map_err(error)?;
+
+    for suffix in &["-wal", "-shm", "-journal"] {
+        let journal = format!("{}{}", path, suffix);
+        if Path::new(&journal).exists() {
+            fs::rename(&journal, format!("{}{}", destination, suffix)).map_err(error)?;
+        }
+    }
+
+    Ok(destination)
+}
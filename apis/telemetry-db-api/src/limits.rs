@@ -0,0 +1,225 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Checking telemetry values against their configured limits.
+//!
+//! Both the telemetry service and the telemetry collector store telemetry in the database, so
+//! both check the values they store with a `LimitChecker` built from the telemetry service's
+//! `[[telemetry-service.limits]]` config entries. Each parameter's current level is read from
+//! its latest alarm in the database, so the two processes agree on it and don't record the same
+//! transition twice.
+
+use super::alarms::dsl;
+use super::{Database, NewAlarm, NewEntry};
+use diesel::prelude::*;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Severity of a telemetry value relative to its limits
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AlarmLevel {
+    /// Within all limits
+    Nominal,
+    /// Below the yellow low limit
+    YellowLow,
+    /// Above the yellow high limit
+    YellowHigh,
+    /// Below the red low limit
+    RedLow,
+    /// Above the red high limit
+    RedHigh,
+}
+
+impl AlarmLevel {
+    /// Name used for the level in the alarms table
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            AlarmLevel::Nominal => "nominal",
+            AlarmLevel::YellowLow => "yellow_low",
+            AlarmLevel::YellowHigh => "yellow_high",
+            AlarmLevel::RedLow => "red_low",
+            AlarmLevel::RedHigh => "red_high",
+        }
+    }
+}
+
+impl FromStr for AlarmLevel {
+    type Err = String;
+
+    /// Convert a name from the alarms table back into a level
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nominal" => Ok(AlarmLevel::Nominal),
+            "yellow_low" => Ok(AlarmLevel::YellowLow),
+            "yellow_high" => Ok(AlarmLevel::YellowHigh),
+            "red_low" => Ok(AlarmLevel::RedLow),
+            "red_high" => Ok(AlarmLevel::RedHigh),
+            _ => Err(format!("Unknown alarm level: {}", s)),
+        }
+    }
+}
+
+/// Safe range for a single telemetry parameter, read from a `[[telemetry-service.limits]]`
+/// entry in the config file. Any of the limits may be omitted.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Limit {
+    pub subsystem: String,
+    pub parameter: String,
+    pub red_low: Option<f64>,
+    pub yellow_low: Option<f64>,
+    pub yellow_high: Option<f64>,
+    pub red_high: Option<f64>,
+}
+
+impl Limit {
+    /// Determine which level a value falls into. Red limits take precedence over yellow ones
+    pub fn evaluate(&self, value: f64) -> AlarmLevel {
+        let below = |limit: Option<f64>| match limit {
+            Some(limit) => value < limit,
+            None => false,
+        };
+        let above = |limit: Option<f64>| match limit {
+            Some(limit) => value > limit,
+            None => false,
+        };
+
+        if below(self.red_low) {
+            AlarmLevel::RedLow
+        } else if above(self.red_high) {
+            AlarmLevel::RedHigh
+        } else if below(self.yellow_low) {
+            AlarmLevel::YellowLow
+        } else if above(self.yellow_high) {
+            AlarmLevel::YellowHigh
+        } else {
+            AlarmLevel::Nominal
+        }
+    }
+}
+
+type Key = (String, String);
+
+/// Evaluates stored telemetry against the configured limits and records alarm
+/// transitions in the database
+pub struct LimitChecker {
+    limits: HashMap<Key, Limit>,
+}
+
+impl LimitChecker {
+    /// Create a checker for a set of limits
+    pub fn new(limits: Vec<Limit>) -> LimitChecker {
+        LimitChecker {
+            limits: limits
+                .into_iter()
+                .map(|limit| ((limit.subsystem.clone(), limit.parameter.clone()), limit))
+                .collect(),
+        }
+    }
+
+    /// Check newly stored entries against their limits, recording an alarm each time a
+    /// parameter moves to a different level.
+    ///
+    /// The entries are checked while holding the database's write lock, so that another
+    /// process checking the same parameters sees the alarms recorded here. An entry whose alarm
+    /// can't be recorded is logged and skipped.
+    ///
+    /// Returns the number of alarms recorded
+    pub fn check(&self, database: &Database, entries: &[NewEntry]) -> QueryResult<usize> {
+        if self.limits.is_empty() {
+            return Ok(0);
+        }
+
+        database.connection.immediate_transaction(|| {
+            let mut levels: HashMap<Key, AlarmLevel> = HashMap::new();
+            let mut count = 0;
+
+            for entry in entries {
+                let key = (entry.subsystem.to_owned(), entry.parameter.to_owned());
+
+                let limit = match self.limits.get(&key) {
+                    Some(limit) => limit,
+                    None => continue,
+                };
+
+                // Non-numeric values can't be compared against limits
+                let value = match entry.value.trim().parse::<f64>() {
+                    Ok(value) => value,
+                    Err(_) => continue,
+                };
+
+                let previous = match levels.get(&key) {
+                    Some(level) => *level,
+                    None => match current_level(database, &key) {
+                        Ok(level) => level,
+                        Err(err) => {
+                            error!(
+                                "Failed to read alarm level of {}.{}: {}",
+                                entry.subsystem, entry.parameter, err
+                            );
+                            continue;
+                        }
+                    },
+                };
+
+                let level = limit.evaluate(value);
+                if level == previous {
+                    levels.insert(key, level);
+                    continue;
+                }
+
+                let result = database.insert_alarm(&NewAlarm {
+                    timestamp: entry.timestamp,
+                    subsystem: entry.subsystem,
+                    parameter: entry.parameter,
+                    value: entry.value,
+                    level: level.as_str(),
+                    previous_level: previous.as_str(),
+                });
+                match result {
+                    Ok(_) => {
+                        levels.insert(key, level);
+                        count += 1;
+                    }
+                    Err(err) => error!(
+                        "Failed to record {} alarm for {}.{}: {}",
+                        level.as_str(),
+                        entry.subsystem,
+                        entry.parameter,
+                        err
+                    ),
+                }
+            }
+
+            Ok(count)
+        })
+    }
+}
+
+// The level a parameter was left at by its latest alarm
+fn current_level(database: &Database, key: &Key) -> QueryResult<AlarmLevel> {
+    let level = dsl::alarms
+        .filter(dsl::subsystem.eq(&key.0))
+        .filter(dsl::parameter.eq(&key.1))
+        .order(dsl::id.desc())
+        .select(dsl::level)
+        .first::<String>(&database.connection)
+        .optional()?;
+
+    Ok(match level {
+        Some(level) => level.parse().unwrap_or(AlarmLevel::Nominal),
+        None => AlarmLevel::Nominal,
+    })
+}
@@ -0,0 +1,105 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use diesel::connection::SimpleConnection;
+use diesel::insert_into;
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+use schema_version;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A single, ordered step in upgrading the database schema
+pub struct Migration {
+    /// Schema version the database is at once this step has been applied
+    pub version: i32,
+    /// Short description of the change
+    pub description: &'static str,
+    /// SQL statements which perform the change
+    pub sql: &'static str,
+}
+
+/// All schema upgrade steps, in the order they must be applied.
+///
+/// Steps must never be edited or removed once released. Changes to the schema are made by
+/// appending a new step. The early steps use `IF NOT EXISTS` so that databases created
+/// before versioning was introduced can be brought up to date.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Create telemetry table",
+        sql: "CREATE TABLE IF NOT EXISTS telemetry (
+            timestamp INTEGER NOT NULL,
+            subsystem VARCHAR(255) NOT NULL,
+            parameter VARCHAR(255) NOT NULL,
+            value VARCHAR(255) NOT NULL,
+            PRIMARY KEY (timestamp, subsystem, parameter))",
+    },
+    Migration {
+        version: 2,
+        description: "Create alarms table",
+        sql: "CREATE TABLE IF NOT EXISTS alarms (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            timestamp INTEGER NOT NULL,
+            subsystem VARCHAR(255) NOT NULL,
+            parameter VARCHAR(255) NOT NULL,
+            value VARCHAR(255) NOT NULL,
+            level VARCHAR(32) NOT NULL,
+            previous_level VARCHAR(32) NOT NULL,
+            acknowledged BOOLEAN NOT NULL DEFAULT 0)",
+    },
+];
+
+/// Schema version produced by applying all known migrations
+pub fn latest_version() -> i32 {
+    MIGRATIONS.last().map(|step| step.version).unwrap_or(0)
+}
+
+pub(crate) fn create_version_table(connection: &SqliteConnection) -> QueryResult<()> {
+    connection.batch_execute(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY NOT NULL,
+            description VARCHAR(255) NOT NULL,
+            applied INTEGER NOT NULL)",
+    )
+}
+
+pub(crate) fn current_version(connection: &SqliteConnection) -> QueryResult<i32> {
+    schema_version::table
+        .select(diesel::dsl::max(schema_version::version))
+        .first::<Option<i32>>(connection)
+        .map(|version| version.unwrap_or(0))
+}
+
+// Apply a single step, recording it in the version table. Both happen in the
+// same transaction so a failed step leaves the database at the previous version
+pub(crate) fn apply(connection: &SqliteConnection, step: &Migration) -> QueryResult<()> {
+    let applied = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|dur| dur.as_secs() as i32)
+        .unwrap_or(0);
+
+    connection.transaction(|| {
+        connection.batch_execute(step.sql)?;
+        insert_into(schema_version::table)
+            .values((
+                schema_version::version.eq(step.version),
+                schema_version::description.eq(step.description),
+                schema_version::applied.eq(applied),
+            ))
+            .execute(connection)?;
+        Ok(())
+    })
+}
@@ -14,7 +14,7 @@
 // limitations under the License.
 //
 
-use super::telemetry;
+use super::{alarms, telemetry};
 
 #[derive(Debug, Queryable)]
 pub struct Entry {
@@ -24,7 +24,7 @@ pub struct Entry {
     pub value: String,
 }
 
-#[derive(Insertable)]
+#[derive(Clone, Insertable)]
 #[table_name = "telemetry"]
 pub struct NewEntry<'a> {
     pub timestamp: i32,
@@ -32,3 +32,26 @@ pub struct NewEntry<'a> {
     pub parameter: &'a str,
     pub value: &'a str,
 }
+
+#[derive(Debug, Queryable)]
+pub struct Alarm {
+    pub id: i32,
+    pub timestamp: i32,
+    pub subsystem: String,
+    pub parameter: String,
+    pub value: String,
+    pub level: String,
+    pub previous_level: String,
+    pub acknowledged: bool,
+}
+
+#[derive(Insertable)]
+#[table_name = "alarms"]
+pub struct NewAlarm<'a> {
+    pub timestamp: i32,
+    pub subsystem: &'a str,
+    pub parameter: &'a str,
+    pub value: &'a str,
+    pub level: &'a str,
+    pub previous_level: &'a str,
+}
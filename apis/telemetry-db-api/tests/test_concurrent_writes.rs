@@ -0,0 +1,86 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+extern crate diesel;
+extern crate kubos_telemetry_db;
+extern crate tempfile;
+
+use diesel::prelude::*;
+use kubos_telemetry_db::*;
+use std::thread;
+use std::time::Duration;
+use tempfile::TempDir;
+
+const BATCHES: i32 = 50;
+const BATCH_SIZE: i32 = 20;
+
+// Write batches to the database through a handle of its own, as the telemetry service and the
+// telemetry collector do
+fn write_batches(path: String, subsystem: &'static str) -> Vec<QueryResult<usize>> {
+    let (db, _) = Database::open(&path).unwrap();
+    db.set_busy_timeout(Duration::from_secs(5)).unwrap();
+
+    (0..BATCHES)
+        .map(|batch| {
+            let timestamps: Vec<i32> = (0..BATCH_SIZE).map(|i| batch * BATCH_SIZE + i).collect();
+            let entries: Vec<NewEntry> = timestamps
+                .iter()
+                .map(|timestamp| NewEntry {
+                    timestamp: *timestamp,
+                    subsystem,
+                    parameter: "voltage",
+                    value: "3.3",
+                })
+                .collect();
+            db.insert_batch(&entries)
+        })
+        .collect()
+}
+
+#[test]
+fn concurrent_writers_wait_for_lock() {
+    let dir = TempDir::new().unwrap();
+    let path = dir
+        .path()
+        .join("telemetry.db")
+        .to_string_lossy()
+        .into_owned();
+    // Create the database up front, so that the writers don't race to set it up
+    Database::open(&path).unwrap();
+
+    let writers: Vec<_> = vec!["eps", "adcs"]
+        .into_iter()
+        .map(|subsystem| {
+            let path = path.clone();
+            thread::spawn(move || write_batches(path, subsystem))
+        })
+        .collect();
+
+    for writer in writers {
+        for result in writer.join().unwrap() {
+            assert_eq!(result, Ok(BATCH_SIZE as usize));
+        }
+    }
+
+    let (db, _) = Database::open(&path).unwrap();
+    assert_eq!(
+        telemetry::table
+            .count()
+            .get_result::<i64>(&db.connection)
+            .unwrap(),
+        i64::from(2 * BATCHES * BATCH_SIZE)
+    );
+}
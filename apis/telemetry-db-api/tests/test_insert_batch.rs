@@ -0,0 +1,129 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+extern crate diesel;
+extern crate kubos_telemetry_db;
+extern crate tempfile;
+
+use diesel::prelude::*;
+use kubos_telemetry_db::*;
+use tempfile::TempDir;
+
+fn entry<'a>(timestamp: i32, parameter: &'a str, value: &'a str) -> NewEntry<'a> {
+    NewEntry {
+        timestamp,
+        subsystem: "eps",
+        parameter,
+        value,
+    }
+}
+
+fn count(db: &Database) -> i64 {
+    telemetry::table
+        .count()
+        .get_result::<i64>(&db.connection)
+        .unwrap()
+}
+
+fn open(dir: &TempDir) -> Database {
+    let path = dir.path().join("telemetry.db");
+    Database::open(&path.to_string_lossy()).unwrap().0
+}
+
+#[test]
+fn batch_inserted() {
+    let dir = TempDir::new().unwrap();
+    let db = open(&dir);
+
+    let entries = vec![
+        entry(1000, "voltage", "3.3"),
+        entry(1000, "current", "0.5"),
+        entry(1001, "voltage", "3.2"),
+    ];
+
+    assert_eq!(db.insert_batch(&entries), Ok(3));
+    assert_eq!(count(&db), 3);
+}
+
+#[test]
+fn batch_rolled_back_on_failure() {
+    let dir = TempDir::new().unwrap();
+    let db = open(&dir);
+    db.insert(1001, "eps", "voltage", "3.2").unwrap();
+
+    // The last entry duplicates the existing row's key, so the whole batch must be discarded
+    let entries = vec![
+        entry(1000, "voltage", "3.3"),
+        entry(1000, "current", "0.5"),
+        entry(1001, "voltage", "3.1"),
+    ];
+
+    assert!(db.insert_batch(&entries).is_err());
+    assert_eq!(count(&db), 1);
+    assert_eq!(
+        telemetry::table
+            .select(telemetry::value)
+            .get_results::<String>(&db.connection),
+        Ok(vec!["3.2".to_owned()])
+    );
+}
+
+#[test]
+fn batch_duplicate_within_batch_rolled_back() {
+    let dir = TempDir::new().unwrap();
+    let db = open(&dir);
+
+    let entries = vec![entry(1000, "voltage", "3.3"), entry(1000, "voltage", "3.4")];
+
+    assert!(db.insert_batch(&entries).is_err());
+    assert_eq!(count(&db), 0);
+
+    // The connection is left usable after the rollback
+    assert_eq!(db.insert_batch(&entries[..1]), Ok(1));
+    assert_eq!(count(&db), 1);
+}
+
+#[test]
+fn partial_skips_duplicates() {
+    let dir = TempDir::new().unwrap();
+    let db = open(&dir);
+    db.insert(1001, "eps", "voltage", "3.2").unwrap();
+
+    let entries = vec![
+        entry(1000, "voltage", "3.3"),
+        entry(1001, "voltage", "3.1"),
+        entry(1001, "current", "0.5"),
+    ];
+
+    let stored = db.insert_batch_partial(entries).unwrap();
+    assert_eq!(stored.len(), 2);
+    assert_eq!(stored[0].timestamp, 1000);
+    assert_eq!(stored[1].parameter, "current");
+    assert_eq!(count(&db), 3);
+}
+
+#[test]
+fn partial_returns_other_errors() {
+    let dir = TempDir::new().unwrap();
+    let db = open(&dir);
+    diesel::sql_query("DROP TABLE telemetry")
+        .execute(&db.connection)
+        .unwrap();
+
+    let entries = vec![entry(1000, "voltage", "3.3")];
+
+    assert!(db.insert_batch_partial(entries).is_err());
+}
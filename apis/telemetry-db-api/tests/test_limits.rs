@@ -0,0 +1,105 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+extern crate diesel;
+extern crate kubos_telemetry_db;
+extern crate tempfile;
+
+use diesel::prelude::*;
+use kubos_telemetry_db::*;
+use tempfile::TempDir;
+
+fn checker() -> LimitChecker {
+    LimitChecker::new(vec![Limit {
+        subsystem: "eps".to_owned(),
+        parameter: "voltage".to_owned(),
+        red_low: Some(3.0),
+        yellow_low: None,
+        yellow_high: None,
+        red_high: Some(4.0),
+    }])
+}
+
+fn entry(timestamp: i32, value: &str) -> NewEntry {
+    NewEntry {
+        timestamp,
+        subsystem: "eps",
+        parameter: "voltage",
+        value,
+    }
+}
+
+fn levels(db: &Database) -> Vec<(String, String)> {
+    alarms::table
+        .order(alarms::id.asc())
+        .load::<Alarm>(&db.connection)
+        .unwrap()
+        .into_iter()
+        .map(|alarm| (alarm.previous_level, alarm.level))
+        .collect()
+}
+
+#[test]
+fn transitions_recorded() {
+    let dir = TempDir::new().unwrap();
+    let (db, _) = Database::open(&dir.path().join("telemetry.db").to_string_lossy()).unwrap();
+    let checker = checker();
+
+    let entries = vec![
+        entry(1000, "3.5"),
+        entry(1001, "4.5"),
+        entry(1002, "4.6"),
+        entry(1003, "not a number"),
+        entry(1004, "2.5"),
+    ];
+
+    assert_eq!(checker.check(&db, &entries), Ok(2));
+    assert_eq!(
+        levels(&db),
+        vec![
+            ("nominal".to_owned(), "red_high".to_owned()),
+            ("red_high".to_owned(), "red_low".to_owned()),
+        ]
+    );
+}
+
+#[test]
+fn level_shared_between_checkers() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("telemetry.db");
+    let path = path.to_string_lossy();
+
+    // The telemetry service and the telemetry collector each have their own checker and
+    // connection to the same database
+    let (service_db, _) = Database::open(&path).unwrap();
+    let (collector_db, _) = Database::open(&path).unwrap();
+    let service = checker();
+    let collector = checker();
+
+    assert_eq!(service.check(&service_db, &[entry(1000, "4.5")]), Ok(1));
+    // The collector sees the service's alarm, so the same level isn't recorded again
+    assert_eq!(collector.check(&collector_db, &[entry(1001, "4.6")]), Ok(0));
+    assert_eq!(collector.check(&collector_db, &[entry(1002, "3.5")]), Ok(1));
+    assert_eq!(service.check(&service_db, &[entry(1003, "3.6")]), Ok(0));
+
+    assert_eq!(
+        levels(&service_db),
+        vec![
+            ("nominal".to_owned(), "red_high".to_owned()),
+            ("red_high".to_owned(), "nominal".to_owned()),
+        ]
+    );
+}
@@ -0,0 +1,191 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+extern crate diesel;
+extern crate kubos_telemetry_db;
+extern crate tempfile;
+
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use kubos_telemetry_db::migrations::latest_version;
+use kubos_telemetry_db::*;
+use std::fs;
+use std::thread;
+use tempfile::TempDir;
+
+fn db_path(dir: &TempDir) -> String {
+    dir.path()
+        .join("telemetry.db")
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[test]
+fn fresh_database() {
+    let dir = TempDir::new().unwrap();
+    let path = db_path(&dir);
+
+    let (db, report) = Database::open(&path).unwrap();
+
+    assert!(report.problems.is_empty());
+    assert_eq!(report.quarantined, None);
+    assert_eq!(report.previous_version, 0);
+    assert_eq!(report.version, latest_version());
+
+    db.insert(1000, "eps", "voltage", "3.3").unwrap();
+    assert_eq!(
+        telemetry::table.count().get_result::<i64>(&db.connection),
+        Ok(1)
+    );
+}
+
+#[test]
+fn reopen_is_noop() {
+    let dir = TempDir::new().unwrap();
+    let path = db_path(&dir);
+
+    {
+        let (db, _) = Database::open(&path).unwrap();
+        db.insert(1000, "eps", "voltage", "3.3").unwrap();
+    }
+
+    let (db, report) = Database::open(&path).unwrap();
+
+    assert_eq!(report.previous_version, latest_version());
+    assert_eq!(report.version, latest_version());
+    assert_eq!(
+        telemetry::table.count().get_result::<i64>(&db.connection),
+        Ok(1)
+    );
+}
+
+#[test]
+fn upgrade_legacy_database() {
+    let dir = TempDir::new().unwrap();
+    let path = db_path(&dir);
+
+    // A database created before schema versioning, with only the telemetry table
+    {
+        let db = Database::new(&path).unwrap();
+        db.connection
+            .batch_execute(
+                "CREATE TABLE telemetry (
+                    timestamp INTEGER NOT NULL,
+                    subsystem VARCHAR(255) NOT NULL,
+                    parameter VARCHAR(255) NOT NULL,
+                    value VARCHAR(255) NOT NULL,
+                    PRIMARY KEY (timestamp, subsystem, parameter));
+                INSERT INTO telemetry VALUES (1000, 'eps', 'voltage', '3.3');",
+            )
+            .unwrap();
+    }
+
+    let (db, report) = Database::open(&path).unwrap();
+
+    assert_eq!(report.previous_version, 0);
+    assert_eq!(report.version, latest_version());
+
+    // Existing data is kept and the newer tables are usable
+    assert_eq!(
+        telemetry::table.count().get_result::<i64>(&db.connection),
+        Ok(1)
+    );
+    assert_eq!(db.acknowledge_alarms(None), Ok(0));
+}
+
+#[test]
+fn newer_schema_rejected() {
+    let dir = TempDir::new().unwrap();
+    let path = db_path(&dir);
+
+    {
+        let (db, _) = Database::open(&path).unwrap();
+        db.connection
+            .batch_execute("INSERT INTO schema_version VALUES (1000, 'From the future', 0)")
+            .unwrap();
+    }
+
+    match Database::open(&path) {
+        Err(DatabaseError::UnsupportedVersion { found, supported }) => {
+            assert_eq!(found, 1000);
+            assert_eq!(supported, latest_version());
+        }
+        Err(other) => panic!("Unexpected error: {}", other),
+        Ok(_) => panic!("Newer schema was accepted"),
+    }
+}
+
+#[test]
+fn corrupted_database_quarantined() {
+    let dir = TempDir::new().unwrap();
+    let path = db_path(&dir);
+
+    fs::write(&path, vec![0x5a; 8192]).unwrap();
+
+    let (db, report) = Database::open(&path).unwrap();
+
+    assert!(!report.problems.is_empty());
+    let quarantined = report.quarantined.expect("Database was not quarantined");
+    assert!(quarantined.starts_with(&format!("{}.corrupt.", path)));
+    assert_eq!(fs::read(&quarantined).unwrap(), vec![0x5a; 8192]);
+
+    // The replacement database is empty and fully usable
+    assert_eq!(report.version, latest_version());
+    assert_eq!(db.check_integrity().unwrap(), Vec::<String>::new());
+    db.insert(1000, "eps", "voltage", "3.3").unwrap();
+}
+
+#[test]
+fn corrupted_database_quarantined_once() {
+    let dir = TempDir::new().unwrap();
+    let path = db_path(&dir);
+
+    fs::write(&path, vec![0x5a; 8192]).unwrap();
+
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            let path = path.clone();
+            thread::spawn(move || Database::open(&path).map(|(_, report)| report))
+        })
+        .collect();
+    let reports: Vec<OpenReport> = threads
+        .into_iter()
+        .map(|thread| thread.join().unwrap().unwrap())
+        .collect();
+
+    // Only the first opener finds the corruption and moves the database aside. The others
+    // wait for it and find the replacement
+    let quarantined: Vec<_> = reports
+        .iter()
+        .filter(|report| report.quarantined.is_some())
+        .collect();
+    assert_eq!(quarantined.len(), 1);
+    assert!(!quarantined[0].problems.is_empty());
+    assert_eq!(
+        reports
+            .iter()
+            .filter(|report| report.problems.is_empty())
+            .count(),
+        3
+    );
+
+    let corrupt: Vec<_> = fs::read_dir(dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| name.starts_with("telemetry.db.corrupt."))
+        .collect();
+    assert_eq!(corrupt.len(), 1);
+}
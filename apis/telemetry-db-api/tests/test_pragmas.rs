@@ -0,0 +1,86 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+#[macro_use]
+extern crate diesel;
+extern crate kubos_telemetry_db;
+extern crate tempfile;
+
+use diesel::sql_query;
+use diesel::sql_types::{Integer, Text};
+use diesel::RunQueryDsl;
+use kubos_telemetry_db::*;
+use tempfile::TempDir;
+
+#[derive(QueryableByName)]
+struct JournalModeRow {
+    #[sql_type = "Text"]
+    journal_mode: String,
+}
+
+#[derive(QueryableByName)]
+struct SynchronousRow {
+    #[sql_type = "Integer"]
+    synchronous: i32,
+}
+
+fn open(dir: &TempDir) -> Database {
+    let path = dir.path().join("telemetry.db");
+    Database::open(&path.to_string_lossy()).unwrap().0
+}
+
+fn journal_mode(db: &Database) -> String {
+    sql_query("PRAGMA journal_mode")
+        .load::<JournalModeRow>(&db.connection)
+        .unwrap()
+        .remove(0)
+        .journal_mode
+}
+
+fn synchronous(db: &Database) -> i32 {
+    sql_query("PRAGMA synchronous")
+        .load::<SynchronousRow>(&db.connection)
+        .unwrap()
+        .remove(0)
+        .synchronous
+}
+
+#[test]
+fn journal_mode_set() {
+    let dir = TempDir::new().unwrap();
+    let db = open(&dir);
+
+    db.set_journal_mode(JournalMode::Wal).unwrap();
+    assert_eq!(journal_mode(&db), "wal");
+
+    db.set_journal_mode(JournalMode::Truncate).unwrap();
+    assert_eq!(journal_mode(&db), "truncate");
+}
+
+#[test]
+fn synchronous_set() {
+    let dir = TempDir::new().unwrap();
+    let db = open(&dir);
+
+    db.set_synchronous(Synchronous::Off).unwrap();
+    assert_eq!(synchronous(&db), 0);
+
+    db.set_synchronous(Synchronous::Normal).unwrap();
+    assert_eq!(synchronous(&db), 1);
+
+    db.set_synchronous(Synchronous::Extra).unwrap();
+    assert_eq!(synchronous(&db), 3);
+}
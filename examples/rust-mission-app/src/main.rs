@@ -18,19 +18,40 @@
 extern crate kubos_app;
 
 use kubos_app::*;
+use std::thread;
+use std::time::Duration;
 
 struct MyApp;
 
 impl AppHandler for MyApp {
-    fn on_boot(&self) {
+    fn on_boot(&self, _context: &AppContext) -> HandlerResult {
         println!("OnBoot logic");
+
+        // Keep running until the applications service stops us
+        while !shutdown_requested() {
+            thread::sleep(Duration::from_secs(1));
+        }
+        Ok(())
+    }
+    fn on_command(&self, context: &AppContext) -> HandlerResult {
+        println!("OnCommand logic with args {:?}", context.args);
+        Ok(())
+    }
+    fn run_levels(&self) -> &[&str] {
+        &["OnPassStart"]
     }
-    fn on_command(&self) {
-        println!("OnCommand logic");
+    fn on_run_level(&self, run_level: &str, _context: &AppContext) -> HandlerResult {
+        println!("{} logic", run_level);
+        Ok(())
+    }
+    fn on_shutdown(&self, _context: &AppContext) -> HandlerResult {
+        println!("OnShutdown logic");
+        Ok(())
     }
 }
 
+static APP: MyApp = MyApp;
+
 fn main() {
-    let app = MyApp;
-    app_main!(&app);
+    app_main!(&APP);
 }
@@ -0,0 +1,203 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Dependencies of apps started at boot.
+//!
+//! An app's manifest may list other apps, by name, and services, by their name in the system
+//! config file, which must be up before the app is started. Apps are started in an order
+//! which puts every app after the apps it depends on, and services are checked by sending them
+//! a `ping` query until they respond. Apps are only waited for until they have been launched,
+//! as they have no way to report that they are ready.
+
+use kubos_app::{query, ServiceConfig};
+use registry::App;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Default number of milliseconds to wait for an app's service dependencies to respond
+pub const DEFAULT_DEPENDENCY_TIMEOUT: u64 = 30000;
+
+// How long to wait for each `ping` response, in milliseconds
+const PING_TIMEOUT: u64 = 500;
+// How long to wait between `ping` attempts, in milliseconds
+const PING_INTERVAL: u64 = 250;
+
+/// The dependencies of an application, read from the `[dependencies]` section of its manifest
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Dependencies {
+    /// Names of the apps which must be started first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub apps: Vec<String>,
+    /// Names of the services which must be responding before the app is started
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub services: Vec<String>,
+    /// Milliseconds to wait for the services to respond before giving up on the app
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+}
+
+impl Default for Dependencies {
+    fn default() -> Self {
+        Dependencies {
+            apps: vec![],
+            services: vec![],
+            timeout: default_timeout(),
+        }
+    }
+}
+
+fn default_timeout() -> u64 {
+    DEFAULT_DEPENDENCY_TIMEOUT
+}
+
+/// Order apps so that every app comes after the apps it depends on. Apps which don't depend on
+/// each other are kept in name order.
+///
+/// Returns the ordered apps, along with the apps which can't be started because they depend on
+/// an app which isn't installed or are part of a dependency cycle, and the reason why
+pub fn boot_order(apps: Vec<App>) -> (Vec<App>, Vec<(App, String)>) {
+    let mut waiting: BTreeMap<String, App> = BTreeMap::new();
+    let mut failed = vec![];
+
+    for app in apps {
+        if waiting.contains_key(&app.metadata.name) {
+            failed.push((
+                app.clone(),
+                format!("Another app is named {}", app.metadata.name),
+            ));
+        } else {
+            waiting.insert(app.metadata.name.clone(), app);
+        }
+    }
+
+    // Apps which depend on an app which isn't installed can never be started, and neither can
+    // the apps which depend on them
+    loop {
+        let missing: Vec<(String, String)> = waiting
+            .values()
+            .filter_map(|app| {
+                app.metadata
+                    .dependencies
+                    .apps
+                    .iter()
+                    .find(|dep| !waiting.contains_key(*dep))
+                    .map(|dep| (app.metadata.name.clone(), dep.clone()))
+            })
+            .collect();
+        if missing.is_empty() {
+            break;
+        }
+
+        for (name, dep) in missing {
+            if let Some(app) = waiting.remove(&name) {
+                failed.push((app, format!("Dependency {} is not available", dep)));
+            }
+        }
+    }
+
+    let mut ordered = vec![];
+    let mut started: BTreeSet<String> = BTreeSet::new();
+    loop {
+        let ready: Vec<String> = waiting
+            .values()
+            .filter(|app| {
+                app.metadata
+                    .dependencies
+                    .apps
+                    .iter()
+                    .all(|dep| started.contains(dep))
+            })
+            .map(|app| app.metadata.name.clone())
+            .collect();
+        if ready.is_empty() {
+            break;
+        }
+
+        for name in ready {
+            if let Some(app) = waiting.remove(&name) {
+                started.insert(name);
+                ordered.push(app);
+            }
+        }
+    }
+
+    // Anything left over depends on itself, directly or indirectly
+    let cycle: Vec<String> = waiting.keys().cloned().collect();
+    for (_, app) in waiting {
+        failed.push((
+            app,
+            format!("Dependency cycle between apps {}", cycle.join(", ")),
+        ));
+    }
+
+    (ordered, failed)
+}
+
+/// Checks that services are responding, remembering the result for each service so that a
+/// service which never comes up only delays the first app which depends on it
+pub struct ServiceChecker {
+    config_path: Option<String>,
+    results: HashMap<String, Result<(), String>>,
+}
+
+impl ServiceChecker {
+    /// Create a checker which reads the addresses of services from the given config file, or
+    /// from the default config file if none is given
+    pub fn new(config_path: Option<String>) -> ServiceChecker {
+        ServiceChecker {
+            config_path,
+            results: HashMap::new(),
+        }
+    }
+
+    /// Wait for a service to respond to a `ping` query, giving up after `timeout`
+    pub fn wait_for(&mut self, service: &str, timeout: Duration) -> Result<(), String> {
+        if let Some(result) = self.results.get(service) {
+            return result.clone();
+        }
+
+        let deadline = Instant::now() + timeout;
+        let result = loop {
+            let config = match self.config_path {
+                Some(ref path) => ServiceConfig::new_from_path(service, path.clone()),
+                None => ServiceConfig::new(service),
+            };
+
+            match query(
+                config,
+                "{ ping }",
+                Some(Duration::from_millis(PING_TIMEOUT)),
+            ) {
+                Ok(_) => break Ok(()),
+                Err(err) => {
+                    if Instant::now() >= deadline {
+                        break Err(format!(
+                            "Service {} did not respond within {}ms: {}",
+                            service,
+                            timeout.as_secs() * 1000 + u64::from(timeout.subsec_millis()),
+                            err
+                        ));
+                    }
+                }
+            }
+            thread::sleep(Duration::from_millis(PING_INTERVAL));
+        };
+
+        self.results.insert(service.to_owned(), result.clone());
+        result
+    }
+}
@@ -0,0 +1,304 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Persistent history of app runs.
+//!
+//! Each app's runs are recorded in `runs.toml` in the app's directory within the registry
+//! (alongside its versions and logs). A run is recorded when the app is started, and updated
+//! with its exit status when it finishes. When a telemetry address is configured, the exit
+//! status of each finished run is also sent to the telemetry service's direct ingestion socket.
+
+use monitor::{AppExit, Launch};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::net::{SocketAddr, UdpSocket};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use toml;
+
+/// Name of the run history file within an app's directory
+pub const RUNS_FILE: &'static str = "runs.toml";
+
+// Number of runs to remember for each app
+const RUN_HISTORY: usize = 100;
+// Subsystem name used for the telemetry sent when a run finishes
+const TELEMETRY_SUBSYSTEM: &'static str = "app-service";
+
+/// Why an app was started
+#[derive(Clone, Debug, PartialEq)]
+pub enum StartCause {
+    /// Started along with the other apps at boot
+    Boot,
+    /// Started by a `startApp` request
+    Command,
+    /// Started by the scheduler
+    Schedule,
+    /// Restarted by its restart policy after the run with the given ID exited
+    Restart(u32),
+}
+
+impl StartCause {
+    /// Name used for the cause in the run history
+    pub fn name(&self) -> &'static str {
+        match *self {
+            StartCause::Boot => "boot",
+            StartCause::Command => "command",
+            StartCause::Schedule => "schedule",
+            StartCause::Restart(_) => "restart",
+        }
+    }
+}
+
+/// A single run of an app
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct AppRun {
+    /// Identifier of the run. IDs increase with each run of an app
+    pub id: u32,
+    /// UUID of the app
+    pub uuid: String,
+    /// Name of the app
+    pub name: String,
+    /// Version of the app which was run
+    pub version: String,
+    /// Run level the app was started with
+    pub run_level: String,
+    /// Extra arguments the app was started with
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    /// Why the app was started: boot, command, schedule or restart
+    pub cause: String,
+    /// ID of the run which was restarted, if this run is a restart
+    pub restart_of: Option<u32>,
+    /// Process ID of the run
+    pub pid: u32,
+    /// Time the run started, in seconds since the epoch
+    pub started: i32,
+    /// Time the run finished, in seconds since the epoch. Not set while the app is running,
+    /// or if the service stopped before the app did
+    pub ended: Option<i32>,
+    /// Exit code, if the app exited normally
+    pub code: Option<i32>,
+    /// Signal which terminated the app, if it didn't exit normally
+    pub signal: Option<i32>,
+    /// The resource limit the app most likely exceeded, if it was terminated for exceeding one
+    pub limit: Option<String>,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+struct RunsFile {
+    #[serde(default)]
+    next_id: u32,
+    // Oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    runs: Vec<AppRun>,
+}
+
+/// Records the runs of every app started by the service
+#[derive(Default)]
+pub struct RunHistory {
+    apps_dir: Option<PathBuf>,
+    telemetry: Mutex<Option<SocketAddr>>,
+    // Run histories which have been loaded, by app UUID
+    apps: Mutex<HashMap<String, RunsFile>>,
+}
+
+impl RunHistory {
+    /// Create a run history which is kept in a registry directory
+    pub fn new(apps_dir: &str) -> RunHistory {
+        RunHistory {
+            apps_dir: Some(PathBuf::from(apps_dir)),
+            ..Default::default()
+        }
+    }
+
+    /// Set the address of the telemetry service's ingestion socket. The exit status of each
+    /// finished run is sent to it
+    pub fn set_telemetry_addr(&self, addr: Option<SocketAddr>) {
+        *self.telemetry.lock().unwrap() = addr;
+    }
+
+    /// Record the start of a run, returning its ID
+    pub fn started(&self, launch: &Launch, pid: u32, started: i32) -> u32 {
+        let mut apps = self.apps.lock().unwrap();
+        let file = self.load(&mut apps, &launch.uuid);
+
+        file.next_id += 1;
+        file.runs.push(AppRun {
+            id: file.next_id,
+            uuid: launch.uuid.clone(),
+            name: launch.name.clone(),
+            version: launch.version.clone(),
+            run_level: format!("{}", launch.run_level),
+            args: launch.args.clone(),
+            cause: launch.cause.name().to_owned(),
+            restart_of: match launch.cause {
+                StartCause::Restart(id) => Some(id),
+                _ => None,
+            },
+            pid,
+            started,
+            ended: None,
+            code: None,
+            signal: None,
+            limit: None,
+        });
+        let excess = file.runs.len().saturating_sub(RUN_HISTORY);
+        file.runs.drain(..excess);
+
+        let id = file.next_id;
+        self.save(&launch.uuid, file);
+        id
+    }
+
+    /// Record the end of a run
+    pub fn finished(&self, exit: &AppExit) {
+        let run = {
+            let mut apps = self.apps.lock().unwrap();
+            let file = self.load(&mut apps, &exit.app.uuid);
+            let run = match file
+                .runs
+                .iter_mut()
+                .find(|run| run.id == exit.app.history_id)
+            {
+                Some(run) => {
+                    run.ended = Some(exit.ended);
+                    run.code = exit.code;
+                    run.signal = exit.signal;
+                    run.limit = exit.limit.map(|limit| limit.to_owned());
+                    run.clone()
+                }
+                None => return,
+            };
+            self.save(&exit.app.uuid, file);
+            run
+        };
+
+        if let Some(addr) = *self.telemetry.lock().unwrap() {
+            if let Err(err) = send_telemetry(addr, &run) {
+                warn!("Failed to send telemetry for app {}: {}", run.name, err);
+            }
+        }
+    }
+
+    /// The recorded runs of an app, most recent first
+    ///
+    /// # Arguments
+    ///
+    /// * `uuid` - UUID of the app
+    /// * `after` - Only return runs older than the run with this ID
+    /// * `limit` - The maximum number of runs to return
+    pub fn runs(&self, uuid: &str, after: Option<u32>, limit: Option<usize>) -> Vec<AppRun> {
+        let mut apps = self.apps.lock().unwrap();
+        self.load(&mut apps, uuid)
+            .runs
+            .iter()
+            .rev()
+            .filter(|run| match after {
+                Some(after) => run.id < after,
+                None => true,
+            })
+            .take(limit.unwrap_or(std::usize::MAX))
+            .cloned()
+            .collect()
+    }
+
+    /// Forget the runs of an app which has been uninstalled
+    pub fn forget(&self, uuid: &str) {
+        self.apps.lock().unwrap().remove(uuid);
+    }
+
+    fn path(&self, uuid: &str) -> Option<PathBuf> {
+        self.apps_dir
+            .as_ref()
+            .map(|dir| dir.join(uuid).join(RUNS_FILE))
+    }
+
+    // Get an app's history, reading it from disk the first time it is needed
+    fn load<'a>(&self, apps: &'a mut HashMap<String, RunsFile>, uuid: &str) -> &'a mut RunsFile {
+        let path = self.path(uuid);
+        apps.entry(uuid.to_owned()).or_insert_with(|| {
+            let path = match path {
+                Some(ref path) if path.exists() => path,
+                _ => return RunsFile::default(),
+            };
+
+            fs::read_to_string(path)
+                .map_err(|err| format!("{}", err))
+                .and_then(|data| toml::from_str(&data).map_err(|err| format!("{}", err)))
+                .unwrap_or_else(|err| {
+                    error!("Failed to load run history {}: {}", path.display(), err);
+                    RunsFile::default()
+                })
+        })
+    }
+
+    // Write an app's history to a temporary file and move it into place, so that the history
+    // file is never left partially written. Failures are only reported, since they shouldn't
+    // stop the app from running
+    fn save(&self, uuid: &str, file: &RunsFile) {
+        let path = match self.path(uuid) {
+            Some(path) => path,
+            None => return,
+        };
+        let tmp_path = path.with_file_name(format!(".{}.tmp", RUNS_FILE));
+
+        let result = toml::to_string(file)
+            .map_err(|err| format!("{}", err))
+            .and_then(|data| {
+                fs::File::create(&tmp_path)
+                    .and_then(|mut file| {
+                        file.write_all(data.as_bytes())?;
+                        file.sync_all()
+                    })
+                    .and_then(|_| fs::rename(&tmp_path, &path))
+                    .map_err(|err| format!("{}", err))
+            });
+
+        if let Err(err) = result {
+            error!("Failed to save run history {}: {}", path.display(), err);
+        }
+    }
+}
+
+// The exit status is sent as the app's exit code, or as the negated signal number if it was
+// terminated by a signal. The version is part of the parameter, so that the exits of different
+// versions of an app can be told apart
+fn send_telemetry(addr: SocketAddr, run: &AppRun) -> Result<(), String> {
+    let value = match (run.code, run.signal) {
+        (Some(code), _) => code,
+        (None, Some(signal)) => -signal,
+        (None, None) => return Ok(()),
+    };
+
+    let record = json!({
+        "timestamp": run.ended,
+        "subsystem": TELEMETRY_SUBSYSTEM,
+        "parameter": format!("{}.{}.exit", run.name, run.version),
+        "value": value,
+    });
+
+    let bind_addr = if addr.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    };
+    UdpSocket::bind(bind_addr)
+        .and_then(|socket| socket.send_to(record.to_string().as_bytes(), addr))
+        .map(|_| ())
+        .map_err(|err| format!("{}", err))
+}
@@ -0,0 +1,167 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Resource limits and sandboxing of app processes.
+//!
+//! Limits are read from the `[limits]` section of an app's manifest, and are applied in the
+//! child process after it has been forked but before the app is executed.
+
+use libc;
+use std::io;
+use std::mem;
+use std::ptr;
+
+/// Resource limits and privileges for an app's process
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ProcessLimits {
+    /// Maximum size of the process's virtual memory, in bytes
+    pub memory: Option<u64>,
+    /// Maximum CPU time, in seconds. The process is sent `SIGXCPU` when it is reached, and
+    /// killed if it is still running a second later
+    pub cpu_time: Option<u64>,
+    /// Maximum number of open file descriptors
+    pub open_files: Option<u64>,
+    /// Maximum size of any file the process creates, in bytes
+    pub file_size: Option<u64>,
+    /// Scheduling priority, from -20 (highest) to 19 (lowest)
+    pub nice: Option<i32>,
+    /// User ID to run the process as
+    pub uid: Option<u32>,
+    /// Group ID to run the process as. Defaults to the primary group of `uid`
+    pub gid: Option<u32>,
+    /// Directory to run the process in. Relative paths are resolved against the directory the
+    /// app is installed in
+    pub working_dir: Option<String>,
+}
+
+fn check(result: libc::c_int) -> io::Result<()> {
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+fn rlimit(soft: u64, hard: u64) -> libc::rlimit {
+    libc::rlimit {
+        rlim_cur: soft as libc::rlim_t,
+        rlim_max: hard as libc::rlim_t,
+    }
+}
+
+// The primary group of a user, from the user database
+fn primary_gid(uid: u32) -> io::Result<u32> {
+    let mut buf: Vec<libc::c_char> = vec![0; 1024];
+    loop {
+        let mut passwd: libc::passwd = unsafe { mem::zeroed() };
+        let mut found = ptr::null_mut();
+        let result =
+            unsafe { libc::getpwuid_r(uid, &mut passwd, buf.as_mut_ptr(), buf.len(), &mut found) };
+
+        match result {
+            0 if found.is_null() => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("User {} does not exist", uid),
+                ))
+            }
+            0 => return Ok(passwd.pw_gid),
+            libc::ERANGE => {
+                let len = buf.len() * 2;
+                buf.resize(len, 0);
+            }
+            err => return Err(io::Error::from_raw_os_error(err)),
+        }
+    }
+}
+
+impl ProcessLimits {
+    /// The limits with any defaults filled in. If a user ID is given without a group ID, the
+    /// group ID is taken from the user's primary group.
+    ///
+    /// This looks the user up, which isn't safe to do in a forked child, so it must be called
+    /// before the process is forked
+    pub fn resolve(&self) -> io::Result<ProcessLimits> {
+        let mut limits = self.clone();
+        if let (Some(uid), None) = (self.uid, self.gid) {
+            limits.gid = Some(primary_gid(uid)?);
+        }
+        Ok(limits)
+    }
+
+    /// Apply the limits to the current process and drop its privileges.
+    ///
+    /// This is intended to be run in a newly forked child process, so it only makes
+    /// async-signal-safe calls
+    pub fn apply(&self) -> io::Result<()> {
+        unsafe {
+            if let Some(memory) = self.memory {
+                check(libc::setrlimit(libc::RLIMIT_AS, &rlimit(memory, memory)))?;
+            }
+            if let Some(cpu_time) = self.cpu_time {
+                check(libc::setrlimit(
+                    libc::RLIMIT_CPU,
+                    &rlimit(cpu_time, cpu_time + 1),
+                ))?;
+            }
+            if let Some(open_files) = self.open_files {
+                check(libc::setrlimit(
+                    libc::RLIMIT_NOFILE,
+                    &rlimit(open_files, open_files),
+                ))?;
+            }
+            if let Some(file_size) = self.file_size {
+                check(libc::setrlimit(
+                    libc::RLIMIT_FSIZE,
+                    &rlimit(file_size, file_size),
+                ))?;
+            }
+            // Raising the priority needs privileges, so it must happen before they're dropped
+            if let Some(nice) = self.nice {
+                check(libc::setpriority(libc::PRIO_PROCESS, 0, nice))?;
+            }
+            // The service's supplementary groups mustn't be passed on to the app, and they can
+            // only be dropped while the process is still root
+            if (self.uid.is_some() || self.gid.is_some()) && libc::geteuid() == 0 {
+                check(libc::setgroups(0, ptr::null()))?;
+            }
+            // Likewise, the group can't be changed once the user has been
+            if let Some(gid) = self.gid {
+                check(libc::setgid(gid))?;
+            }
+            if let Some(uid) = self.uid {
+                check(libc::setuid(uid))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Work out which limit, if any, caused a process to be terminated by `signal`, given the
+    /// number of seconds of CPU time it used.
+    ///
+    /// Running out of memory isn't reported, as a failed allocation doesn't end in any signal
+    /// which tells it apart from other crashes
+    pub fn exceeded(&self, signal: Option<i32>, cpu_used: u64) -> Option<&'static str> {
+        match (signal, self.cpu_time) {
+            (Some(libc::SIGXCPU), Some(_)) => Some("cpu-time"),
+            // The process is killed once it reaches the hard limit, a second after the soft one
+            (Some(libc::SIGKILL), Some(cpu_time)) if cpu_used > cpu_time => Some("cpu-time"),
+            (Some(libc::SIGXFSZ), _) if self.file_size.is_some() => Some("file-size"),
+            _ => None,
+        }
+    }
+}
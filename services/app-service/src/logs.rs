@@ -0,0 +1,236 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Capture of app output into per-run log files.
+//!
+//! Each app has a `logs` directory alongside its versions. Every run of the app gets a pair of
+//! files, `run-<n>.stdout.log` and `run-<n>.stderr.log`. When a file grows past the size limit it
+//! is moved to `<file>.1` (replacing any previous backup) and a new file is started. Only the
+//! most recent runs are kept.
+
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+
+/// Default number of runs to keep logs for
+pub const DEFAULT_LOG_RUNS: usize = 10;
+/// Default size in bytes a log file may reach before it is rotated
+pub const DEFAULT_LOG_MAX_SIZE: u64 = 1024 * 1024;
+/// Longest line buffered before it is written out. Longer lines are written in pieces.
+const MAX_LINE_LENGTH: u64 = 4096;
+
+/// Limits on the logs kept for each app
+#[derive(Clone, Copy, Debug)]
+pub struct LogConfig {
+    /// Number of runs to keep logs for
+    pub max_runs: usize,
+    /// Size in bytes a log file may reach before it is rotated
+    pub max_size: u64,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            max_runs: DEFAULT_LOG_RUNS,
+            max_size: DEFAULT_LOG_MAX_SIZE,
+        }
+    }
+}
+
+/// An output stream of an app
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+impl Stream {
+    fn name(self) -> &'static str {
+        match self {
+            Stream::Stdout => "stdout",
+            Stream::Stderr => "stderr",
+        }
+    }
+}
+
+/// Path of the log file for one stream of a run
+pub fn log_path(dir: &Path, run: u32, stream: Stream) -> PathBuf {
+    dir.join(format!("run-{}.{}.log", run, stream.name()))
+}
+
+fn backup_path(path: &Path) -> PathBuf {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(".1");
+    PathBuf::from(backup)
+}
+
+/// The runs which have logs in a directory, oldest first
+pub fn runs(dir: &Path) -> Vec<u32> {
+    let mut runs: Vec<u32> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let name = entry.file_name();
+                let name = name.to_string_lossy();
+                if !name.ends_with(".stdout.log") {
+                    return None;
+                }
+                name.trim_start_matches("run-")
+                    .trim_end_matches(".stdout.log")
+                    .parse::<u32>()
+                    .ok()
+            })
+            .collect(),
+        Err(_) => vec![],
+    };
+
+    runs.sort();
+    runs
+}
+
+/// Create the log files for a new run of an app, removing the logs of old runs so that
+/// at most `config.max_runs` are kept.
+///
+/// Returns the new run number along with writers for its stdout and stderr logs
+pub fn start_run(dir: &Path, config: LogConfig) -> io::Result<(u32, LogWriter, LogWriter)> {
+    fs::create_dir_all(dir)?;
+
+    let existing = runs(dir);
+    let run = existing.last().map_or(1, |last| last + 1);
+
+    let keep = config.max_runs.saturating_sub(1);
+    if existing.len() > keep {
+        for old in &existing[..existing.len() - keep] {
+            for stream in &[Stream::Stdout, Stream::Stderr] {
+                let path = log_path(dir, *old, *stream);
+                let _ = fs::remove_file(backup_path(&path));
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+
+    let stdout = LogWriter::create(log_path(dir, run, Stream::Stdout), config.max_size)?;
+    let stderr = LogWriter::create(log_path(dir, run, Stream::Stderr), config.max_size)?;
+
+    Ok((run, stdout, stderr))
+}
+
+/// Writes to a log file, rotating it when it exceeds its size limit
+pub struct LogWriter {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+}
+
+impl LogWriter {
+    fn create(path: PathBuf, max_size: u64) -> io::Result<LogWriter> {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&path)?;
+
+        Ok(LogWriter {
+            path,
+            file,
+            size: 0,
+            max_size,
+        })
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        fs::rename(&self.path, backup_path(&self.path))?;
+        self.file = File::create(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+
+    /// Copy everything from `source` into the log on a background thread, until the
+    /// source is closed
+    ///
+    /// If the log can't be written, the rest of the output is read and discarded so that the
+    /// app doesn't fail writing to a closed pipe
+    pub fn capture<R: Read + Send + 'static>(mut self, source: R) {
+        thread::spawn(move || {
+            let mut source = BufReader::new(source);
+            let mut line = vec![];
+            let mut failed = false;
+            loop {
+                line.clear();
+                match (&mut source)
+                    .take(MAX_LINE_LENGTH)
+                    .read_until(b'\n', &mut line)
+                {
+                    Ok(0) => break,
+                    Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(_) => break,
+                    Ok(_) => {
+                        if failed {
+                            continue;
+                        }
+                        if let Err(err) = self.write_all(&line) {
+                            error!("Failed to write to {}: {}", self.path.display(), err);
+                            failed = true;
+                        }
+                    }
+                }
+            }
+        });
+    }
+}
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Read the last `count` lines logged by one stream of a run, including any lines which
+/// have been rotated into the backup file
+pub fn tail(dir: &Path, run: u32, stream: Stream, count: usize) -> io::Result<Vec<String>> {
+    let path = log_path(dir, run, stream);
+    let mut lines = VecDeque::with_capacity(count + 1);
+
+    for file in &[backup_path(&path), path] {
+        let file = match File::open(file) {
+            Ok(file) => file,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err),
+        };
+
+        for line in BufReader::new(file).split(b'\n') {
+            lines.push_back(String::from_utf8_lossy(&line?).into_owned());
+            if lines.len() > count {
+                lines.pop_front();
+            }
+        }
+    }
+
+    Ok(lines.into_iter().collect())
+}
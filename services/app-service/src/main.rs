@@ -15,30 +15,46 @@
  */
 #![deny(warnings)]
 
+extern crate flate2;
 extern crate getopts;
 #[macro_use]
 extern crate juniper;
 extern crate kubos_app;
+extern crate kubos_logging;
 extern crate kubos_service;
+extern crate libc;
+#[macro_use]
+extern crate log;
+extern crate nix;
 #[macro_use]
 extern crate serde_derive;
-#[cfg(test)]
 #[macro_use]
 extern crate serde_json;
-#[cfg(test)]
+extern crate sha2;
+extern crate tar;
 extern crate tempfile;
 extern crate toml;
 extern crate uuid;
 
+mod dependencies;
+mod history;
+mod limits;
+mod logs;
+mod monitor;
+mod package;
 mod registry;
+mod schedule;
 mod schema;
 #[cfg(test)]
 mod tests;
 
 use getopts::Options;
+use kubos_logging::Facility;
 use kubos_service::{Config, Service};
+use logs::LogConfig;
 use registry::AppRegistry;
 use std::env;
+use std::time::Duration;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
@@ -58,18 +74,59 @@ fn main() {
         Some(file) => Config::new_from_path("app-service", file),
         None => Config::new("app-service"),
     };
+    // Apps may be started before the service is, so logging is set up first
+    if let Err(err) = kubos_logging::init(Facility::Daemon, &config) {
+        eprintln!("Failed to set up logging: {}", err);
+    }
 
-    let registry = {
+    let mut registry = {
         match config.get("registry-dir") {
             Some(dir) => AppRegistry::new_from_dir(dir.as_str().unwrap()),
             None => AppRegistry::new(),
         }
     };
+    // The addresses of the services apps depend on are read from the same config file
+    registry.config_path = matches.opt_str("c");
+
+    if let Some(keep) = config.get("keep-versions") {
+        let keep = keep
+            .as_integer()
+            .expect("Invalid keep-versions in config file");
+        registry.keep_versions = Some(keep.max(1) as usize);
+    }
+
+    if let Some(grace_period) = config.get("stop-grace-period") {
+        let grace_period = grace_period
+            .as_integer()
+            .expect("Invalid stop-grace-period in config file");
+        registry
+            .monitor
+            .set_grace_period(Duration::from_millis(grace_period as u64));
+    }
+
+    let mut log_config = LogConfig::default();
+    if let Some(runs) = config.get("log-runs") {
+        log_config.max_runs = runs.as_integer().expect("Invalid log-runs in config file") as usize;
+    }
+    if let Some(size) = config.get("log-max-size") {
+        log_config.max_size = size
+            .as_integer()
+            .expect("Invalid log-max-size in config file") as u64;
+    }
+    registry.monitor.set_log_config(log_config);
+
+    if let Some(addr) = config.get("telemetry-addr") {
+        let addr = addr
+            .as_str()
+            .and_then(|addr| addr.parse().ok())
+            .expect("Invalid telemetry-addr in config file");
+        registry.monitor.history.set_telemetry_addr(Some(addr));
+    }
 
     match matches.opt_present("b") {
         true => registry
             .run_onboot()
-            .unwrap_or_else(|err| eprintln!("Error starting applications: {}", err)),
+            .unwrap_or_else(|err| error!("Error starting applications: {}", err)),
         false => {}
     }
 
@@ -0,0 +1,649 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+use history::{RunHistory, StartCause};
+use kubos_app::RunLevel;
+use libc;
+use limits::ProcessLimits;
+use logs::{self, LogConfig};
+use nix::sys::signal::{kill, Signal};
+use nix::unistd::Pid;
+use registry::{RestartMode, RestartPolicy};
+use std::cmp;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io;
+use std::mem;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::PathBuf;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Default number of milliseconds a stopped app is given to exit before it is killed
+pub const DEFAULT_GRACE_PERIOD: u64 = 5000;
+
+// How often running apps are checked for exits
+const REAP_INTERVAL: u64 = 100;
+// Number of finished app instances to remember
+const EXIT_HISTORY: usize = 50;
+
+/// Everything needed to start (and restart) an app
+#[derive(Clone, Debug)]
+pub struct Launch {
+    /// UUID of the app
+    pub uuid: String,
+    /// Name of the app
+    pub name: String,
+    /// Version of the app
+    pub version: String,
+    /// Absolute path to the app binary
+    pub path: String,
+    /// Run level to start the app with
+    pub run_level: RunLevel,
+    /// Extra arguments to pass to the app, after `--`
+    pub args: Vec<String>,
+    /// Environment variables to set for the app
+    pub env: BTreeMap<String, String>,
+    /// Configuration file to pass to the app with `-c`
+    pub config: Option<String>,
+    /// Resource limits and privileges for the app's process
+    pub limits: ProcessLimits,
+    /// What to do when the app exits
+    pub restart: RestartPolicy,
+    /// Directory the app's output is logged to
+    pub log_dir: PathBuf,
+    /// Why the app is being started
+    pub cause: StartCause,
+}
+
+/// An app instance which is currently running
+#[derive(Clone, Debug)]
+pub struct RunningApp {
+    /// UUID of the app
+    pub uuid: String,
+    /// Name of the app
+    pub name: String,
+    /// Version of the app which was started
+    pub version: String,
+    /// Process ID of the instance
+    pub pid: u32,
+    /// Run level the instance was started with
+    pub run_level: RunLevel,
+    /// Run number the instance's output is logged under, if its output is being logged
+    pub run: Option<u32>,
+    /// ID of the instance's entry in the app's run history
+    pub history_id: u32,
+    /// Time the instance was started, in seconds since the epoch
+    pub started: i32,
+    /// Whether the instance has been asked to stop
+    pub stopping: bool,
+}
+
+/// An app instance which has finished running
+#[derive(Clone, Debug)]
+pub struct AppExit {
+    /// The instance which finished
+    pub app: RunningApp,
+    /// Time the instance finished, in seconds since the epoch
+    pub ended: i32,
+    /// Exit code of the instance, if it exited normally
+    pub code: Option<i32>,
+    /// Signal which terminated the instance, if it didn't exit normally
+    pub signal: Option<i32>,
+    /// The resource limit which the instance most likely exceeded, if it was terminated for
+    /// exceeding one
+    pub limit: Option<&'static str>,
+}
+
+struct Tracked {
+    app: RunningApp,
+    launch: Launch,
+    child: Child,
+    since: Instant,
+    kill_at: Option<Instant>,
+}
+
+// Restart bookkeeping for a single app version
+#[derive(Default)]
+struct RestartState {
+    // Total number of restarts
+    count: u32,
+    // Restarts since the app last ran for longer than its maximum backoff
+    retries: u32,
+}
+
+/// Keeps track of the app processes started by the app service, reaping them as they exit
+/// and restarting `OnBoot` apps according to their restart policy
+pub struct ProcessMonitor {
+    grace_period: Mutex<Duration>,
+    log_config: Mutex<LogConfig>,
+    running: Mutex<Vec<Tracked>>,
+    exits: Mutex<VecDeque<AppExit>>,
+    pending: Mutex<Vec<(Instant, Launch)>>,
+    restarts: Mutex<HashMap<(String, String), RestartState>>,
+    /// Records the runs of each app
+    pub history: RunHistory,
+}
+
+impl Default for ProcessMonitor {
+    fn default() -> Self {
+        ProcessMonitor {
+            grace_period: Mutex::new(Duration::from_millis(DEFAULT_GRACE_PERIOD)),
+            log_config: Mutex::new(LogConfig::default()),
+            running: Mutex::new(vec![]),
+            exits: Mutex::new(VecDeque::new()),
+            pending: Mutex::new(vec![]),
+            restarts: Mutex::new(HashMap::new()),
+            history: RunHistory::default(),
+        }
+    }
+}
+
+impl ProcessMonitor {
+    /// Create a new monitor along with the background thread which reaps and restarts its
+    /// processes. The run history of each app is kept in `apps_dir`. The thread exits once
+    /// the monitor is dropped.
+    pub fn start(apps_dir: &str) -> Arc<ProcessMonitor> {
+        let monitor = Arc::new(ProcessMonitor {
+            history: RunHistory::new(apps_dir),
+            ..Default::default()
+        });
+        let weak: Weak<ProcessMonitor> = Arc::downgrade(&monitor);
+
+        thread::spawn(move || loop {
+            thread::sleep(Duration::from_millis(REAP_INTERVAL));
+            match weak.upgrade() {
+                Some(monitor) => monitor.supervise(),
+                None => break,
+            }
+        });
+
+        monitor
+    }
+
+    /// Set how long stopped apps are given to exit before they are killed
+    pub fn set_grace_period(&self, grace_period: Duration) {
+        *self.grace_period.lock().unwrap() = grace_period;
+    }
+
+    /// Set the limits on the logs kept for each app
+    pub fn set_log_config(&self, config: LogConfig) {
+        *self.log_config.lock().unwrap() = config;
+    }
+
+    /// Start an app and begin tracking it. The app's stdout and stderr are captured in a
+    /// new set of log files. Returns the process ID of the new instance
+    pub fn launch(&self, launch: Launch) -> Result<u32, String> {
+        let app_path = PathBuf::from(&launch.path);
+        if !app_path.exists() {
+            // TODO: Unregister app if path doesn't exist
+            return Err(format!("{} does not exist", &launch.path));
+        }
+
+        let mut command = Command::new(app_path);
+        command
+            .envs(&launch.env)
+            .env("KUBOS_APP_UUID", launch.uuid.clone())
+            .arg("-r")
+            .arg(format!("{}", launch.run_level));
+        if let Some(ref config) = launch.config {
+            command.arg("-c").arg(config);
+        }
+        if !launch.args.is_empty() {
+            command.arg("--").args(&launch.args);
+        }
+        if let Some(ref dir) = launch.limits.working_dir {
+            command.current_dir(dir);
+        }
+        let limits = launch
+            .limits
+            .resolve()
+            .map_err(|err| format!("Failed to apply limits: {}", err))?;
+        #[allow(deprecated)]
+        command.before_exec(move || limits.apply());
+
+        // If the log files can't be created, the app's output is left going to the
+        // service's own stdout and stderr rather than refusing to start the app
+        let log_config = *self.log_config.lock().unwrap();
+        let logs = match logs::start_run(&launch.log_dir, log_config) {
+            Ok(logs) => {
+                command.stdout(Stdio::piped()).stderr(Stdio::piped());
+                Some(logs)
+            }
+            Err(err) => {
+                error!(
+                    "Failed to create log files in {}: {}",
+                    launch.log_dir.display(),
+                    err
+                );
+                None
+            }
+        };
+
+        let mut child = match command.spawn() {
+            Ok(child) => child,
+            Err(err) => return Err(format!("Failed to spawn app: {:?}", err)),
+        };
+
+        let run = match logs {
+            Some((run, stdout_log, stderr_log)) => {
+                if let Some(stdout) = child.stdout.take() {
+                    stdout_log.capture(stdout);
+                }
+                if let Some(stderr) = child.stderr.take() {
+                    stderr_log.capture(stderr);
+                }
+                Some(run)
+            }
+            None => None,
+        };
+
+        let pid = child.id();
+        let started = timestamp();
+        let history_id = self.history.started(&launch, pid, started);
+        self.running.lock().unwrap().push(Tracked {
+            app: RunningApp {
+                uuid: launch.uuid.clone(),
+                name: launch.name.clone(),
+                version: launch.version.clone(),
+                pid,
+                run_level: launch.run_level.clone(),
+                run,
+                history_id,
+                started,
+                stopping: false,
+            },
+            launch,
+            child,
+            since: Instant::now(),
+            kill_at: None,
+        });
+
+        Ok(pid)
+    }
+
+    /// All app instances which are currently running
+    pub fn running(&self) -> Vec<RunningApp> {
+        self.running
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|tracked| tracked.app.clone())
+            .collect()
+    }
+
+    /// Recently finished app instances, most recent first
+    pub fn exits(&self) -> Vec<AppExit> {
+        self.exits.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Process ID of a running instance of a particular app version
+    pub fn pid_of(&self, uuid: &str, version: &str) -> Option<u32> {
+        self.running
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|tracked| tracked.app.uuid == uuid && tracked.app.version == version)
+            .map(|tracked| tracked.app.pid)
+    }
+
+    /// Number of times a particular app version has been automatically restarted
+    pub fn restart_count(&self, uuid: &str, version: &str) -> u32 {
+        self.restarts
+            .lock()
+            .unwrap()
+            .get(&(uuid.to_owned(), version.to_owned()))
+            .map_or(0, |state| state.count)
+    }
+
+    /// Ask the running instances of an app to stop by sending them SIGTERM. Any instance
+    /// still running once the grace period has passed is killed. Stopped instances are not
+    /// restarted, and any pending restart of the app is cancelled.
+    ///
+    /// Returns the process IDs which were signalled
+    ///
+    /// # Arguments
+    ///
+    /// * `uuid` - UUID of the app to stop
+    /// * `pid` - Only stop the instance with this process ID
+    /// * `grace_period` - Overrides the default grace period
+    pub fn stop(
+        &self,
+        uuid: &str,
+        pid: Option<u32>,
+        grace_period: Option<Duration>,
+    ) -> Result<Vec<u32>, String> {
+        let grace_period = grace_period.unwrap_or(*self.grace_period.lock().unwrap());
+        let kill_at = Instant::now() + grace_period;
+
+        self.signal(uuid, pid, |tracked| {
+            kill(Pid::from_raw(tracked.app.pid as i32), Signal::SIGTERM)
+                .map_err(|err| format!("Failed to stop process {}: {}", tracked.app.pid, err))?;
+            tracked.app.stopping = true;
+            tracked.kill_at = Some(kill_at);
+            Ok(())
+        })
+    }
+
+    /// Immediately kill the running instances of an app with SIGKILL. Killed instances are not
+    /// restarted, and any pending restart of the app is cancelled.
+    ///
+    /// Returns the process IDs which were killed
+    ///
+    /// # Arguments
+    ///
+    /// * `uuid` - UUID of the app to kill
+    /// * `pid` - Only kill the instance with this process ID
+    pub fn kill(&self, uuid: &str, pid: Option<u32>) -> Result<Vec<u32>, String> {
+        self.signal(uuid, pid, |tracked| {
+            tracked
+                .child
+                .kill()
+                .map_err(|err| format!("Failed to kill process {}: {}", tracked.app.pid, err))?;
+            tracked.app.stopping = true;
+            Ok(())
+        })
+    }
+
+    /// Stop the running instances of an app, or of one version of it, and wait until they have
+    /// exited and been reaped, so that nothing is still using the app's files. Instances still
+    /// running once the grace period has passed are killed. Any pending restart is cancelled.
+    ///
+    /// # Arguments
+    ///
+    /// * `uuid` - UUID of the app to stop
+    /// * `version` - Only stop the instances of this version
+    pub fn stop_and_wait(&self, uuid: &str, version: Option<&str>) -> Result<(), String> {
+        let selected = |app: &str, app_version: &str| {
+            app == uuid
+                && match version {
+                    Some(version) => app_version == version,
+                    None => true,
+                }
+        };
+
+        {
+            let kill_at = Instant::now() + *self.grace_period.lock().unwrap();
+            let mut running = self.running.lock().unwrap();
+            self.pending
+                .lock()
+                .unwrap()
+                .retain(|(_, launch)| !selected(&launch.uuid, &launch.version));
+
+            for tracked in running
+                .iter_mut()
+                .filter(|tracked| selected(&tracked.app.uuid, &tracked.app.version))
+            {
+                if tracked.app.stopping {
+                    continue;
+                }
+                kill(Pid::from_raw(tracked.app.pid as i32), Signal::SIGTERM).map_err(|err| {
+                    format!("Failed to stop process {}: {}", tracked.app.pid, err)
+                })?;
+                tracked.app.stopping = true;
+                tracked.kill_at = Some(kill_at);
+            }
+        }
+
+        loop {
+            self.reap();
+            if !self
+                .running
+                .lock()
+                .unwrap()
+                .iter()
+                .any(|tracked| selected(&tracked.app.uuid, &tracked.app.version))
+            {
+                return Ok(());
+            }
+            thread::sleep(Duration::from_millis(REAP_INTERVAL));
+        }
+    }
+
+    fn signal<F>(&self, uuid: &str, pid: Option<u32>, mut action: F) -> Result<Vec<u32>, String>
+    where
+        F: FnMut(&mut Tracked) -> Result<(), String>,
+    {
+        let mut running = self.running.lock().unwrap();
+        let mut pids = vec![];
+
+        let cancelled = if pid.is_none() {
+            let mut pending = self.pending.lock().unwrap();
+            let before = pending.len();
+            pending.retain(|(_, launch)| launch.uuid != uuid);
+            pending.len() != before
+        } else {
+            false
+        };
+
+        for tracked in running.iter_mut().filter(|tracked| {
+            tracked.app.uuid == uuid
+                && match pid {
+                    Some(pid) => tracked.app.pid == pid,
+                    None => true,
+                }
+        }) {
+            action(tracked)?;
+            pids.push(tracked.app.pid);
+        }
+
+        if pids.is_empty() && !cancelled {
+            return Err(match pid {
+                Some(pid) => format!("App {} has no running process {}", uuid, pid),
+                None => format!("App {} is not running", uuid),
+            });
+        }
+
+        Ok(pids)
+    }
+
+    /// Reap finished instances, then start any restarts which are due
+    pub fn supervise(&self) {
+        self.reap();
+
+        let now = Instant::now();
+        let due: Vec<Launch> = {
+            let mut pending = self.pending.lock().unwrap();
+            let (due, waiting) = pending.drain(..).partition(|&(at, _)| at <= now);
+            *pending = waiting;
+            due.into_iter().map(|(_, launch)| launch).collect()
+        };
+
+        for launch in due {
+            info!("Restarting app {} ({})", launch.name, launch.uuid);
+            if let Err(err) = self.launch(launch.clone()) {
+                error!("Failed to restart app {}: {}", launch.name, err);
+                self.schedule_restart(launch);
+            }
+        }
+    }
+
+    /// Collect the exit status of any instances which have finished, and kill stopped
+    /// instances which have outlived their grace period. Restarts are scheduled for
+    /// finished instances whose restart policy calls for one.
+    ///
+    /// Returns the instances which finished since the last call
+    pub fn reap(&self) -> Vec<AppExit> {
+        let mut running = self.running.lock().unwrap();
+        let now = Instant::now();
+        let mut finished = vec![];
+        let mut restart = vec![];
+
+        let mut index = 0;
+        while index < running.len() {
+            let status = match try_wait(running[index].app.pid) {
+                Ok(status) => status,
+                Err(err) => {
+                    error!(
+                        "Failed to check status of process {}: {}",
+                        running[index].app.pid, err
+                    );
+                    None
+                }
+            };
+
+            match status {
+                Some((status, cpu_used)) => {
+                    let tracked = running.remove(index);
+                    let limit = tracked.launch.limits.exceeded(status.signal(), cpu_used);
+                    if let Some(limit) = limit {
+                        warn!(
+                            "App {} (process {}) exceeded its {} limit",
+                            tracked.app.name, tracked.app.pid, limit
+                        );
+                    }
+                    let exit = AppExit {
+                        app: tracked.app,
+                        ended: timestamp(),
+                        code: status.code(),
+                        signal: status.signal(),
+                        limit,
+                    };
+
+                    if wants_restart(&tracked.launch, &exit) {
+                        let mut launch = tracked.launch;
+                        launch.cause = StartCause::Restart(exit.app.history_id);
+                        restart.push((launch, tracked.since));
+                    }
+                    finished.push(exit);
+                }
+                None => {
+                    let tracked = &mut running[index];
+                    let overdue = match tracked.kill_at {
+                        Some(kill_at) => kill_at <= now,
+                        None => false,
+                    };
+                    if overdue {
+                        warn!(
+                            "App {} did not stop within its grace period. Killing process {}",
+                            tracked.app.name, tracked.app.pid
+                        );
+                        let _ = tracked.child.kill();
+                        tracked.kill_at = None;
+                    }
+                    index += 1;
+                }
+            }
+        }
+        drop(running);
+
+        for exit in &finished {
+            self.history.finished(exit);
+        }
+
+        for (launch, since) in restart {
+            // An instance which stayed up for longer than the maximum backoff is considered
+            // to have recovered, so it gets a fresh set of retries
+            if since.elapsed() >= Duration::from_millis(launch.restart.max_backoff) {
+                if let Some(state) = self
+                    .restarts
+                    .lock()
+                    .unwrap()
+                    .get_mut(&(launch.uuid.clone(), launch.version.clone()))
+                {
+                    state.retries = 0;
+                }
+            }
+            self.schedule_restart(launch);
+        }
+
+        if !finished.is_empty() {
+            let mut exits = self.exits.lock().unwrap();
+            for exit in &finished {
+                exits.push_front(exit.clone());
+            }
+            exits.truncate(EXIT_HISTORY);
+        }
+
+        finished
+    }
+
+    // Queue a restart of an app after its backoff delay, unless it has used up its retries
+    fn schedule_restart(&self, launch: Launch) {
+        let mut restarts = self.restarts.lock().unwrap();
+        let state = restarts
+            .entry((launch.uuid.clone(), launch.version.clone()))
+            .or_default();
+
+        let exhausted = match launch.restart.max_retries {
+            Some(max) => state.retries >= max,
+            None => false,
+        };
+        if exhausted {
+            error!(
+                "App {} has failed {} times. Giving up on restarting it",
+                launch.name, state.retries
+            );
+            return;
+        }
+
+        let delay = backoff(&launch.restart, state.retries);
+        state.retries += 1;
+        state.count += 1;
+
+        self.pending
+            .lock()
+            .unwrap()
+            .push((Instant::now() + delay, launch));
+    }
+}
+
+// Reap a process if it has finished, returning its exit status along with the number of seconds
+// of CPU time it used, which `Child::try_wait` doesn't give
+fn try_wait(pid: u32) -> io::Result<Option<(ExitStatus, u64)>> {
+    let mut status = 0;
+    let mut usage: libc::rusage = unsafe { mem::zeroed() };
+    match unsafe { libc::wait4(pid as libc::pid_t, &mut status, libc::WNOHANG, &mut usage) } {
+        0 => Ok(None),
+        -1 => Err(io::Error::last_os_error()),
+        _ => {
+            let cpu_used = usage.ru_utime.tv_sec + usage.ru_stime.tv_sec;
+            Ok(Some((ExitStatus::from_raw(status), cpu_used as u64)))
+        }
+    }
+}
+
+// Only apps started at boot are supervised
+fn wants_restart(launch: &Launch, exit: &AppExit) -> bool {
+    if launch.run_level != RunLevel::OnBoot || exit.app.stopping {
+        return false;
+    }
+
+    match launch.restart.policy {
+        RestartMode::Never => false,
+        RestartMode::OnFailure => exit.code != Some(0),
+        RestartMode::Always => true,
+    }
+}
+
+// The delay before a restart doubles with each consecutive retry, up to the maximum backoff
+fn backoff(policy: &RestartPolicy, retries: u32) -> Duration {
+    let factor = 1u64
+        .checked_shl(cmp::min(retries, 32))
+        .unwrap_or(std::u64::MAX);
+    Duration::from_millis(cmp::min(
+        policy.backoff.saturating_mul(factor),
+        policy.max_backoff,
+    ))
+}
+
+/// Current time in seconds since the epoch
+pub fn timestamp() -> i32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|dur| dur.as_secs() as i32)
+        .unwrap_or(0)
+}
@@ -0,0 +1,248 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Application packages which are registered with the service.
+//!
+//! A package is either a directory or a tarball (`.tar`, `.tar.gz` or `.tgz`) containing a
+//! `manifest.toml` file along with the application's executable and any supporting files.
+//! Tarballs may either hold the package files directly or wrap them in a single top-level
+//! directory.
+
+use flate2::read::GzDecoder;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use tar::Archive;
+use tempfile::TempDir;
+
+/// Name of the manifest file within a package
+pub const MANIFEST_FILE: &'static str = "manifest.toml";
+
+/// The contents of an application package
+pub struct Package {
+    root: PathBuf,
+    /// Paths of the files in the package, other than the manifest, relative to its root
+    files: Vec<String>,
+    // Holds the extracted contents of a tarball until the package is dropped
+    _extracted: Option<TempDir>,
+}
+
+impl Package {
+    /// Open the package at `path`, extracting it first if it is a tarball
+    pub fn open(path: &str) -> Result<Package, String> {
+        let package_path = Path::new(path);
+        if !package_path.exists() {
+            return Err(format!("{} does not exist", path));
+        }
+
+        let (root, extracted) = if package_path.is_dir() {
+            (package_path.to_path_buf(), None)
+        } else if is_tarball(path) {
+            let dir = extract(package_path)?;
+            (package_root(dir.path()), Some(dir))
+        } else {
+            return Err(format!("{} is not a directory or tarball", path));
+        };
+
+        if !root.join(MANIFEST_FILE).is_file() {
+            return Err("Failed to find manifest file".to_owned());
+        }
+
+        let mut files = vec![];
+        list_files(&root, Path::new(""), &mut files)?;
+        files.retain(|file| file != MANIFEST_FILE);
+        files.sort();
+
+        Ok(Package {
+            root,
+            files,
+            _extracted: extracted,
+        })
+    }
+
+    /// Read the package's manifest file
+    pub fn manifest(&self) -> Result<String, String> {
+        let mut data = String::new();
+        fs::File::open(self.root.join(MANIFEST_FILE))
+            .and_then(|mut fp| fp.read_to_string(&mut data))
+            .map_err(|error| format!("Failed to read manifest: {}", error))?;
+        Ok(data)
+    }
+
+    /// Work out which file is the application's executable. The manifest must name it unless
+    /// it is the only file in the package
+    pub fn executable(&self, name: Option<&String>) -> Result<String, String> {
+        match name {
+            Some(name) => {
+                if self.files.contains(name) {
+                    Ok(name.clone())
+                } else {
+                    Err(format!("Executable {} not found in package", name))
+                }
+            }
+            None => match self.files.len() {
+                0 => Err("Failed to find app file".to_owned()),
+                1 => Ok(self.files[0].clone()),
+                _ => Err(
+                    "The manifest must name the executable when the package contains more than one file"
+                        .to_owned(),
+                ),
+            },
+        }
+    }
+
+    /// Check the package's files against the SHA-256 checksums listed in its manifest.
+    ///
+    /// If any checksums are given then every file in the package must be listed
+    pub fn verify(&self, checksums: &BTreeMap<String, String>) -> Result<(), String> {
+        if checksums.is_empty() {
+            return Ok(());
+        }
+
+        if let Some(file) = self
+            .files
+            .iter()
+            .find(|file| !checksums.contains_key(*file))
+        {
+            return Err(format!("No checksum given for {}", file));
+        }
+
+        for (file, expected) in checksums {
+            if !self.files.contains(file) {
+                return Err(format!(
+                    "File {} listed in checksums not found in package",
+                    file
+                ));
+            }
+
+            let actual = sha256(&self.root.join(file))
+                .map_err(|err| format!("Failed to read {}: {}", file, err))?;
+            if actual != expected.to_lowercase() {
+                return Err(format!(
+                    "Checksum mismatch for {}: expected {}, found {}",
+                    file, expected, actual
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Copy the package's files, including the manifest, into `dest`
+    pub fn install(&self, dest: &Path) -> Result<(), String> {
+        for file in self
+            .files
+            .iter()
+            .map(|file| file.as_str())
+            .chain(Some(MANIFEST_FILE))
+        {
+            let target = dest.join(file);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent).map_err(|err| {
+                    format!("Couldn't create app dir {}: {:?}", parent.display(), err)
+                })?;
+            }
+
+            fs::copy(self.root.join(file), &target)
+                .map_err(|err| format!("Couldn't copy app file {}: {:?}", file, err))?;
+        }
+
+        Ok(())
+    }
+}
+
+fn is_tarball(path: &str) -> bool {
+    path.ends_with(".tar") || path.ends_with(".tar.gz") || path.ends_with(".tgz")
+}
+
+fn extract(path: &Path) -> Result<TempDir, String> {
+    let dir = TempDir::new().map_err(|err| format!("Failed to create temp dir: {}", err))?;
+    let file = fs::File::open(path).map_err(|err| format!("Failed to open tarball: {}", err))?;
+
+    let result = if path.to_string_lossy().ends_with(".tar") {
+        Archive::new(file).unpack(dir.path())
+    } else {
+        Archive::new(GzDecoder::new(file)).unpack(dir.path())
+    };
+    result.map_err(|err| format!("Failed to extract tarball: {}", err))?;
+
+    Ok(dir)
+}
+
+// Tarballs are often created from the package directory itself, so step into a lone
+// top-level directory if the manifest isn't found at the top level
+fn package_root(dir: &Path) -> PathBuf {
+    if dir.join(MANIFEST_FILE).exists() {
+        return dir.to_path_buf();
+    }
+
+    let entries: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|e| e.path())
+            .collect(),
+        Err(_) => return dir.to_path_buf(),
+    };
+
+    match entries.as_slice() {
+        [only] if only.is_dir() => only.clone(),
+        _ => dir.to_path_buf(),
+    }
+}
+
+fn list_files(root: &Path, relative: &Path, files: &mut Vec<String>) -> Result<(), String> {
+    let entries = fs::read_dir(root.join(relative))
+        .map_err(|error| format!("Failed to read directory: {}", error))?;
+
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = relative.join(entry.file_name());
+        let file_type = entry
+            .file_type()
+            .map_err(|error| format!("Failed to read directory: {}", error))?;
+
+        if file_type.is_dir() {
+            list_files(root, &path, files)?;
+        } else if file_type.is_file() {
+            files.push(path.to_string_lossy().into_owned());
+        } else {
+            return Err(format!(
+                "{} is not a regular file or directory",
+                path.display()
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Calculate the SHA-256 checksum of a file as a lowercase hex string
+pub fn sha256(path: &Path) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::default();
+    let mut buf = [0; 4096];
+
+    loop {
+        let count = file.read(&mut buf)?;
+        if count == 0 {
+            break;
+        }
+        hasher.input(&buf[..count]);
+    }
+
+    Ok(format!("{:x}", hasher.result()))
+}
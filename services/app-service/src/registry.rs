@@ -13,13 +13,22 @@
  * See the License for the specific language governing permissions and
  * limitations under the License.
  */
+use dependencies::{self, Dependencies, ServiceChecker};
+use history::StartCause;
 use kubos_app::RunLevel;
+use limits::ProcessLimits;
+use monitor::{Launch, ProcessMonitor};
+use package::Package;
+use schedule::Scheduler;
 use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
 use std::fs;
 use std::io::{Read, Write};
 use std::os::unix;
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::sync::Arc;
+use std::time::Duration;
 
 use toml;
 use uuid::Uuid;
@@ -27,6 +36,65 @@ use uuid::Uuid;
 /// The default application registry directory in KubOS
 pub const K_APPS_DIR: &'static str = "/home/system/kubos/apps";
 
+// Default number of milliseconds to wait before the first restart of a failed app
+const DEFAULT_RESTART_BACKOFF: u64 = 1000;
+// Default limit on the delay between restarts, in milliseconds
+const DEFAULT_RESTART_MAX_BACKOFF: u64 = 60000;
+
+/// When an `OnBoot` application should be restarted after it exits
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartMode {
+    /// Never restart the application
+    Never,
+    /// Restart the application if it exits with a non-zero code or is killed by a signal
+    OnFailure,
+    /// Always restart the application when it exits
+    Always,
+}
+
+impl Default for RestartMode {
+    fn default() -> Self {
+        RestartMode::Never
+    }
+}
+
+/// The restart policy of an application, read from the `[restart]` section of its manifest
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct RestartPolicy {
+    /// When to restart the application
+    #[serde(default)]
+    pub policy: RestartMode,
+    /// Number of consecutive restarts to attempt before giving up. Unlimited if not given
+    pub max_retries: Option<u32>,
+    /// Milliseconds to wait before the first restart. Doubles with each consecutive restart
+    #[serde(default = "default_backoff")]
+    pub backoff: u64,
+    /// Maximum number of milliseconds to wait between restarts. An application which runs for
+    /// longer than this before exiting gets a fresh set of retries
+    #[serde(default = "default_max_backoff")]
+    pub max_backoff: u64,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        RestartPolicy {
+            policy: RestartMode::default(),
+            max_retries: None,
+            backoff: default_backoff(),
+            max_backoff: default_max_backoff(),
+        }
+    }
+}
+
+fn default_backoff() -> u64 {
+    DEFAULT_RESTART_BACKOFF
+}
+
+fn default_max_backoff() -> u64 {
+    DEFAULT_RESTART_MAX_BACKOFF
+}
+
 /// The high level metadata of an application
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct AppMetadata {
@@ -36,6 +104,45 @@ pub struct AppMetadata {
     pub version: String,
     /// The author of the application
     pub author: String,
+    /// Path of the application's executable within its package. May be omitted if the
+    /// executable is the only file in the package besides the manifest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub executable: Option<String>,
+    /// Configuration file passed to the application with `-c`. Relative paths are resolved
+    /// against the directory the application is installed in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config: Option<String>,
+    /// Arguments passed to the application when it is started without any explicit arguments
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    /// SHA-256 checksums of the files in the package, keyed by their path within the package.
+    /// If given, the files are verified before the application is registered
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub checksums: BTreeMap<String, String>,
+    /// Environment variables set for the application whenever it is started
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Apps and services which must be up before the application is started at boot
+    #[serde(default)]
+    pub dependencies: Dependencies,
+    /// Resource limits and privileges for the application's process
+    #[serde(default)]
+    pub limits: ProcessLimits,
+    /// What to do when the application exits after being started at boot
+    #[serde(default)]
+    pub restart: RestartPolicy,
+}
+
+/// Options which override the defaults in an application's manifest when it is started
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct StartOptions {
+    /// Arguments to pass to the application instead of the manifest's `args`
+    pub args: Option<Vec<String>>,
+    /// Configuration file to use instead of the manifest's `config`
+    pub config: Option<String>,
+    /// Environment variables to set in addition to, or in place of, the manifest's `env`
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
 }
 
 /// Kubos App struct
@@ -45,12 +152,67 @@ pub struct App {
     pub uuid: String,
     /// The process ID of the application, if it's currently running (0 otherwise)
     pub pid: u32,
+    /// The number of times the application has been automatically restarted
+    #[serde(skip)]
+    pub restarts: u32,
     /// The absolute path to the application binary
     pub path: String,
     /// The associated metadata of the application
     pub metadata: AppMetadata,
 }
 
+impl App {
+    /// Everything needed to start this app, applying any overrides of its manifest defaults
+    ///
+    /// # Arguments
+    ///
+    /// * `apps_dir` - The root directory of the registry the app belongs to
+    /// * `run_level` - Which Run Level to run the app with
+    /// * `options` - Overrides for the app's manifest defaults
+    /// * `cause` - Why the app is being started
+    pub fn launch(
+        &self,
+        apps_dir: &str,
+        run_level: RunLevel,
+        options: StartOptions,
+        cause: StartCause,
+    ) -> Launch {
+        let mut env = self.metadata.env.clone();
+        env.extend(options.env);
+
+        // Relative config paths are resolved against the app's install directory, so that
+        // a package can ship its own config file
+        let app_dir = format!("{}/{}/{}", apps_dir, self.uuid, self.metadata.version);
+        let config = options
+            .config
+            .or_else(|| self.metadata.config.clone())
+            .map(|config| Path::new(&app_dir).join(config).to_string_lossy().into_owned());
+
+        let mut limits = self.metadata.limits.clone();
+        limits.working_dir = limits.working_dir.map(|dir| {
+            Path::new(&app_dir)
+                .join(dir)
+                .to_string_lossy()
+                .into_owned()
+        });
+
+        Launch {
+            uuid: self.uuid.clone(),
+            name: self.metadata.name.clone(),
+            version: self.metadata.version.clone(),
+            path: self.path.clone(),
+            run_level,
+            args: options.args.unwrap_or_else(|| self.metadata.args.clone()),
+            env,
+            config,
+            limits,
+            restart: self.metadata.restart.clone(),
+            log_dir: log_dir(apps_dir, &self.uuid),
+            cause,
+        }
+    }
+}
+
 /// AppRegistryEntry
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct AppRegistryEntry {
@@ -82,13 +244,36 @@ impl AppRegistryEntry {
         }
     }
 
+    // The directory this version of the app is installed in. The executable may be nested
+    // within the package, so strip its path within the package from the app's path
+    fn version_dir(&self) -> PathBuf {
+        let mut dir = PathBuf::from(&self.app.path);
+        let depth = self
+            .app
+            .metadata
+            .executable
+            .as_ref()
+            .map_or(1, |executable| Path::new(executable).components().count());
+        for _ in 0..depth {
+            dir.pop();
+        }
+        dir
+    }
+
     fn save(&self) -> Result<bool, String> {
-        let mut app_toml = PathBuf::from(self.app.path.clone());
-        app_toml.set_file_name("app.toml");
+        let version_dir = self.version_dir();
+        let app_toml = version_dir.join("app.toml");
+        let tmp_toml = version_dir.join(".app.toml.tmp");
 
-        match fs::File::create(app_toml) {
+        // Write to a temporary file and move it into place, so that app.toml is never left
+        // partially written
+        match fs::File::create(&tmp_toml) {
             Ok(mut file) => match toml::to_string(&self) {
-                Ok(toml_str) => match file.write_all(&toml_str.into_bytes()) {
+                Ok(toml_str) => match file
+                    .write_all(&toml_str.into_bytes())
+                    .and_then(|_| file.sync_all())
+                    .and_then(|_| fs::rename(&tmp_toml, &app_toml))
+                {
                     Ok(_) => Ok(true),
                     Err(err) => Err(format!("{}", err)),
                 },
@@ -99,6 +284,98 @@ impl AppRegistryEntry {
     }
 }
 
+/// Load the active version of an app directly from a registry directory, through its `active`
+/// symlink. Unlike [`AppRegistry`] lookups, this doesn't need access to the registry itself
+///
+/// [`AppRegistry`]: struct.AppRegistry.html
+pub fn load_active(apps_dir: &str, app_uuid: &str) -> Result<App, String> {
+    AppRegistryEntry::from_dir(&format!("{}/active/{}", apps_dir, app_uuid))
+        .map(|entry| entry.app)
+        .ok_or_else(|| format!("Active app with UUID {} does not exist", app_uuid))
+}
+
+fn log_dir(apps_dir: &str, app_uuid: &str) -> PathBuf {
+    PathBuf::from(format!("{}/{}/logs", apps_dir, app_uuid))
+}
+
+// Index of the highest installed version of an app
+fn newest_version(entries: &[AppRegistryEntry], app_uuid: &str) -> Option<usize> {
+    entries
+        .iter()
+        .enumerate()
+        .filter(|(_, e)| e.app.uuid == app_uuid)
+        .max_by(|(_, a), (_, b)| compare_versions(&a.app.metadata.version, &b.app.metadata.version))
+        .map(|(index, _)| index)
+}
+
+// Total size of the files under a directory, in bytes. Symlinks aren't followed
+fn dir_size(path: &Path) -> u64 {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(_) => return 0,
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+
+    fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| dir_size(&entry.path()))
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+// Order two version strings. Versions are compared component by component (split on `.`),
+// numerically where both components are numbers
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let mut a_parts = a.split('.');
+    let mut b_parts = b.split('.');
+
+    loop {
+        match (a_parts.next(), b_parts.next()) {
+            (Some(a), Some(b)) => {
+                let order = match (a.parse::<u64>(), b.parse::<u64>()) {
+                    (Ok(a), Ok(b)) => a.cmp(&b),
+                    _ => a.cmp(b),
+                };
+                if order != Ordering::Equal {
+                    return order;
+                }
+            }
+            (Some(_), None) => return Ordering::Greater,
+            (None, Some(_)) => return Ordering::Less,
+            (None, None) => return Ordering::Equal,
+        }
+    }
+}
+
+/// The disk space used by a version of an application
+#[derive(Clone, Debug, PartialEq)]
+pub struct VersionDiskUsage {
+    /// The version of the app
+    pub version: String,
+    /// Bytes used by the version's files
+    pub bytes: u64,
+}
+
+/// The disk space used by an application
+#[derive(Clone, Debug, PartialEq)]
+pub struct AppDiskUsage {
+    /// The UUID of the app
+    pub uuid: String,
+    /// The name of the app's active version
+    pub name: String,
+    /// Usage of each installed version, lowest version first
+    pub versions: Vec<VersionDiskUsage>,
+    /// Bytes used by the app's logs
+    pub logs: u64,
+    /// Bytes used by everything stored for the app, including its versions, logs and run history
+    pub total: u64,
+}
+
 /// AppRegistry
 #[derive(Deserialize, Serialize)]
 pub struct AppRegistry {
@@ -106,6 +383,21 @@ pub struct AppRegistry {
     pub entries: RefCell<Vec<AppRegistryEntry>>,
     /// The managed root directory of the AppRegistry
     pub apps_dir: String,
+    /// Tracks the app processes started by the registry
+    #[serde(skip)]
+    pub monitor: Arc<ProcessMonitor>,
+    /// Runs apps at scheduled times
+    #[serde(skip)]
+    pub scheduler: Arc<Scheduler>,
+    /// Config file the addresses of services are read from when checking the dependencies of
+    /// apps started at boot. The config file given on the command line (or the default config
+    /// file) is used if not set
+    #[serde(skip)]
+    pub config_path: Option<String>,
+    /// The number of versions of each app to keep. When a new version is registered, the
+    /// oldest versions beyond this number are uninstalled. All versions are kept if not set
+    #[serde(skip)]
+    pub keep_versions: Option<usize>,
 }
 
 impl AppRegistry {
@@ -122,15 +414,20 @@ impl AppRegistry {
     /// let registry = AppRegistry::new_from_dir("/my/apps");
     /// ```
     pub fn new_from_dir(apps_dir: &str) -> AppRegistry {
+        let monitor = ProcessMonitor::start(apps_dir);
         let registry = AppRegistry {
             entries: RefCell::new(Vec::new()),
             apps_dir: String::from(apps_dir),
+            scheduler: Scheduler::start(apps_dir, monitor.clone()),
+            monitor,
+            config_path: None,
+            keep_versions: None,
         };
 
         let apps_dir = Path::new(apps_dir);
         if !apps_dir.exists() {
             if let Err(err) = fs::create_dir(apps_dir) {
-                eprintln!("Couldn't create apps dir {}: {:?}", apps_dir.display(), err);
+                error!("Couldn't create apps dir {}: {:?}", apps_dir.display(), err);
                 return registry;
             }
         }
@@ -138,7 +435,7 @@ impl AppRegistry {
         let active_dir = apps_dir.clone().join("active");
         if !active_dir.exists() {
             if let Err(err) = fs::create_dir_all(&active_dir) {
-                eprintln!(
+                error!(
                     "Couldn't create 'active' dir {}: {:?}",
                     active_dir.display(),
                     err
@@ -215,12 +512,15 @@ impl AppRegistry {
         reg_entries
     }
 
-    /// Register an application binary with the AppRegistry, extracting metadata and installing it
+    /// Register an application package with the AppRegistry, extracting metadata and installing it
     /// into the proper folder structure under the AppRegistry directory.
     ///
+    /// Any checksums listed in the package's manifest are verified before the package is
+    /// installed, so a corrupted package never replaces the active version of an application.
+    ///
     /// # Arguments
     ///
-    /// * `path` - The path to an application binary
+    /// * `path` - The path to an application package directory or tarball
     ///
     /// # Examples
     ///
@@ -230,52 +530,15 @@ impl AppRegistry {
     /// registry.register("/home/kubos/my-app-bin");
     /// ```
     pub fn register(&self, path: &str) -> Result<AppRegistryEntry, String> {
-        let app_path = Path::new(path);
-        if !app_path.exists() {
-            return Err(format!("{} does not exist", path));
-        }
-
-        if !app_path.is_dir() {
-            return Err(format!("{} is not a directory", path));
-        }
+        let package = Package::open(path)?;
 
-        let files: Vec<fs::DirEntry> = match fs::read_dir(app_path) {
-            Ok(v) => v.filter_map(|file| file.ok()).collect(),
-            Err(error) => return Err(format!("Failed to read directory: {}", error)),
-        };
-
-        if files.len() != 2 {
-            return Err("Exactly two files should be present in the app directory".to_owned());
-        }
-
-        let mut manifest_file: Option<fs::DirEntry> = None;
-        let mut app_file: Option<fs::DirEntry> = None;
-
-        for file in files {
-            match file.file_name().to_str() {
-                Some("manifest.toml") => manifest_file = Some(file),
-                Some(_) => app_file = Some(file),
-                _ => {}
-            }
-        }
-
-        let manifest = match manifest_file {
-            Some(file) => file,
-            None => return Err("Failed to find manifest file".to_owned()),
-        };
-        let app = match app_file {
-            Some(file) => file,
-            None => return Err("Failed to find app file".to_owned()),
-        };
-
-        let mut data = String::new();
-        fs::File::open(manifest.path())
-            .and_then(|mut fp| fp.read_to_string(&mut data))
-            .or_else(|error| return Err(format!("Failed to read manifest: {}", error)))?;
-
-        let metadata: AppMetadata = toml::from_str(&data)
+        let mut metadata: AppMetadata = toml::from_str(&package.manifest()?)
             .or_else(|error| return Err(format!("Failed to parse manifest: {}", error)))?;
 
+        let executable = package.executable(metadata.executable.as_ref())?;
+        package.verify(&metadata.checksums)?;
+        metadata.executable = Some(executable.clone());
+
         let mut entries = self.entries.borrow_mut();
         let mut app_uuid = Uuid::new_v4().hyphenated().to_string();
         // TODO: Do the lookup based on the passed UUID
@@ -309,45 +572,17 @@ impl AppRegistry {
             })?;
         }
 
-        match fs::copy(app.path(), app_dir.join(app.file_name())) {
-            Err(err) => {
-                return Err(format!("Couldn't copy app binary: {:?}", err));
-            }
-            Ok(_) => {}
-        }
+        package.install(app_dir)?;
 
-        let active_symlink = PathBuf::from(format!("{}/active/{}", self.apps_dir, app_uuid));
-        if active_symlink.exists() {
-            match fs::remove_file(active_symlink.clone()) {
-                Err(err) => {
-                    return Err(format!(
-                        "Couldn't remove symlink {}: {:?}",
-                        active_symlink.display(),
-                        err
-                    ));
-                }
-                Ok(_) => {}
-            }
-        }
-
-        match unix::fs::symlink(&app_dir_str, active_symlink.clone()) {
-            Err(err) => {
-                return Err(format!(
-                    "Couldn't symlink {} to {}: {:?}",
-                    active_symlink.display(),
-                    app_dir_str,
-                    err
-                ));
-            }
-            Ok(_) => {}
-        }
+        self.link_active(&app_uuid, &app_dir_str)?;
 
         let reg_entry = AppRegistryEntry {
             app: App {
                 uuid: app_uuid,
                 metadata: metadata,
                 pid: 0,
-                path: format!("{}/{}", app_dir_str, app.file_name().to_string_lossy()),
+                restarts: 0,
+                path: format!("{}/{}", app_dir_str, executable),
             },
             active_version: true,
         };
@@ -356,10 +591,180 @@ impl AppRegistry {
         entries.push(reg_entry);
         // Create the app.toml file and save the metadata information
         entries[entries.len() - 1].save()?;
-        Ok(entries[entries.len() - 1].clone())
+        let entry = entries[entries.len() - 1].clone();
+        drop(entries);
+
+        if let Some(keep) = self.keep_versions {
+            match self.prune_versions(&entry.app.uuid, keep) {
+                Ok(removed) => {
+                    for version in removed {
+                        info!(
+                            "Removed version {} of app {}",
+                            version, entry.app.metadata.name
+                        );
+                    }
+                }
+                Err(err) => warn!(
+                    "Failed to remove old versions of {}: {}",
+                    entry.app.metadata.name, err
+                ),
+            }
+        }
+
+        Ok(entry)
+    }
+
+    // Point the app's `active/<uuid>` symlink at a version directory. The new link is created
+    // under a temporary name and then renamed over the old one, so the link always exists
+    fn link_active(&self, app_uuid: &str, version_dir: &str) -> Result<(), String> {
+        let active_symlink = PathBuf::from(format!("{}/active/{}", self.apps_dir, app_uuid));
+        let tmp_symlink = PathBuf::from(format!("{}/active/.{}.tmp", self.apps_dir, app_uuid));
+
+        // Clean up after any previous failed attempt
+        let _ = fs::remove_file(&tmp_symlink);
+
+        unix::fs::symlink(version_dir, &tmp_symlink).map_err(|err| {
+            format!(
+                "Couldn't symlink {} to {}: {:?}",
+                tmp_symlink.display(),
+                version_dir,
+                err
+            )
+        })?;
+
+        fs::rename(&tmp_symlink, &active_symlink).map_err(|err| {
+            let _ = fs::remove_file(&tmp_symlink);
+            format!(
+                "Couldn't replace symlink {}: {:?}",
+                active_symlink.display(),
+                err
+            )
+        })
+    }
+
+    /// Make a particular version of an application the active one. Any running instances of
+    /// the previously active version are left running.
+    ///
+    /// # Arguments
+    ///
+    /// * `app_uuid` - The UUID generated for the app when it was registered
+    /// * `version` - The version to activate
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use kubos_app::registry::AppRegistry;
+    /// let registry = AppRegistry::new();
+    /// registry.set_version("01234567-89ab-cdef0-1234-56789abcdef0", "1.0");
+    /// ```
+    pub fn set_version(&self, app_uuid: &str, version: &str) -> Result<AppRegistryEntry, String> {
+        let mut entries = self.entries.borrow_mut();
+
+        let target = match entries
+            .iter()
+            .position(|e| e.app.uuid == app_uuid && e.app.metadata.version == version)
+        {
+            Some(index) => index,
+            None => {
+                return Err(format!(
+                    "Version {} of app with UUID {} does not exist",
+                    version, app_uuid
+                ))
+            }
+        };
+
+        let current = entries
+            .iter()
+            .position(|e| e.app.uuid == app_uuid && e.active_version);
+
+        if current == Some(target) {
+            return Ok(entries[target].clone());
+        }
+
+        let version_dir = entries[target].version_dir().to_string_lossy().into_owned();
+
+        // Update the app.toml files first, then switch the symlink. If anything fails, the
+        // app.toml files are put back the way they were
+        let mut updated = entries.clone();
+        updated[target].active_version = true;
+        if let Some(current) = current {
+            updated[current].active_version = false;
+        }
+
+        let result = updated[target]
+            .save()
+            .and_then(|_| match current {
+                Some(current) => updated[current].save(),
+                None => Ok(true),
+            })
+            .and_then(|_| self.link_active(app_uuid, &version_dir));
+
+        if let Err(err) = result {
+            let _ = entries[target].save();
+            if let Some(current) = current {
+                let _ = entries[current].save();
+            }
+            return Err(err);
+        }
+
+        *entries = updated;
+        Ok(entries[target].clone())
+    }
+
+    /// Revert an application to the version before its currently active one, i.e. the
+    /// highest installed version which is lower than the active version
+    ///
+    /// # Arguments
+    ///
+    /// * `app_uuid` - The UUID generated for the app when it was registered
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use kubos_app::registry::AppRegistry;
+    /// let registry = AppRegistry::new();
+    /// registry.rollback("01234567-89ab-cdef0-1234-56789abcdef0");
+    /// ```
+    pub fn rollback(&self, app_uuid: &str) -> Result<AppRegistryEntry, String> {
+        let previous = {
+            let entries = self.entries.borrow();
+
+            let active = match entries
+                .iter()
+                .find(|e| e.app.uuid == app_uuid && e.active_version)
+            {
+                Some(entry) => entry.app.metadata.version.clone(),
+                None => return Err(format!("Active app with UUID {} does not exist", app_uuid)),
+            };
+
+            match entries
+                .iter()
+                .filter(|e| e.app.uuid == app_uuid)
+                .map(|e| &e.app.metadata.version)
+                .filter(|version| compare_versions(version, &active) == Ordering::Less)
+                .max_by(|a, b| compare_versions(a, b))
+            {
+                Some(version) => version.clone(),
+                None => {
+                    return Err(format!(
+                        "App with UUID {} has no version older than {}",
+                        app_uuid, active
+                    ))
+                }
+            }
+        };
+
+        self.set_version(app_uuid, &previous)
     }
 
-    /// Uninstall an application from the AppRegistry
+    /// Uninstall a version of an application from the AppRegistry.
+    ///
+    /// If the active version is removed, the highest remaining version becomes the active one.
+    /// Removing the last version of an application removes the application entirely, as with
+    /// [`uninstall_app`]. Any running instances of the version are stopped, and have exited,
+    /// before its files are removed.
+    ///
+    /// [`uninstall_app`]: #method.uninstall_app
     ///
     /// # Arguments
     ///
@@ -376,36 +781,201 @@ impl AppRegistry {
     ///
     pub fn uninstall(&self, app_uuid: &str, version: &str) -> Result<bool, String> {
         let mut entries = self.entries.borrow_mut();
-        let app_index = match entries.binary_search_by(|ref e| {
-            e.app
-                .uuid
-                .cmp(&String::from(app_uuid))
-                .then(e.app.metadata.version.cmp(&String::from(version)))
-        }) {
-            Ok(index) => index,
-            Err(_) => return Err(format!("Active app with UUID {} does not exist", app_uuid)),
+        let index = match entries
+            .iter()
+            .position(|e| e.app.uuid == app_uuid && e.app.metadata.version == version)
+        {
+            Some(index) => index,
+            None => {
+                return Err(format!(
+                    "Version {} of app with UUID {} does not exist",
+                    version, app_uuid
+                ))
+            }
         };
 
-        let app_path = PathBuf::from(&entries[app_index].app.path);
-        if app_path.exists() {
-            let parent = match app_path.parent() {
-                Some(parent) => parent,
-                // This should never happen
-                None => return Err(String::from("Error finding parent path of app")),
-            };
+        if !entries
+            .iter()
+            .any(|e| e.app.uuid == app_uuid && e.app.metadata.version != version)
+        {
+            drop(entries);
+            return self.uninstall_app(app_uuid);
+        }
+
+        self.monitor.stop_and_wait(app_uuid, Some(version))?;
+
+        let dir = entries[index].version_dir();
+        if dir.exists() {
+            fs::remove_dir_all(&dir)
+                .map_err(|err| format!("Error removing app directory: {}", err))?;
+        }
+        let removed = entries.remove(index);
 
-            if let Err(err) = fs::remove_dir_all(parent.clone()) {
-                return Err(format!("Error removing app directory: {}", err));
+        // Promote the highest remaining version so that the active symlink never dangles
+        if removed.active_version {
+            if let Some(index) = newest_version(&entries, app_uuid) {
+                entries[index].active_version = true;
+                entries[index].save()?;
+                self.link_active(app_uuid, &entries[index].version_dir().to_string_lossy())?;
             }
         }
 
-        if app_index < entries.len() {
-            entries.remove(app_index);
+        Ok(true)
+    }
+
+    /// Uninstall every version of an application, along with its logs, run history and
+    /// schedule. Any running instances of the application are stopped, and have exited, before
+    /// its files are removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `app` - The UUID or name of the app
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use kubos_app::registry::AppRegistry;
+    /// let registry = AppRegistry::new();
+    /// registry.uninstall_app("my-app");
+    /// ```
+    pub fn uninstall_app(&self, app: &str) -> Result<bool, String> {
+        let app_uuid = self.find_uuid(app)?;
+
+        for entry in self.scheduler.entries(Some(&app_uuid)) {
+            let _ = self.scheduler.remove(entry.id);
         }
+        self.monitor.stop_and_wait(&app_uuid, None)?;
+
+        let active_symlink = PathBuf::from(format!("{}/active/{}", self.apps_dir, app_uuid));
+        if fs::symlink_metadata(&active_symlink).is_ok() {
+            fs::remove_file(&active_symlink)
+                .map_err(|err| format!("Error removing active symlink: {}", err))?;
+        }
+
+        let app_dir = PathBuf::from(format!("{}/{}", self.apps_dir, app_uuid));
+        if app_dir.exists() {
+            fs::remove_dir_all(&app_dir)
+                .map_err(|err| format!("Error removing app directory: {}", err))?;
+        }
+
+        self.monitor.history.forget(&app_uuid);
+        self.entries
+            .borrow_mut()
+            .retain(|e| e.app.uuid != app_uuid);
 
         Ok(true)
     }
 
+    /// Look up the UUID of an application from either its UUID or its name
+    pub fn find_uuid(&self, app: &str) -> Result<String, String> {
+        let entries = self.entries.borrow();
+        if entries.iter().any(|e| e.app.uuid == app) {
+            return Ok(app.to_owned());
+        }
+
+        let mut uuids: Vec<&String> = entries
+            .iter()
+            .filter(|e| e.app.metadata.name == app)
+            .map(|e| &e.app.uuid)
+            .collect();
+        uuids.dedup();
+
+        match uuids.as_slice() {
+            [] => Err(format!("App {} does not exist", app)),
+            [uuid] => Ok((*uuid).clone()),
+            _ => Err(format!(
+                "More than one app is named {}. Use its UUID instead",
+                app
+            )),
+        }
+    }
+
+    /// Remove the oldest versions of an application, keeping only the `keep` highest versions.
+    /// The active version is always kept, even if it isn't one of them.
+    ///
+    /// Returns the versions which were removed
+    ///
+    /// # Arguments
+    ///
+    /// * `app_uuid` - The UUID generated for the app
+    /// * `keep` - The number of versions to keep
+    pub fn prune_versions(&self, app_uuid: &str, keep: usize) -> Result<Vec<String>, String> {
+        let mut versions: Vec<(String, bool)> = self
+            .entries
+            .borrow()
+            .iter()
+            .filter(|e| e.app.uuid == app_uuid)
+            .map(|e| (e.app.metadata.version.clone(), e.active_version))
+            .collect();
+        versions.sort_by(|a, b| compare_versions(&b.0, &a.0));
+
+        let mut removed = vec![];
+        let mut kept = 0;
+        for (version, active) in versions {
+            if active || kept < keep {
+                kept += 1;
+                continue;
+            }
+
+            self.uninstall(app_uuid, &version)?;
+            removed.push(version);
+        }
+
+        Ok(removed)
+    }
+
+    /// The disk space used by each application, or by a single application
+    ///
+    /// # Arguments
+    ///
+    /// * `app_uuid` - Only report the usage of the app with this UUID
+    pub fn disk_usage(&self, app_uuid: Option<&str>) -> Vec<AppDiskUsage> {
+        let entries = self.entries.borrow();
+
+        let mut uuids: Vec<&String> = entries
+            .iter()
+            .map(|e| &e.app.uuid)
+            .filter(|uuid| match app_uuid {
+                Some(app_uuid) => *uuid == app_uuid,
+                None => true,
+            })
+            .collect();
+        uuids.sort();
+        uuids.dedup();
+
+        uuids
+            .into_iter()
+            .map(|uuid| {
+                let mut versions: Vec<&AppRegistryEntry> =
+                    entries.iter().filter(|e| &e.app.uuid == uuid).collect();
+                versions.sort_by(|a, b| {
+                    compare_versions(&a.app.metadata.version, &b.app.metadata.version)
+                });
+
+                AppDiskUsage {
+                    uuid: uuid.clone(),
+                    name: versions
+                        .iter()
+                        .find(|e| e.active_version)
+                        .unwrap_or(&versions[0])
+                        .app
+                        .metadata
+                        .name
+                        .clone(),
+                    versions: versions
+                        .iter()
+                        .map(|e| VersionDiskUsage {
+                            version: e.app.metadata.version.clone(),
+                            bytes: dir_size(&e.version_dir()),
+                        })
+                        .collect(),
+                    logs: dir_size(&log_dir(&self.apps_dir, uuid)),
+                    total: dir_size(Path::new(&format!("{}/{}", self.apps_dir, uuid))),
+                }
+            })
+            .collect()
+    }
+
     /// Start an application. If successful, returns the pid of the application process.
     ///
     /// # Arguments
@@ -420,7 +990,37 @@ impl AppRegistry {
     /// let registry = AppRegistry::new();
     /// registry.start_app("01234567-89ab-cdef0-1234-56789abcdef0", RunLevel::OnCommand);
     /// ```
+    // The service itself always starts apps with explicit options, or from `run_onboot`
+    #[allow(dead_code)]
     pub fn start_app(&self, app_uuid: &str, run_level: RunLevel) -> Result<u32, String> {
+        self.start_app_with(app_uuid, run_level, StartOptions::default())
+    }
+
+    /// Start an application with arguments, environment variables or a configuration file
+    /// other than the defaults given in its manifest. If successful, returns the pid of the
+    /// application process.
+    ///
+    /// # Arguments
+    ///
+    /// * `app_uuid` - The UUID generated for the app when it was registered
+    /// * `run_level` - Which Run Level to run the app with
+    /// * `options` - Overrides for the app's manifest defaults
+    pub fn start_app_with(
+        &self,
+        app_uuid: &str,
+        run_level: RunLevel,
+        options: StartOptions,
+    ) -> Result<u32, String> {
+        self.launch_active(app_uuid, run_level, options, StartCause::Command)
+    }
+
+    fn launch_active(
+        &self,
+        app_uuid: &str,
+        run_level: RunLevel,
+        options: StartOptions,
+        cause: StartCause,
+    ) -> Result<u32, String> {
         let entries = self.entries.borrow();
 
         let app = match entries
@@ -431,25 +1031,25 @@ impl AppRegistry {
             None => return Err(format!("Active app with UUID {} does not exist", app_uuid)),
         };
 
-        let app_path = PathBuf::from(&app.path);
-        if !app_path.exists() {
-            // TODO: Unregister app if path doesn't exist
-            return Err(format!("{} does not exist", &app.path));
-        }
+        self.monitor
+            .launch(app.launch(&self.apps_dir, run_level, options, cause))
+    }
 
-        match Command::new(app_path)
-            .env("KUBOS_APP_UUID", app.uuid.clone())
-            .arg("-r")
-            .arg(format!("{}", run_level))
-            .spawn()
-        {
-            Ok(child) => Ok(child.id()),
-            Err(err) => Err(format!("Failed to spawn app: {:?}", err)),
-        }
+    /// The directory an application's output is logged to
+    ///
+    /// # Arguments
+    ///
+    /// * `app_uuid` - The UUID generated for the app when it was registered
+    pub fn log_dir(&self, app_uuid: &str) -> PathBuf {
+        log_dir(&self.apps_dir, app_uuid)
     }
 
     /// Call the active version of all registered applications with the "OnBoot" run level
     ///
+    /// Applications are started after the applications they depend on, and once the services
+    /// they depend on are responding. An application is not started if any of its
+    /// dependencies can't be started or don't respond in time.
+    ///
     /// # Examples
     ///
     /// ```
@@ -467,23 +1067,66 @@ impl AppRegistry {
             return Err(format!("Failed to get list of active UUIDs"));
         }
 
+        let mut apps = vec![];
         for entry in fs::read_dir(active_symlink)
             .or_else(|error| return Err(format!("Failed to process existing apps: {}", error)))?
         {
-            match entry {
-                Ok(file) => {
-                    let uuid = file.file_name();
-                    match self.start_app(&uuid.to_string_lossy(), RunLevel::OnBoot) {
-                        Ok(_) => apps_started += 1,
-                        Err(_) => apps_not_started += 1,
-                    }
+            let uuid = match entry {
+                Ok(file) => file.file_name().to_string_lossy().into_owned(),
+                Err(_) => {
+                    apps_not_started += 1;
+                    continue;
+                }
+            };
+
+            match self
+                .entries
+                .borrow()
+                .iter()
+                .find(|e| e.active_version && e.app.uuid == uuid)
+            {
+                Some(entry) => apps.push(entry.app.clone()),
+                None => apps_not_started += 1,
+            }
+        }
+
+        let (ordered, unstartable) = dependencies::boot_order(apps);
+        for (app, err) in unstartable {
+            warn!("Not starting app {}: {}", app.metadata.name, err);
+            apps_not_started += 1;
+        }
+
+        let mut checker = ServiceChecker::new(self.config_path.clone());
+        let mut failed: Vec<String> = vec![];
+        for app in ordered {
+            let deps = &app.metadata.dependencies;
+            let result = match deps.apps.iter().find(|dep| failed.contains(dep)) {
+                Some(dep) => Err(format!("Dependency {} failed to start", dep)),
+                None => deps.services.iter().try_for_each(|service| {
+                    checker.wait_for(service, Duration::from_millis(deps.timeout))
+                }),
+            }
+            .and_then(|_| {
+                self.launch_active(
+                    &app.uuid,
+                    RunLevel::OnBoot,
+                    StartOptions::default(),
+                    StartCause::Boot,
+                )
+            });
+
+            match result {
+                Ok(_) => apps_started += 1,
+                Err(err) => {
+                    error!("Failed to start app {}: {}", app.metadata.name, err);
+                    failed.push(app.metadata.name.clone());
+                    apps_not_started += 1;
                 }
-                Err(_) => apps_not_started += 1,
             }
         }
 
         // QUESTION: Keep this or not? It's kind of a nice informational message
-        println!(
+        info!(
             "Apps started: {}, Apps failed: {}",
             apps_started, apps_not_started
         );
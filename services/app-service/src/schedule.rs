@@ -0,0 +1,411 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Time-based scheduling of app runs.
+//!
+//! The schedule is kept in `schedule.toml` in the registry directory, so that it survives
+//! restarts of the service. A background thread starts apps with the `OnCommand` run level
+//! as their entries fall due, recording each run it attempts.
+//!
+//! Repeating entries which fall due while the service isn't running are run once when it
+//! starts again, rather than once for every missed interval.
+
+use history::StartCause;
+use kubos_app::RunLevel;
+use monitor::ProcessMonitor;
+use registry::{self, StartOptions};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use toml;
+
+/// Name of the schedule file within the registry directory
+pub const SCHEDULE_FILE: &'static str = "schedule.toml";
+
+// How often the schedule is checked for entries which are due, in milliseconds
+const CHECK_INTERVAL: u64 = 200;
+// Number of scheduled runs to remember
+const RUN_HISTORY: usize = 100;
+
+/// When a new schedule entry should run. Times are in seconds since the epoch
+#[derive(Clone, Debug, Default)]
+pub struct ScheduleRequest {
+    /// Time to run the app at
+    pub at: Option<i64>,
+    /// Number of seconds to wait before running the app
+    pub delay: Option<u64>,
+    /// Number of seconds between runs of a repeating entry. If neither `at` nor `delay` is
+    /// given, the first run happens one interval from now
+    pub interval: Option<u64>,
+    /// Time before which the app shouldn't be run
+    pub window_start: Option<i64>,
+    /// Time after which the app shouldn't be run. The entry is removed once it has passed
+    pub window_end: Option<i64>,
+}
+
+/// A scheduled invocation of an app
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ScheduleEntry {
+    /// Identifier of the entry
+    pub id: u32,
+    /// UUID of the app to run. The app's active version at the time of each run is used
+    pub uuid: String,
+    /// Time of the next run
+    pub next_run: i64,
+    /// Number of seconds between runs, for repeating entries
+    pub interval: Option<u64>,
+    /// Time before which the app shouldn't be run
+    pub window_start: Option<i64>,
+    /// Time after which the app shouldn't be run
+    pub window_end: Option<i64>,
+    /// Arguments, environment variables and config file to start the app with
+    pub options: StartOptions,
+}
+
+/// A run of an app which was started (or failed to start) from the schedule
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ScheduledRun {
+    /// Identifier of the schedule entry which started the run
+    pub entry: u32,
+    /// UUID of the app
+    pub uuid: String,
+    /// Time the run was scheduled for
+    pub scheduled: i64,
+    /// Time the run was started
+    pub started: i64,
+    /// Process ID of the app, if it was started
+    pub pid: Option<u32>,
+    /// Why the app couldn't be started
+    pub error: Option<String>,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+struct ScheduleFile {
+    #[serde(default)]
+    next_id: u32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    entries: Vec<ScheduleEntry>,
+    // Oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    history: Vec<ScheduledRun>,
+}
+
+/// Keeps the schedule of app runs and starts apps as their entries fall due
+#[derive(Default)]
+pub struct Scheduler {
+    apps_dir: String,
+    path: Option<PathBuf>,
+    monitor: Arc<ProcessMonitor>,
+    state: Mutex<ScheduleFile>,
+}
+
+impl Scheduler {
+    /// Load the schedule kept in a registry directory, and start the background thread which
+    /// runs its entries. Apps are started through `monitor`. The thread exits once the
+    /// scheduler is dropped.
+    pub fn start(apps_dir: &str, monitor: Arc<ProcessMonitor>) -> Arc<Scheduler> {
+        let path = Path::new(apps_dir).join(SCHEDULE_FILE);
+        let state = load(&path).unwrap_or_else(|err| {
+            error!("Failed to load schedule {}: {}", path.display(), err);
+            ScheduleFile::default()
+        });
+
+        let scheduler = Arc::new(Scheduler {
+            apps_dir: apps_dir.to_owned(),
+            path: Some(path),
+            monitor,
+            state: Mutex::new(state),
+        });
+        let weak: Weak<Scheduler> = Arc::downgrade(&scheduler);
+
+        thread::spawn(move || loop {
+            thread::sleep(Duration::from_millis(CHECK_INTERVAL));
+            match weak.upgrade() {
+                Some(scheduler) => scheduler.run_due(now()),
+                None => break,
+            }
+        });
+
+        scheduler
+    }
+
+    /// Add an entry to the schedule, returning the new entry
+    ///
+    /// # Arguments
+    ///
+    /// * `uuid` - UUID of the app to run
+    /// * `request` - When the app should be run
+    /// * `options` - Arguments, environment variables and config file to start the app with
+    pub fn add(
+        &self,
+        uuid: &str,
+        request: ScheduleRequest,
+        options: StartOptions,
+    ) -> Result<ScheduleEntry, String> {
+        registry::load_active(&self.apps_dir, uuid)?;
+
+        if request.interval == Some(0) {
+            return Err("Interval must be greater than zero".to_owned());
+        }
+
+        let now = now();
+        let mut first = match (request.at, request.delay, request.interval) {
+            (Some(_), Some(_), _) => {
+                return Err("Only one of a time and a delay may be given".to_owned())
+            }
+            (Some(at), None, _) => at,
+            (None, Some(delay), _) => now + delay as i64,
+            (None, None, Some(interval)) => now + interval as i64,
+            (None, None, None) => return Err("A time, delay or interval must be given".to_owned()),
+        };
+
+        if let Some(start) = request.window_start {
+            first = first.max(start);
+        }
+        if first < now {
+            match request.interval {
+                Some(interval) => first = next_after(first, interval, now),
+                None => return Err(format!("Time {} has already passed", first)),
+            }
+        }
+        if let Some(end) = request.window_end {
+            if first > end {
+                return Err("The run window ends before the first run".to_owned());
+            }
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let entry = ScheduleEntry {
+            id: state.next_id + 1,
+            uuid: uuid.to_owned(),
+            next_run: first,
+            interval: request.interval,
+            window_start: request.window_start,
+            window_end: request.window_end,
+            options,
+        };
+
+        state.next_id = entry.id;
+        state.entries.push(entry.clone());
+        if let Err(err) = self.save(&state) {
+            state.entries.pop();
+            return Err(err);
+        }
+
+        Ok(entry)
+    }
+
+    /// Remove an entry from the schedule, returning the removed entry
+    pub fn remove(&self, id: u32) -> Result<ScheduleEntry, String> {
+        let mut state = self.state.lock().unwrap();
+        let index = match state.entries.iter().position(|entry| entry.id == id) {
+            Some(index) => index,
+            None => return Err(format!("Schedule entry {} does not exist", id)),
+        };
+
+        let entry = state.entries.remove(index);
+        if let Err(err) = self.save(&state) {
+            state.entries.insert(index, entry);
+            return Err(err);
+        }
+
+        Ok(entry)
+    }
+
+    /// The entries in the schedule, optionally only those for a particular app, in the order
+    /// they will next run
+    pub fn entries(&self, uuid: Option<&str>) -> Vec<ScheduleEntry> {
+        let mut entries: Vec<ScheduleEntry> = self
+            .state
+            .lock()
+            .unwrap()
+            .entries
+            .iter()
+            .filter(|entry| match uuid {
+                Some(uuid) => entry.uuid == uuid,
+                None => true,
+            })
+            .cloned()
+            .collect();
+        entries.sort_by_key(|entry| (entry.next_run, entry.id));
+        entries
+    }
+
+    /// Recent runs started from the schedule, optionally only those of a particular app,
+    /// most recent first
+    pub fn history(&self, uuid: Option<&str>) -> Vec<ScheduledRun> {
+        self.state
+            .lock()
+            .unwrap()
+            .history
+            .iter()
+            .rev()
+            .filter(|run| match uuid {
+                Some(uuid) => run.uuid == uuid,
+                None => true,
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Start the apps of all entries which are due at time `now`. One-off entries are removed
+    /// once they have run, and repeating entries are moved on to their next run
+    pub fn run_due(&self, now: i64) {
+        // The schedule isn't kept locked while the apps are started, so that it can still be
+        // read and changed while a slow app launches
+        let due = self.take_due(now);
+        if due.is_empty() {
+            return;
+        }
+
+        let runs: Vec<ScheduledRun> = due.iter().map(|entry| self.start_run(entry, now)).collect();
+
+        let mut state = self.state.lock().unwrap();
+        state.history.extend(runs);
+        let excess = state.history.len().saturating_sub(RUN_HISTORY);
+        state.history.drain(..excess);
+
+        if let Err(err) = self.save(&state) {
+            error!("{}", err);
+        }
+    }
+
+    // Remove or move on the entries which are due at time `now`, returning them as they were
+    // before being moved on
+    fn take_due(&self, now: i64) -> Vec<ScheduleEntry> {
+        let mut state = self.state.lock().unwrap();
+        let mut due = vec![];
+
+        let mut index = 0;
+        while index < state.entries.len() {
+            if state.entries[index].next_run > now {
+                index += 1;
+                continue;
+            }
+
+            let entry = state.entries[index].clone();
+            let next = entry
+                .interval
+                .map(|interval| next_after(entry.next_run, interval, now))
+                .filter(|next| match entry.window_end {
+                    Some(end) => *next <= end,
+                    None => true,
+                });
+            match next {
+                Some(next) => {
+                    state.entries[index].next_run = next;
+                    index += 1;
+                }
+                None => {
+                    state.entries.remove(index);
+                }
+            }
+            due.push(entry);
+        }
+
+        due
+    }
+
+    // Start the app of a due entry
+    fn start_run(&self, entry: &ScheduleEntry, now: i64) -> ScheduledRun {
+        let missed = match entry.window_end {
+            Some(end) => now > end,
+            None => false,
+        };
+        let result = if missed {
+            Err("The run window ended before the app could be started".to_owned())
+        } else {
+            registry::load_active(&self.apps_dir, &entry.uuid).and_then(|app| {
+                info!(
+                    "Starting scheduled run of app {} ({})",
+                    app.metadata.name, app.uuid
+                );
+                self.monitor.launch(app.launch(
+                    &self.apps_dir,
+                    RunLevel::OnCommand,
+                    entry.options.clone(),
+                    StartCause::Schedule,
+                ))
+            })
+        };
+
+        if let Err(ref err) = result {
+            error!("Failed to start scheduled app {}: {}", entry.uuid, err);
+        }
+        ScheduledRun {
+            entry: entry.id,
+            uuid: entry.uuid.clone(),
+            scheduled: entry.next_run,
+            started: now,
+            pid: result.as_ref().ok().cloned(),
+            error: result.err(),
+        }
+    }
+
+    // Write the schedule to a temporary file and move it into place, so that the schedule
+    // file is never left partially written
+    fn save(&self, state: &ScheduleFile) -> Result<(), String> {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+        let tmp_path = path.with_file_name(format!(".{}.tmp", SCHEDULE_FILE));
+
+        toml::to_string(state)
+            .map_err(|err| format!("{}", err))
+            .and_then(|data| {
+                fs::File::create(&tmp_path)
+                    .and_then(|mut file| {
+                        file.write_all(data.as_bytes())?;
+                        file.sync_all()
+                    })
+                    .and_then(|_| fs::rename(&tmp_path, path))
+                    .map_err(|err| format!("{}", err))
+            })
+            .map_err(|err| format!("Failed to save schedule {}: {}", path.display(), err))
+    }
+}
+
+fn load(path: &Path) -> Result<ScheduleFile, String> {
+    if !path.exists() {
+        return Ok(ScheduleFile::default());
+    }
+
+    fs::read_to_string(path)
+        .map_err(|err| format!("{}", err))
+        .and_then(|data| toml::from_str(&data).map_err(|err| format!("{}", err)))
+}
+
+// The first run of a repeating entry after `now`
+fn next_after(from: i64, interval: u64, now: i64) -> i64 {
+    let interval = interval as i64;
+    if from > now {
+        return from;
+    }
+    from + ((now - from) / interval + 1) * interval
+}
+
+/// The current time, in seconds since the epoch
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|dur| dur.as_secs() as i64)
+        .unwrap_or(0)
+}
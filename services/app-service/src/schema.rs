@@ -14,9 +14,15 @@
  * limitations under the License.
  */
 use juniper::{FieldError, FieldResult, Value};
+use history::AppRun;
 use kubos_app::RunLevel;
 use kubos_service;
-use registry::{self, AppRegistry};
+use logs::{self, Stream};
+use monitor::{AppExit, RunningApp};
+use registry::{self, AppDiskUsage, AppRegistry, RestartMode, StartOptions, VersionDiskUsage};
+use schedule::{ScheduleEntry, ScheduleRequest, ScheduledRun};
+use std::collections::BTreeMap;
+use std::time::Duration;
 
 type Context = kubos_service::Context<AppRegistry>;
 
@@ -60,6 +66,22 @@ graphql_object!(KApp: () as "App" |&self| {
     {
         Ok(&self.0.path)
     }
+
+    field restart_policy() -> FieldResult<String>
+        as "When the app is restarted after exiting: never, on-failure or always"
+    {
+        Ok(match self.0.metadata.restart.policy {
+            RestartMode::Never => "never",
+            RestartMode::OnFailure => "on-failure",
+            RestartMode::Always => "always",
+        }.to_owned())
+    }
+
+    field restarts() -> FieldResult<i32>
+        as "Number of times the app has been automatically restarted"
+    {
+        Ok(self.0.restarts as i32)
+    }
 });
 
 pub struct KAppRegistryEntry(pub registry::AppRegistryEntry);
@@ -78,6 +100,359 @@ graphql_object!(KAppRegistryEntry: () as "AppRegistryEntry" |&self| {
     }
 });
 
+graphql_object!(RunningApp: () as "RunningApp" |&self| {
+    description: "A running instance of an app"
+
+    field uuid() -> FieldResult<&String>
+        as "UUID"
+    {
+        Ok(&self.uuid)
+    }
+
+    field name() -> FieldResult<&String>
+        as "Name"
+    {
+        Ok(&self.name)
+    }
+
+    field version() -> FieldResult<&String>
+        as "Version"
+    {
+        Ok(&self.version)
+    }
+
+    field pid() -> FieldResult<i32>
+        as "Process ID"
+    {
+        Ok(self.pid as i32)
+    }
+
+    field run_level() -> FieldResult<String>
+        as "Run level the app was started with"
+    {
+        Ok(self.run_level.to_string())
+    }
+
+    field run() -> FieldResult<Option<i32>>
+        as "Run number the app's output is logged under"
+    {
+        Ok(self.run.map(|run| run as i32))
+    }
+
+    field started() -> FieldResult<i32>
+        as "Time the app was started"
+    {
+        Ok(self.started)
+    }
+
+    field stopping() -> FieldResult<bool>
+        as "Whether the app has been asked to stop"
+    {
+        Ok(self.stopping)
+    }
+});
+
+graphql_object!(AppExit: () as "AppExit" |&self| {
+    description: "A finished instance of an app"
+
+    field app() -> FieldResult<&RunningApp>
+        as "The instance which finished"
+    {
+        Ok(&self.app)
+    }
+
+    field ended() -> FieldResult<i32>
+        as "Time the app finished"
+    {
+        Ok(self.ended)
+    }
+
+    field exit_code() -> FieldResult<Option<i32>>
+        as "Exit code, if the app exited normally"
+    {
+        Ok(self.code)
+    }
+
+    field signal() -> FieldResult<Option<i32>>
+        as "Signal which terminated the app, if it didn't exit normally"
+    {
+        Ok(self.signal)
+    }
+
+    field limit_exceeded() -> FieldResult<Option<&str>>
+        as "The resource limit (cpu-time or file-size) the app most likely exceeded, if it was terminated for exceeding one"
+    {
+        Ok(self.limit)
+    }
+});
+
+graphql_object!(AppRun: () as "AppRun" |&self| {
+    description: "A recorded run of an app"
+
+    field id() -> FieldResult<i32>
+        as "Run ID. IDs increase with each run of an app"
+    {
+        Ok(self.id as i32)
+    }
+
+    field uuid() -> FieldResult<&String>
+        as "UUID of the app"
+    {
+        Ok(&self.uuid)
+    }
+
+    field name() -> FieldResult<&String>
+        as "Name of the app"
+    {
+        Ok(&self.name)
+    }
+
+    field version() -> FieldResult<&String>
+        as "Version of the app which was run"
+    {
+        Ok(&self.version)
+    }
+
+    field run_level() -> FieldResult<&String>
+        as "Run level the app was started with"
+    {
+        Ok(&self.run_level)
+    }
+
+    field args() -> FieldResult<&Vec<String>>
+        as "Extra arguments the app was started with"
+    {
+        Ok(&self.args)
+    }
+
+    field cause() -> FieldResult<&String>
+        as "Why the app was started: boot, command, schedule or restart"
+    {
+        Ok(&self.cause)
+    }
+
+    field restart_of() -> FieldResult<Option<i32>>
+        as "ID of the run which was restarted, if this run is a restart"
+    {
+        Ok(self.restart_of.map(|id| id as i32))
+    }
+
+    field pid() -> FieldResult<i32>
+        as "Process ID of the run"
+    {
+        Ok(self.pid as i32)
+    }
+
+    field started() -> FieldResult<i32>
+        as "Time the run started"
+    {
+        Ok(self.started)
+    }
+
+    field ended() -> FieldResult<Option<i32>>
+        as "Time the run finished. Not set while the app is running, or if the service stopped before the app did"
+    {
+        Ok(self.ended)
+    }
+
+    field exit_code() -> FieldResult<Option<i32>>
+        as "Exit code, if the app exited normally"
+    {
+        Ok(self.code)
+    }
+
+    field signal() -> FieldResult<Option<i32>>
+        as "Signal which terminated the app, if it didn't exit normally"
+    {
+        Ok(self.signal)
+    }
+
+    field limit_exceeded() -> FieldResult<Option<&String>>
+        as "The resource limit the app most likely exceeded, if it was terminated for exceeding one"
+    {
+        Ok(self.limit.as_ref())
+    }
+});
+
+graphql_object!(VersionDiskUsage: () as "VersionDiskUsage" |&self| {
+    description: "Disk space used by a version of an app"
+
+    field version() -> FieldResult<&String>
+        as "Version of the app"
+    {
+        Ok(&self.version)
+    }
+
+    field bytes() -> FieldResult<f64>
+        as "Bytes used by the version's files"
+    {
+        Ok(self.bytes as f64)
+    }
+});
+
+graphql_object!(AppDiskUsage: () as "AppDiskUsage" |&self| {
+    description: "Disk space used by an app. Sizes are given as floats, since GraphQL integers are only 32 bits"
+
+    field uuid() -> FieldResult<&String>
+        as "UUID of the app"
+    {
+        Ok(&self.uuid)
+    }
+
+    field name() -> FieldResult<&String>
+        as "Name of the app's active version"
+    {
+        Ok(&self.name)
+    }
+
+    field versions() -> FieldResult<&Vec<VersionDiskUsage>>
+        as "Usage of each installed version, lowest version first"
+    {
+        Ok(&self.versions)
+    }
+
+    field logs() -> FieldResult<f64>
+        as "Bytes used by the app's logs"
+    {
+        Ok(self.logs as f64)
+    }
+
+    field total() -> FieldResult<f64>
+        as "Bytes used by everything stored for the app"
+    {
+        Ok(self.total as f64)
+    }
+});
+
+graphql_object!(ScheduleEntry: () as "ScheduleEntry" |&self| {
+    description: "A scheduled invocation of an app"
+
+    field id() -> FieldResult<i32>
+        as "Schedule entry ID"
+    {
+        Ok(self.id as i32)
+    }
+
+    field uuid() -> FieldResult<&String>
+        as "UUID of the app to run"
+    {
+        Ok(&self.uuid)
+    }
+
+    field next_run() -> FieldResult<i32>
+        as "Time of the next run"
+    {
+        Ok(self.next_run as i32)
+    }
+
+    field interval() -> FieldResult<Option<i32>>
+        as "Seconds between runs, if the entry repeats"
+    {
+        Ok(self.interval.map(|interval| interval as i32))
+    }
+
+    field window_start() -> FieldResult<Option<i32>>
+        as "Time before which the app won't be run"
+    {
+        Ok(self.window_start.map(|time| time as i32))
+    }
+
+    field window_end() -> FieldResult<Option<i32>>
+        as "Time after which the app won't be run"
+    {
+        Ok(self.window_end.map(|time| time as i32))
+    }
+
+    field args() -> FieldResult<&Option<Vec<String>>>
+        as "Arguments to start the app with, in place of its manifest's defaults"
+    {
+        Ok(&self.options.args)
+    }
+
+    field env() -> FieldResult<Vec<String>>
+        as "Environment variables to start the app with, as KEY=VALUE"
+    {
+        Ok(self.options.env.iter().map(|(key, value)| format!("{}={}", key, value)).collect())
+    }
+
+    field config() -> FieldResult<&Option<String>>
+        as "Config file to start the app with, in place of its manifest's default"
+    {
+        Ok(&self.options.config)
+    }
+});
+
+graphql_object!(ScheduledRun: () as "ScheduledRun" |&self| {
+    description: "A run of an app started from the schedule"
+
+    field entry() -> FieldResult<i32>
+        as "ID of the schedule entry which started the run"
+    {
+        Ok(self.entry as i32)
+    }
+
+    field uuid() -> FieldResult<&String>
+        as "UUID of the app"
+    {
+        Ok(&self.uuid)
+    }
+
+    field scheduled() -> FieldResult<i32>
+        as "Time the run was scheduled for"
+    {
+        Ok(self.scheduled as i32)
+    }
+
+    field started() -> FieldResult<i32>
+        as "Time the run was started"
+    {
+        Ok(self.started as i32)
+    }
+
+    field pid() -> FieldResult<Option<i32>>
+        as "Process ID of the app, if it was started"
+    {
+        Ok(self.pid.map(|pid| pid as i32))
+    }
+
+    field error() -> FieldResult<&Option<String>>
+        as "Why the app couldn't be started"
+    {
+        Ok(&self.error)
+    }
+});
+
+// Default number of log lines returned for each stream
+const DEFAULT_LOG_LINES: usize = 100;
+
+pub struct AppLogs {
+    run: u32,
+    stdout: Vec<String>,
+    stderr: Vec<String>,
+}
+
+graphql_object!(AppLogs: () as "AppLogs" |&self| {
+    description: "Output logged by a run of an app"
+
+    field run() -> FieldResult<i32>
+        as "Run number"
+    {
+        Ok(self.run as i32)
+    }
+
+    field stdout() -> FieldResult<&Vec<String>>
+        as "Last lines written to stdout"
+    {
+        Ok(&self.stdout)
+    }
+
+    field stderr() -> FieldResult<&Vec<String>>
+        as "Last lines written to stderr"
+    {
+        Ok(&self.stderr)
+    }
+});
+
 ///
 pub struct QueryRoot;
 
@@ -91,7 +466,8 @@ graphql_object!(QueryRoot : Context as "Query" |&self| {
         -> FieldResult<Vec<KAppRegistryEntry>> as "Kubos Apps Query"
     {
         let mut result: Vec<KAppRegistryEntry> = Vec::new();
-        let entries = executor.context().subsystem().entries.borrow();
+        let registry = executor.context().subsystem();
+        let entries = registry.entries.borrow();
         let mut final_iter = entries.iter().filter(|ref e| {
             if uuid.is_some() && &e.app.uuid != uuid.as_ref().unwrap() {
                 return false;
@@ -109,13 +485,130 @@ graphql_object!(QueryRoot : Context as "Query" |&self| {
         });
 
         for entry in final_iter {
-            result.push(KAppRegistryEntry(entry.clone()));
+            let mut entry = entry.clone();
+            entry.app.pid = registry
+                .monitor
+                .pid_of(&entry.app.uuid, &entry.app.metadata.version)
+                .unwrap_or(0);
+            entry.app.restarts = registry
+                .monitor
+                .restart_count(&entry.app.uuid, &entry.app.metadata.version);
+            result.push(KAppRegistryEntry(entry));
         }
 
         Ok(result)
     }
+
+    field running_apps(&executor, uuid: Option<String>) -> FieldResult<Vec<RunningApp>>
+        as "Running app instances"
+    {
+        Ok(executor
+            .context()
+            .subsystem()
+            .monitor
+            .running()
+            .into_iter()
+            .filter(|app| match uuid {
+                Some(ref uuid) => &app.uuid == uuid,
+                None => true,
+            })
+            .collect())
+    }
+
+    field app_logs(&executor, uuid: String, run: Option<i32>, lines: Option<i32>)
+        -> FieldResult<AppLogs>
+        as "Tail of the output logged by a run of an app. Defaults to the most recent run"
+    {
+        let registry = executor.context().subsystem();
+        if !registry.entries.borrow().iter().any(|e| e.app.uuid == uuid) {
+            return Err(FieldError::new(format!("App with UUID {} does not exist", uuid), Value::null()));
+        }
+
+        let dir = registry.log_dir(&uuid);
+        let runs = logs::runs(&dir);
+        let run = match run {
+            Some(run) if runs.contains(&(run as u32)) => run as u32,
+            Some(run) => return Err(FieldError::new(format!("No logs found for run {} of app {}", run, uuid), Value::null())),
+            None => match runs.last() {
+                Some(run) => *run,
+                None => return Err(FieldError::new(format!("No logs found for app {}", uuid), Value::null())),
+            },
+        };
+        let lines = lines.map_or(DEFAULT_LOG_LINES, |lines| lines.max(0) as usize);
+
+        let read = |stream| {
+            logs::tail(&dir, run, stream, lines)
+                .map_err(|err| FieldError::new(format!("Failed to read logs for run {}: {}", run, err), Value::null()))
+        };
+
+        Ok(AppLogs {
+            run,
+            stdout: read(Stream::Stdout)?,
+            stderr: read(Stream::Stderr)?,
+        })
+    }
+
+    field exited_apps(&executor, uuid: Option<String>) -> FieldResult<Vec<AppExit>>
+        as "Recently finished app instances, most recent first"
+    {
+        Ok(executor
+            .context()
+            .subsystem()
+            .monitor
+            .exits()
+            .into_iter()
+            .filter(|exit| match uuid {
+                Some(ref uuid) => &exit.app.uuid == uuid,
+                None => true,
+            })
+            .collect())
+    }
+
+    field app_runs(&executor, uuid: String, after: Option<i32>, limit: Option<i32>) -> FieldResult<Vec<AppRun>>
+        as "Recorded runs of an app, most recent first. Pass the ID of the last run returned as `after` to fetch the next page"
+    {
+        Ok(executor.context().subsystem().monitor.history.runs(
+            &uuid,
+            after.map(|after| after.max(0) as u32),
+            limit.map(|limit| limit.max(0) as usize),
+        ))
+    }
+
+    field disk_usage(&executor, uuid: Option<String>) -> FieldResult<Vec<AppDiskUsage>>
+        as "Disk space used by each app"
+    {
+        let uuid = uuid.as_ref().map(|uuid| uuid.as_str());
+        Ok(executor.context().subsystem().disk_usage(uuid))
+    }
+
+    field schedule(&executor, uuid: Option<String>) -> FieldResult<Vec<ScheduleEntry>>
+        as "Upcoming scheduled app runs, soonest first"
+    {
+        let uuid = uuid.as_ref().map(|uuid| uuid.as_str());
+        Ok(executor.context().subsystem().scheduler.entries(uuid))
+    }
+
+    field schedule_history(&executor, uuid: Option<String>) -> FieldResult<Vec<ScheduledRun>>
+        as "Past scheduled app runs, most recent first"
+    {
+        let uuid = uuid.as_ref().map(|uuid| uuid.as_str());
+        Ok(executor.context().subsystem().scheduler.history(uuid))
+    }
 });
 
+// Convert `KEY=VALUE` strings into a set of environment variables
+fn parse_env(vars: Vec<String>) -> Result<BTreeMap<String, String>, String> {
+    vars.into_iter()
+        .map(|var| match var.find('=') {
+            Some(index) if index > 0 => Ok((var[..index].to_owned(), var[index + 1..].to_owned())),
+            _ => Err(format!(
+                "Invalid environment variable {}: expected KEY=VALUE",
+                var
+            )),
+        })
+        .collect()
+}
+
 ///
 pub struct MutationRoot;
 
@@ -132,31 +625,142 @@ graphql_object!(MutationRoot : Context as "Mutation" |&self| {
         }
     }
 
-    field uninstall(&executor, uuid: String, version: String) -> FieldResult<bool>
-        as "Uninstall App"
+    field uninstall(
+        &executor,
+        uuid: Option<String>,
+        name: Option<String>,
+        version: Option<String>
+    ) -> FieldResult<bool>
+        as "Uninstall an app, given either its UUID or name. All versions are removed unless a version is given"
     {
-        match executor.context().subsystem().uninstall(&uuid, &version) {
+        let registry = executor.context().subsystem();
+        let result = match (uuid, name) {
+            (Some(app), None) | (None, Some(app)) => match version {
+                Some(version) => registry
+                    .find_uuid(&app)
+                    .and_then(|uuid| registry.uninstall(&uuid, &version)),
+                None => registry.uninstall_app(&app),
+            },
+            _ => Err("Exactly one of a UUID and a name must be given".to_owned()),
+        };
+
+        match result {
             Ok(v) => Ok(v),
             Err(msg) => {
-                println!("{}", msg);
+                error!("{}", msg);
                 Err(FieldError::new(msg, Value::null()))
             }
         }
     }
 
-    field start_app(&executor, uuid: String, run_level: String) -> FieldResult<i32>
-        as "Start App"
+    field set_version(&executor, uuid: String, version: String) -> FieldResult<KAppRegistryEntry>
+        as "Make a version of an app the active one"
     {
-        let run_level_o = {
-            match run_level.as_ref() {
-                "OnBoot" => RunLevel::OnBoot,
-                _ => RunLevel::OnCommand
-            }
+        match executor.context().subsystem().set_version(&uuid, &version) {
+            Ok(entry) => Ok(KAppRegistryEntry(entry)),
+            Err(err) => Err(FieldError::new(err, Value::null()))
+        }
+    }
+
+    field rollback(&executor, uuid: String) -> FieldResult<KAppRegistryEntry>
+        as "Revert an app to the version before its active one"
+    {
+        match executor.context().subsystem().rollback(&uuid) {
+            Ok(entry) => Ok(KAppRegistryEntry(entry)),
+            Err(err) => Err(FieldError::new(err, Value::null()))
+        }
+    }
+
+    field start_app(
+        &executor,
+        uuid: String,
+        run_level: String,
+        args: Option<Vec<String>>,
+        env: Option<Vec<String>>,
+        config: Option<String>
+    ) -> FieldResult<i32>
+        as "Start App. Arguments, environment variables (as KEY=VALUE) and config file override the defaults in the app's manifest"
+    {
+        let run_level_o = RunLevel::from_name(&run_level);
+
+        let options = StartOptions {
+            args,
+            env: parse_env(env.unwrap_or_default())
+                .map_err(|err| FieldError::new(err, Value::null()))?,
+            config,
         };
 
-        match executor.context().subsystem().start_app(&uuid, run_level_o) {
+        match executor.context().subsystem().start_app_with(&uuid, run_level_o, options) {
             Ok(pid) => Ok(pid as i32),
             Err(err) => Err(FieldError::new(err, Value::null()))
         }
     }
+
+    field schedule_app(
+        &executor,
+        uuid: String,
+        at: Option<i32>,
+        delay: Option<i32>,
+        interval: Option<i32>,
+        window_start: Option<i32>,
+        window_end: Option<i32>,
+        args: Option<Vec<String>>,
+        env: Option<Vec<String>>,
+        config: Option<String>
+    ) -> FieldResult<ScheduleEntry>
+        as "Schedule an app to run at a time (seconds since the epoch), after a delay (in seconds) or repeatedly at an interval (in seconds), optionally within a run window"
+    {
+        let request = ScheduleRequest {
+            at: at.map(|at| at as i64),
+            delay: delay.map(|delay| delay.max(0) as u64),
+            interval: interval.map(|interval| interval.max(0) as u64),
+            window_start: window_start.map(|time| time as i64),
+            window_end: window_end.map(|time| time as i64),
+        };
+        let options = StartOptions {
+            args,
+            env: parse_env(env.unwrap_or_default())
+                .map_err(|err| FieldError::new(err, Value::null()))?,
+            config,
+        };
+
+        executor
+            .context()
+            .subsystem()
+            .scheduler
+            .add(&uuid, request, options)
+            .map_err(|err| FieldError::new(err, Value::null()))
+    }
+
+    field unschedule_app(&executor, id: i32) -> FieldResult<ScheduleEntry>
+        as "Remove an entry from the schedule"
+    {
+        executor
+            .context()
+            .subsystem()
+            .scheduler
+            .remove(id as u32)
+            .map_err(|err| FieldError::new(err, Value::null()))
+    }
+
+    field stop_app(&executor, uuid: String, pid: Option<i32>, grace_period: Option<i32>)
+        -> FieldResult<Vec<i32>>
+        as "Stop App. Sends SIGTERM, then SIGKILL if the app is still running after the grace period (in milliseconds)"
+    {
+        let grace_period = grace_period.map(|ms| Duration::from_millis(ms.max(0) as u64));
+
+        match executor.context().subsystem().monitor.stop(&uuid, pid.map(|pid| pid as u32), grace_period) {
+            Ok(pids) => Ok(pids.into_iter().map(|pid| pid as i32).collect()),
+            Err(err) => Err(FieldError::new(err, Value::null()))
+        }
+    }
+
+    field kill_app(&executor, uuid: String, pid: Option<i32>) -> FieldResult<Vec<i32>>
+        as "Kill App. Sends SIGKILL"
+    {
+        match executor.context().subsystem().monitor.kill(&uuid, pid.map(|pid| pid as u32)) {
+            Ok(pids) => Ok(pids.into_iter().map(|pid| pid as i32).collect()),
+            Err(err) => Err(FieldError::new(err, Value::null()))
+        }
+    }
 });
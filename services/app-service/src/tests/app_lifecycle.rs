@@ -0,0 +1,188 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use kubos_app::RunLevel;
+use kubos_service::{Config, Service};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tempfile::TempDir;
+
+use super::register_script;
+use registry::*;
+use schema;
+
+// Wait for the app to finish, returning its exit code and signal
+fn wait_for_exit(registry: &AppRegistry, uuid: &str) -> (Option<i32>, Option<i32>) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while Instant::now() < deadline {
+        if let Some(exit) = registry
+            .monitor
+            .exits()
+            .into_iter()
+            .find(|exit| exit.app.uuid == uuid)
+        {
+            return (exit.code, exit.signal);
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    panic!("App {} did not exit", uuid);
+}
+
+#[test]
+fn exit_code_recorded() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let uuid = register_script(&registry, "failing-app", "exit 3", "");
+
+    let pid = registry.start_app(&uuid, RunLevel::OnCommand).unwrap();
+
+    assert_eq!(wait_for_exit(&registry, &uuid), (Some(3), None));
+    assert!(registry.monitor.running().is_empty());
+    assert_eq!(registry.monitor.exits()[0].app.pid, pid);
+}
+
+#[test]
+fn stop_running_app() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let uuid = register_script(&registry, "sleepy-app", "exec sleep 30", "");
+
+    let pid = registry.start_app(&uuid, RunLevel::OnCommand).unwrap();
+
+    let running = registry.monitor.running();
+    assert_eq!(running.len(), 1);
+    assert_eq!(running[0].pid, pid);
+    assert_eq!(running[0].run_level, RunLevel::OnCommand);
+
+    assert_eq!(registry.monitor.stop(&uuid, None, None), Ok(vec![pid]));
+    assert_eq!(wait_for_exit(&registry, &uuid), (None, Some(15)));
+}
+
+#[test]
+fn stop_escalates_to_kill() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let uuid = register_script(
+        &registry,
+        "stubborn-app",
+        "trap '' TERM\nwhile true; do sleep 0.05; done",
+        "",
+    );
+
+    registry.start_app(&uuid, RunLevel::OnCommand).unwrap();
+    // Give the shell a moment to install its signal handler
+    thread::sleep(Duration::from_millis(100));
+
+    registry
+        .monitor
+        .stop(&uuid, None, Some(Duration::from_millis(200)))
+        .unwrap();
+
+    assert_eq!(wait_for_exit(&registry, &uuid), (None, Some(9)));
+}
+
+#[test]
+fn stop_not_running() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let uuid = register_script(&registry, "idle-app", "exit 0", "");
+
+    assert_eq!(
+        registry.monitor.kill(&uuid, None),
+        Err(format!("App {} is not running", uuid))
+    );
+}
+
+#[test]
+fn running_apps_query() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let uuid = register_script(&registry, "sleepy-app", "exec sleep 30", "");
+    let pid = registry.start_app(&uuid, RunLevel::OnBoot).unwrap();
+
+    let config = r#"
+        [app-service.addr]
+        ip = "127.0.0.1"
+        port = 9999"#;
+    let service = Service::new(
+        Config::new_from_str("app-service", config),
+        registry,
+        schema::QueryRoot,
+        schema::MutationRoot,
+    );
+
+    let expected = json!({
+        "errs": "",
+        "msg": {
+            "runningApps": [{
+                "name": "sleepy-app",
+                "pid": pid,
+                "runLevel": "OnBoot",
+                "stopping": false
+            }],
+            "apps": [{
+                "app": { "pid": pid }
+            }]
+        }
+    })
+    .to_string();
+    assert_eq!(
+        service.process(
+            "{ runningApps { name, pid, runLevel, stopping }, apps { app { pid } } }".to_owned()
+        ),
+        expected
+    );
+
+    let expected = json!({
+        "errs": "",
+        "msg": {
+            "killApp": [pid]
+        }
+    })
+    .to_string();
+    assert_eq!(
+        service.process(format!(r#"mutation {{ killApp(uuid: "{}") }}"#, uuid)),
+        expected
+    );
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let response =
+            service.process("{ exitedApps { app { pid }, exitCode, signal } }".to_owned());
+        let expected = json!({
+            "errs": "",
+            "msg": {
+                "exitedApps": [{
+                    "app": { "pid": pid },
+                    "exitCode": null,
+                    "signal": 9
+                }]
+            }
+        })
+        .to_string();
+
+        if response == expected {
+            break;
+        }
+        assert!(
+            Instant::now() < deadline,
+            "Unexpected response: {}",
+            response
+        );
+        thread::sleep(Duration::from_millis(20));
+    }
+}
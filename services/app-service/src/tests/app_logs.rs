@@ -0,0 +1,209 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use kubos_app::RunLevel;
+use kubos_service::{Config, Service};
+use std::fs;
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tempfile::TempDir;
+
+use super::register_script;
+use logs::{self, LogConfig, Stream};
+use registry::*;
+use schema;
+
+// Run an app to completion and wait for its output to be logged
+fn run_to_completion(registry: &AppRegistry, uuid: &str) -> u32 {
+    let exits = registry.monitor.exits().len();
+    registry.start_app(uuid, RunLevel::OnCommand).unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while registry.monitor.exits().len() == exits {
+        assert!(Instant::now() < deadline, "App did not exit");
+        thread::sleep(Duration::from_millis(20));
+    }
+    // Give the capture threads a moment to drain the pipes
+    thread::sleep(Duration::from_millis(100));
+
+    registry.monitor.exits()[0].app.run.unwrap()
+}
+
+#[test]
+fn output_captured_per_run() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let uuid = register_script(
+        &registry,
+        "chatty-app",
+        "echo \"hello from $KUBOS_APP_UUID\"\necho oops >&2",
+        "",
+    );
+
+    assert_eq!(run_to_completion(&registry, &uuid), 1);
+    assert_eq!(run_to_completion(&registry, &uuid), 2);
+
+    let dir = registry.log_dir(&uuid);
+    assert_eq!(logs::runs(&dir), vec![1, 2]);
+    assert_eq!(
+        logs::tail(&dir, 1, Stream::Stdout, 10).unwrap(),
+        vec![format!("hello from {}", uuid)]
+    );
+    assert_eq!(
+        logs::tail(&dir, 2, Stream::Stderr, 10).unwrap(),
+        vec!["oops".to_owned()]
+    );
+}
+
+#[test]
+fn old_runs_removed() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    registry.monitor.set_log_config(LogConfig {
+        max_runs: 2,
+        max_size: 1024,
+    });
+    let uuid = register_script(&registry, "chatty-app", "echo hello", "");
+
+    for _ in 0..3 {
+        run_to_completion(&registry, &uuid);
+    }
+
+    assert_eq!(logs::runs(&registry.log_dir(&uuid)), vec![2, 3]);
+}
+
+#[test]
+fn large_output_rotated() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    registry.monitor.set_log_config(LogConfig {
+        max_runs: 2,
+        max_size: 25,
+    });
+    let uuid = register_script(
+        &registry,
+        "chatty-app",
+        "for i in 1 2 3 4 5; do echo \"line-$i...\"; done",
+        "",
+    );
+
+    run_to_completion(&registry, &uuid);
+
+    // Each line is 10 bytes, so only the current file and one backup's worth survive
+    assert_eq!(
+        logs::tail(&registry.log_dir(&uuid), 1, Stream::Stdout, 100).unwrap(),
+        vec!["line-3...", "line-4...", "line-5..."]
+    );
+}
+
+#[test]
+fn app_logs_query() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let uuid = register_script(
+        &registry,
+        "chatty-app",
+        "echo one\necho two\necho three\necho failed >&2\nexit 1",
+        "",
+    );
+    run_to_completion(&registry, &uuid);
+
+    let config = r#"
+        [app-service.addr]
+        ip = "127.0.0.1"
+        port = 9999"#;
+    let service = Service::new(
+        Config::new_from_str("app-service", config),
+        registry,
+        schema::QueryRoot,
+        schema::MutationRoot,
+    );
+
+    let expected = json!({
+        "errs": "",
+        "msg": {
+            "appLogs": {
+                "run": 1,
+                "stdout": ["two", "three"],
+                "stderr": ["failed"]
+            }
+        }
+    })
+    .to_string();
+    assert_eq!(
+        service.process(format!(
+            r#"{{ appLogs(uuid: "{}", lines: 2) {{ run, stdout, stderr }} }}"#,
+            uuid
+        )),
+        expected
+    );
+
+    let response = service.process(format!(
+        r#"{{ appLogs(uuid: "{}", run: 5) {{ run }} }}"#,
+        uuid
+    ));
+    assert!(response.contains(&format!("No logs found for run 5 of app {}", uuid)));
+}
+
+// Produces a fixed number of lines, then records that it was read to the end
+struct Lines {
+    remaining: usize,
+    done: Arc<AtomicBool>,
+}
+
+impl Read for Lines {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 {
+            self.done.store(true, Ordering::SeqCst);
+            return Ok(0);
+        }
+        self.remaining -= 1;
+        (&b"line...\n"[..]).read(buf)
+    }
+}
+
+#[test]
+fn output_drained_after_write_failure() {
+    let dir = TempDir::new().unwrap();
+    let log_dir = dir.path().join("logs");
+    let (_, stdout, _) = logs::start_run(
+        &log_dir,
+        LogConfig {
+            max_runs: 1,
+            max_size: 20,
+        },
+    )
+    .unwrap();
+
+    // Rotating the log fails once its directory is gone
+    fs::remove_dir_all(&log_dir).unwrap();
+
+    let done = Arc::new(AtomicBool::new(false));
+    stdout.capture(Lines {
+        remaining: 100,
+        done: done.clone(),
+    });
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !done.load(Ordering::SeqCst) {
+        assert!(Instant::now() < deadline, "Output was not drained");
+        thread::sleep(Duration::from_millis(20));
+    }
+}
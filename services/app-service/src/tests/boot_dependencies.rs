@@ -0,0 +1,223 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::fs;
+use std::net::UdpSocket;
+use std::thread;
+
+use tempfile::TempDir;
+
+use super::register_script;
+use dependencies;
+use registry::*;
+
+fn active_apps(registry: &AppRegistry) -> Vec<App> {
+    registry
+        .entries
+        .borrow()
+        .iter()
+        .filter(|entry| entry.active_version)
+        .map(|entry| entry.app.clone())
+        .collect()
+}
+
+fn names(apps: &[App]) -> Vec<&str> {
+    apps.iter().map(|app| app.metadata.name.as_str()).collect()
+}
+
+// Answer `ping` queries like a KubOS service, returning the port it listens on
+fn ping_responder() -> u16 {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let port = socket.local_addr().unwrap().port();
+
+    thread::spawn(move || {
+        let mut buf = [0; 1024];
+        while let Ok((_, source)) = socket.recv_from(&mut buf) {
+            let _ = socket.send_to(br#"{"errs":"","msg":{"ping":"pong"}}"#, source);
+        }
+    });
+
+    port
+}
+
+// A port which nothing is listening on
+fn unused_port() -> u16 {
+    UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+fn write_config(dir: &TempDir, service: &str, port: u16) -> String {
+    let path = dir.path().join("config.toml");
+    fs::write(
+        &path,
+        format!("[{}.addr]\nip = \"127.0.0.1\"\nport = {}\n", service, port),
+    )
+    .unwrap();
+    path.to_string_lossy().into_owned()
+}
+
+#[test]
+fn apps_ordered_by_dependencies() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    register_script(
+        &registry,
+        "adcs-app",
+        "exit 0",
+        "[dependencies]\napps = [\"gps-app\", \"logger-app\"]",
+    );
+    register_script(
+        &registry,
+        "gps-app",
+        "exit 0",
+        "[dependencies]\napps = [\"logger-app\"]",
+    );
+    register_script(&registry, "logger-app", "exit 0", "");
+    register_script(&registry, "beacon-app", "exit 0", "");
+
+    let (ordered, failed) = dependencies::boot_order(active_apps(&registry));
+
+    assert_eq!(
+        names(&ordered),
+        vec!["beacon-app", "logger-app", "gps-app", "adcs-app"]
+    );
+    assert!(failed.is_empty());
+
+    registry.run_onboot().unwrap();
+    for app in ordered {
+        assert_eq!(
+            registry.monitor.history.runs(&app.uuid, None, None).len(),
+            1
+        );
+    }
+}
+
+#[test]
+fn missing_and_cyclic_dependencies() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    register_script(
+        &registry,
+        "ping-app",
+        "exit 0",
+        "[dependencies]\napps = [\"pong-app\"]",
+    );
+    register_script(
+        &registry,
+        "pong-app",
+        "exit 0",
+        "[dependencies]\napps = [\"ping-app\"]",
+    );
+    register_script(
+        &registry,
+        "lonely-app",
+        "exit 0",
+        "[dependencies]\napps = [\"missing-app\"]",
+    );
+    register_script(
+        &registry,
+        "follower-app",
+        "exit 0",
+        "[dependencies]\napps = [\"lonely-app\"]",
+    );
+    register_script(&registry, "good-app", "exit 0", "");
+
+    let (ordered, failed) = dependencies::boot_order(active_apps(&registry));
+
+    assert_eq!(names(&ordered), vec!["good-app"]);
+    let mut failed: Vec<(&str, &str)> = failed
+        .iter()
+        .map(|(app, err)| (app.metadata.name.as_str(), err.as_str()))
+        .collect();
+    failed.sort();
+    assert_eq!(
+        failed,
+        vec![
+            ("follower-app", "Dependency lonely-app is not available"),
+            ("lonely-app", "Dependency missing-app is not available"),
+            (
+                "ping-app",
+                "Dependency cycle between apps ping-app, pong-app"
+            ),
+            (
+                "pong-app",
+                "Dependency cycle between apps ping-app, pong-app"
+            ),
+        ]
+    );
+
+    assert_eq!(
+        registry.run_onboot().unwrap_err(),
+        "Failed to start 4 app/s"
+    );
+}
+
+#[test]
+fn waits_for_service() {
+    let registry_dir = TempDir::new().unwrap();
+    let config_dir = TempDir::new().unwrap();
+    let mut registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    registry.config_path = Some(write_config(&config_dir, "gps-service", ping_responder()));
+
+    let uuid = register_script(
+        &registry,
+        "gps-app",
+        "exit 0",
+        "[dependencies]\nservices = [\"gps-service\"]\ntimeout = 2000",
+    );
+
+    registry.run_onboot().unwrap();
+    assert_eq!(registry.monitor.history.runs(&uuid, None, None).len(), 1);
+}
+
+#[test]
+fn service_timeout() {
+    let registry_dir = TempDir::new().unwrap();
+    let config_dir = TempDir::new().unwrap();
+    let mut registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    registry.config_path = Some(write_config(&config_dir, "gps-service", unused_port()));
+
+    let gps_app = register_script(
+        &registry,
+        "gps-app",
+        "exit 0",
+        "[dependencies]\nservices = [\"gps-service\"]\ntimeout = 300",
+    );
+    let nav_app = register_script(
+        &registry,
+        "nav-app",
+        "exit 0",
+        "[dependencies]\napps = [\"gps-app\"]",
+    );
+
+    assert_eq!(
+        registry.run_onboot().unwrap_err(),
+        "Failed to start 2 app/s"
+    );
+    assert!(registry
+        .monitor
+        .history
+        .runs(&gps_app, None, None)
+        .is_empty());
+    assert!(registry
+        .monitor
+        .history
+        .runs(&nav_app, None, None)
+        .is_empty());
+}
@@ -14,6 +14,69 @@
  * limitations under the License.
  */
 
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+
+use tempfile::TempDir;
+
+use registry::AppRegistry;
+
+mod app_lifecycle;
+mod app_logs;
+mod boot_dependencies;
 mod register_app;
+mod register_package;
 mod registry_test;
 mod registry_onboot;
+mod resource_limits;
+mod restart_policy;
+mod run_history;
+mod schedule;
+mod set_version;
+mod start_options;
+mod uninstall;
+
+// Register a shell script as version 0.0.1 of an app, returning its UUID. `manifest` is
+// appended to the app's manifest file
+pub fn register_script(registry: &AppRegistry, name: &str, script: &str, manifest: &str) -> String {
+    register_version(registry, name, "0.0.1", script, manifest)
+}
+
+// Register a shell script as a particular version of an app, returning its UUID
+pub fn register_version(
+    registry: &AppRegistry,
+    name: &str,
+    version: &str,
+    script: &str,
+    manifest: &str,
+) -> String {
+    let app_dir = TempDir::new().unwrap();
+    let app_bin = app_dir.path().join(name);
+
+    fs::create_dir(app_bin.clone()).unwrap();
+
+    let mut bin = fs::File::create(app_bin.join(name)).unwrap();
+    bin.write_all(format!("#!/bin/sh\n{}\n", script).as_bytes())
+        .unwrap();
+    let mut perms = bin.metadata().unwrap().permissions();
+    perms.set_mode(0o755);
+    bin.set_permissions(perms).unwrap();
+
+    let manifest = format!(
+        r#"
+            name = "{}"
+            version = "{}"
+            author = "user"
+            {}
+            "#,
+        name, version, manifest
+    );
+    fs::write(app_bin.join("manifest.toml"), manifest).unwrap();
+
+    registry
+        .register(&app_bin.to_string_lossy())
+        .unwrap()
+        .app
+        .uuid
+}
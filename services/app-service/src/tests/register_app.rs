@@ -118,7 +118,7 @@ fn register_no_manifest() {
         app_bin.to_str().unwrap()
     );
 
-    let expected = "{\"errs\":\"{\\\"message\\\":\\\"Exactly two files should be present in the app directory\\\",\\\"locations\\\":[{\\\"line\\\":2,\\\"column\\\":9}],\\\"path\\\":[\\\"register\\\"]}\",\"msg\":null}";
+    let expected = "{\"errs\":\"{\\\"message\\\":\\\"Failed to find manifest file\\\",\\\"locations\\\":[{\\\"line\\\":2,\\\"column\\\":9}],\\\"path\\\":[\\\"register\\\"]}\",\"msg\":null}";
 
     assert_eq!(service.process(register_query.to_owned()), expected);
 }
@@ -158,7 +158,7 @@ fn register_extra_file() {
         app_bin.to_str().unwrap()
     );
 
-    let expected = "{\"errs\":\"{\\\"message\\\":\\\"Exactly two files should be present in the app directory\\\",\\\"locations\\\":[{\\\"line\\\":2,\\\"column\\\":9}],\\\"path\\\":[\\\"register\\\"]}\",\"msg\":null}";
+    let expected = "{\"errs\":\"{\\\"message\\\":\\\"The manifest must name the executable when the package contains more than one file\\\",\\\"locations\\\":[{\\\"line\\\":2,\\\"column\\\":9}],\\\"path\\\":[\\\"register\\\"]}\",\"msg\":null}";
 
     assert_eq!(service.process(register_query.to_owned()), expected);
 }
@@ -0,0 +1,229 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use tar::Builder;
+use tempfile::TempDir;
+
+use package::sha256;
+use registry::*;
+
+// Create a package directory containing an executable, a config file and a data table
+fn create_package(dir: &TempDir) -> PathBuf {
+    let package = dir.path().join("multi-app");
+    fs::create_dir_all(package.join("data")).unwrap();
+    fs::write(package.join("multi-app"), "#!/bin/sh\nexit 0\n").unwrap();
+    fs::write(package.join("config.toml"), "rate = 5\n").unwrap();
+    fs::write(package.join("data/table.csv"), "1,2,3\n").unwrap();
+    package
+}
+
+// Write the package's manifest, optionally listing checksums for the given files
+fn write_manifest(package: &Path, executable: &str, checksums: &[&str]) {
+    let mut manifest = format!(
+        r#"
+        name = "multi-app"
+        version = "1.0"
+        author = "user"
+        executable = "{}"
+        "#,
+        executable
+    );
+
+    if !checksums.is_empty() {
+        manifest.push_str("[checksums]\n");
+        for file in checksums {
+            manifest.push_str(&format!(
+                "\"{}\" = \"{}\"\n",
+                file,
+                sha256(&package.join(file)).unwrap()
+            ));
+        }
+    }
+
+    fs::write(package.join("manifest.toml"), manifest).unwrap();
+}
+
+const ALL_FILES: &[&str] = &["multi-app", "config.toml", "data/table.csv"];
+
+#[test]
+fn register_multi_file_dir() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let app_dir = TempDir::new().unwrap();
+    let package = create_package(&app_dir);
+    write_manifest(&package, "multi-app", ALL_FILES);
+
+    let entry = registry.register(&package.to_string_lossy()).unwrap();
+
+    let version_dir = registry_dir.path().join(&entry.app.uuid).join("1.0");
+    assert_eq!(
+        entry.app.path,
+        version_dir.join("multi-app").to_string_lossy()
+    );
+    assert_eq!(
+        fs::read_to_string(version_dir.join("data/table.csv")).unwrap(),
+        "1,2,3\n"
+    );
+    assert!(version_dir.join("config.toml").exists());
+    assert!(version_dir.join("manifest.toml").exists());
+}
+
+#[test]
+fn register_checksum_mismatch() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let app_dir = TempDir::new().unwrap();
+    let package = create_package(&app_dir);
+    write_manifest(&package, "multi-app", ALL_FILES);
+
+    // Corrupt one of the files after the checksums were calculated
+    fs::write(package.join("config.toml"), "rate = 6\n").unwrap();
+
+    let err = registry.register(&package.to_string_lossy()).unwrap_err();
+    assert!(err.starts_with("Checksum mismatch for config.toml"), "{}", err);
+    assert!(registry.entries.borrow().is_empty());
+    assert!(registry_dir
+        .path()
+        .join("active")
+        .read_dir()
+        .unwrap()
+        .next()
+        .is_none());
+}
+
+#[test]
+fn register_corrupt_upgrade_keeps_active_version() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let app_dir = TempDir::new().unwrap();
+    let package = create_package(&app_dir);
+    write_manifest(&package, "multi-app", ALL_FILES);
+    registry.register(&package.to_string_lossy()).unwrap();
+
+    let manifest = fs::read_to_string(package.join("manifest.toml")).unwrap();
+    fs::write(
+        package.join("manifest.toml"),
+        manifest.replace("\"1.0\"", "\"2.0\""),
+    )
+    .unwrap();
+    fs::write(package.join("multi-app"), "#!/bin/sh\nexit 1\n").unwrap();
+
+    assert!(registry.register(&package.to_string_lossy()).is_err());
+
+    let entries = registry.entries.borrow();
+    assert_eq!(entries.len(), 1);
+    assert!(entries[0].active_version);
+    assert_eq!(entries[0].app.metadata.version, "1.0");
+}
+
+#[test]
+fn register_unlisted_file() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let app_dir = TempDir::new().unwrap();
+    let package = create_package(&app_dir);
+    write_manifest(&package, "multi-app", &["multi-app", "config.toml"]);
+
+    assert_eq!(
+        registry.register(&package.to_string_lossy()).unwrap_err(),
+        "No checksum given for data/table.csv"
+    );
+}
+
+#[test]
+fn register_missing_executable() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let app_dir = TempDir::new().unwrap();
+    let package = create_package(&app_dir);
+    write_manifest(&package, "bin/multi-app", &[]);
+
+    assert_eq!(
+        registry.register(&package.to_string_lossy()).unwrap_err(),
+        "Executable bin/multi-app not found in package"
+    );
+}
+
+#[test]
+fn register_tarball() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let app_dir = TempDir::new().unwrap();
+    let package = create_package(&app_dir);
+    write_manifest(&package, "multi-app", ALL_FILES);
+
+    // Wrap the files in a top-level directory, as `tar czf multi-app.tgz multi-app` would
+    let tarball = app_dir.path().join("multi-app.tgz");
+    let mut builder = Builder::new(GzEncoder::new(
+        fs::File::create(&tarball).unwrap(),
+        Compression::default(),
+    ));
+    builder.append_dir_all("multi-app", &package).unwrap();
+    builder.into_inner().unwrap().finish().unwrap();
+
+    let entry = registry.register(&tarball.to_string_lossy()).unwrap();
+
+    assert_eq!(entry.app.metadata.name, "multi-app");
+    assert!(Path::new(&entry.app.path).exists());
+    assert!(Path::new(&entry.app.path)
+        .with_file_name("data")
+        .join("table.csv")
+        .exists());
+}
+
+#[test]
+fn register_not_a_package() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let app_dir = TempDir::new().unwrap();
+    let file = app_dir.path().join("multi-app.zip");
+    fs::write(&file, "").unwrap();
+
+    assert_eq!(
+        registry.register(&file.to_string_lossy()).unwrap_err(),
+        format!("{} is not a directory or tarball", file.display())
+    );
+}
+
+#[test]
+fn register_nested_executable() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let app_dir = TempDir::new().unwrap();
+    let package = create_package(&app_dir);
+    fs::create_dir(package.join("bin")).unwrap();
+    fs::rename(package.join("multi-app"), package.join("bin/multi-app")).unwrap();
+    write_manifest(&package, "bin/multi-app", &[]);
+
+    let entry = registry.register(&package.to_string_lossy()).unwrap();
+
+    let version_dir = registry_dir.path().join(&entry.app.uuid).join("1.0");
+    assert!(version_dir.join("app.toml").exists());
+
+    // The app is found again when the registry is reloaded
+    let reloaded = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let entries = reloaded.entries.borrow();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(
+        entries[0].app.path,
+        version_dir.join("bin/multi-app").to_string_lossy()
+    );
+}
@@ -17,10 +17,13 @@
 extern crate kubos_app;
 extern crate toml;
 
+use std::collections::BTreeMap;
 use std::env;
 use std::fs;
 use std::path::PathBuf;
 
+use dependencies::Dependencies;
+use limits::ProcessLimits;
 use registry::*;
 
 fn setup_registry() -> PathBuf {
@@ -70,8 +73,17 @@ fn serialize_entry() {
                 name: String::from("dummy"),
                 version: String::from("0.0.1"),
                 author: String::from("noone"),
+                executable: None,
+                config: None,
+                args: vec![],
+                checksums: BTreeMap::new(),
+                env: BTreeMap::new(),
+                dependencies: Dependencies::default(),
+                limits: ProcessLimits::default(),
+                restart: RestartPolicy::default(),
             },
             pid: 101,
+            restarts: 0,
             path: String::from("/fake/path"),
         },
         active_version: true,
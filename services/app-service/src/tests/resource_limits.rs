@@ -0,0 +1,242 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use kubos_app::RunLevel;
+use kubos_service::{Config, Service};
+use libc;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tempfile::TempDir;
+
+use super::register_script;
+use limits::ProcessLimits;
+use logs::{self, Stream};
+use monitor::AppExit;
+use registry::*;
+use schema;
+
+// Wait for the started app to exit, returning its exit and what it wrote to stdout
+fn wait_for_exit(registry: &AppRegistry, uuid: &str) -> (AppExit, Vec<String>) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !registry.monitor.running().is_empty() {
+        assert!(Instant::now() < deadline, "App did not exit");
+        thread::sleep(Duration::from_millis(20));
+    }
+    // Give the capture threads a moment to drain the pipes
+    thread::sleep(Duration::from_millis(100));
+
+    let exit = registry.monitor.exits().remove(0);
+    let run = exit.app.run.unwrap();
+    let output = logs::tail(&registry.log_dir(uuid), run, Stream::Stdout, 10).unwrap();
+    (exit, output)
+}
+
+#[test]
+fn limits_applied() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let uuid = register_script(
+        &registry,
+        "limited-app",
+        "ulimit -n\nulimit -v\nnice\npwd -P",
+        r#"
+        [limits]
+        memory = 268435456
+        open_files = 64
+        nice = 5
+        working_dir = ".."
+        "#,
+    );
+
+    registry.start_app(&uuid, RunLevel::OnCommand).unwrap();
+    let (exit, output) = wait_for_exit(&registry, &uuid);
+
+    let app_dir = registry_dir.path().join(&uuid).canonicalize().unwrap();
+    assert_eq!(exit.code, Some(0));
+    assert_eq!(exit.limit, None);
+    assert_eq!(
+        output,
+        vec![
+            "64".to_owned(),
+            "262144".to_owned(),
+            "5".to_owned(),
+            app_dir.to_string_lossy().into_owned(),
+        ]
+    );
+}
+
+#[test]
+fn cpu_time_exceeded() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let uuid = register_script(
+        &registry,
+        "busy-app",
+        "while :; do :; done",
+        "[limits]\ncpu_time = 1",
+    );
+
+    registry.start_app(&uuid, RunLevel::OnCommand).unwrap();
+    let (exit, _) = wait_for_exit(&registry, &uuid);
+
+    assert_eq!(exit.signal, Some(libc::SIGXCPU));
+    assert_eq!(exit.limit, Some("cpu-time"));
+}
+
+#[test]
+fn file_size_exceeded() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let uuid = register_script(
+        &registry,
+        "writer-app",
+        "exec head -c 2048 /dev/zero > out",
+        "[limits]\nfile_size = 1024\nworking_dir = \".\"",
+    );
+
+    let config = r#"
+        [app-service.addr]
+        ip = "127.0.0.1"
+        port = 9999"#;
+    let service = Service::new(
+        Config::new_from_str("app-service", config),
+        registry,
+        schema::QueryRoot,
+        schema::MutationRoot,
+    );
+    // Start the app through the service, so that it's the service's registry which tracks it
+    let response = service.process(format!(
+        r#"mutation {{ startApp(uuid: "{}", runLevel: "OnCommand") }}"#,
+        uuid
+    ));
+    assert!(response.contains("\"errs\":\"\""), "{}", response);
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let expected = json!({
+        "errs": "",
+        "msg": {
+            "exitedApps": [{
+                "signal": libc::SIGXFSZ,
+                "limitExceeded": "file-size"
+            }]
+        }
+    })
+    .to_string();
+    loop {
+        let response = service.process("{ exitedApps { signal, limitExceeded } }".to_owned());
+        if response == expected {
+            break;
+        }
+        assert!(Instant::now() < deadline, "{}", response);
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[test]
+fn drop_privileges() {
+    // Only root can switch to another user
+    if unsafe { libc::geteuid() } != 0 {
+        return;
+    }
+
+    let registry_dir = TempDir::new().unwrap();
+    // The app needs to be able to reach its executable once it has dropped its privileges
+    fs::set_permissions(registry_dir.path(), fs::Permissions::from_mode(0o755)).unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let uuid = register_script(
+        &registry,
+        "unprivileged-app",
+        "id -u\nid -g\nid -G",
+        "[limits]\nuid = 65534\ngid = 65534",
+    );
+
+    registry.start_app(&uuid, RunLevel::OnCommand).unwrap();
+    let (exit, output) = wait_for_exit(&registry, &uuid);
+
+    assert_eq!(exit.code, Some(0));
+    assert_eq!(output, vec!["65534", "65534", "65534"]);
+}
+
+#[test]
+fn drop_privileges_default_group() {
+    if unsafe { libc::geteuid() } != 0 {
+        return;
+    }
+
+    let registry_dir = TempDir::new().unwrap();
+    fs::set_permissions(registry_dir.path(), fs::Permissions::from_mode(0o755)).unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let uuid = register_script(
+        &registry,
+        "unprivileged-app",
+        "id -u\nid -g\nid -G",
+        "[limits]\nuid = 65534",
+    );
+
+    registry.start_app(&uuid, RunLevel::OnCommand).unwrap();
+    let (exit, output) = wait_for_exit(&registry, &uuid);
+
+    // The group is the user's primary group, and none of the service's groups are kept
+    let gid = unsafe { (*libc::getpwuid(65534)).pw_gid }.to_string();
+    assert_eq!(exit.code, Some(0));
+    assert_eq!(output, vec!["65534".to_owned(), gid.clone(), gid]);
+}
+
+#[test]
+fn unknown_user() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let uuid = register_script(
+        &registry,
+        "unknown-user-app",
+        "exit 0",
+        "[limits]\nuid = 3999999",
+    );
+
+    assert_eq!(
+        registry.start_app(&uuid, RunLevel::OnCommand).unwrap_err(),
+        "Failed to apply limits: User 3999999 does not exist"
+    );
+}
+
+#[test]
+fn exceeded_limit() {
+    let limits = ProcessLimits {
+        memory: Some(1 << 20),
+        cpu_time: Some(1),
+        file_size: Some(1024),
+        ..Default::default()
+    };
+
+    assert_eq!(limits.exceeded(Some(libc::SIGXCPU), 1), Some("cpu-time"));
+    assert_eq!(limits.exceeded(Some(libc::SIGKILL), 2), Some("cpu-time"));
+    assert_eq!(limits.exceeded(Some(libc::SIGXFSZ), 0), Some("file-size"));
+
+    // A process killed before reaching the hard CPU time limit was killed by something else
+    assert_eq!(limits.exceeded(Some(libc::SIGKILL), 1), None);
+    // Crashes can't be put down to the memory limit
+    assert_eq!(limits.exceeded(Some(libc::SIGSEGV), 0), None);
+    assert_eq!(limits.exceeded(Some(libc::SIGABRT), 0), None);
+    assert_eq!(limits.exceeded(None, 2), None);
+
+    assert_eq!(
+        ProcessLimits::default().exceeded(Some(libc::SIGXCPU), 1),
+        None
+    );
+}
@@ -0,0 +1,178 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use kubos_app::RunLevel;
+use kubos_service::{Config, Service};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tempfile::TempDir;
+
+use super::register_script;
+use registry::*;
+use schema;
+
+fn wait_until<F: Fn() -> bool>(condition: F) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !condition() {
+        assert!(Instant::now() < deadline, "Timed out waiting for condition");
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+#[test]
+fn on_failure_retries_until_limit() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let uuid = register_script(
+        &registry,
+        "crashy-app",
+        "exit 1",
+        "[restart]\npolicy = \"on-failure\"\nmax_retries = 2\nbackoff = 50",
+    );
+
+    registry.start_app(&uuid, RunLevel::OnBoot).unwrap();
+
+    // The original run plus two restarts
+    wait_until(|| registry.monitor.exits().len() == 3);
+    thread::sleep(Duration::from_millis(500));
+
+    assert_eq!(registry.monitor.exits().len(), 3);
+    assert_eq!(registry.monitor.restart_count(&uuid, "0.0.1"), 2);
+    assert!(registry.monitor.running().is_empty());
+}
+
+#[test]
+fn on_failure_ignores_success() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let uuid = register_script(
+        &registry,
+        "good-app",
+        "exit 0",
+        "[restart]\npolicy = \"on-failure\"\nbackoff = 50",
+    );
+
+    registry.start_app(&uuid, RunLevel::OnBoot).unwrap();
+
+    wait_until(|| registry.monitor.exits().len() == 1);
+    thread::sleep(Duration::from_millis(300));
+
+    assert_eq!(registry.monitor.exits().len(), 1);
+    assert_eq!(registry.monitor.restart_count(&uuid, "0.0.1"), 0);
+}
+
+#[test]
+fn always_restarts_after_success() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let uuid = register_script(
+        &registry,
+        "loop-app",
+        "exit 0",
+        "[restart]\npolicy = \"always\"\nmax_retries = 1\nbackoff = 50",
+    );
+
+    registry.start_app(&uuid, RunLevel::OnBoot).unwrap();
+
+    wait_until(|| registry.monitor.exits().len() == 2);
+    assert_eq!(registry.monitor.restart_count(&uuid, "0.0.1"), 1);
+}
+
+#[test]
+fn on_command_not_restarted() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let uuid = register_script(
+        &registry,
+        "crashy-app",
+        "exit 1",
+        "[restart]\npolicy = \"always\"\nbackoff = 50",
+    );
+
+    registry.start_app(&uuid, RunLevel::OnCommand).unwrap();
+
+    wait_until(|| registry.monitor.exits().len() == 1);
+    thread::sleep(Duration::from_millis(300));
+
+    assert_eq!(registry.monitor.exits().len(), 1);
+    assert_eq!(registry.monitor.restart_count(&uuid, "0.0.1"), 0);
+}
+
+#[test]
+fn stopped_app_not_restarted() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let uuid = register_script(
+        &registry,
+        "sleepy-app",
+        "exec sleep 30",
+        "[restart]\npolicy = \"always\"\nbackoff = 50",
+    );
+
+    registry.start_app(&uuid, RunLevel::OnBoot).unwrap();
+    registry.monitor.stop(&uuid, None, None).unwrap();
+
+    wait_until(|| registry.monitor.exits().len() == 1);
+    thread::sleep(Duration::from_millis(300));
+
+    assert!(registry.monitor.running().is_empty());
+    assert_eq!(registry.monitor.restart_count(&uuid, "0.0.1"), 0);
+}
+
+#[test]
+fn restarts_reported_by_apps_query() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let uuid = register_script(
+        &registry,
+        "crashy-app",
+        "exit 1",
+        "[restart]\npolicy = \"on-failure\"\nmax_retries = 1\nbackoff = 50",
+    );
+
+    registry.start_app(&uuid, RunLevel::OnBoot).unwrap();
+    wait_until(|| registry.monitor.exits().len() == 2);
+
+    let config = r#"
+        [app-service.addr]
+        ip = "127.0.0.1"
+        port = 9999"#;
+    let service = Service::new(
+        Config::new_from_str("app-service", config),
+        registry,
+        schema::QueryRoot,
+        schema::MutationRoot,
+    );
+
+    let expected = json!({
+        "errs": "",
+        "msg": {
+            "apps": [{
+                "app": {
+                    "restartPolicy": "on-failure",
+                    "restarts": 1
+                }
+            }]
+        }
+    })
+    .to_string();
+
+    assert_eq!(
+        service.process("{ apps { app { restartPolicy, restarts } } }".to_owned()),
+        expected
+    );
+}
@@ -0,0 +1,184 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use kubos_app::RunLevel;
+use kubos_service::{Config, Service};
+use serde_json;
+use std::net::UdpSocket;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tempfile::TempDir;
+
+use super::register_script;
+use registry::*;
+use schema;
+
+fn wait_until<F: Fn() -> bool>(condition: F) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !condition() {
+        assert!(Instant::now() < deadline, "Timed out waiting for condition");
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+#[test]
+fn run_recorded() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let uuid = register_script(&registry, "history-app", "exit 3", "");
+
+    let options = StartOptions {
+        args: Some(vec!["--mode".to_owned(), "safe".to_owned()]),
+        ..Default::default()
+    };
+    let pid = registry
+        .start_app_with(&uuid, RunLevel::OnCommand, options)
+        .unwrap();
+
+    // The run is recorded as soon as the app starts
+    let runs = registry.monitor.history.runs(&uuid, None, None);
+    assert_eq!(runs.len(), 1);
+    assert_eq!(runs[0].pid, pid);
+
+    wait_until(|| registry.monitor.exits().len() == 1);
+
+    let runs = registry.monitor.history.runs(&uuid, None, None);
+    assert_eq!(runs[0].id, 1);
+    assert_eq!(runs[0].run_level, "OnCommand");
+    assert_eq!(runs[0].args, vec!["--mode", "safe"]);
+    assert_eq!(runs[0].cause, "command");
+    assert_eq!(runs[0].restart_of, None);
+    assert!(runs[0].ended.is_some());
+    assert_eq!(runs[0].code, Some(3));
+    assert_eq!(runs[0].signal, None);
+
+    // The history survives a restart of the service
+    let reloaded = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    assert_eq!(reloaded.monitor.history.runs(&uuid, None, None), runs);
+}
+
+#[test]
+fn restart_cause_recorded() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let uuid = register_script(
+        &registry,
+        "crashy-app",
+        "exit 1",
+        "[restart]\npolicy = \"on-failure\"\nmax_retries = 1\nbackoff = 50",
+    );
+
+    registry.run_onboot().unwrap();
+    wait_until(|| registry.monitor.exits().len() == 2);
+
+    let runs = registry.monitor.history.runs(&uuid, None, None);
+    assert_eq!(runs.len(), 2);
+    assert_eq!(runs[1].cause, "boot");
+    assert_eq!(runs[1].run_level, "OnBoot");
+    assert_eq!(runs[0].cause, "restart");
+    assert_eq!(runs[0].restart_of, Some(runs[1].id));
+    assert_eq!(runs[0].code, Some(1));
+}
+
+#[test]
+fn app_runs_paginated() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let uuid = register_script(&registry, "history-app", "exit 0", "");
+
+    for count in 1..4 {
+        registry.start_app(&uuid, RunLevel::OnCommand).unwrap();
+        wait_until(|| registry.monitor.exits().len() == count);
+    }
+
+    let config = r#"
+        [app-service.addr]
+        ip = "127.0.0.1"
+        port = 9999"#;
+    let service = Service::new(
+        Config::new_from_str("app-service", config),
+        registry,
+        schema::QueryRoot,
+        schema::MutationRoot,
+    );
+
+    let expected = json!({
+        "errs": "",
+        "msg": {
+            "appRuns": [
+                { "id": 3, "cause": "command", "exitCode": 0 },
+                { "id": 2, "cause": "command", "exitCode": 0 }
+            ]
+        }
+    })
+    .to_string();
+    assert_eq!(
+        service.process(format!(
+            r#"{{ appRuns(uuid: "{}", limit: 2) {{ id, cause, exitCode }} }}"#,
+            uuid
+        )),
+        expected
+    );
+
+    let expected = json!({
+        "errs": "",
+        "msg": {
+            "appRuns": [{ "id": 1 }]
+        }
+    })
+    .to_string();
+    assert_eq!(
+        service.process(format!(
+            r#"{{ appRuns(uuid: "{}", after: 2, limit: 2) {{ id }} }}"#,
+            uuid
+        )),
+        expected
+    );
+}
+
+#[test]
+fn telemetry_sent_on_exit() {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    registry
+        .monitor
+        .history
+        .set_telemetry_addr(Some(socket.local_addr().unwrap()));
+    let uuid = register_script(&registry, "telemetry-app", "exit 2", "");
+
+    registry.start_app(&uuid, RunLevel::OnCommand).unwrap();
+
+    let mut buf = [0; 1024];
+    let (len, _) = socket.recv_from(&mut buf).unwrap();
+    let record: serde_json::Value = serde_json::from_slice(&buf[..len]).unwrap();
+
+    let runs = registry.monitor.history.runs(&uuid, None, None);
+    assert_eq!(
+        record,
+        json!({
+            "timestamp": runs[0].ended.unwrap(),
+            "subsystem": "app-service",
+            "parameter": "telemetry-app.0.0.1.exit",
+            "value": 2
+        })
+    );
+}
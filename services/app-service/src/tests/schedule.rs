@@ -0,0 +1,328 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use kubos_service::{Config, Service};
+use std::collections::BTreeMap;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tempfile::TempDir;
+
+use super::register_script;
+use registry::*;
+use schedule::{self, ScheduleRequest};
+use schema;
+
+fn every(interval: u64) -> ScheduleRequest {
+    ScheduleRequest {
+        interval: Some(interval),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn delayed_run() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let uuid = register_script(&registry, "scheduled-app", "exit 0", "");
+
+    let request = ScheduleRequest {
+        delay: Some(1),
+        ..Default::default()
+    };
+    let entry = registry
+        .scheduler
+        .add(&uuid, request, StartOptions::default())
+        .unwrap();
+    assert_eq!(registry.scheduler.entries(None), vec![entry.clone()]);
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while registry.scheduler.history(None).is_empty() {
+        assert!(Instant::now() < deadline, "Scheduled run did not happen");
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    let history = registry.scheduler.history(None);
+    assert_eq!(history[0].entry, entry.id);
+    assert_eq!(history[0].scheduled, entry.next_run);
+    assert!(history[0].pid.is_some());
+    assert_eq!(history[0].error, None);
+
+    // One-off entries are removed once they have run
+    assert!(registry.scheduler.entries(None).is_empty());
+}
+
+#[test]
+fn repeating_run_skips_missed_intervals() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let uuid = register_script(&registry, "scheduled-app", "exit 0", "");
+
+    let entry = registry
+        .scheduler
+        .add(&uuid, every(60), StartOptions::default())
+        .unwrap();
+    let first = entry.next_run;
+
+    registry.scheduler.run_due(first - 1);
+    assert!(registry.scheduler.history(None).is_empty());
+
+    registry.scheduler.run_due(first);
+    assert_eq!(registry.scheduler.entries(None)[0].next_run, first + 60);
+
+    // Several intervals pass without the schedule being checked
+    registry.scheduler.run_due(first + 250);
+    assert_eq!(registry.scheduler.entries(None)[0].next_run, first + 300);
+
+    let history = registry.scheduler.history(Some(&uuid));
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0].scheduled, first + 60);
+    assert_eq!(history[0].started, first + 250);
+}
+
+#[test]
+fn run_window() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let uuid = register_script(&registry, "scheduled-app", "exit 0", "");
+    let now = schedule::now();
+
+    let request = ScheduleRequest {
+        interval: Some(60),
+        window_start: Some(now + 1000),
+        window_end: Some(now + 1100),
+        ..Default::default()
+    };
+    let entry = registry
+        .scheduler
+        .add(&uuid, request, StartOptions::default())
+        .unwrap();
+    assert_eq!(entry.next_run, now + 1000);
+
+    registry.scheduler.run_due(now + 1000);
+    assert_eq!(registry.scheduler.entries(None)[0].next_run, now + 1060);
+
+    // The run after this one would fall outside the window, so the entry is finished
+    registry.scheduler.run_due(now + 1060);
+    assert!(registry.scheduler.entries(None).is_empty());
+    assert_eq!(registry.scheduler.history(None).len(), 2);
+}
+
+#[test]
+fn missed_run_window() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let uuid = register_script(&registry, "scheduled-app", "exit 0", "");
+    let now = schedule::now();
+
+    let request = ScheduleRequest {
+        at: Some(now + 1000),
+        window_end: Some(now + 1010),
+        ..Default::default()
+    };
+    registry
+        .scheduler
+        .add(&uuid, request, StartOptions::default())
+        .unwrap();
+
+    registry.scheduler.run_due(now + 1020);
+
+    let history = registry.scheduler.history(None);
+    assert_eq!(history[0].pid, None);
+    assert_eq!(
+        history[0].error,
+        Some("The run window ended before the app could be started".to_owned())
+    );
+    assert!(registry.scheduler.entries(None).is_empty());
+}
+
+#[test]
+fn invalid_requests() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let uuid = register_script(&registry, "scheduled-app", "exit 0", "");
+    let now = schedule::now();
+
+    let add = |uuid: &str, request| {
+        registry
+            .scheduler
+            .add(uuid, request, StartOptions::default())
+            .unwrap_err()
+    };
+
+    assert_eq!(
+        add("not-an-app", every(60)),
+        "Active app with UUID not-an-app does not exist"
+    );
+    assert_eq!(
+        add(&uuid, ScheduleRequest::default()),
+        "A time, delay or interval must be given"
+    );
+    assert_eq!(add(&uuid, every(0)), "Interval must be greater than zero");
+    assert_eq!(
+        add(
+            &uuid,
+            ScheduleRequest {
+                at: Some(now + 10),
+                delay: Some(10),
+                ..Default::default()
+            }
+        ),
+        "Only one of a time and a delay may be given"
+    );
+    assert_eq!(
+        add(
+            &uuid,
+            ScheduleRequest {
+                at: Some(1000),
+                ..Default::default()
+            }
+        ),
+        "Time 1000 has already passed"
+    );
+    assert_eq!(
+        add(
+            &uuid,
+            ScheduleRequest {
+                delay: Some(100),
+                window_end: Some(now + 10),
+                ..Default::default()
+            }
+        ),
+        "The run window ends before the first run"
+    );
+    assert!(registry.scheduler.entries(None).is_empty());
+}
+
+#[test]
+fn schedule_persisted() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let uuid = register_script(&registry, "scheduled-app", "exit 0", "");
+
+    let mut env = BTreeMap::new();
+    env.insert("MISSION".to_owned(), "1".to_owned());
+    let options = StartOptions {
+        args: Some(vec!["--mode".to_owned(), "safe".to_owned()]),
+        config: None,
+        env,
+    };
+
+    let first = registry
+        .scheduler
+        .add(&uuid, every(60), options.clone())
+        .unwrap();
+    let second = registry
+        .scheduler
+        .add(&uuid, every(3600), StartOptions::default())
+        .unwrap();
+    registry.scheduler.run_due(first.next_run);
+
+    let reloaded = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let entries = reloaded.scheduler.entries(None);
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].id, first.id);
+    assert_eq!(entries[0].options, options);
+    assert_eq!(entries[1], second);
+    assert_eq!(
+        reloaded.scheduler.history(None),
+        registry.scheduler.history(None)
+    );
+
+    // IDs aren't reused after a reload
+    reloaded.scheduler.remove(second.id).unwrap();
+    let third = reloaded
+        .scheduler
+        .add(&uuid, every(60), StartOptions::default())
+        .unwrap();
+    assert_eq!(third.id, 3);
+}
+
+#[test]
+fn schedule_mutations() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let uuid = register_script(&registry, "scheduled-app", "exit 0", "");
+    let at = schedule::now() + 3600;
+
+    let config = r#"
+        [app-service.addr]
+        ip = "127.0.0.1"
+        port = 9999"#;
+    let service = Service::new(
+        Config::new_from_str("app-service", config),
+        registry,
+        schema::QueryRoot,
+        schema::MutationRoot,
+    );
+
+    let expected = json!({
+        "errs": "",
+        "msg": {
+            "scheduleApp": {
+                "id": 1,
+                "nextRun": at,
+                "interval": 600,
+                "args": ["fast"],
+                "env": ["MODE=test"]
+            }
+        }
+    })
+    .to_string();
+    assert_eq!(
+        service.process(format!(
+            r#"mutation {{
+                scheduleApp(uuid: "{}", at: {}, interval: 600, args: ["fast"], env: ["MODE=test"]) {{
+                    id, nextRun, interval, args, env
+                }}
+            }}"#,
+            uuid, at
+        )),
+        expected
+    );
+
+    let expected = json!({
+        "errs": "",
+        "msg": {
+            "schedule": [{ "id": 1, "uuid": uuid }],
+            "scheduleHistory": []
+        }
+    })
+    .to_string();
+    assert_eq!(
+        service.process("{ schedule { id, uuid }, scheduleHistory { entry } }".to_owned()),
+        expected
+    );
+
+    let expected = json!({
+        "errs": "",
+        "msg": {
+            "unscheduleApp": { "id": 1 }
+        }
+    })
+    .to_string();
+    assert_eq!(
+        service.process("mutation { unscheduleApp(id: 1) { id } }".to_owned()),
+        expected
+    );
+
+    let response = service.process("mutation { unscheduleApp(id: 1) { id } }".to_owned());
+    assert!(
+        response.contains("Schedule entry 1 does not exist"),
+        "{}",
+        response
+    );
+}
@@ -0,0 +1,164 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use kubos_service::{Config, Service};
+use std::fs;
+use std::path::PathBuf;
+
+use tempfile::TempDir;
+
+use super::register_version;
+use registry::*;
+use schema;
+
+// Install versions of the same app in the given order, returning the app's UUID
+fn install(registry: &AppRegistry, versions: &[&str]) -> String {
+    let mut uuid = String::new();
+    for version in versions {
+        uuid = register_version(registry, "versioned-app", version, "exit 0", "");
+    }
+    uuid
+}
+
+fn active_versions(registry: &AppRegistry) -> Vec<String> {
+    registry
+        .entries
+        .borrow()
+        .iter()
+        .filter(|e| e.active_version)
+        .map(|e| e.app.metadata.version.clone())
+        .collect()
+}
+
+fn symlink_target(registry_dir: &TempDir, uuid: &str) -> PathBuf {
+    fs::read_link(registry_dir.path().join("active").join(uuid)).unwrap()
+}
+
+#[test]
+fn set_version_good() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let uuid = install(&registry, &["1.0", "2.0"]);
+
+    let entry = registry.set_version(&uuid, "1.0").unwrap();
+    assert!(entry.active_version);
+    assert_eq!(entry.app.metadata.version, "1.0");
+
+    assert_eq!(active_versions(&registry), vec!["1.0"]);
+    assert_eq!(
+        symlink_target(&registry_dir, &uuid),
+        registry_dir.path().join(&uuid).join("1.0")
+    );
+
+    // The change is persisted in the app.toml files
+    let reloaded = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    assert_eq!(active_versions(&reloaded), vec!["1.0"]);
+}
+
+#[test]
+fn set_version_missing() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let uuid = install(&registry, &["1.0"]);
+
+    assert_eq!(
+        registry.set_version(&uuid, "3.0").unwrap_err(),
+        format!("Version 3.0 of app with UUID {} does not exist", uuid)
+    );
+    assert_eq!(active_versions(&registry), vec!["1.0"]);
+}
+
+#[test]
+fn rollback_to_previous_version() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    // Versions are compared numerically, so 1.10 is newer than 1.9
+    let uuid = install(&registry, &["1.2", "1.10", "1.9"]);
+    registry.set_version(&uuid, "1.10").unwrap();
+
+    assert_eq!(
+        registry.rollback(&uuid).unwrap().app.metadata.version,
+        "1.9"
+    );
+    assert_eq!(
+        registry.rollback(&uuid).unwrap().app.metadata.version,
+        "1.2"
+    );
+    assert_eq!(
+        registry.rollback(&uuid).unwrap_err(),
+        format!("App with UUID {} has no version older than 1.2", uuid)
+    );
+
+    assert_eq!(active_versions(&registry), vec!["1.2"]);
+    assert_eq!(
+        symlink_target(&registry_dir, &uuid),
+        registry_dir.path().join(&uuid).join("1.2")
+    );
+}
+
+#[test]
+fn rollback_mutation() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let uuid = install(&registry, &["1.0", "2.0"]);
+
+    let config = r#"
+        [app-service.addr]
+        ip = "127.0.0.1"
+        port = 9999"#;
+    let service = Service::new(
+        Config::new_from_str("app-service", config),
+        registry,
+        schema::QueryRoot,
+        schema::MutationRoot,
+    );
+
+    let expected = json!({
+        "errs": "",
+        "msg": {
+            "rollback": {
+                "active": true,
+                "app": { "version": "1.0" }
+            }
+        }
+    })
+    .to_string();
+    assert_eq!(
+        service.process(format!(
+            r#"mutation {{ rollback(uuid: "{}") {{ active, app {{ version }} }} }}"#,
+            uuid
+        )),
+        expected
+    );
+
+    let expected = json!({
+        "errs": "",
+        "msg": {
+            "setVersion": {
+                "active": true,
+                "app": { "version": "2.0" }
+            }
+        }
+    })
+    .to_string();
+    assert_eq!(
+        service.process(format!(
+            r#"mutation {{ setVersion(uuid: "{}", version: "2.0") {{ active, app {{ version }} }} }}"#,
+            uuid
+        )),
+        expected
+    );
+}
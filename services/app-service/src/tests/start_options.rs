@@ -0,0 +1,163 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use kubos_app::RunLevel;
+use kubos_service::{Config, Service};
+use std::collections::BTreeMap;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tempfile::TempDir;
+
+use super::register_script;
+use logs::{self, Stream};
+use registry::*;
+use schema;
+
+const SCRIPT: &str = "echo \"args: $*\"\necho \"mode: $MISSION_MODE\"";
+
+const MANIFEST: &str = r#"
+    config = "mission.toml"
+    args = ["--mode", "nominal"]
+
+    [env]
+    MISSION_MODE = "nominal"
+    "#;
+
+// Wait for the most recently started app to exit and return what it wrote to stdout
+fn output(registry: &AppRegistry, uuid: &str) -> Vec<String> {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !registry.monitor.running().is_empty() {
+        assert!(Instant::now() < deadline, "App did not exit");
+        thread::sleep(Duration::from_millis(20));
+    }
+    // Give the capture threads a moment to drain the pipes
+    thread::sleep(Duration::from_millis(100));
+
+    let run = registry.monitor.exits()[0].app.run.unwrap();
+    logs::tail(&registry.log_dir(uuid), run, Stream::Stdout, 10).unwrap()
+}
+
+#[test]
+fn manifest_defaults() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let uuid = register_script(&registry, "mission-app", SCRIPT, MANIFEST);
+
+    registry.start_app(&uuid, RunLevel::OnCommand).unwrap();
+
+    let config = registry_dir
+        .path()
+        .join(&uuid)
+        .join("0.0.1")
+        .join("mission.toml");
+    assert_eq!(
+        output(&registry, &uuid),
+        vec![
+            format!(
+                "args: -r OnCommand -c {} -- --mode nominal",
+                config.display()
+            ),
+            "mode: nominal".to_owned(),
+        ]
+    );
+}
+
+#[test]
+fn options_override_defaults() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let uuid = register_script(&registry, "mission-app", SCRIPT, MANIFEST);
+
+    let mut env = BTreeMap::new();
+    env.insert("MISSION_MODE".to_owned(), "safe".to_owned());
+    let options = StartOptions {
+        args: Some(vec!["--mode".to_owned(), "safe".to_owned()]),
+        env,
+        config: Some("/home/kubos/safe.toml".to_owned()),
+    };
+
+    registry
+        .start_app_with(&uuid, RunLevel::OnBoot, options)
+        .unwrap();
+
+    assert_eq!(
+        output(&registry, &uuid),
+        vec![
+            "args: -r OnBoot -c /home/kubos/safe.toml -- --mode safe",
+            "mode: safe",
+        ]
+    );
+}
+
+#[test]
+fn start_app_mutation_options() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let uuid = register_script(&registry, "mission-app", SCRIPT, "");
+
+    let config = r#"
+        [app-service.addr]
+        ip = "127.0.0.1"
+        port = 9999"#;
+    let service = Service::new(
+        Config::new_from_str("app-service", config),
+        registry,
+        schema::QueryRoot,
+        schema::MutationRoot,
+    );
+
+    let response = service.process(format!(
+        r#"mutation {{
+            startApp(uuid: "{}", runLevel: "OnCommand", args: ["one", "two"], env: ["MISSION_MODE=test"])
+        }}"#,
+        uuid
+    ));
+    assert!(response.contains("\"startApp\":"), "{}", response);
+
+    let expected = json!({
+        "errs": "",
+        "msg": {
+            "appLogs": {
+                "stdout": ["args: -r OnCommand -- one two", "mode: test"]
+            }
+        }
+    })
+    .to_string();
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let response = service.process(format!(r#"{{ appLogs(uuid: "{}") {{ stdout }} }}"#, uuid));
+        if response == expected {
+            break;
+        }
+        assert!(
+            Instant::now() < deadline,
+            "Unexpected response: {}",
+            response
+        );
+        thread::sleep(Duration::from_millis(20));
+    }
+
+    let response = service.process(format!(
+        r#"mutation {{ startApp(uuid: "{}", runLevel: "OnCommand", env: ["MISSION_MODE"]) }}"#,
+        uuid
+    ));
+    assert!(
+        response.contains("Invalid environment variable MISSION_MODE: expected KEY=VALUE"),
+        "{}",
+        response
+    );
+}
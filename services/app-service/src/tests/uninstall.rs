@@ -0,0 +1,272 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use kubos_app::RunLevel;
+use kubos_service::{Config, Service};
+use std::fs;
+use std::path::Path;
+
+use tempfile::TempDir;
+
+use super::register_version;
+use registry::*;
+use schedule::ScheduleRequest;
+use schema;
+
+fn versions(registry: &AppRegistry, uuid: &str) -> Vec<(String, bool)> {
+    let mut versions: Vec<(String, bool)> = registry
+        .entries
+        .borrow()
+        .iter()
+        .filter(|entry| entry.app.uuid == uuid)
+        .map(|entry| (entry.app.metadata.version.clone(), entry.active_version))
+        .collect();
+    versions.sort();
+    versions
+}
+
+fn active_link(registry_dir: &TempDir, uuid: &str) -> Option<String> {
+    fs::read_link(registry_dir.path().join("active").join(uuid))
+        .ok()
+        .map(|target| target.to_string_lossy().into_owned())
+}
+
+#[test]
+fn uninstall_from_unsorted_registry() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let b_app = register_version(&registry, "b-app", "2.0", "exit 0", "");
+    let a_app = register_version(&registry, "a-app", "1.0", "exit 0", "");
+    register_version(&registry, "b-app", "1.0", "exit 0", "");
+
+    assert!(registry.uninstall(&b_app, "2.0").unwrap());
+
+    assert_eq!(versions(&registry, &b_app), vec![("1.0".to_owned(), true)]);
+    assert_eq!(versions(&registry, &a_app), vec![("1.0".to_owned(), true)]);
+    assert!(!registry_dir.path().join(&b_app).join("2.0").exists());
+
+    assert_eq!(
+        registry.uninstall(&b_app, "2.0").unwrap_err(),
+        format!("Version 2.0 of app with UUID {} does not exist", b_app)
+    );
+}
+
+#[test]
+fn uninstall_active_version_promotes_newest() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let uuid = register_version(&registry, "mission-app", "1.0", "exit 0", "");
+    register_version(&registry, "mission-app", "3.0", "exit 0", "");
+    register_version(&registry, "mission-app", "2.0", "exit 0", "");
+
+    registry.uninstall(&uuid, "2.0").unwrap();
+
+    assert_eq!(
+        versions(&registry, &uuid),
+        vec![("1.0".to_owned(), false), ("3.0".to_owned(), true)]
+    );
+    assert_eq!(
+        active_link(&registry_dir, &uuid),
+        Some(
+            registry_dir
+                .path()
+                .join(&uuid)
+                .join("3.0")
+                .to_string_lossy()
+                .into_owned()
+        )
+    );
+
+    // The promotion is saved, so it survives a reload
+    let reloaded = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    assert_eq!(
+        versions(&reloaded, &uuid),
+        vec![("1.0".to_owned(), false), ("3.0".to_owned(), true)]
+    );
+    assert_eq!(
+        load_active(&registry_dir.path().to_string_lossy(), &uuid)
+            .unwrap()
+            .metadata
+            .version,
+        "3.0"
+    );
+}
+
+#[test]
+fn uninstall_last_version_removes_app() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let uuid = register_version(&registry, "mission-app", "1.0", "exit 0", "");
+    registry.start_app(&uuid, RunLevel::OnCommand).unwrap();
+
+    registry.uninstall(&uuid, "1.0").unwrap();
+
+    assert!(registry.entries.borrow().is_empty());
+    assert!(fs::symlink_metadata(registry_dir.path().join("active").join(&uuid)).is_err());
+    assert!(!registry_dir.path().join(&uuid).exists());
+    assert!(registry.run_onboot().is_ok());
+}
+
+#[test]
+fn uninstall_running_version() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let uuid = register_version(&registry, "mission-app", "1.0", "exec sleep 10", "");
+    register_version(&registry, "mission-app", "2.0", "exec sleep 10", "");
+    let pid = registry.start_app(&uuid, RunLevel::OnCommand).unwrap();
+
+    registry.uninstall(&uuid, "2.0").unwrap();
+
+    // The instance has been reaped, and its run recorded, before its version was removed
+    assert!(registry.monitor.running().is_empty());
+    let runs = registry.monitor.history.runs(&uuid, None, None);
+    assert_eq!(runs[0].pid, pid);
+    assert_eq!(runs[0].version, "2.0");
+    assert!(runs[0].ended.is_some());
+    assert!(!registry_dir.path().join(&uuid).join("2.0").exists());
+    assert_eq!(versions(&registry, &uuid), vec![("1.0".to_owned(), true)]);
+}
+
+#[test]
+fn uninstall_app_by_name() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let uuid = register_version(&registry, "mission-app", "1.0", "exec sleep 10", "");
+    register_version(&registry, "mission-app", "2.0", "exec sleep 10", "");
+    let other = register_version(&registry, "other-app", "1.0", "exit 0", "");
+
+    registry.start_app(&uuid, RunLevel::OnCommand).unwrap();
+    registry
+        .scheduler
+        .add(
+            &uuid,
+            ScheduleRequest {
+                delay: Some(3600),
+                ..Default::default()
+            },
+            StartOptions::default(),
+        )
+        .unwrap();
+
+    assert!(registry.uninstall_app("mission-app").unwrap());
+
+    assert!(versions(&registry, &uuid).is_empty());
+    assert_eq!(versions(&registry, &other), vec![("1.0".to_owned(), true)]);
+    assert!(!registry_dir.path().join(&uuid).exists());
+    assert!(registry.scheduler.entries(None).is_empty());
+    assert!(registry.monitor.running().is_empty());
+    assert_eq!(registry.monitor.exits()[0].app.uuid, uuid);
+
+    assert_eq!(
+        registry.uninstall_app("mission-app").unwrap_err(),
+        "App mission-app does not exist"
+    );
+}
+
+#[test]
+fn keep_versions() {
+    let registry_dir = TempDir::new().unwrap();
+    let mut registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    registry.keep_versions = Some(2);
+
+    let uuid = register_version(&registry, "mission-app", "1.0", "exit 0", "");
+    register_version(&registry, "mission-app", "2.0", "exit 0", "");
+    register_version(&registry, "mission-app", "3.0", "exit 0", "");
+
+    assert_eq!(
+        versions(&registry, &uuid),
+        vec![("2.0".to_owned(), false), ("3.0".to_owned(), true)]
+    );
+    assert!(!registry_dir.path().join(&uuid).join("1.0").exists());
+
+    // The active version is kept even when it's one of the oldest
+    registry.keep_versions = None;
+    register_version(&registry, "mission-app", "4.0", "exit 0", "");
+    registry.set_version(&uuid, "2.0").unwrap();
+
+    assert_eq!(registry.prune_versions(&uuid, 1).unwrap(), vec!["3.0"]);
+    assert_eq!(
+        versions(&registry, &uuid),
+        vec![("2.0".to_owned(), true), ("4.0".to_owned(), false)]
+    );
+}
+
+#[test]
+fn disk_usage_and_uninstall_mutation() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy());
+    let uuid = register_version(&registry, "mission-app", "1.0", "exit 0", "");
+    register_version(&registry, "mission-app", "2.0", "exit 0", "");
+
+    let usage = registry.disk_usage(None);
+    assert_eq!(usage.len(), 1);
+    assert_eq!(usage[0].uuid, uuid);
+    assert_eq!(usage[0].name, "mission-app");
+    assert_eq!(usage[0].versions.len(), 2);
+    assert_eq!(usage[0].versions[0].version, "1.0");
+    assert!(usage[0].versions[0].bytes > 0);
+    let version_bytes: u64 = usage[0].versions.iter().map(|v| v.bytes).sum();
+    assert!(usage[0].total >= version_bytes);
+
+    let config = r#"
+        [app-service.addr]
+        ip = "127.0.0.1"
+        port = 9999"#;
+    let service = Service::new(
+        Config::new_from_str("app-service", config),
+        registry,
+        schema::QueryRoot,
+        schema::MutationRoot,
+    );
+
+    let expected = json!({
+        "errs": "",
+        "msg": {
+            "diskUsage": [{
+                "uuid": uuid,
+                "versions": [{ "version": "1.0" }, { "version": "2.0" }]
+            }]
+        }
+    })
+    .to_string();
+    assert_eq!(
+        service.process("{ diskUsage { uuid, versions { version } } }".to_owned()),
+        expected
+    );
+
+    let expected = json!({
+        "errs": "",
+        "msg": { "uninstall": true }
+    })
+    .to_string();
+    assert_eq!(
+        service
+            .process(r#"mutation { uninstall(name: "mission-app", version: "2.0") }"#.to_owned()),
+        expected
+    );
+    assert_eq!(
+        service.process(r#"mutation { uninstall(name: "mission-app") }"#.to_owned()),
+        expected
+    );
+    assert!(!Path::new(&registry_dir.path().join(&uuid)).exists());
+
+    let response = service.process(r#"mutation { uninstall(version: "1.0") }"#.to_owned());
+    assert!(
+        response.contains("Exactly one of a UUID and a name must be given"),
+        "{}",
+        response
+    );
+}
@@ -198,9 +198,11 @@ impl AppServiceFixture {
                 r#"
             [app-service]
             registry-dir = "{}"
+            log-file = "{}/app-service.log"
             [app-service.addr]
             ip = "127.0.0.1"
             port = {}"#,
+                registry_dir.path().to_str().unwrap(),
                 registry_dir.path().to_str().unwrap(),
                 port
             ).as_bytes(),
@@ -56,6 +56,14 @@
 //! The `[service-name.addr]` section is required for all services and is used to set
 //! the ip/port on which the service will listen for messages. Any service specific
 //! configuration values can be specified directly under the `[service-name]` section.
+//!
+//! ## Logging
+//!
+//! Starting a service sets up logging with the `kubos_logging` crate, so messages logged with
+//! the `log` crate's macros are sent to syslog, tagged with the service's name. The level of
+//! messages which are logged is set with the `log-level` key in the `[service-name]` section.
+//! A service which logs before it's started can call `kubos_logging::init` itself, and the
+//! logging it sets up is then kept.
 //! Note - the `service-name` used in the sections must match the name used when creating
 //! the `Config` instance inside your service.
 //!
@@ -113,7 +121,10 @@ extern crate serde;
 #[macro_use]
 extern crate serde_json;
 
+extern crate kubos_logging;
 extern crate kubos_system;
+#[macro_use]
+extern crate log;
 
 mod macros;
 mod service;
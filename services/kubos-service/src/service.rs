@@ -15,6 +15,7 @@
 //
 
 use juniper::{execute, Context as JuniperContext, GraphQLType, RootNode, Variables};
+use kubos_logging::{self, Facility, LogError};
 use kubos_system::Config;
 use serde_json;
 use std::cell::RefCell;
@@ -134,16 +135,24 @@ where
     /// Starts the service's GraphQL/UDP server. This function runs
     /// without return.
     ///
+    /// Logging is set up for the service before the server is started, unless the service has
+    /// already set it up itself.
+    ///
     /// # Panics
     ///
     /// The UDP interface will panic if the ip address and port provided
     /// cannot be bound (like if they are already in use), or if for some reason the socket fails
     /// to receive a message.
     pub fn start(&self) {
+        match kubos_logging::init(Facility::Daemon, &self.config) {
+            Ok(()) | Err(LogError::AlreadyInitialized) => {}
+            Err(err) => eprintln!("Failed to set up logging: {}", err),
+        }
+
         let addr = self.config.hosturl().parse::<SocketAddr>().unwrap();
 
         let socket = UdpSocket::bind(&addr).unwrap();
-        println!("Listening on: {}", socket.local_addr().unwrap());
+        info!("Listening on: {}", socket.local_addr().unwrap());
 
         let mut buf = [0; 4096];
         loop {
@@ -0,0 +1,241 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use kubos_app::{query, ServiceConfig};
+use kubos_telemetry_db::{Database, LimitChecker, NewEntry};
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use std::thread::{sleep, spawn};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const DEFAULT_TIMEOUT: u64 = 1000;
+
+/// A single collection job, read from a `[[telemetry-collector.jobs]]` entry in the
+/// config file
+#[derive(Clone, Debug, Deserialize)]
+pub struct JobConfig {
+    /// Name of the service to query, as it appears in the config file
+    pub service: String,
+    /// GraphQL query to send to the service
+    pub query: String,
+    /// Number of seconds between runs
+    pub period: u64,
+    /// Subsystem name to store the results under. Defaults to the service name
+    pub subsystem: Option<String>,
+    /// Number of milliseconds to wait for the service to respond
+    pub timeout: Option<u64>,
+}
+
+/// Running counters for a collection job
+#[derive(Clone, Debug, Default)]
+pub struct JobStatus {
+    /// Number of times the job has been run
+    pub runs: u64,
+    /// Number of runs which failed
+    pub failures: u64,
+    /// Number of runs which have failed since the last successful one
+    pub consecutive_failures: u64,
+    /// Number of telemetry entries stored by the job
+    pub entries: u64,
+    /// Time of the most recent run
+    pub last_run: Option<i32>,
+    /// Time of the most recent successful run
+    pub last_success: Option<i32>,
+    /// Error from the most recent failed run
+    pub last_error: Option<String>,
+}
+
+/// A collection job along with its current status
+pub struct Job {
+    pub config: JobConfig,
+    pub status: Mutex<JobStatus>,
+}
+
+impl Job {
+    /// Create a new job which has not been run yet
+    pub fn new(config: JobConfig) -> Job {
+        Job {
+            config,
+            status: Mutex::new(JobStatus::default()),
+        }
+    }
+
+    /// Name of the subsystem the job's results are stored under
+    pub fn subsystem(&self) -> &str {
+        self.config
+            .subsystem
+            .as_ref()
+            .unwrap_or(&self.config.service)
+    }
+
+    /// Query the job's service once and store the results, updating the job's status.
+    ///
+    /// Results which can't be stored (ex. a duplicate of an existing entry) are skipped. The
+    /// stored results are checked against their limits.
+    ///
+    /// Returns the number of entries stored
+    ///
+    /// # Arguments
+    ///
+    /// * `config_path` - Path to the config file containing the service's address
+    /// * `database` - Database to store the results in
+    /// * `limits` - Limits to check the stored results against
+    pub fn run(
+        &self,
+        config_path: &str,
+        database: &Mutex<Database>,
+        limits: &LimitChecker,
+    ) -> Result<usize, String> {
+        let timestamp = now();
+        let result = self.collect(config_path, database, limits, timestamp);
+
+        let mut status = self.status.lock().unwrap();
+        status.runs += 1;
+        status.last_run = Some(timestamp);
+        match result {
+            Ok(count) => {
+                status.consecutive_failures = 0;
+                status.entries += count as u64;
+                status.last_success = Some(timestamp);
+            }
+            Err(ref err) => {
+                status.failures += 1;
+                status.consecutive_failures += 1;
+                status.last_error = Some(err.clone());
+            }
+        }
+
+        result
+    }
+
+    fn collect(
+        &self,
+        config_path: &str,
+        database: &Mutex<Database>,
+        limits: &LimitChecker,
+        timestamp: i32,
+    ) -> Result<usize, String> {
+        let timeout = Duration::from_millis(self.config.timeout.unwrap_or(DEFAULT_TIMEOUT));
+
+        let response = query(
+            ServiceConfig::new_from_path(&self.config.service, config_path.to_owned()),
+            &self.config.query,
+            Some(timeout),
+        )
+        .map_err(|err| format!("Failed to query {}: {}", self.config.service, err))?;
+
+        let values = flatten(&response);
+        let subsystem = self.subsystem();
+        let entries: Vec<NewEntry> = values
+            .iter()
+            .map(|(parameter, value)| NewEntry {
+                timestamp,
+                subsystem,
+                parameter,
+                value,
+            })
+            .collect();
+
+        let database = database
+            .lock()
+            .map_err(|_| "Database lock poisoned".to_owned())?;
+
+        let stored = database
+            .insert_batch_partial(entries)
+            .map_err(|err| format!("Failed to store telemetry: {}", err))?;
+
+        if let Err(err) = limits.check(&database, &stored) {
+            error!("Failed to record telemetry alarms: {}", err);
+        }
+
+        Ok(stored.len())
+    }
+}
+
+fn now() -> i32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|dur| dur.as_secs() as i32)
+        .unwrap_or(0)
+}
+
+/// Flatten a GraphQL response into `(parameter, value)` pairs.
+///
+/// Each parameter name is the path to its value, with object keys and array indices joined
+/// by `.`. For example, `{"power": {"voltages": [3.3, 5.0]}}` becomes `power.voltages.0 = 3.3`
+/// and `power.voltages.1 = 5.0`. Null values are skipped.
+pub fn flatten(value: &Value) -> Vec<(String, String)> {
+    let mut values = vec![];
+    flatten_into(value, String::new(), &mut values);
+    values
+}
+
+fn flatten_into(value: &Value, path: String, values: &mut Vec<(String, String)>) {
+    let join = |key: &str| {
+        if path.is_empty() {
+            key.to_owned()
+        } else {
+            format!("{}.{}", path, key)
+        }
+    };
+
+    match *value {
+        Value::Object(ref map) => {
+            for (key, child) in map {
+                flatten_into(child, join(key), values);
+            }
+        }
+        Value::Array(ref list) => {
+            for (index, child) in list.iter().enumerate() {
+                flatten_into(child, join(&index.to_string()), values);
+            }
+        }
+        Value::Null => {}
+        Value::String(ref string) => values.push((path, string.clone())),
+        ref other => values.push((path, other.to_string())),
+    }
+}
+
+/// Start a thread for each job which runs it on its configured period
+pub fn start(
+    jobs: &[Arc<Job>],
+    config_path: &str,
+    database: &Arc<Mutex<Database>>,
+    limits: &Arc<LimitChecker>,
+) {
+    for job in jobs {
+        let job = job.clone();
+        let config_path = config_path.to_owned();
+        let database = database.clone();
+        let limits = limits.clone();
+
+        spawn(move || {
+            let period = Duration::from_secs(job.config.period.max(1));
+            loop {
+                let start = Instant::now();
+
+                if let Err(err) = job.run(&config_path, &database, &limits) {
+                    warn!("Collection from {} failed: {}", job.config.service, err);
+                }
+
+                let elapsed = start.elapsed();
+                if elapsed < period {
+                    sleep(period - elapsed);
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,224 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+#![deny(missing_docs)]
+#![deny(warnings)]
+
+//! Kubos Service which periodically collects telemetry from other services and stores it in the
+//! telemetry database.
+//!
+//! # Configuration
+//!
+//! The service can be configured in the `/home/system/etc/config.toml` with the following fields:
+//!
+//! ```
+//! [telemetry-collector]
+//! database = "/var/lib/telemetry.db"
+//!
+//! [telemetry-collector.addr]
+//! ip = "127.0.0.1"
+//! port = 8090
+//!
+//! [[telemetry-collector.jobs]]
+//! service = "mai400-service"
+//! query = "{ telemetry { nominal { acsMode, gyro } } }"
+//! period = 10
+//! subsystem = "mai400"
+//! timeout = 1000
+//! ```
+//!
+//! Where `database` specifies the path to the telemetry database file, `ip` specifies the
+//! service's IP address, and `port` specifies the port on which the service will be
+//! listening for UDP packets.
+//!
+//! Each `[[telemetry-collector.jobs]]` entry defines one collection job:
+//!
+//! - `service` - The name of the service to query. Its address is looked up in the same config file
+//! - `query` - The GraphQL query to send to the service
+//! - `period` - The number of seconds between runs of the job
+//! - `subsystem` - (Optional) The subsystem name to store the results under. Defaults to the service name
+//! - `timeout` - (Optional) The number of milliseconds to wait for a response. Defaults to 1000
+//!
+//! # Collected Telemetry
+//!
+//! The JSON result of each query is flattened into one telemetry entry per value. The parameter
+//! name is the path to the value, with object keys and array indices joined by `.`, and all
+//! entries from a single run share the same timestamp. For example, the response
+//!
+//! ```json
+//! { "telemetry": { "nominal": { "acsMode": "NORMAL", "gyro": [0.1, 0.2, 0.3] } } }
+//! ```
+//!
+//! is stored as the `mai400` subsystem entries `telemetry.nominal.acsMode = NORMAL`,
+//! `telemetry.nominal.gyro.0 = 0.1`, `telemetry.nominal.gyro.1 = 0.2` and
+//! `telemetry.nominal.gyro.2 = 0.3`. Null values are skipped.
+//!
+//! An entry is skipped if the database already holds an entry with the same timestamp, subsystem
+//! and parameter. The rest of the run's entries are still stored.
+//!
+//! # Limit Checking
+//!
+//! Collected entries are checked against the limits defined by the `[[telemetry-service.limits]]`
+//! entries in the same config file, and alarms are recorded in the database just as they are for
+//! telemetry stored by the telemetry service. See the telemetry service's documentation for the
+//! format of the limit definitions.
+//!
+//! # Starting the Service
+//!
+//! The service should be started automatically by its init script, but may also be started manually:
+//!
+//! ```
+//! $ telemetry-collector
+//! Listening on: 127.0.0.1:8090
+//! ```
+//!
+//! # Panics
+//!
+//! Attempts to grab database path from Configuration and will `panic!` if not found.
+//! Attempts to open and upgrade the database at provided path and will `panic!` if that fails.
+//! Attempts to parse the job and limit definitions and will `panic!` if they are invalid.
+//!
+//! # GraphQL Schema
+//!
+//! ```graphql
+//! type Job {
+//!   service: String!
+//!   subsystem: String!
+//!   query: String!
+//!   period: Integer!
+//!   runs: Integer!
+//!   failures: Integer!
+//!   consecutiveFailures: Integer!
+//!   entriesStored: Integer!
+//!   lastRun: Integer
+//!   lastSuccess: Integer
+//!   lastError: String
+//! }
+//!
+//! query ping: String!
+//! query jobs(service: String): [Job]
+//! ```
+//!
+//! # Example Queries
+//!
+//! ## Check the status of all jobs
+//! ```graphql
+//! {
+//!   jobs {
+//!     service,
+//!     runs,
+//!     failures,
+//!     lastError
+//!   }
+//! }
+//! ```
+
+extern crate getopts;
+#[macro_use]
+extern crate juniper;
+extern crate kubos_app;
+extern crate kubos_logging;
+extern crate kubos_service;
+extern crate kubos_system;
+extern crate kubos_telemetry_db;
+#[macro_use]
+extern crate log;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+
+#[cfg(test)]
+extern crate diesel;
+#[cfg(test)]
+extern crate tempfile;
+
+mod collector;
+mod model;
+mod schema;
+#[cfg(test)]
+mod tests;
+
+use collector::JobConfig;
+use getopts::Options;
+use kubos_logging::Facility;
+use kubos_service::{Config, Service};
+use kubos_telemetry_db::{Database, Limit, LimitChecker};
+use model::Subsystem;
+use schema::{MutationRoot, QueryRoot};
+use std::env;
+use std::time::Duration;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let mut opts = Options::new();
+
+    opts.optopt("c", "config", "Path to config file", "CONFIG");
+    let matches = match opts.parse(&args[1..]) {
+        Ok(m) => m,
+        Err(err) => {
+            eprintln!("Unable to parse command options: {}", err);
+            return;
+        }
+    };
+
+    // The job services' addresses are read from the same config file
+    let config_path = matches
+        .opt_str("c")
+        .unwrap_or_else(|| kubos_system::DEFAULT_PATH.to_owned());
+    let config = Config::new_from_path("telemetry-collector", config_path.clone());
+    // Set up logging before the database is opened, so that problems with it are logged
+    if let Err(err) = kubos_logging::init(Facility::Daemon, &config) {
+        eprintln!("Failed to set up logging: {}", err);
+    }
+
+    let db_path = config
+        .get("database")
+        .expect("No database path found in config file");
+    let db_path = db_path.as_str().unwrap_or("");
+
+    let (db, report) = Database::open(db_path).unwrap_or_else(|err| panic!("{}", err));
+    if let Some(path) = report.quarantined {
+        warn!("Corrupted database moved to {}", path);
+    }
+
+    // The telemetry service writes to the same database file
+    db.set_busy_timeout(Duration::from_secs(1))
+        .expect("Failed to set database busy timeout");
+
+    let jobs: Vec<JobConfig> = match config.get("jobs") {
+        Some(jobs) => jobs
+            .try_into()
+            .expect("Invalid job definitions in config file"),
+        None => vec![],
+    };
+
+    // Collected telemetry is checked against the same limits as the telemetry service's
+    let limits: Vec<Limit> =
+        match Config::new_from_path("telemetry-service", config_path.clone()).get("limits") {
+            Some(limits) => limits
+                .try_into()
+                .expect("Invalid limit definitions in config file"),
+            None => vec![],
+        };
+
+    Service::new(
+        config,
+        Subsystem::new(db, jobs, &config_path, LimitChecker::new(limits)),
+        QueryRoot,
+        MutationRoot,
+    )
+    .start();
+}
@@ -0,0 +1,43 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use collector::{self, Job, JobConfig};
+use kubos_telemetry_db::{Database, LimitChecker};
+use std::sync::{Arc, Mutex};
+
+pub struct Subsystem {
+    pub jobs: Vec<Arc<Job>>,
+}
+
+impl Subsystem {
+    pub fn new(
+        database: Database,
+        jobs: Vec<JobConfig>,
+        config_path: &str,
+        limits: LimitChecker,
+    ) -> Self {
+        let database = Arc::new(Mutex::new(database));
+        let limits = Arc::new(limits);
+        let jobs: Vec<Arc<Job>> = jobs
+            .into_iter()
+            .map(|job| Arc::new(Job::new(job)))
+            .collect();
+
+        collector::start(&jobs, config_path, &database, &limits);
+
+        Subsystem { jobs }
+    }
+}
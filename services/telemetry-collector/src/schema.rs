@@ -0,0 +1,117 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use collector::{JobConfig, JobStatus};
+use juniper::FieldResult;
+use kubos_service;
+use model::Subsystem;
+
+type Context = kubos_service::Context<Subsystem>;
+
+pub struct Job {
+    config: JobConfig,
+    subsystem: String,
+    status: JobStatus,
+}
+
+graphql_object!(Job: () |&self| {
+    description: "A telemetry collection job"
+
+    field service() -> &String as "Service being queried" {
+        &self.config.service
+    }
+
+    field subsystem() -> &String as "Subsystem the results are stored under" {
+        &self.subsystem
+    }
+
+    field query() -> &String as "GraphQL query sent to the service" {
+        &self.config.query
+    }
+
+    field period() -> i32 as "Number of seconds between runs" {
+        self.config.period as i32
+    }
+
+    field runs() -> i32 as "Number of times the job has been run" {
+        self.status.runs as i32
+    }
+
+    field failures() -> i32 as "Number of runs which failed" {
+        self.status.failures as i32
+    }
+
+    field consecutive_failures() -> i32 as "Number of runs which have failed since the last success" {
+        self.status.consecutive_failures as i32
+    }
+
+    field entries_stored() -> i32 as "Number of telemetry entries stored" {
+        self.status.entries as i32
+    }
+
+    field last_run() -> Option<i32> as "Timestamp of the most recent run" {
+        self.status.last_run
+    }
+
+    field last_success() -> Option<i32> as "Timestamp of the most recent successful run" {
+        self.status.last_success
+    }
+
+    field last_error() -> &Option<String> as "Error from the most recent failed run" {
+        &self.status.last_error
+    }
+});
+
+pub struct QueryRoot;
+
+graphql_object!(QueryRoot: Context |&self| {
+    // Test query to verify service is running without
+    // attempting to communicate with any other services
+    //
+    // {
+    //     ping: "pong"
+    // }
+    field ping() -> FieldResult<String>
+    {
+        Ok(String::from("pong"))
+    }
+
+    field jobs(&executor, service: Option<String>) -> FieldResult<Vec<Job>>
+        as "Collection jobs and their current status"
+    {
+        let jobs = executor
+            .context()
+            .subsystem()
+            .jobs
+            .iter()
+            .filter(|job| match service {
+                Some(ref service) => &job.config.service == service,
+                None => true,
+            })
+            .map(|job| Job {
+                config: job.config.clone(),
+                subsystem: job.subsystem().to_owned(),
+                status: job.status.lock().unwrap().clone(),
+            })
+            .collect();
+
+        Ok(jobs)
+    }
+});
+
+pub struct MutationRoot;
+
+graphql_object!(MutationRoot: Context | &self | {});
@@ -0,0 +1,199 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use super::mock_service::*;
+use collector::*;
+use diesel::prelude::*;
+use kubos_service::{Config, Service};
+use kubos_telemetry_db::alarms::dsl as alarms_dsl;
+use kubos_telemetry_db::telemetry::dsl;
+use kubos_telemetry_db::{Alarm, Database, Entry, Limit, LimitChecker};
+use serde_json::Value;
+use std::sync::Mutex;
+use tempfile::TempDir;
+
+fn job(service: &str, subsystem: Option<&str>) -> Job {
+    Job::new(JobConfig {
+        service: service.to_owned(),
+        query: "{ power { state, voltages, fault } }".to_owned(),
+        period: 1,
+        subsystem: subsystem.map(|sub| sub.to_owned()),
+        timeout: Some(200),
+    })
+}
+
+fn no_limits() -> LimitChecker {
+    LimitChecker::new(vec![])
+}
+
+fn setup_db(dir: &TempDir) -> Mutex<Database> {
+    let (db, _) = Database::open(&dir.path().join("telemetry.db").to_string_lossy()).unwrap();
+    Mutex::new(db)
+}
+
+#[test]
+fn flatten_nested() {
+    let value: Value = ::serde_json::from_str(
+        r#"{"power": {"state": "ON", "voltages": [3.3, 5], "fault": null, "enabled": true}}"#,
+    )
+    .unwrap();
+
+    let mut result = flatten(&value);
+    result.sort();
+
+    assert_eq!(
+        result,
+        vec![
+            ("power.enabled".to_owned(), "true".to_owned()),
+            ("power.state".to_owned(), "ON".to_owned()),
+            ("power.voltages.0".to_owned(), "3.3".to_owned()),
+            ("power.voltages.1".to_owned(), "5".to_owned()),
+        ]
+    );
+}
+
+#[test]
+fn run_good() {
+    let dir = TempDir::new().unwrap();
+    let config = dir.path().join("config.toml");
+    mock_service!(config, 8790);
+    let database = setup_db(&dir);
+
+    let job = job("mock-service", Some("eps"));
+    let count = job
+        .run(&config.to_string_lossy(), &database, &no_limits())
+        .unwrap();
+    assert_eq!(count, 3);
+
+    let entries = dsl::telemetry
+        .order(dsl::parameter.asc())
+        .load::<Entry>(&database.lock().unwrap().connection)
+        .unwrap();
+    let values: Vec<(&str, &str, &str)> = entries
+        .iter()
+        .map(|entry| {
+            (
+                entry.subsystem.as_str(),
+                entry.parameter.as_str(),
+                entry.value.as_str(),
+            )
+        })
+        .collect();
+
+    assert_eq!(
+        values,
+        vec![
+            ("eps", "power.state", "ON"),
+            ("eps", "power.voltages.0", "3.3"),
+            ("eps", "power.voltages.1", "5.0"),
+        ]
+    );
+
+    let status = job.status.lock().unwrap();
+    assert_eq!(status.runs, 1);
+    assert_eq!(status.failures, 0);
+    assert_eq!(status.entries, 3);
+    assert!(status.last_success.is_some());
+}
+
+#[test]
+fn run_no_service() {
+    let dir = TempDir::new().unwrap();
+    let config = dir.path().join("config.toml");
+    mock_service!(config, 8791);
+    let database = setup_db(&dir);
+
+    let job = job("missing-service", None);
+    assert_eq!(job.subsystem(), "missing-service");
+    assert!(job
+        .run(&config.to_string_lossy(), &database, &no_limits())
+        .is_err());
+    assert!(job
+        .run(&config.to_string_lossy(), &database, &no_limits())
+        .is_err());
+
+    let status = job.status.lock().unwrap();
+    assert_eq!(status.runs, 2);
+    assert_eq!(status.failures, 2);
+    assert_eq!(status.consecutive_failures, 2);
+    assert_eq!(status.entries, 0);
+    assert!(status.last_success.is_none());
+    assert!(status.last_error.is_some());
+}
+
+#[test]
+fn run_skips_duplicates() {
+    let dir = TempDir::new().unwrap();
+    let config = dir.path().join("config.toml");
+    mock_service!(config, 8792);
+    let database = setup_db(&dir);
+
+    // An existing entry for one of the parameters at whichever second the job runs in
+    let now = ::std::time::SystemTime::now()
+        .duration_since(::std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i32;
+    for timestamp in now..now + 10 {
+        database
+            .lock()
+            .unwrap()
+            .insert(timestamp, "eps", "power.state", "OFF")
+            .unwrap();
+    }
+
+    let job = job("mock-service", Some("eps"));
+    let count = job
+        .run(&config.to_string_lossy(), &database, &no_limits())
+        .unwrap();
+    assert_eq!(count, 2);
+
+    let voltages = dsl::telemetry
+        .filter(dsl::parameter.like("power.voltages.%"))
+        .count()
+        .get_result::<i64>(&database.lock().unwrap().connection);
+    assert_eq!(voltages, Ok(2));
+    assert_eq!(job.status.lock().unwrap().entries, 2);
+}
+
+#[test]
+fn run_checks_limits() {
+    let dir = TempDir::new().unwrap();
+    let config = dir.path().join("config.toml");
+    mock_service!(config, 8793);
+    let database = setup_db(&dir);
+
+    let limits = LimitChecker::new(vec![Limit {
+        subsystem: "eps".to_owned(),
+        parameter: "power.voltages.1".to_owned(),
+        red_low: None,
+        yellow_low: None,
+        yellow_high: Some(4.0),
+        red_high: Some(4.5),
+    }]);
+
+    let job = job("mock-service", Some("eps"));
+    job.run(&config.to_string_lossy(), &database, &limits)
+        .unwrap();
+
+    let alarms = alarms_dsl::alarms
+        .load::<Alarm>(&database.lock().unwrap().connection)
+        .unwrap();
+    assert_eq!(alarms.len(), 1);
+    assert_eq!(alarms[0].parameter, "power.voltages.1");
+    assert_eq!(alarms[0].value, "5.0");
+    assert_eq!(alarms[0].level, "red_high");
+    assert_eq!(alarms[0].previous_level, "nominal");
+}
@@ -0,0 +1,50 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use juniper::FieldResult;
+use kubos_service;
+
+pub struct Subsystem;
+type Context = kubos_service::Context<Subsystem>;
+
+pub struct Power;
+
+graphql_object!(Power: () |&self| {
+    field state() -> String {
+        String::from("ON")
+    }
+
+    field voltages() -> Vec<f64> {
+        vec![3.3, 5.0]
+    }
+
+    field fault() -> Option<String> {
+        None
+    }
+});
+
+pub struct QueryRoot;
+
+graphql_object!(QueryRoot: Context as "Query" |&self| {
+    field power() -> FieldResult<Power>
+    {
+        Ok(Power)
+    }
+});
+
+pub struct MutationRoot;
+
+graphql_object!(MutationRoot: Context as "Mutation" |&self| {});
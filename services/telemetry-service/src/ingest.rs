@@ -0,0 +1,393 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use kubos_service::Config;
+use kubos_telemetry_db::{Database, LimitChecker, NewEntry};
+use serde_json;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{sleep, spawn};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const DEFAULT_QUEUE_SIZE: usize = 1000;
+const DEFAULT_BATCH_SIZE: usize = 100;
+const DEFAULT_FLUSH_INTERVAL: u64 = 500;
+
+// Largest possible UDP payload
+const MAX_DATAGRAM: usize = 65535;
+
+// How long to wait before receiving again after the ingestion socket fails
+const RECEIVE_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+// Leading byte of a binary ingestion datagram
+const BINARY_VERSION: u8 = 1;
+
+/// Settings for the direct ingestion socket, read from the
+/// `[telemetry-service.ingest]` section of the config file
+#[derive(Clone, Debug)]
+pub struct IngestConfig {
+    /// Address the ingestion socket should bind to
+    pub addr: SocketAddr,
+    /// Maximum number of records waiting to be written before new ones are dropped
+    pub queue_size: usize,
+    /// Maximum number of records written in a single transaction
+    pub batch_size: usize,
+    /// Maximum amount of time a record will wait before its batch is written
+    pub flush_interval: Duration,
+}
+
+impl IngestConfig {
+    /// Read the ingestion settings from the service config.
+    ///
+    /// Returns `None` if the `ingest` section or its `port` value is missing,
+    /// in which case the ingestion socket should not be started, or an error if
+    /// the port isn't valid.
+    pub fn from_config(config: &Config) -> Result<Option<IngestConfig>, String> {
+        let ingest = match config.get("ingest") {
+            Some(ingest) => ingest,
+            None => return Ok(None),
+        };
+        let port = match ingest.get("port").and_then(|val| val.as_integer()) {
+            Some(port) => port,
+            None => return Ok(None),
+        };
+        if port < 1 || port > 65535 {
+            return Err(format!("Invalid ingestion port: {}", port));
+        }
+
+        let mut addr = match config.hosturl().parse::<SocketAddr>() {
+            Ok(addr) => addr,
+            Err(_) => return Ok(None),
+        };
+        addr.set_port(port as u16);
+
+        let get_num = |key: &str, default: u64| -> u64 {
+            ingest
+                .get(key)
+                .and_then(|val| val.as_integer())
+                .map(|val| val as u64)
+                .unwrap_or(default)
+        };
+
+        Ok(Some(IngestConfig {
+            addr,
+            queue_size: get_num("queue_size", DEFAULT_QUEUE_SIZE as u64) as usize,
+            batch_size: get_num("batch_size", DEFAULT_BATCH_SIZE as u64) as usize,
+            flush_interval: Duration::from_millis(get_num(
+                "flush_interval",
+                DEFAULT_FLUSH_INTERVAL,
+            )),
+        }))
+    }
+}
+
+/// Running counters for the ingestion socket
+#[derive(Debug, Default)]
+pub struct IngestStats {
+    /// Number of records successfully parsed from incoming datagrams
+    pub received: AtomicUsize,
+    /// Number of records discarded because the write queue was full
+    pub dropped: AtomicUsize,
+    /// Number of datagrams which could not be parsed
+    pub malformed: AtomicUsize,
+    /// Number of records written to the database
+    pub stored: AtomicUsize,
+    /// Number of records which could not be written to the database
+    pub failed: AtomicUsize,
+}
+
+/// A single telemetry sample waiting to be written to the database
+#[derive(Debug, PartialEq)]
+pub struct Record {
+    pub timestamp: i32,
+    pub subsystem: String,
+    pub parameter: String,
+    pub value: String,
+}
+
+impl Record {
+    fn as_entry<'a>(&'a self) -> NewEntry<'a> {
+        NewEntry {
+            timestamp: self.timestamp,
+            subsystem: &self.subsystem,
+            parameter: &self.parameter,
+            value: &self.value,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonRecord {
+    timestamp: Option<i32>,
+    subsystem: String,
+    parameter: String,
+    value: serde_json::Value,
+}
+
+impl JsonRecord {
+    fn into_record(self, now: i32) -> Record {
+        let value = match self.value {
+            serde_json::Value::String(val) => val,
+            other => other.to_string(),
+        };
+
+        Record {
+            timestamp: self.timestamp.unwrap_or(now),
+            subsystem: self.subsystem,
+            parameter: self.parameter,
+            value,
+        }
+    }
+}
+
+fn now() -> i32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|dur| dur.as_secs() as i32)
+        .unwrap_or(0)
+}
+
+/// Parse all of the telemetry records contained in a single datagram.
+///
+/// Datagrams starting with `{` or `[` are treated as JSON and must contain either a single
+/// record object or an array of them. Any other datagram is treated as binary.
+pub fn parse_datagram(data: &[u8], now: i32) -> Result<Vec<Record>, String> {
+    match data.first() {
+        Some(b'{') => serde_json::from_slice::<JsonRecord>(data)
+            .map(|record| vec![record.into_record(now)])
+            .map_err(|err| err.to_string()),
+        Some(b'[') => serde_json::from_slice::<Vec<JsonRecord>>(data)
+            .map(|records| {
+                records
+                    .into_iter()
+                    .map(|record| record.into_record(now))
+                    .collect()
+            })
+            .map_err(|err| err.to_string()),
+        Some(&BINARY_VERSION) => parse_binary(&data[1..], now),
+        Some(other) => Err(format!("Unknown record format: {:#x}", other)),
+        None => Err("Empty datagram".to_owned()),
+    }
+}
+
+// Binary records are packed back-to-back, each laid out (big-endian) as:
+//
+//     timestamp: i32 (0 means "time of receipt")
+//     subsystem length: u8, followed by the subsystem name
+//     parameter length: u8, followed by the parameter name
+//     value: f64
+fn parse_binary(mut data: &[u8], now: i32) -> Result<Vec<Record>, String> {
+    let mut records = vec![];
+
+    while !data.is_empty() {
+        let timestamp = be_uint(take(&mut data, 4)?) as u32 as i32;
+
+        let subsystem = take_string(&mut data)?;
+        let parameter = take_string(&mut data)?;
+
+        let value = f64::from_bits(be_uint(take(&mut data, 8)?));
+
+        records.push(Record {
+            timestamp: if timestamp == 0 { now } else { timestamp },
+            subsystem,
+            parameter,
+            value: value.to_string(),
+        });
+    }
+
+    Ok(records)
+}
+
+fn take<'a>(data: &mut &'a [u8], len: usize) -> Result<&'a [u8], String> {
+    if data.len() < len {
+        return Err("Truncated binary record".to_owned());
+    }
+
+    let (head, tail) = data.split_at(len);
+    *data = tail;
+    Ok(head)
+}
+
+fn take_string(data: &mut &[u8]) -> Result<String, String> {
+    let len = take(data, 1)?[0] as usize;
+    let raw = take(data, len)?;
+    String::from_utf8(raw.to_vec()).map_err(|err| err.to_string())
+}
+
+// Decode a big-endian unsigned integer of up to 8 bytes
+fn be_uint(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(0, |value, byte| (value << 8) | u64::from(*byte))
+}
+
+/// Bind the ingestion socket and start the threads which read from it and
+/// write the received records to the database. Stored records are checked
+/// against their limits
+pub fn start(
+    config: IngestConfig,
+    database: Arc<Mutex<Database>>,
+    stats: Arc<IngestStats>,
+    checker: Arc<LimitChecker>,
+) -> Result<SocketAddr, String> {
+    let socket = UdpSocket::bind(config.addr).map_err(|err| {
+        format!(
+            "Failed to bind ingestion socket to {}: {}",
+            config.addr, err
+        )
+    })?;
+    let addr = socket.local_addr().map_err(|err| err.to_string())?;
+
+    let (sender, receiver) = sync_channel(config.queue_size);
+
+    let recv_stats = stats.clone();
+    spawn(move || receive_thread(socket, sender, recv_stats));
+
+    let batch_size = config.batch_size.max(1);
+    let flush_interval = config.flush_interval;
+    spawn(move || {
+        write_thread(
+            database,
+            receiver,
+            stats,
+            checker,
+            batch_size,
+            flush_interval,
+        )
+    });
+
+    Ok(addr)
+}
+
+fn receive_thread(socket: UdpSocket, sender: SyncSender<Record>, stats: Arc<IngestStats>) {
+    let mut buf = vec![0; MAX_DATAGRAM];
+    loop {
+        let size = match socket.recv_from(&mut buf) {
+            Ok((size, _)) => size,
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => {
+                // Don't spin if the socket keeps failing
+                error!("Failed to receive telemetry: {}", err);
+                sleep(RECEIVE_RETRY_DELAY);
+                continue;
+            }
+        };
+
+        let records = match parse_datagram(&buf[0..size], now()) {
+            Ok(records) => records,
+            Err(_) => {
+                stats.malformed.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+        };
+
+        for record in records {
+            stats.received.fetch_add(1, Ordering::Relaxed);
+
+            // Never block the socket on the database. If the writer can't keep up,
+            // new samples are dropped and counted so the condition is visible
+            match sender.try_send(record) {
+                Ok(_) => {}
+                Err(TrySendError::Full(_)) => {
+                    stats.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(TrySendError::Disconnected(_)) => return,
+            }
+        }
+    }
+}
+
+fn write_thread(
+    database: Arc<Mutex<Database>>,
+    receiver: Receiver<Record>,
+    stats: Arc<IngestStats>,
+    checker: Arc<LimitChecker>,
+    batch_size: usize,
+    flush_interval: Duration,
+) {
+    let mut batch = Vec::with_capacity(batch_size);
+
+    // Wait for the first record of a batch, then keep collecting until either the
+    // batch is full or the flush interval has passed
+    while let Ok(record) = receiver.recv() {
+        batch.push(record);
+
+        let deadline = Instant::now() + flush_interval;
+        while batch.len() < batch_size {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+
+            match receiver.recv_timeout(deadline - now) {
+                Ok(record) => batch.push(record),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+
+        store_batch(&database, &batch, &stats, &checker);
+        batch.clear();
+    }
+}
+
+fn store_batch(
+    database: &Arc<Mutex<Database>>,
+    batch: &[Record],
+    stats: &IngestStats,
+    checker: &LimitChecker,
+) {
+    let database = match database.lock() {
+        Ok(database) => database,
+        Err(_) => {
+            stats.failed.fetch_add(batch.len(), Ordering::Relaxed);
+            return;
+        }
+    };
+
+    let entries: Vec<NewEntry> = batch.iter().map(Record::as_entry).collect();
+    let stored = store_entries(&database, entries, stats);
+
+    if let Err(err) = checker.check(&database, &stored) {
+        error!("Failed to record telemetry alarms: {}", err);
+    }
+}
+
+// Insert the entries, returning the ones which were successfully stored
+fn store_entries<'a>(
+    database: &Database,
+    entries: Vec<NewEntry<'a>>,
+    stats: &IngestStats,
+) -> Vec<NewEntry<'a>> {
+    let total = entries.len();
+    match database.insert_batch_partial(entries) {
+        Ok(stored) => {
+            stats.stored.fetch_add(stored.len(), Ordering::Relaxed);
+            stats
+                .failed
+                .fetch_add(total - stored.len(), Ordering::Relaxed);
+            stored
+        }
+        Err(err) => {
+            error!("Failed to store {} telemetry entries: {}", total, err);
+            stats.failed.fetch_add(total, Ordering::Relaxed);
+            vec![]
+        }
+    }
+}
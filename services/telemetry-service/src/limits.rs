@@ -0,0 +1,67 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use kubos_service::Config;
+use kubos_telemetry_db::{self, Limit, LimitChecker};
+
+/// Severity of a telemetry value relative to its limits
+#[derive(GraphQLEnum, Clone, Copy, Debug, PartialEq)]
+pub enum AlarmLevel {
+    /// Within all limits
+    Nominal,
+    /// Below the yellow low limit
+    YellowLow,
+    /// Above the yellow high limit
+    YellowHigh,
+    /// Below the red low limit
+    RedLow,
+    /// Above the red high limit
+    RedHigh,
+}
+
+impl AlarmLevel {
+    /// Convert a name from the alarms table back into a level
+    pub fn from_str(level: &str) -> Option<AlarmLevel> {
+        level
+            .parse::<kubos_telemetry_db::AlarmLevel>()
+            .ok()
+            .map(AlarmLevel::from)
+    }
+}
+
+impl From<kubos_telemetry_db::AlarmLevel> for AlarmLevel {
+    fn from(level: kubos_telemetry_db::AlarmLevel) -> AlarmLevel {
+        match level {
+            kubos_telemetry_db::AlarmLevel::Nominal => AlarmLevel::Nominal,
+            kubos_telemetry_db::AlarmLevel::YellowLow => AlarmLevel::YellowLow,
+            kubos_telemetry_db::AlarmLevel::YellowHigh => AlarmLevel::YellowHigh,
+            kubos_telemetry_db::AlarmLevel::RedLow => AlarmLevel::RedLow,
+            kubos_telemetry_db::AlarmLevel::RedHigh => AlarmLevel::RedHigh,
+        }
+    }
+}
+
+/// Read the limit definitions from the service config
+pub fn from_config(config: &Config) -> Result<LimitChecker, String> {
+    let limits: Vec<Limit> = match config.get("limits") {
+        Some(raw) => raw
+            .try_into()
+            .map_err(|err| format!("Invalid limit definitions: {}", err))?,
+        None => vec![],
+    };
+
+    Ok(LimitChecker::new(limits))
+}
@@ -36,6 +36,86 @@
 //! service's IP address, and `port` specifies the port on which the service will be
 //! listening for UDP packets.
 //!
+//! The SQLite journal mode and synchronous level may optionally be configured as well:
+//!
+//! ```
+//! [telemetry-service]
+//! database = "/var/lib/telemetry.db"
+//! journal_mode = "wal"
+//! synchronous = "normal"
+//! ```
+//!
+//! `journal_mode` may be one of `delete`, `truncate`, `persist`, `memory`, `wal` or `off`, and
+//! `synchronous` may be one of `off`, `normal`, `full` or `extra`. When they are omitted, the
+//! SQLite defaults (`delete` and `full`) are used. For high-frequency logging, `wal` combined
+//! with `normal` greatly reduces the number of writes made to flash for each transaction.
+//!
+//! # Direct Ingestion
+//!
+//! High-rate producers can skip the GraphQL round trip and send telemetry samples to a
+//! secondary UDP socket. It is enabled by adding an `ingest` section to the service's config:
+//!
+//! ```
+//! [telemetry-service.ingest]
+//! port = 8090
+//! queue_size = 1000
+//! batch_size = 100
+//! flush_interval = 500
+//! ```
+//!
+//! Where `port` specifies the port the ingestion socket listens on (using the service's IP
+//! address), `queue_size` specifies the number of samples which may be waiting to be written
+//! before new samples are dropped, `batch_size` specifies the maximum number of samples written
+//! per SQLite transaction, and `flush_interval` specifies, in milliseconds, the longest a sample
+//! will wait before its batch is written. Only `port` is required.
+//!
+//! Nothing is sent back to the producer. Each datagram contains one or more samples as either JSON:
+//!
+//! ```json
+//! [
+//!   {"timestamp": 1000, "subsystem": "mai400", "parameter": "gyro_x", "value": 0.25},
+//!   {"subsystem": "mai400", "parameter": "acs_mode", "value": "normal"}
+//! ]
+//! ```
+//!
+//! or, more compactly, as a binary datagram starting with the version byte `0x01`, followed by
+//! back-to-back big-endian records:
+//!
+//! ```
+//! timestamp: i32
+//! subsystem length: u8, subsystem: [u8]
+//! parameter length: u8, parameter: [u8]
+//! value: f64
+//! ```
+//!
+//! Samples without a timestamp (or with a binary timestamp of `0`) are stamped with the time they
+//! were received. The `ingestStats` query reports how many samples have been received, dropped
+//! because the queue was full, stored or failed to store, as well as how many datagrams could
+//! not be parsed.
+//!
+//! # Limit Checking
+//!
+//! Safe ranges for individual telemetry parameters may be defined in the service's config:
+//!
+//! ```
+//! [[telemetry-service.limits]]
+//! subsystem = "eps"
+//! parameter = "voltage"
+//! red_low = 3.0
+//! yellow_low = 3.2
+//! yellow_high = 4.1
+//! red_high = 4.2
+//! ```
+//!
+//! Any of the four limits may be omitted. Every value stored by the service for a parameter with
+//! limits is evaluated against them, and each time the parameter moves to a different level
+//! (ex. from nominal to yellow high, or from red low back to nominal) an entry is recorded in the
+//! database's `alarms` table. Values which aren't numeric are ignored. The telemetry collector
+//! checks the telemetry it stores against the same limits.
+//!
+//! Alarms can be retrieved with the `alarms` query and marked as seen with the
+//! `acknowledgeAlarms` mutation.
+//!
 //! # Starting the Service
 //!
 //! The service should be started automatically by its init script, but may also be started manually:
@@ -48,8 +128,15 @@
 //! # Panics
 //!
 //! Attempts to grab database path from Configuration and will `panic!` if not found.
-//! Attempts to connect to database at provided path and will `panic!` if connection fails.
-//! Attempts to create telemetry table and will `panic!` if table creation fails.
+//! Checks the integrity of the database at the provided path when it starts. A corrupted database
+//! is moved aside to `<path>.corrupt.<timestamp>` and a new one is created in its place. The
+//! database's schema is then upgraded to the latest version. If the database can't be opened or
+//! upgraded, the service keeps running with a temporary in-memory database and reports the
+//! problem through the `databaseStatus` query. Telemetry stored in the in-memory database is lost
+//! when the service stops, and the service keeps using it until it is restarted.
+//! Attempts to parse the configured limit definitions and will `panic!` if they are invalid.
+//! Attempts to apply the configured `journal_mode` and `synchronous` settings and will `panic!`
+//! if they are invalid or cannot be applied.
 //!
 //! # GraphQL Schema
 //!
@@ -59,9 +146,111 @@
 //!   subsystem: String!
 //!   parameter: String!
 //!   value: Float!
+//!   cursor: String!
+//! }
+//!
+//! enum Order {
+//!   ASC
+//!   DESC
+//! }
+//!
+//! enum DatabaseState {
+//!   OK
+//!   RECOVERED
+//!   UNAVAILABLE
+//! }
+//!
+//! type DatabaseStatus {
+//!   state: DatabaseState!
+//!   schemaVersion: Integer!
+//!   quarantinedPath: String
+//!   errors: [String]!
+//! }
+//!
+//! query databaseStatus: DatabaseStatus!
+//!
+//! type IngestStats {
+//!   received: Integer!
+//!   dropped: Integer!
+//!   malformed: Integer!
+//!   stored: Integer!
+//!   failed: Integer!
+//! }
+//!
+//! query telemetry(
+//!     timestampGe: Integer,
+//!     timestampLe: Integer,
+//!     subsystem: String,
+//!     parameter: String,
+//!     subsystems: [String],
+//!     parameters: [String],
+//!     subsystemPrefix: String,
+//!     parameterPrefix: String,
+//!     order: Order,
+//!     after: String,
+//!     before: String,
+//!     limit: Integer
+//! ): [Entry]
+//! query telemetryCount(
+//!     timestampGe: Integer,
+//!     timestampLe: Integer,
+//!     subsystem: String,
+//!     parameter: String,
+//!     subsystems: [String],
+//!     parameters: [String],
+//!     subsystemPrefix: String,
+//!     parameterPrefix: String
+//! ): Integer
+//! type TelemetryPage {
+//!   entries: [Entry]
+//!   totalCount: Integer!
 //! }
 //!
-//! query telemetry(timestampGe: Integer, timestampLe: Integer, subsystem: String, parameter: String): Entry
+//! query telemetryPage(
+//!     timestampGe: Integer,
+//!     timestampLe: Integer,
+//!     subsystem: String,
+//!     parameter: String,
+//!     subsystems: [String],
+//!     parameters: [String],
+//!     subsystemPrefix: String,
+//!     parameterPrefix: String,
+//!     order: Order,
+//!     after: String,
+//!     before: String,
+//!     limit: Integer
+//! ): TelemetryPage!
+//! query ingestStats: IngestStats!
+//!
+//! enum AlarmLevel {
+//!   NOMINAL
+//!   YELLOW_LOW
+//!   YELLOW_HIGH
+//!   RED_LOW
+//!   RED_HIGH
+//! }
+//!
+//! type Alarm {
+//!   id: Integer!
+//!   timestamp: Integer!
+//!   subsystem: String!
+//!   parameter: String!
+//!   value: String!
+//!   level: AlarmLevel!
+//!   previousLevel: AlarmLevel!
+//!   acknowledged: Boolean!
+//! }
+//!
+//! query alarms(
+//!     subsystem: String,
+//!     parameter: String,
+//!     acknowledged: Boolean,
+//!     timestampGe: Integer,
+//!     timestampLe: Integer,
+//!     limit: Integer
+//! ): [Alarm]
+//!
+//! mutation acknowledgeAlarms(ids: [Integer]): Integer
 //! ```
 //!
 //! # Example Queries
@@ -137,28 +326,168 @@
 //!   }
 //! }
 //! ```
+//!
+//! ## Select all entries for the eps or gps subsystems whose parameter starts with "x_"
+//! ```graphql
+//! {
+//!   telemetry(subsystems: ["eps", "gps"], parameterPrefix: "x_") {
+//!     timestamp,
+//!     subsystem,
+//!     parameter,
+//!     value
+//!   }
+//! }
+//! ```
+//!
+//! Prefix matching uses SQLite's `LIKE`, so it ignores the case of ASCII characters.
+//! `subsystem` and `parameter` are shorthand for a one-item `subsystems` or `parameters` list, so
+//! a query giving both `subsystem` and `subsystems` (or `parameter` and `parameters`) is rejected.
+//!
+//! ## Fetch all unacknowledged alarms, then acknowledge them
+//! ```graphql
+//! {
+//!   alarms(acknowledged: false) {
+//!     id,
+//!     timestamp,
+//!     subsystem,
+//!     parameter,
+//!     value,
+//!     level
+//!   }
+//! }
+//! ```
+//!
+//! ```graphql
+//! mutation {
+//!   acknowledgeAlarms
+//! }
+//! ```
+//!
+//! ## Count all entries for the eps subsystem
+//! ```graphql
+//! {
+//!   telemetryCount(subsystem: "eps")
+//! }
+//! ```
+//!
+//! # Pagination
+//!
+//! Entries are sorted by timestamp, then subsystem, then parameter. They are returned newest first
+//! by default, or oldest first with `order: ASC`.
+//!
+//! Each entry has an opaque `cursor` identifying its position. Passing the cursor of the last entry
+//! of a page as `after` returns the entries which follow it, so a large time range can be walked
+//! one page at a time:
+//!
+//! ```graphql
+//! {
+//!   telemetry(order: ASC, limit: 100, after: "<cursor of the last entry of the previous page>") {
+//!     timestamp,
+//!     subsystem,
+//!     parameter,
+//!     value,
+//!     cursor
+//!   }
+//! }
+//! ```
+//!
+//! Passing a cursor as `before` (without `after`) returns the `limit` entries immediately preceding
+//! it, still in the requested order, which allows paging backwards.
+//!
+//! `telemetryCount` is read separately from `telemetry`, so new entries may be stored in between.
+//! `telemetryPage` returns a page together with the total number of matching entries, both read
+//! from the same snapshot of the database:
+//!
+//! ```graphql
+//! {
+//!   telemetryPage(order: ASC, limit: 100) {
+//!     entries { timestamp, subsystem, parameter, value, cursor }
+//!     totalCount
+//!   }
+//! }
+//! ```
 extern crate diesel;
 #[macro_use]
 extern crate juniper;
+extern crate kubos_logging;
 extern crate kubos_service;
 extern crate kubos_telemetry_db;
+#[macro_use]
+extern crate log;
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate serde_json;
 
+mod ingest;
+mod limits;
+mod model;
+mod query;
 mod schema;
 
+use ingest::IngestConfig;
+use kubos_logging::Facility;
 use kubos_service::{Config, Service};
-use kubos_telemetry_db::Database;
+use kubos_telemetry_db::{Database, JournalMode, Synchronous};
+use model::{DatabaseStatus, Subsystem};
 use schema::{MutationRoot, QueryRoot};
+use std::time::Duration;
 
 fn main() {
     let config = Config::new("telemetry-service");
+    // Set up logging before the database is opened, so that problems with it are logged
+    if let Err(err) = kubos_logging::init(Facility::Daemon, &config) {
+        eprintln!("Failed to set up logging: {}", err);
+    }
 
     let db_path = config
         .get("database")
         .expect("No database path found in config file");
     let db_path = db_path.as_str().unwrap_or("");
 
-    let db = Database::new(&db_path);
-    db.setup();
+    // Keep the service running even if the database can't be used, so that its state can
+    // still be queried. Telemetry is then only kept in memory: the service doesn't switch back
+    // to the database file on its own, and everything stored is lost when it stops. The
+    // service has to be restarted to use the database file again.
+    let (db, db_status) = match Database::open(db_path) {
+        Ok((db, report)) => (db, DatabaseStatus::from_report(report)),
+        Err(err) => {
+            error!("Failed to open database {}: {}", db_path, err);
+            let db = Database::new(":memory:").expect("Failed to create in-memory database");
+            db.setup().expect("Failed to set up in-memory database");
+            (db, DatabaseStatus::unavailable(err.to_string()))
+        }
+    };
+
+    // The telemetry collector writes to the same database file
+    db.set_busy_timeout(Duration::from_secs(1))
+        .expect("Failed to set database busy timeout");
+
+    if let Some(mode) = config.get("journal_mode") {
+        let mode = mode
+            .as_str()
+            .and_then(|mode| mode.parse::<JournalMode>().ok())
+            .expect("Invalid journal_mode in config file");
+        db.set_journal_mode(mode)
+            .expect("Failed to set database journal mode");
+    }
+
+    if let Some(level) = config.get("synchronous") {
+        let level = level
+            .as_str()
+            .and_then(|level| level.parse::<Synchronous>().ok())
+            .expect("Invalid synchronous level in config file");
+        db.set_synchronous(level)
+            .expect("Failed to set database synchronous level");
+    }
+
+    let ingest_config = IngestConfig::from_config(&config).unwrap_or_else(|err| panic!("{}", err));
+    let limits = limits::from_config(&config).unwrap_or_else(|err| panic!("{}", err));
 
-    Service::new(config, db, QueryRoot, MutationRoot).start();
+    Service::new(
+        config,
+        Subsystem::new(db, db_status, ingest_config, limits),
+        QueryRoot,
+        MutationRoot,
+    ).start();
 }
@@ -0,0 +1,102 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use ingest::{self, IngestConfig, IngestStats};
+use kubos_telemetry_db::{Database, LimitChecker, OpenReport};
+use std::sync::{Arc, Mutex};
+
+/// Health of the service's database
+#[derive(GraphQLEnum, Clone, Copy, Debug, PartialEq)]
+pub enum DatabaseState {
+    /// The database passed its integrity check
+    Ok,
+    /// The database was corrupted and has been recreated
+    Recovered,
+    /// The database couldn't be opened. Telemetry is only being kept in memory
+    Unavailable,
+}
+
+/// Outcome of opening the database at startup
+#[derive(Clone, Debug)]
+pub struct DatabaseStatus {
+    pub state: DatabaseState,
+    pub schema_version: i32,
+    pub quarantined: Option<String>,
+    pub errors: Vec<String>,
+}
+
+impl DatabaseStatus {
+    /// Status of a database which was opened successfully
+    pub fn from_report(report: OpenReport) -> Self {
+        DatabaseStatus {
+            state: if report.quarantined.is_some() {
+                DatabaseState::Recovered
+            } else {
+                DatabaseState::Ok
+            },
+            schema_version: report.version,
+            quarantined: report.quarantined,
+            errors: report.problems,
+        }
+    }
+
+    /// Status of a database which couldn't be opened
+    pub fn unavailable(error: String) -> Self {
+        DatabaseStatus {
+            state: DatabaseState::Unavailable,
+            schema_version: 0,
+            quarantined: None,
+            errors: vec![error],
+        }
+    }
+}
+
+pub struct Subsystem {
+    pub database: Arc<Mutex<Database>>,
+    pub database_status: DatabaseStatus,
+    pub ingest_stats: Arc<IngestStats>,
+}
+
+impl Subsystem {
+    pub fn new(
+        database: Database,
+        database_status: DatabaseStatus,
+        ingest_config: Option<IngestConfig>,
+        limits: LimitChecker,
+    ) -> Self {
+        let database = Arc::new(Mutex::new(database));
+        let ingest_stats = Arc::new(IngestStats::default());
+        let limits = Arc::new(limits);
+
+        if let Some(config) = ingest_config {
+            match ingest::start(
+                config,
+                database.clone(),
+                ingest_stats.clone(),
+                limits.clone(),
+            ) {
+                Ok(addr) => info!("Ingesting telemetry on: {}", addr),
+                Err(err) => error!("{}", err),
+            }
+        }
+
+        Subsystem {
+            database,
+            database_status,
+            ingest_stats,
+        }
+    }
+}
@@ -0,0 +1,242 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use diesel::prelude::*;
+use diesel::sqlite::Sqlite;
+use kubos_telemetry_db::telemetry::{self, dsl};
+use kubos_telemetry_db::{Database, Entry};
+use serde_json;
+
+type BoxedQuery<'a> = telemetry::BoxedQuery<'a, Sqlite>;
+
+/// Order in which telemetry entries are returned
+#[derive(GraphQLEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Order {
+    /// Oldest entries first
+    Asc,
+    /// Newest entries first
+    Desc,
+}
+
+/// Position of an entry within the telemetry table, used to resume a query
+/// from where a previous page ended
+#[derive(Debug, PartialEq)]
+pub struct Cursor {
+    pub timestamp: i32,
+    pub subsystem: String,
+    pub parameter: String,
+}
+
+impl Cursor {
+    /// Create the cursor pointing at a particular entry
+    pub fn from_entry(entry: &Entry) -> Cursor {
+        Cursor {
+            timestamp: entry.timestamp,
+            subsystem: entry.subsystem.clone(),
+            parameter: entry.parameter.clone(),
+        }
+    }
+
+    /// Convert the cursor into the opaque string handed to clients
+    pub fn encode(&self) -> String {
+        json!([self.timestamp, self.subsystem, self.parameter]).to_string()
+    }
+
+    /// Parse a cursor string previously returned by `encode`
+    pub fn decode(raw: &str) -> Result<Cursor, String> {
+        let (timestamp, subsystem, parameter) = serde_json::from_str::<(i32, String, String)>(raw)
+            .map_err(|_| format!("Invalid cursor: {}", raw))?;
+
+        Ok(Cursor {
+            timestamp,
+            subsystem,
+            parameter,
+        })
+    }
+}
+
+/// Conditions an entry must meet to be returned
+#[derive(Debug, Default)]
+pub struct Filter {
+    pub timestamp_ge: Option<i32>,
+    pub timestamp_le: Option<i32>,
+    pub subsystems: Option<Vec<String>>,
+    pub parameters: Option<Vec<String>>,
+    pub subsystem_prefix: Option<String>,
+    pub parameter_prefix: Option<String>,
+}
+
+impl Filter {
+    fn apply<'a>(&self, mut query: BoxedQuery<'a>) -> BoxedQuery<'a> {
+        if let Some(ref subs) = self.subsystems {
+            query = query.filter(dsl::subsystem.eq_any(subs.clone()));
+        }
+
+        if let Some(ref params) = self.parameters {
+            query = query.filter(dsl::parameter.eq_any(params.clone()));
+        }
+
+        if let Some(ref prefix) = self.subsystem_prefix {
+            query = query.filter(dsl::subsystem.like(like_prefix(prefix)).escape('\\'));
+        }
+
+        if let Some(ref prefix) = self.parameter_prefix {
+            query = query.filter(dsl::parameter.like(like_prefix(prefix)).escape('\\'));
+        }
+
+        if let Some(time_ge) = self.timestamp_ge {
+            query = query.filter(dsl::timestamp.ge(time_ge));
+        }
+
+        if let Some(time_le) = self.timestamp_le {
+            query = query.filter(dsl::timestamp.le(time_le));
+        }
+
+        query
+    }
+}
+
+// Build a LIKE pattern matching anything starting with `prefix`
+fn like_prefix(prefix: &str) -> String {
+    let mut pattern = String::with_capacity(prefix.len() + 1);
+    for c in prefix.chars() {
+        if c == '%' || c == '_' || c == '\\' {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+// Only keep entries which sort strictly after the cursor in ascending order
+fn greater_than<'a>(query: BoxedQuery<'a>, cursor: &Cursor) -> BoxedQuery<'a> {
+    query.filter(
+        dsl::timestamp
+            .gt(cursor.timestamp)
+            .or(dsl::timestamp.eq(cursor.timestamp).and(
+                dsl::subsystem
+                    .gt(cursor.subsystem.clone())
+                    .or(dsl::subsystem
+                        .eq(cursor.subsystem.clone())
+                        .and(dsl::parameter.gt(cursor.parameter.clone()))),
+            )),
+    )
+}
+
+// Only keep entries which sort strictly before the cursor in ascending order
+fn less_than<'a>(query: BoxedQuery<'a>, cursor: &Cursor) -> BoxedQuery<'a> {
+    query.filter(
+        dsl::timestamp
+            .lt(cursor.timestamp)
+            .or(dsl::timestamp.eq(cursor.timestamp).and(
+                dsl::subsystem
+                    .lt(cursor.subsystem.clone())
+                    .or(dsl::subsystem
+                        .eq(cursor.subsystem.clone())
+                        .and(dsl::parameter.lt(cursor.parameter.clone()))),
+            )),
+    )
+}
+
+/// Fetch a page of telemetry entries.
+///
+/// Entries are sorted by timestamp, subsystem and parameter in the requested order.
+/// `after` and `before` restrict the results to entries sorting after/before the given
+/// cursors. When only `before` is given, the `limit` entries immediately preceding it are
+/// returned, so a client can page backwards as well as forwards.
+pub fn load_entries(
+    database: &Database,
+    filter: &Filter,
+    order: Order,
+    after: Option<Cursor>,
+    before: Option<Cursor>,
+    limit: Option<i32>,
+) -> QueryResult<Vec<Entry>> {
+    let backward = before.is_some() && after.is_none();
+
+    let mut query = filter.apply(telemetry::table.into_boxed());
+
+    if let Some(ref cursor) = after {
+        query = match order {
+            Order::Asc => greater_than(query, cursor),
+            Order::Desc => less_than(query, cursor),
+        };
+    }
+
+    if let Some(ref cursor) = before {
+        query = match order {
+            Order::Asc => less_than(query, cursor),
+            Order::Desc => greater_than(query, cursor),
+        };
+    }
+
+    // When walking backwards from a cursor, fetch the closest entries first and then
+    // flip them back into the requested order
+    let ascending = (order == Order::Asc) != backward;
+    query = if ascending {
+        query.order((
+            dsl::timestamp.asc(),
+            dsl::subsystem.asc(),
+            dsl::parameter.asc(),
+        ))
+    } else {
+        query.order((
+            dsl::timestamp.desc(),
+            dsl::subsystem.desc(),
+            dsl::parameter.desc(),
+        ))
+    };
+
+    if let Some(l) = limit {
+        query = query.limit(l.into());
+    }
+
+    let mut entries = query.load::<Entry>(&database.connection)?;
+
+    if backward {
+        entries.reverse();
+    }
+
+    Ok(entries)
+}
+
+/// Count all of the entries matching a filter
+pub fn count_entries(database: &Database, filter: &Filter) -> QueryResult<i64> {
+    filter
+        .apply(telemetry::table.into_boxed())
+        .count()
+        .get_result(&database.connection)
+}
+
+/// Fetch a page of telemetry entries along with the number of entries matching the filter.
+///
+/// Both are read within a single transaction, so the count can't include entries written after
+/// the page was read, or miss entries which appear in it.
+pub fn load_page(
+    database: &Database,
+    filter: &Filter,
+    order: Order,
+    after: Option<Cursor>,
+    before: Option<Cursor>,
+    limit: Option<i32>,
+) -> QueryResult<(Vec<Entry>, i64)> {
+    database.connection.transaction(|| {
+        let entries = load_entries(database, filter, order, after, before, limit)?;
+        let total = count_entries(database, filter)?;
+        Ok((entries, total))
+    })
+}
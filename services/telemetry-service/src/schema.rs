@@ -14,12 +14,17 @@
 // limitations under the License.
 //
 
-use diesel::prelude::*;
+use ingest::IngestStats;
 use juniper::FieldResult;
 use kubos_service;
-use kubos_telemetry_db::{self, Database};
+use diesel::prelude::*;
+use kubos_telemetry_db;
+use limits::AlarmLevel;
+use model::{DatabaseState, DatabaseStatus, Subsystem};
+use query::{self, Cursor, Filter, Order};
+use std::sync::atomic::Ordering;
 
-type Context = kubos_service::Context<Database>;
+type Context = kubos_service::Context<Subsystem>;
 
 pub struct Entry(kubos_telemetry_db::Entry);
 
@@ -41,8 +46,138 @@ graphql_object!(Entry: () |&self| {
     field value() -> &String as "Telemetry value" {
         &self.0.value
     }
+
+    field cursor() -> String as "Cursor used to fetch the entries after or before this one" {
+        Cursor::from_entry(&self.0).encode()
+    }
+});
+
+pub struct Alarm(kubos_telemetry_db::Alarm);
+
+graphql_object!(Alarm: () |&self| {
+    description: "A change in the alarm level of a telemetry parameter"
+
+    field id() -> i32 as "Alarm ID" {
+        self.0.id
+    }
+
+    field timestamp() -> i32 as "Timestamp of the value which caused the transition" {
+        self.0.timestamp
+    }
+
+    field subsystem() -> &String as "Subsystem name" {
+        &self.0.subsystem
+    }
+
+    field parameter() -> &String as "Telemetry parameter" {
+        &self.0.parameter
+    }
+
+    field value() -> &String as "Telemetry value which caused the transition" {
+        &self.0.value
+    }
+
+    field level() -> FieldResult<AlarmLevel> as "New alarm level" {
+        AlarmLevel::from_str(&self.0.level)
+            .ok_or_else(|| format!("Unknown alarm level: {}", self.0.level).into())
+    }
+
+    field previous_level() -> FieldResult<AlarmLevel> as "Previous alarm level" {
+        AlarmLevel::from_str(&self.0.previous_level)
+            .ok_or_else(|| format!("Unknown alarm level: {}", self.0.previous_level).into())
+    }
+
+    field acknowledged() -> bool as "Whether the alarm has been acknowledged" {
+        self.0.acknowledged
+    }
+});
+
+graphql_object!(IngestStats: () |&self| {
+    description: "Counters for the direct telemetry ingestion socket"
+
+    field received() -> i32 as "Number of records received" {
+        self.received.load(Ordering::Relaxed) as i32
+    }
+
+    field dropped() -> i32 as "Number of records dropped because the write queue was full" {
+        self.dropped.load(Ordering::Relaxed) as i32
+    }
+
+    field malformed() -> i32 as "Number of datagrams which could not be parsed" {
+        self.malformed.load(Ordering::Relaxed) as i32
+    }
+
+    field stored() -> i32 as "Number of records written to the database" {
+        self.stored.load(Ordering::Relaxed) as i32
+    }
+
+    field failed() -> i32 as "Number of records which could not be written to the database" {
+        self.failed.load(Ordering::Relaxed) as i32
+    }
+});
+
+graphql_object!(DatabaseStatus: () |&self| {
+    description: "Health of the telemetry database"
+
+    field state() -> DatabaseState as "Result of opening the database at startup" {
+        self.state
+    }
+
+    field schema_version() -> i32 as "Schema version of the database" {
+        self.schema_version
+    }
+
+    field quarantined_path() -> Option<&String>
+        as "Where the corrupted database was moved to, if it had to be recreated"
+    {
+        self.quarantined.as_ref()
+    }
+
+    field errors() -> &Vec<String> as "Problems found while opening the database" {
+        &self.errors
+    }
+});
+
+pub struct TelemetryPage {
+    entries: Vec<Entry>,
+    total: i64,
+}
+
+graphql_object!(TelemetryPage: () |&self| {
+    description: "A page of telemetry entries"
+
+    field entries() -> &Vec<Entry> as "Telemetry entries in the page" {
+        &self.entries
+    }
+
+    field total_count() -> i32
+        as "Total number of telemetry entries matching the filters when the page was read"
+    {
+        self.total as i32
+    }
 });
 
+// The single `subsystem`/`parameter` arguments are shorthand for a one-item list. Giving both
+// forms of an argument is rejected rather than guessing whether they should be combined
+fn list_filter(
+    name: &str,
+    single: Option<String>,
+    list: Option<Vec<String>>,
+) -> Result<Option<Vec<String>>, String> {
+    match (single, list) {
+        (Some(_), Some(_)) => Err(format!("Only one of `{0}` and `{0}s` may be given", name)),
+        (Some(single), None) => Ok(Some(vec![single])),
+        (None, list) => Ok(list),
+    }
+}
+
+fn decode_cursor(raw: Option<String>) -> Result<Option<Cursor>, String> {
+    match raw {
+        Some(raw) => Ok(Some(Cursor::decode(&raw)?)),
+        None => Ok(None),
+    }
+}
+
 pub struct QueryRoot;
 
 graphql_object!(QueryRoot: Context |&self| {
@@ -52,15 +187,130 @@ graphql_object!(QueryRoot: Context |&self| {
         timestamp_le: Option<i32>,
         subsystem: Option<String>,
         parameter: Option<String>,
+        subsystems: Option<Vec<String>>,
+        parameters: Option<Vec<String>>,
+        subsystem_prefix: Option<String>,
+        parameter_prefix: Option<String>,
+        order: Option<Order>,
+        after: Option<String>,
+        before: Option<String>,
         limit: Option<i32>,
     ) -> FieldResult<Vec<Entry>>
         as "Telemetry entries in database"
     {
-        use kubos_telemetry_db::telemetry::dsl;
-        use kubos_telemetry_db::telemetry;
-        use diesel::sqlite::SqliteConnection;
+        let filter = Filter {
+            timestamp_ge,
+            timestamp_le,
+            subsystems: list_filter("subsystem", subsystem, subsystems)?,
+            parameters: list_filter("parameter", parameter, parameters)?,
+            subsystem_prefix,
+            parameter_prefix,
+        };
+
+        let after = decode_cursor(after)?;
+        let before = decode_cursor(before)?;
+
+        let database = executor.context().subsystem().database.lock()?;
+        let entries = query::load_entries(
+            &database,
+            &filter,
+            order.unwrap_or(Order::Desc),
+            after,
+            before,
+            limit,
+        )?;
+
+        Ok(entries.into_iter().map(Entry).collect())
+    }
+
+    field telemetry_page(
+        &executor,
+        timestamp_ge: Option<i32>,
+        timestamp_le: Option<i32>,
+        subsystem: Option<String>,
+        parameter: Option<String>,
+        subsystems: Option<Vec<String>>,
+        parameters: Option<Vec<String>>,
+        subsystem_prefix: Option<String>,
+        parameter_prefix: Option<String>,
+        order: Option<Order>,
+        after: Option<String>,
+        before: Option<String>,
+        limit: Option<i32>,
+    ) -> FieldResult<TelemetryPage>
+        as "A page of telemetry entries, along with the total number of entries matching the \
+            filters. Both are read from the same snapshot of the database"
+    {
+        let filter = Filter {
+            timestamp_ge,
+            timestamp_le,
+            subsystems: list_filter("subsystem", subsystem, subsystems)?,
+            parameters: list_filter("parameter", parameter, parameters)?,
+            subsystem_prefix,
+            parameter_prefix,
+        };
 
-        let mut query = telemetry::table.into_boxed::<<SqliteConnection as Connection>::Backend>();
+        let after = decode_cursor(after)?;
+        let before = decode_cursor(before)?;
+
+        let database = executor.context().subsystem().database.lock()?;
+        let (entries, total) = query::load_page(
+            &database,
+            &filter,
+            order.unwrap_or(Order::Desc),
+            after,
+            before,
+            limit,
+        )?;
+
+        Ok(TelemetryPage {
+            entries: entries.into_iter().map(Entry).collect(),
+            total,
+        })
+    }
+
+    field telemetry_count(
+        &executor,
+        timestamp_ge: Option<i32>,
+        timestamp_le: Option<i32>,
+        subsystem: Option<String>,
+        parameter: Option<String>,
+        subsystems: Option<Vec<String>>,
+        parameters: Option<Vec<String>>,
+        subsystem_prefix: Option<String>,
+        parameter_prefix: Option<String>,
+    ) -> FieldResult<i32>
+        as "Total number of telemetry entries matching the filters"
+    {
+        let filter = Filter {
+            timestamp_ge,
+            timestamp_le,
+            subsystems: list_filter("subsystem", subsystem, subsystems)?,
+            parameters: list_filter("parameter", parameter, parameters)?,
+            subsystem_prefix,
+            parameter_prefix,
+        };
+
+        let database = executor.context().subsystem().database.lock()?;
+        Ok(query::count_entries(&database, &filter)? as i32)
+    }
+
+    field alarms(
+        &executor,
+        subsystem: Option<String>,
+        parameter: Option<String>,
+        acknowledged: Option<bool>,
+        timestamp_ge: Option<i32>,
+        timestamp_le: Option<i32>,
+        limit: Option<i32>,
+    ) -> FieldResult<Vec<Alarm>>
+        as "Alarm transitions, newest first"
+    {
+        use kubos_telemetry_db::alarms::dsl;
+        use kubos_telemetry_db::alarms;
+        use diesel::sqlite::Sqlite;
+
+        let mut query = alarms::table.into_boxed::<Sqlite>();
 
         if let Some(sub) = subsystem {
             query = query.filter(dsl::subsystem.eq(sub));
@@ -70,6 +320,10 @@ graphql_object!(QueryRoot: Context |&self| {
             query = query.filter(dsl::parameter.eq(param));
         }
 
+        if let Some(ack) = acknowledged {
+            query = query.filter(dsl::acknowledged.eq(ack));
+        }
+
         if let Some(time_ge) = timestamp_ge {
             query = query.filter(dsl::timestamp.ge(time_ge));
         }
@@ -82,19 +336,37 @@ graphql_object!(QueryRoot: Context |&self| {
             query = query.limit(l.into());
         }
 
-        query = query.order(dsl::timestamp.desc());
+        query = query.order(dsl::id.desc());
 
-        let entries = query.load::<kubos_telemetry_db::Entry>(
-            &executor.context().subsystem().connection)?;
-        let mut g_entries: Vec<Entry> = Vec::new();
-        for entry in entries {
-            g_entries.push(Entry(entry));
-        }
+        let database = executor.context().subsystem().database.lock()?;
+        let alarms = query.load::<kubos_telemetry_db::Alarm>(&database.connection)?;
 
-        Ok(g_entries)
+        Ok(alarms.into_iter().map(Alarm).collect())
+    }
+
+    field ingest_stats(&executor) -> FieldResult<&IngestStats>
+        as "Direct ingestion socket counters"
+    {
+        Ok(executor.context().subsystem().ingest_stats.as_ref())
+    }
+
+    field database_status(&executor) -> FieldResult<&DatabaseStatus>
+        as "Health of the telemetry database"
+    {
+        Ok(&executor.context().subsystem().database_status)
     }
 });
 
 pub struct MutationRoot;
 
-graphql_object!(MutationRoot: Context | &self | {});
+graphql_object!(MutationRoot: Context | &self | {
+    field acknowledge_alarms(&executor, ids: Option<Vec<i32>>) -> FieldResult<i32>
+        as "Acknowledge alarms by ID, or all unacknowledged alarms if no IDs are given. \
+            Returns the number of alarms acknowledged"
+    {
+        let database = executor.context().subsystem().database.lock()?;
+        let count = database.acknowledge_alarms(ids.as_ref().map(|ids| &ids[..]))?;
+
+        Ok(count as i32)
+    }
+});
@@ -0,0 +1,92 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+#[macro_use]
+extern crate serde_json;
+
+mod utils;
+use utils::*;
+
+use std::net::UdpSocket;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn test() {
+    let (handle, sender) = setup(None);
+
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket
+        .send_to(
+            br#"[
+                {"timestamp": 1000, "subsystem": "eps", "parameter": "voltage", "value": 3.3},
+                {"timestamp": 1001, "subsystem": "eps", "parameter": "voltage", "value": 3.1},
+                {"timestamp": 1002, "subsystem": "eps", "parameter": "voltage", "value": 3.15},
+                {"timestamp": 1003, "subsystem": "eps", "parameter": "voltage", "value": 2.9},
+                {"timestamp": 1004, "subsystem": "eps", "parameter": "voltage", "value": 3.5},
+                {"timestamp": 1005, "subsystem": "eps", "parameter": "current", "value": 100},
+                {"timestamp": 1006, "subsystem": "eps", "parameter": "voltage", "value": "broken"}
+            ]"#,
+            "127.0.0.1:8112",
+        )
+        .unwrap();
+
+    // Give the batch time to be flushed
+    thread::sleep(Duration::from_millis(500));
+
+    let alarms =
+        do_query("{alarms{timestamp,subsystem,parameter,value,level,previousLevel,acknowledged}}");
+    let acked = do_query("mutation {acknowledgeAlarms}");
+    let pending = do_query("{alarms(acknowledged: false){timestamp}}");
+    teardown(handle, sender);
+
+    assert_eq!(
+        alarms,
+        json!({
+            "errs": "",
+            "msg": {
+                "alarms":[
+                    {"timestamp":1004,"subsystem":"eps","parameter":"voltage","value":"3.5",
+                     "level":"NOMINAL","previousLevel":"RED_LOW","acknowledged":false},
+                    {"timestamp":1003,"subsystem":"eps","parameter":"voltage","value":"2.9",
+                     "level":"RED_LOW","previousLevel":"YELLOW_LOW","acknowledged":false},
+                    {"timestamp":1001,"subsystem":"eps","parameter":"voltage","value":"3.1",
+                     "level":"YELLOW_LOW","previousLevel":"NOMINAL","acknowledged":false},
+                ]
+            }
+        })
+    );
+
+    assert_eq!(
+        acked,
+        json!({
+            "errs": "",
+            "msg": {
+                "acknowledgeAlarms": 3
+            }
+        })
+    );
+
+    assert_eq!(
+        pending,
+        json!({
+            "errs": "",
+            "msg": {
+                "alarms": []
+            }
+        })
+    );
+}
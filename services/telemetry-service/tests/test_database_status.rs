@@ -0,0 +1,42 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+#[macro_use]
+extern crate serde_json;
+
+mod utils;
+use utils::*;
+
+#[test]
+fn test() {
+    let (handle, sender) = setup(None);
+    let res = do_query("{databaseStatus{state,schemaVersion,quarantinedPath,errors}}");
+    teardown(handle, sender);
+    assert_eq!(
+        res,
+        json!({
+            "errs": "",
+            "msg": {
+                "databaseStatus": {
+                    "state": "OK",
+                    "schemaVersion": 2,
+                    "quarantinedPath": null,
+                    "errors": []
+                }
+            }
+        })
+    );
+}
@@ -0,0 +1,85 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+#[macro_use]
+extern crate serde_json;
+
+mod utils;
+use utils::*;
+
+static SQL: &'static str = r"
+insert into telemetry values(1000, 'eps', 'voltage', '3.3');
+insert into telemetry values(1001, 'gps', 'x_position', '-1.0');
+insert into telemetry values(1002, 'gps', 'y_position', '2.0');
+insert into telemetry values(1003, 'adcs', 'mode', 'normal');
+insert into telemetry values(1004, 'eps', 'x_current', '0.1');
+";
+
+#[test]
+fn test() {
+    let (handle, sender) = setup(Some(SQL));
+    let lists = do_query(
+        "{telemetry(subsystems: [\"eps\", \"adcs\"], parameters: [\"voltage\", \"mode\"]){timestamp,subsystem,parameter,value}}",
+    );
+    let prefix = do_query(
+        "{telemetry(parameterPrefix: \"x_\", order: ASC){timestamp,subsystem,parameter,value}}",
+    );
+    let count = do_query("{telemetryCount(subsystemPrefix: \"gp\")}");
+    let both = do_query("{telemetry(subsystem: \"eps\", subsystems: [\"gps\"]){timestamp}}");
+    teardown(handle, sender);
+
+    assert_eq!(
+        lists,
+        json!({
+            "errs": "",
+            "msg": {
+                "telemetry":[
+                    {"timestamp":1003,"subsystem":"adcs","parameter":"mode","value":"normal"},
+                    {"timestamp":1000,"subsystem":"eps","parameter":"voltage","value":"3.3"},
+                ]
+            }
+        })
+    );
+
+    assert_eq!(
+        prefix,
+        json!({
+            "errs": "",
+            "msg": {
+                "telemetry":[
+                    {"timestamp":1001,"subsystem":"gps","parameter":"x_position","value":"-1.0"},
+                    {"timestamp":1004,"subsystem":"eps","parameter":"x_current","value":"0.1"},
+                ]
+            }
+        })
+    );
+
+    assert_eq!(
+        count,
+        json!({
+            "errs": "",
+            "msg": {
+                "telemetryCount": 2
+            }
+        })
+    );
+
+    assert_eq!(both["msg"], serde_json::Value::Null);
+    assert!(both["errs"]
+        .as_str()
+        .unwrap()
+        .contains("Only one of `subsystem` and `subsystems` may be given"));
+}
@@ -0,0 +1,104 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+#[macro_use]
+extern crate serde_json;
+
+mod utils;
+use utils::*;
+
+use std::net::UdpSocket;
+use std::thread;
+use std::time::Duration;
+
+fn binary_record(timestamp: i32, subsystem: &str, parameter: &str, value: f64) -> Vec<u8> {
+    let mut record = vec![];
+    push_be(&mut record, timestamp as u32 as u64, 4);
+    record.push(subsystem.len() as u8);
+    record.extend_from_slice(subsystem.as_bytes());
+    record.push(parameter.len() as u8);
+    record.extend_from_slice(parameter.as_bytes());
+    push_be(&mut record, value.to_bits(), 8);
+    record
+}
+
+// Append the low `len` bytes of `value`, most significant first
+fn push_be(buf: &mut Vec<u8>, value: u64, len: usize) {
+    for shift in (0..len).rev() {
+        buf.push((value >> (shift * 8)) as u8);
+    }
+}
+
+#[test]
+fn test() {
+    let (handle, sender) = setup(None);
+
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket
+        .send_to(
+            br#"[
+                {"timestamp": 1000, "subsystem": "mai400", "parameter": "gyro_x", "value": 0.25},
+                {"timestamp": 1001, "subsystem": "mai400", "parameter": "acs_mode", "value": "normal"}
+            ]"#,
+            "127.0.0.1:8112",
+        )
+        .unwrap();
+
+    let mut datagram = vec![1];
+    datagram.extend(binary_record(1002, "mai400", "gyro_y", -1.5));
+    datagram.extend(binary_record(1003, "eps", "voltage", 3.3));
+    socket.send_to(&datagram, "127.0.0.1:8112").unwrap();
+
+    socket.send_to(b"not telemetry", "127.0.0.1:8112").unwrap();
+
+    // Give the batch time to be flushed
+    thread::sleep(Duration::from_millis(500));
+
+    let entries = do_query("{telemetry{timestamp,subsystem,parameter,value}}");
+    let stats = do_query("{ingestStats{received,dropped,malformed,stored,failed}}");
+    teardown(handle, sender);
+
+    assert_eq!(
+        entries,
+        json!({
+            "errs": "",
+            "msg": {
+                "telemetry":[
+                    {"timestamp":1003,"subsystem":"eps","parameter":"voltage","value":"3.3"},
+                    {"timestamp":1002,"subsystem":"mai400","parameter":"gyro_y","value":"-1.5"},
+                    {"timestamp":1001,"subsystem":"mai400","parameter":"acs_mode","value":"normal"},
+                    {"timestamp":1000,"subsystem":"mai400","parameter":"gyro_x","value":"0.25"},
+                ]
+            }
+        })
+    );
+
+    assert_eq!(
+        stats,
+        json!({
+            "errs": "",
+            "msg": {
+                "ingestStats": {
+                    "received": 4,
+                    "dropped": 0,
+                    "malformed": 1,
+                    "stored": 4,
+                    "failed": 0
+                }
+            }
+        })
+    );
+}
@@ -0,0 +1,139 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+#[macro_use]
+extern crate serde_json;
+
+mod utils;
+use utils::*;
+
+static SQL: &'static str = r"
+insert into telemetry values(1000, 'eps', 'voltage', '3.3');
+insert into telemetry values(1000, 'eps', 'current', '0.5');
+insert into telemetry values(1001, 'eps', 'voltage', '3.4');
+insert into telemetry values(1001, 'gps', 'x_position', '-1.0');
+insert into telemetry values(1002, 'eps', 'voltage', '3.2');
+";
+
+fn cursor(res: &serde_json::Value, index: usize) -> String {
+    res["msg"]["telemetry"][index]["cursor"]
+        .as_str()
+        .unwrap()
+        .replace("\"", "\\\"")
+}
+
+#[test]
+fn test() {
+    let (handle, sender) = setup(Some(SQL));
+
+    let first =
+        do_query("{telemetry(order: ASC, limit: 2){timestamp,subsystem,parameter,value,cursor}}");
+    let second = do_query(&format!(
+        "{{telemetry(order: ASC, limit: 2, after: \"{}\"){{timestamp,subsystem,parameter,value}}}}",
+        cursor(&first, 1)
+    ));
+    let previous = do_query(&format!(
+        "{{telemetry(order: ASC, limit: 1, before: \"{}\"){{timestamp,subsystem,parameter,value}}}}",
+        cursor(&first, 1)
+    ));
+    let newest = do_query(&format!(
+        "{{telemetry(limit: 2, after: \"{}\"){{timestamp,subsystem,parameter,value}}}}",
+        cursor(&first, 1)
+    ));
+    let count = do_query("{telemetryCount}");
+    let page = do_query(&format!(
+        "{{telemetryPage(order: ASC, limit: 2, after: \"{}\"){{entries{{timestamp,subsystem,parameter}},totalCount}}}}",
+        cursor(&first, 1)
+    ));
+    let bad_cursor = do_query("{telemetry(after: \"garbage\"){timestamp}}");
+
+    teardown(handle, sender);
+
+    assert_eq!(
+        first["msg"]["telemetry"],
+        json!([
+            {"timestamp":1000,"subsystem":"eps","parameter":"current","value":"0.5",
+             "cursor":"[1000,\"eps\",\"current\"]"},
+            {"timestamp":1000,"subsystem":"eps","parameter":"voltage","value":"3.3",
+             "cursor":"[1000,\"eps\",\"voltage\"]"},
+        ])
+    );
+
+    assert_eq!(
+        second,
+        json!({
+            "errs": "",
+            "msg": {
+                "telemetry":[
+                    {"timestamp":1001,"subsystem":"eps","parameter":"voltage","value":"3.4"},
+                    {"timestamp":1001,"subsystem":"gps","parameter":"x_position","value":"-1.0"},
+                ]
+            }
+        })
+    );
+
+    assert_eq!(
+        previous,
+        json!({
+            "errs": "",
+            "msg": {
+                "telemetry":[
+                    {"timestamp":1000,"subsystem":"eps","parameter":"current","value":"0.5"},
+                ]
+            }
+        })
+    );
+
+    assert_eq!(
+        newest,
+        json!({
+            "errs": "",
+            "msg": {
+                "telemetry":[
+                    {"timestamp":1000,"subsystem":"eps","parameter":"current","value":"0.5"},
+                ]
+            }
+        })
+    );
+
+    assert_eq!(
+        count,
+        json!({
+            "errs": "",
+            "msg": {
+                "telemetryCount": 5
+            }
+        })
+    );
+
+    assert_eq!(
+        page,
+        json!({
+            "errs": "",
+            "msg": {
+                "telemetryPage": {
+                    "entries": [
+                        {"timestamp":1001,"subsystem":"eps","parameter":"voltage"},
+                        {"timestamp":1001,"subsystem":"gps","parameter":"x_position"},
+                    ],
+                    "totalCount": 5
+                }
+            }
+        })
+    );
+
+    assert_eq!(bad_cursor["msg"], serde_json::Value::Null);
+}
@@ -30,7 +30,7 @@ static UP_SQL: &'static str = r"CREATE TABLE telemetry (
     value VARCHAR(255) NOT NULL,
     PRIMARY KEY (timestamp, subsystem, parameter))";
 
-static DOWN_SQL: &'static str = r"DROP TABLE telemetry;";
+static DOWN_SQL: &'static str = r"DROP TABLE telemetry; DROP TABLE IF EXISTS alarms; DROP TABLE IF EXISTS schema_version;";
 
 fn setup_db(sql: Option<&str>) {
     Command::new("sqlite3")